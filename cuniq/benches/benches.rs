@@ -4,10 +4,10 @@
 //! Benchmarks for the built binary
 
 use std::fs::File;
-use std::path::PathBuf;
 use std::process::{Command, Stdio};
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use test_support::{Fixture, CARDINALITY_1000, HAMLET_WORDS, LARGE, SHUFFLED_NUMBERS, SMALL};
 
 // require certain features for this benchmark
 #[cfg(not(all(feature = "memmap")))]
@@ -21,52 +21,42 @@ criterion_group!(benches, bench_cuniq_count_vs_shell, bench_cuniq_report_vs_shel
 criterion_main!(benches);
 
 /// primary test condition for comparing high cardinality
-const TEST_FILE_ENGLISH_WORDS: TestFile = TestFile::new("hamlet_words.txt", "hamlet", 5414, 20);
+static TEST_FILE_ENGLISH_WORDS: TestFile = TestFile::new(&HAMLET_WORDS, "hamlet", 20);
 
-const TEST_FILE_SMALL: TestFile = TestFile::new("small.txt", "small", 3, 100);
+static TEST_FILE_SMALL: TestFile = TestFile::new(&SMALL, "small", 100);
 
-const TEST_FILE_LARGE: TestFile = TestFile::new("large.txt", "large", 100000, 10);
+static TEST_FILE_LARGE: TestFile = TestFile::new(&LARGE, "large", 10);
 
 /// Various different test conditions for comparing against different programs.
 /// Some of these are disabled as they don't provide much insight but they slow down the benchmarks.
-static TEST_FILES: &[TestFile] = &[
-    // TestFile::new("empty.txt", "c1 empty", 1, 20),
-    // TestFile::new("same_line.txt", "c1", 1, 20),
-    // TestFile::new("needle1.txt", "needle@start", 2, 20),
-    // TestFile::new("needle2.txt", "needle@end", 2, 20),
-    // TestFile::new("cardinality_10.txt", "c10", 10, 20),
-    // TestFile::new("cardinality_100.txt", "c100", 100, 20),
-    TestFile::new("cardinality_1000.txt", "c1e3", 1000, 20),
-    TestFile::new("shuffled_numbers.txt", "c1e6", 1000000, 10),
-    TEST_FILE_SMALL,
-    TEST_FILE_ENGLISH_WORDS,
-    TEST_FILE_LARGE,
+static TEST_FILES: &[&TestFile] = &[
+    // &TestFile::new(&EMPTY, "c1 empty", 20),
+    // &TestFile::new(&SAME_LINE, "c1", 20),
+    // &TestFile::new(&NEEDLE_AT_START, "needle@start", 20),
+    // &TestFile::new(&NEEDLE_AT_END, "needle@end", 20),
+    // &TestFile::new(&CARDINALITY_10, "c10", 20),
+    // &TestFile::new(&CARDINALITY_100, "c100", 20),
+    &TestFile::new(&CARDINALITY_1000, "c1e3", 20),
+    &TestFile::new(&SHUFFLED_NUMBERS, "c1e6", 10),
+    &TEST_FILE_SMALL,
+    &TEST_FILE_ENGLISH_WORDS,
+    &TEST_FILE_LARGE,
 ];
 
 struct TestFile {
-    filename: &'static str,
+    fixture: &'static Fixture,
     description: &'static str,
-    expected: usize,
     sample_size: usize,
 }
 
 impl TestFile {
-    const fn new(filename: &'static str, description: &'static str, expected: usize, sample_size: usize) -> Self {
+    const fn new(fixture: &'static Fixture, description: &'static str, sample_size: usize) -> Self {
         Self {
-            filename,
+            fixture,
             description,
-            expected,
             sample_size,
         }
     }
-
-    fn relative_path(&self) -> PathBuf {
-        let mut path: PathBuf = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        path.pop();
-        path.push("test_files");
-        path.push(self.filename);
-        path
-    }
 }
 
 /// Benches cuniq counts vs other shell commands.
@@ -77,10 +67,10 @@ fn bench_cuniq_count_vs_shell(c: &mut Criterion) {
     println!("running benchmarks against \"{cuniq_path}\"");
 
     for test_file in TEST_FILES {
-        let path_buf = test_file.relative_path();
+        let path_buf = test_file.fixture.path();
         let mut group = c.benchmark_group(format!("count/{}", test_file.description));
         group.sample_size(test_file.sample_size);
-        let expected = format!("{}\n", test_file.expected);
+        let expected = format!("{}\n", test_file.fixture.distinct.plain);
 
         // sort input.txt | uniq | wc -l
         group.bench_function("uniq", |bencher| {
@@ -261,7 +251,7 @@ fn bench_cuniq_report_vs_shell(c: &mut Criterion) {
     println!("running benchmarks against \"{cuniq_path}\"");
 
     for test_file in TEST_FILES {
-        let path_buf = test_file.relative_path();
+        let path_buf = test_file.fixture.path();
         let mut group = c.benchmark_group(format!("report/{}", test_file.description));
         group.sample_size(test_file.sample_size);
 
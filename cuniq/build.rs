@@ -18,24 +18,18 @@ fn main() -> io::Result<()> {
 /// generate rust source to send constants into the actual build
 fn create_constants<P: AsRef<Path>>(path: P) -> io::Result<()> {
     let git_commit_hash = git_commit_hash();
-    let clap_version = clap_version(&git_commit_hash);
+    let features = enabled_features();
+    let feature_diff = feature_diff(&features);
+    let feature_list: Vec<String> = features.iter().map(|feature| format!("\"{feature}\"")).collect();
 
     let file = fs::File::create(path)?;
     let mut writer = BufWriter::new(file);
     writer.write_fmt(format_args!("pub const GIT_COMMIT_HASH: &str = \"{git_commit_hash}\";\n"))?;
-    writer.write_fmt(format_args!("pub const CLAP_VERSION: &str = \"{clap_version}\";\n"))?;
+    writer.write_fmt(format_args!("pub const FEATURE_DIFF: &str = \"{feature_diff}\";\n"))?;
+    writer.write_fmt(format_args!("pub const FEATURES: &[&str] = &[{}];\n", feature_list.join(", ")))?;
     writer.flush()
 }
 
-/// override version string displayed by clap
-fn clap_version(git_commit_hash: &str) -> String {
-    format!("{} commit {}\\nBuilt with feature flags: [{}]\\nCopyright 2024 cuniq contributors\\nLicense: GNU GPL v3.0 or any later version\\nWritten by: {}",
-            env!("CARGO_PKG_VERSION"),
-            git_commit_hash, feature_diff(),
-            env!("CARGO_PKG_AUTHORS"),
-    )
-}
-
 /// Read git commit hash
 fn git_commit_hash() -> String {
     let output = Command::new("git").args(["rev-parse", "HEAD"]).output().expect("failed to get git commit hash");
@@ -43,26 +37,43 @@ fn git_commit_hash() -> String {
     untrimmed_git_commit_hash.trim().to_string()
 }
 
+/// The enabled features, sorted, without the ones that are just aliases for sets of real features
+fn enabled_features() -> Vec<String> {
+    let alias_features = HashSet::from([
+        "default",
+        "minimal",
+        "bench",
+    ]);
+
+    const FEATURE_PREFIX: &str = "CARGO_FEATURE_"; // all features are passed as environment vars with this prefix
+    let mut features: Vec<String> = env::vars()
+        .filter(|(var, _value)| var.starts_with(FEATURE_PREFIX))
+        .map(|(var, _value)| var[FEATURE_PREFIX.len()..].to_lowercase()) // environment vars need to be lowercased
+        .map(|var| var.replace('_', "-")) // environment vars need to have their snake case fixed
+        .filter(|feature| !alias_features.contains(feature.as_str()))
+        .collect();
+    features.sort_unstable();
+    features
+}
+
 /// Calculate the diff from actual features and expected features
-fn feature_diff() -> String {
+fn feature_diff(enabled_features: &[String]) -> String {
     // features we expect for this binary
     let mut expected_features = HashSet::from([
-        "compile-time-rng",
-        "memmap",
+        "regex",
     ]);
 
-    // features that are just aliases for sets of real features
+    // features that only enable line_cardinality features, which line_cardinality::build_info() reports
     let ignored_features = HashSet::from([
-        "default",
+        "compile-time-rng",
+        "diagnostics",
+        "memmap",
+        "unicode",
     ]);
 
-    const FEATURE_PREFIX: &str = "CARGO_FEATURE_"; // all features are passed as environment vars with this prefix
     let mut features = String::new();
     let mut first = true;
-    env::vars()
-        .filter(|(var, _value)| var.starts_with(FEATURE_PREFIX))
-        .map(|(var, _value)| var[FEATURE_PREFIX.len()..].to_lowercase()) // environment vars need to be lowercased
-        .map(|var| var.replace('_', "-")) // environment vars need to have their snake case fixed
+    enabled_features.iter()
         .filter(|feature| !ignored_features.contains(feature.as_str()))
         .for_each(|feature| {
             // add each unexpected feature
@@ -73,7 +84,7 @@ fn feature_diff() -> String {
                     features.push(',');
                 }
                 features.push('+');
-                features.push_str(&feature);
+                features.push_str(feature);
             }
         });
     expected_features.into_iter().for_each(|feature| {
@@ -0,0 +1,107 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Counting runs of adjacent identical lines, for `--adjacent`
+
+use std::mem::size_of;
+
+use line_cardinality::CountUnique;
+
+/// A [`CountUnique`] that counts runs of identical consecutive lines rather than distinct lines, as
+/// `uniq | wc -l` does. Only the previous line is kept, so memory use doesn't depend on the input.
+///
+/// A line that reappears after a different line starts a new run, so it is counted again. Lines are
+/// compared after the line mapper has been applied.
+pub struct AdjacentLineCounter<M> {
+    /// the last line counted, after line mapping
+    previous: Option<Vec<u8>>,
+    string_buffer: Vec<u8>,
+    runs: usize,
+    line_mapper: M,
+}
+
+impl<M> AdjacentLineCounter<M>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    pub fn with_line_mapper(line_mapper: M) -> Self {
+        AdjacentLineCounter {
+            previous: None,
+            string_buffer: Vec::new(),
+            runs: 0,
+            line_mapper,
+        }
+    }
+}
+
+impl<M> CountUnique for AdjacentLineCounter<M>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    #[inline(always)]
+    fn count_line(&mut self, line: &[u8]) {
+        let line = (self.line_mapper)(line, &mut self.string_buffer);
+        match &mut self.previous {
+            Some(previous) if previous.as_slice() == line => (),
+            Some(previous) => {
+                self.runs += 1;
+                // reuse the allocation of the previous line
+                previous.clear();
+                previous.extend_from_slice(line);
+            }
+            None => {
+                self.runs += 1;
+                self.previous = Some(line.to_vec());
+            }
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.runs
+    }
+
+    fn estimated_memory(&self) -> usize {
+        size_of::<Self>() + self.previous.as_ref().map_or(0, Vec::capacity) + self.string_buffer.capacity()
+    }
+
+    fn reset(&mut self) {
+        self.previous = None;
+        self.runs = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bstr::ByteSlice;
+
+    use super::*;
+
+    fn identity<'a>(line: &'a [u8], _buffer: &'a mut Vec<u8>) -> &'a [u8] {
+        line
+    }
+
+    fn trim<'a>(line: &'a [u8], _buffer: &'a mut Vec<u8>) -> &'a [u8] {
+        line.trim()
+    }
+
+    #[test]
+    fn test_runs() {
+        let mut counter = AdjacentLineCounter::with_line_mapper(identity);
+        assert_eq!(counter.count(), 0);
+        counter.count_unique_in_bytes(b"a\na\nb\na\n\n\nb\nb");
+        assert_eq!(counter.count(), 5);
+        // a run continues across calls, as if the inputs were concatenated
+        counter.count_unique_in_bytes(b"b\nc\n");
+        assert_eq!(counter.count(), 6);
+        counter.reset();
+        counter.count_unique_in_bytes(b"c\n");
+        assert_eq!(counter.count(), 1);
+    }
+
+    #[test]
+    fn test_line_mapper() {
+        let mut counter = AdjacentLineCounter::with_line_mapper(trim);
+        counter.count_unique_in_bytes(b"a\n a\na \nb\n");
+        assert_eq!(counter.count(), 2);
+    }
+}
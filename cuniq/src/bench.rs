@@ -0,0 +1,125 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! `cuniq bench`: time each counting mode on the user's own file, to help pick the best one for
+//! their data
+
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use line_cardinality::{CountUnique, Error, HyperLogLog, InexactHashingLineCounter, LineCounter};
+
+use crate::cli_args::{BenchArgs, Mode};
+use crate::logging::log_info;
+use crate::number::format_bytes;
+use crate::paths::{escaped, os_bytes};
+use crate::OUTPUT_ERROR_MESSAGE;
+
+/// How a file was read
+#[derive(Clone, Copy)]
+enum Input {
+    Read,
+    #[cfg_attr(not(feature = "memmap"), allow(dead_code))]
+    Memmap,
+}
+
+impl Input {
+    fn name(self) -> &'static str {
+        match self {
+            Input::Read => "read",
+            Input::Memmap => "memmap",
+        }
+    }
+}
+
+/// The fastest of several runs of one mode
+struct Measurement {
+    mode: Mode,
+    input: Input,
+    time: Duration,
+    count: usize,
+    memory: usize,
+}
+
+pub fn run(args: &BenchArgs) -> Result<(), Error> {
+    let bytes = File::open(&args.file)
+        .and_then(|file| file.metadata())
+        .map_err(|e| Error::io(format!("error opening file \"{}\"", escaped(&args.file)), e))?
+        .len();
+
+    let inputs: &[Input] = if cfg!(feature = "memmap") { &[Input::Read, Input::Memmap] } else { &[Input::Read] };
+    let mut measurements = Vec::new();
+    for mode in [Mode::Exact, Mode::NearExact, Mode::Estimate] {
+        for &input in inputs {
+            log_info!("timing {mode} mode with {} input", input.name());
+            let measurement = match mode {
+                Mode::Exact => measure(&args.file, mode, input, args.runs, LineCounter::new)?,
+                Mode::NearExact => measure(&args.file, mode, input, args.runs, InexactHashingLineCounter::new)?,
+                Mode::Estimate => measure(&args.file, mode, input, args.runs, HyperLogLog::new)?,
+            };
+            measurements.push(measurement);
+        }
+    }
+
+    let mut stdout = io::stdout().lock();
+    write_table(&mut stdout, &args.file, bytes, args.runs, &measurements).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))
+}
+
+/// Count the file `runs` times with fresh counters from `new`, keeping the fastest run. Opening the
+/// file and dropping the counter are not timed.
+fn measure<T, F>(path: &Path, mode: Mode, input: Input, runs: usize, new: F) -> Result<Measurement, Error>
+where
+    T: CountUnique,
+    F: Fn() -> T,
+{
+    let mut measurement = Measurement { mode, input, time: Duration::MAX, count: 0, memory: 0 };
+    for _ in 0..runs {
+        let file = File::open(path).map_err(|e| Error::io(format!("error opening file \"{}\"", escaped(path)), e))?;
+        let mut processor = new();
+        let start = Instant::now();
+        count_file(&mut processor, &file, input)?;
+        let time = start.elapsed();
+        measurement.time = measurement.time.min(time);
+        measurement.count = processor.count();
+        measurement.memory = processor.estimated_memory();
+    }
+    Ok(measurement)
+}
+
+fn count_file<T: CountUnique>(processor: &mut T, file: &File, input: Input) -> Result<(), Error> {
+    match input {
+        Input::Read => processor.count_unique_in_read(BufReader::new(file)),
+        #[cfg(feature = "memmap")]
+        Input::Memmap => {
+            let mapped_file = line_cardinality::MappedFile::new(file)?;
+            processor.count_unique_in_bytes(&mapped_file);
+            Ok(())
+        }
+        #[cfg(not(feature = "memmap"))]
+        Input::Memmap => unreachable!("memmap is only measured with the memmap feature"),
+    }
+}
+
+fn write_table<W: Write>(writer: &mut W, path: &Path, bytes: u64, runs: usize, measurements: &[Measurement]) -> io::Result<()> {
+    writer.write_all(os_bytes(path))?;
+    writeln!(writer, " ({}), fastest of {runs} runs", format_bytes(bytes))?;
+    writeln!(writer)?;
+    writeln!(writer, "{:<12} {:<7} {:>10} {:>12} {:>10} {:>10}", "mode", "input", "time", "throughput", "distinct", "memory")?;
+    for measurement in measurements {
+        let seconds = measurement.time.as_secs_f64();
+        let throughput = if seconds > 0.0 { format!("{}/s", format_bytes((bytes as f64 / seconds) as u64)) } else { "-".to_string() };
+        writeln!(
+            writer,
+            "{:<12} {:<7} {:>10} {:>12} {:>10} {:>10}",
+            measurement.mode.to_string(),
+            measurement.input.name(),
+            format!("{:.1?}", measurement.time),
+            throughput,
+            measurement.count,
+            format_bytes(measurement.memory as u64),
+        )?;
+    }
+    Ok(())
+}
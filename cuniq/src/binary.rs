@@ -0,0 +1,110 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! `--text-only`, which refuses to count input that looks like binary data rather than text lines
+
+use std::fmt::{Display, Formatter};
+use std::io::{self, Read};
+
+use bstr::ByteSlice;
+
+/// How many bytes at the start of the first input that isn't empty are checked
+pub const SAMPLE_BYTES: usize = 4096;
+
+/// Why a sample of input looks binary
+#[derive(Debug, PartialEq, Eq)]
+pub enum Binary {
+    /// The sample contains a NUL byte at this offset. Text almost never does, while most binary
+    /// formats are full of them.
+    Nul(usize),
+    /// `count` of the `sampled` bytes are control characters or invalid UTF-8
+    NonText { count: usize, sampled: usize },
+}
+
+impl Display for Binary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Binary::Nul(offset) => write!(f, "it contains a NUL byte at offset {offset}"),
+            Binary::NonText { count, sampled } => write!(f, "{count} of its first {sampled} bytes are not text"),
+        }
+    }
+}
+
+/// Read up to [`SAMPLE_BYTES`] from the start of `input`. Pipes can return less than that from
+/// each read, so this keeps reading until it has them all or the input ends.
+pub fn read_sample(input: impl Read) -> io::Result<Vec<u8>> {
+    let mut sample = Vec::with_capacity(SAMPLE_BYTES);
+    input.take(SAMPLE_BYTES as u64).read_to_end(&mut sample)?;
+    Ok(sample)
+}
+
+/// Check whether `sample`, from the start of an input, looks binary. This is the heuristic Perl's
+/// `-B` uses: any NUL byte, or more than 30% of bytes that aren't printable text. Bytes that are
+/// part of valid UTF-8 count as text, so non-English text passes.
+pub fn check_sample(sample: &[u8]) -> Option<Binary> {
+    if let Some(offset) = sample.find_byte(0) {
+        return Some(Binary::Nul(offset));
+    }
+    let count = non_text_bytes(sample);
+    (count * 10 > sample.len() * 3).then_some(Binary::NonText { count, sampled: sample.len() })
+}
+
+/// The number of bytes in `sample` that are control characters or invalid UTF-8. A multi-byte
+/// character cut off by the end of the sample is not counted.
+fn non_text_bytes(mut sample: &[u8]) -> usize {
+    let mut count = 0;
+    loop {
+        match std::str::from_utf8(sample) {
+            Ok(_) => return count + control_bytes(sample),
+            Err(e) => {
+                let (valid, rest) = sample.split_at(e.valid_up_to());
+                count += control_bytes(valid);
+                match e.error_len() {
+                    Some(invalid) => {
+                        count += invalid;
+                        sample = &rest[invalid..];
+                    }
+                    None => return count,
+                }
+            }
+        }
+    }
+}
+
+/// The number of ASCII control characters in `text`, other than whitespace, backspace, and the
+/// escape that starts ANSI color codes, which are all common in text
+fn control_bytes(text: &[u8]) -> usize {
+    text.iter()
+        .filter(|byte| byte.is_ascii_control() && !matches!(byte, b'\t' | b'\n' | 0x0b | 0x0c | b'\r' | 0x08 | 0x1b))
+        .count()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_text() {
+        assert_eq!(check_sample(b""), None);
+        assert_eq!(check_sample(b"hello\r\nworld\n"), None);
+        assert_eq!(check_sample("naïve café, 日本語\n".as_bytes()), None);
+        assert_eq!(check_sample(b"\x1b[31mred\x1b[0m\tcolumn\n"), None);
+        // cut off in the middle of a character
+        assert_eq!(check_sample(&"日本".as_bytes()[..4]), None);
+    }
+
+    #[test]
+    fn test_binary() {
+        assert_eq!(check_sample(b"text\0more"), Some(Binary::Nul(4)));
+        assert_eq!(check_sample(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Some(Binary::Nul(8)));
+        assert_eq!(check_sample(b"ab\x01\x02\xff\xfe\x7f"), Some(Binary::NonText { count: 5, sampled: 7 }));
+    }
+
+    /// A few stray bytes in mostly text, such as a Latin-1 character, are tolerated
+    #[test]
+    fn test_threshold() {
+        assert_eq!(check_sample(b"caf\xe9 au lait\n"), None);
+        assert_eq!(check_sample(b"abcdefg\x01\x02\x03"), None);
+        assert_eq!(check_sample(b"abcdef\x01\x02\x03\x04"), Some(Binary::NonText { count: 4, sampled: 10 }));
+    }
+}
@@ -0,0 +1,75 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Grouping the lines of a report by their lowercase form, for `--group-case`
+
+use std::collections::HashMap;
+
+use bstr::ByteSlice;
+
+use crate::Count;
+
+/// Distinct lines that are the same when lowercased
+pub struct CaseGroup<'a> {
+    /// the lowercase form shared by every line in the group
+    pub key: Vec<u8>,
+    /// the sum of every variant's count
+    pub subtotal: Count,
+    /// each distinct line in the group and its count, most common first. Lines with the same count
+    /// are sorted bytewise.
+    pub variants: Vec<(&'a [u8], Count)>,
+}
+
+/// Group report entries by their lowercase form. Groups with the largest subtotal come first, and
+/// groups with the same subtotal are sorted bytewise by key.
+pub fn group_by_case<'a, I>(entries: I) -> Vec<CaseGroup<'a>>
+where
+    I: IntoIterator<Item = (&'a [u8], Count)>,
+{
+    let mut groups: HashMap<Vec<u8>, CaseGroup<'a>> = HashMap::new();
+    let mut key = Vec::new();
+    for (line, count) in entries {
+        key.clear();
+        line.to_lowercase_into(&mut key);
+        let group = match groups.get_mut(key.as_slice()) {
+            Some(group) => group,
+            None => groups.entry(key.clone()).or_insert_with(|| CaseGroup { key: key.clone(), subtotal: 0, variants: Vec::new() }),
+        };
+        group.subtotal += count;
+        group.variants.push((line, count));
+    }
+
+    let mut groups: Vec<CaseGroup<'a>> = groups.into_values().collect();
+    for group in &mut groups {
+        group.variants.sort_unstable_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+    }
+    groups.sort_unstable_by(|a, b| b.subtotal.cmp(&a.subtotal).then_with(|| a.key.cmp(&b.key)));
+    groups
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_group_by_case() {
+        let entries: [(&[u8], Count); 6] = [(b"Apple", 2), (b"banana", 4), (b"APPLE", 1), (b"apple", 3), (b"Banana", 2), (b"cherry", 1)];
+        let groups = group_by_case(entries);
+        let keys: Vec<(&[u8], Count)> = groups.iter().map(|group| (group.key.as_slice(), group.subtotal)).collect();
+        assert_eq!(keys, [(b"apple".as_slice(), 6), (b"banana", 6), (b"cherry", 1)]);
+        assert_eq!(groups[0].variants, [(b"apple".as_slice(), 3), (b"Apple", 2), (b"APPLE", 1)]);
+        assert_eq!(groups[1].variants, [(b"banana".as_slice(), 4), (b"Banana", 2)]);
+        assert_eq!(groups[2].variants, [(b"cherry".as_slice(), 1)]);
+    }
+
+    #[test]
+    fn test_non_ascii() {
+        let entries: [(&[u8], Count); 3] = [("ÉCOLE".as_bytes(), 1), ("école".as_bytes(), 1), (b"\xFF\xC3", 1)];
+        let groups = group_by_case(entries);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].key, "école".as_bytes());
+        assert_eq!(groups[0].variants.len(), 2);
+        // invalid UTF-8 is left as it is
+        assert_eq!(groups[1].key, b"\xFF\xC3");
+    }
+}
@@ -0,0 +1,365 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Computing `--checksum`, the `--header` input checksums, and the `--stats` and metrics byte and
+//! line counts while input is read, and scanning it for `--strict-input`
+
+use std::ffi::OsString;
+use std::io::{self, BufRead, Read};
+
+use crc32fast::Hasher;
+
+use crate::strict_input::ForbiddenBytes;
+
+/// CRC-32 checksums of the input, computed in the same pass as counting
+#[derive(Default)]
+pub struct Checksums {
+    /// checksum of all inputs concatenated, for `--checksum`
+    total: Option<Hasher>,
+    /// checksums of each input, for `--header`
+    per_input: Option<PerInput>,
+    /// total size of all inputs, for `--stats`
+    bytes: Option<u64>,
+    /// total records in all inputs, for metrics
+    lines: Option<LineCount>,
+    /// bytes that `--strict-input` looks for
+    forbidden: Option<ForbiddenBytes>,
+}
+
+struct LineCount {
+    delimiter: u8,
+    count: u64,
+    /// whether the current input has bytes after its last delimiter, which are one more record
+    partial: bool,
+}
+
+struct PerInput {
+    /// only this many bytes at the start of each input are hashed, or all of them if `None`
+    limit: Option<u64>,
+    finished: Vec<InputChecksum>,
+    current: Option<InputChecksum>,
+    hasher: Hasher,
+}
+
+/// The checksum of a single input
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct InputChecksum {
+    /// the path of a file, or a description such as `stdin`
+    pub name: OsString,
+    /// total size of the input
+    pub bytes: u64,
+    /// number of bytes at the start of the input covered by `crc32`
+    pub hashed_bytes: u64,
+    pub crc32: u32,
+}
+
+/// Finished [`Checksums`]
+#[derive(Default)]
+pub struct ChecksumResults {
+    pub total: Option<u32>,
+    pub inputs: Vec<InputChecksum>,
+    pub bytes: Option<u64>,
+    pub lines: Option<u64>,
+}
+
+impl Checksums {
+    /// Checksums that compute the total if `total` is set, and nothing else
+    pub fn new(total: bool) -> Self {
+        Checksums {
+            total: total.then(Hasher::new),
+            per_input: None,
+            bytes: None,
+            lines: None,
+            forbidden: None,
+        }
+    }
+
+    /// Also count the total size of all inputs. This is much cheaper than a checksum, but still
+    /// means input has to be passed through [`Checksums::update`].
+    pub fn with_byte_count(mut self) -> Self {
+        self.bytes = Some(0);
+        self
+    }
+
+    /// Also count the records in all inputs, which end with `delimiter` or at the end of an input.
+    /// Like [`Checksums::with_byte_count`], this means input has to be passed through
+    /// [`Checksums::update`].
+    pub fn with_line_count(mut self, delimiter: u8) -> Self {
+        self.lines = Some(LineCount { delimiter, count: 0, partial: false });
+        self
+    }
+
+    /// Also checksum each input separately, hashing at most `limit` bytes of each
+    pub fn with_per_input(mut self, limit: Option<u64>) -> Self {
+        self.per_input = Some(PerInput {
+            limit,
+            finished: Vec::new(),
+            current: None,
+            hasher: Hasher::new(),
+        });
+        self
+    }
+
+    /// Also scan all inputs for forbidden bytes, for `--strict-input`
+    pub fn with_forbidden_bytes(mut self, forbidden: ForbiddenBytes) -> Self {
+        self.forbidden = Some(forbidden);
+        self
+    }
+
+    /// Whether any checksums are being computed. If not, input doesn't need to be passed through
+    /// [`Checksums::update`].
+    pub fn is_enabled(&self) -> bool {
+        self.total.is_some() || self.per_input.is_some() || self.bytes.is_some() || self.lines.is_some() || self.forbidden.is_some()
+    }
+
+    /// The `--strict-input` scan, if there is one
+    pub fn forbidden_bytes(&self) -> Option<&ForbiddenBytes> {
+        self.forbidden.as_ref()
+    }
+
+    /// Whether reading should stop early, because `--strict-input` found a forbidden byte and the
+    /// run is going to fail anyway
+    pub fn stopped(&self) -> bool {
+        self.forbidden.as_ref().is_some_and(ForbiddenBytes::stopped)
+    }
+
+    /// Start a new input called `name`. Following bytes are counted towards its checksum.
+    pub fn start_input(&mut self, name: OsString) {
+        if let Some(forbidden) = &mut self.forbidden {
+            forbidden.start_input(name.clone());
+        }
+        if let Some(lines) = &mut self.lines {
+            lines.finish_input();
+        }
+        if let Some(per_input) = &mut self.per_input {
+            per_input.finish_current();
+            per_input.current = Some(InputChecksum { name, bytes: 0, hashed_bytes: 0, crc32: 0 });
+        }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        if let Some(total_bytes) = &mut self.bytes {
+            *total_bytes += bytes.len() as u64;
+        }
+        if let (Some(lines), Some(&last)) = (&mut self.lines, bytes.last()) {
+            let delimiter = lines.delimiter;
+            lines.count += bytes.iter().filter(|&&byte| byte == delimiter).count() as u64;
+            lines.partial = last != delimiter;
+        }
+        if let Some(total) = &mut self.total {
+            total.update(bytes);
+        }
+        if let Some(forbidden) = &mut self.forbidden {
+            forbidden.scan(bytes);
+        }
+        if let Some(PerInput { limit, current: Some(current), hasher, .. }) = &mut self.per_input {
+            current.bytes += bytes.len() as u64;
+            let remaining = limit.map_or(u64::MAX, |limit| limit - current.hashed_bytes);
+            let hashed = &bytes[..usize::try_from(remaining).unwrap_or(usize::MAX).min(bytes.len())];
+            hasher.update(hashed);
+            current.hashed_bytes += hashed.len() as u64;
+        }
+    }
+
+    pub fn finish(mut self) -> ChecksumResults {
+        if let Some(lines) = &mut self.lines {
+            lines.finish_input();
+        }
+        let inputs = match self.per_input {
+            Some(mut per_input) => {
+                per_input.finish_current();
+                per_input.finished
+            }
+            None => Vec::new(),
+        };
+        ChecksumResults {
+            total: self.total.map(Hasher::finalize),
+            inputs,
+            bytes: self.bytes,
+            lines: self.lines.map(|lines| lines.count),
+        }
+    }
+}
+
+impl LineCount {
+    fn finish_input(&mut self) {
+        if self.partial {
+            self.count += 1;
+            self.partial = false;
+        }
+    }
+}
+
+impl PerInput {
+    fn finish_current(&mut self) {
+        if let Some(mut current) = self.current.take() {
+            current.crc32 = std::mem::take(&mut self.hasher).finalize();
+            self.finished.push(current);
+        }
+    }
+}
+
+/// Wraps a reader, and feeds every byte read through it into [`Checksums`]. Bytes are hashed as
+/// they are consumed rather than as the buffer is filled, as a [`BufRead`] user may look at the same
+/// buffer many times before consuming it. Once [`Checksums::stopped`], the reader reports the end
+/// of input, so that nothing more is read.
+pub struct ChecksumReader<'a, R> {
+    inner: R,
+    checksums: &'a mut Checksums,
+}
+
+impl<'a, R: BufRead> ChecksumReader<'a, R> {
+    pub fn new(inner: R, checksums: &'a mut Checksums) -> Self {
+        ChecksumReader { inner, checksums }
+    }
+}
+
+impl<R: BufRead> Read for ChecksumReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.checksums.stopped() {
+            return Ok(0);
+        }
+        let read = self.inner.read(buf)?;
+        self.checksums.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for ChecksumReader<'_, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.checksums.stopped() {
+            return Ok(&[]);
+        }
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // nothing was returned by fill_buf to consume, and the inner reader mustn't be read again
+        if self.checksums.stopped() {
+            return;
+        }
+        // the buffer is already filled, so this doesn't read anything and can't fail
+        if let Ok(buf) = self.inner.fill_buf() {
+            self.checksums.update(&buf[..amt.min(buf.len())]);
+        }
+        self.inner.consume(amt);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::BufReader;
+
+    use super::*;
+    use crate::cli_args::StrictInput;
+
+    fn checksum(bytes: &[u8]) -> u32 {
+        let mut hasher = Hasher::new();
+        hasher.update(bytes);
+        hasher.finalize()
+    }
+
+    #[test]
+    fn test_known_value() {
+        // the standard CRC-32 check value
+        assert_eq!(checksum(b"123456789"), 0xcbf43926);
+    }
+
+    #[test]
+    fn test_buffered_lines() {
+        let input: Vec<u8> = (0..10_000).flat_map(|i| format!("line {}\n", i % 700).into_bytes()).collect();
+        let mut checksums = Checksums::new(true);
+        // a small buffer, so lines span buffer refills
+        let reader = ChecksumReader::new(BufReader::with_capacity(64, input.as_slice()), &mut checksums);
+        let lines = reader.split(b'\n').count();
+        assert_eq!(lines, 10_000);
+        assert_eq!(checksums.finish().total, Some(checksum(&input)));
+    }
+
+    #[test]
+    fn test_read() {
+        let input = b"some bytes\nwithout a trailing newline";
+        let mut checksums = Checksums::new(true);
+        let mut output = Vec::new();
+        ChecksumReader::new(input.as_slice(), &mut checksums).read_to_end(&mut output).unwrap();
+        assert_eq!(output, input);
+        assert_eq!(checksums.finish().total, Some(checksum(input)));
+    }
+
+    #[test]
+    fn test_per_input() {
+        let mut checksums = Checksums::new(true).with_per_input(Some(4));
+        checksums.start_input("a".into());
+        checksums.update(b"abc");
+        checksums.update(b"def");
+        checksums.start_input("b".into());
+        checksums.update(b"xy");
+        checksums.start_input("empty".into());
+        let results = checksums.finish();
+        assert_eq!(results.total, Some(checksum(b"abcdefxy")));
+        assert_eq!(results.inputs, [
+            InputChecksum { name: "a".into(), bytes: 6, hashed_bytes: 4, crc32: checksum(b"abcd") },
+            InputChecksum { name: "b".into(), bytes: 2, hashed_bytes: 2, crc32: checksum(b"xy") },
+            InputChecksum { name: "empty".into(), bytes: 0, hashed_bytes: 0, crc32: 0 },
+        ]);
+    }
+
+    #[test]
+    fn test_per_input_unlimited() {
+        let mut checksums = Checksums::default().with_per_input(None);
+        checksums.start_input("a".into());
+        checksums.update(b"abc");
+        checksums.update(b"def");
+        let results = checksums.finish();
+        assert_eq!(results.total, None);
+        assert_eq!(results.inputs, [InputChecksum { name: "a".into(), bytes: 6, hashed_bytes: 6, crc32: checksum(b"abcdef") }]);
+    }
+
+    #[test]
+    fn test_byte_count() {
+        let mut checksums = Checksums::new(false).with_byte_count();
+        assert!(checksums.is_enabled());
+        let reader = ChecksumReader::new(BufReader::with_capacity(3, b"ab\ncd\r\nlast".as_slice()), &mut checksums);
+        assert_eq!(reader.split(b'\n').count(), 3);
+        checksums.update(b"mapped");
+        let results = checksums.finish();
+        assert_eq!(results.bytes, Some(17));
+        assert_eq!(results.total, None);
+        assert_eq!(Checksums::new(true).finish().bytes, None);
+    }
+
+    /// Reading stops once `--strict-input` finds a forbidden byte in what has been consumed
+    #[test]
+    fn test_stopped() {
+        let mut input = b"line\n".repeat(1000);
+        // in the middle of line 21
+        input[102] = b'\r';
+        let mut checksums = Checksums::new(false).with_byte_count().with_forbidden_bytes(ForbiddenBytes::new(b"\r", StrictInput::Error));
+        checksums.start_input("input".into());
+        let reader = ChecksumReader::new(BufReader::with_capacity(64, input.as_slice()), &mut checksums);
+        assert_eq!(reader.split(b'\n').count(), 21);
+        assert!(checksums.stopped());
+        assert!(checksums.forbidden_bytes().unwrap().check().is_err());
+        assert_eq!(checksums.finish().bytes, Some(105));
+    }
+
+    /// A record without a trailing delimiter is only counted once its input ends
+    #[test]
+    fn test_line_count() {
+        let mut checksums = Checksums::new(false).with_line_count(b'\n');
+        assert!(checksums.is_enabled());
+        checksums.start_input("a".into());
+        checksums.update(b"ab\ncd\r\n\n");
+        checksums.update(b"last");
+        checksums.update(b"");
+        checksums.start_input("b".into());
+        checksums.update(b"one\ntwo");
+        checksums.update(b"\n");
+        checksums.start_input("c".into());
+        checksums.update(b"three");
+        let results = checksums.finish();
+        assert_eq!(results.lines, Some(7));
+        assert_eq!(results.bytes, None);
+        assert_eq!(Checksums::new(true).finish().lines, None);
+    }
+}
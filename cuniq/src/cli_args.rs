@@ -1,19 +1,64 @@
 // This file is part of cuniq. Copyright © 2024 cuniq contributors.
 // cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
 
+use std::ffi::OsString;
 use std::fmt::{Display, Formatter};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
-use clap::{Parser, ValueEnum};
+use bstr::{ByteSlice, ByteVec};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use line_cardinality::{Error, LineCounter};
 
-use crate::constants::CLAP_VERSION;
+use crate::constants::{FEATURE_DIFF, GIT_COMMIT_HASH};
+use crate::expectation::{parse_expectation, Expectation};
+use crate::masking::{parse_byte_ranges, ByteRanges};
+use crate::metrics::{parse_metric_label, parse_metrics_prefix, parse_statsd_address, MetricLabel};
+use crate::paths::escaped;
+
+/// Modes that don't print a count, so have no summary for `--metrics-file` or `--statsd`
+const METRICS_CONFLICTS: [&str; 8] = ["report", "unique", "only_in", "report_unseen", "inconsistencies", "tabulate", "watch", "cap"];
+
+/// Largest `--count-width`. Counts are at most 20 digits, so anything wider is only padding.
+const MAX_COUNT_WIDTH: usize = 64;
+
+/// Documents the exit codes. Scripts may rely on these, so they must not change.
+pub const EXIT_STATUS_HELP: &str = "Exit status:
+  0  success
+  1  failed to read input or write output
+  2  invalid arguments, such as an unsupported `--size`
+  3  the count did not meet `--expect`
+  4  `--max-distinct` was exceeded, with `--strict-max-distinct`
+  5  the input was rejected, such as by `--strict-input`, `--text-only`, `--decode` or `--assume-sorted`";
+
+/// The version string displayed by clap. Feature flags cuniq only passes on to line_cardinality
+/// are listed by line_cardinality, rather than in cuniq's own feature flags.
+fn version() -> &'static str {
+    static VERSION: OnceLock<String> = OnceLock::new();
+    VERSION.get_or_init(|| {
+        format!(
+            "{} commit {}\nBuilt with feature flags: [{}]\n{}\nCopyright 2024 cuniq contributors\nLicense: GNU GPL v3.0 or any later version\nWritten by: {}",
+            env!("CARGO_PKG_VERSION"),
+            GIT_COMMIT_HASH,
+            FEATURE_DIFF,
+            line_cardinality::build_info(),
+            env!("CARGO_PKG_AUTHORS"),
+        )
+    })
+}
 
 /// Counts unique lines from newline (\n) delimited input. Input can be provided via stdin and/or
 /// file arguments.
+///
+/// An argument of the form @FILE is replaced by the lines of FILE, each of which is a single
+/// argument. This allows more input files than fit on a command line. Empty lines are skipped, and
+/// lines are used as-is, without quoting or further @FILE expansion. Arguments after `--` are
+/// never expanded, so a file whose name starts with @ can be given after `--` or as `./@name`.
 #[derive(Parser)]
-#[command(version = CLAP_VERSION, about, long_about, author)]
+#[command(version = version(), about, long_about, author, after_help = EXIT_STATUS_HELP, args_conflicts_with_subcommands = true)]
 pub struct CliArgs {
-    /// Files to process.
+    /// Files to process. A file named like a command, such as `bench`, must be given with a path,
+    /// such as `./bench`.
     pub files: Vec<PathBuf>,
 
     /// Instead of printing total unique lines, print a report showing occurrence count of each line.
@@ -21,9 +66,248 @@ pub struct CliArgs {
     #[arg(short = 'c', long)]
     pub report: bool,
 
-    /// Sort report output alphabetically by line. Has no effect unless used with `--report`.
-    #[arg(short = 's', long)]
-    pub sort: bool,
+    /// Format of each `--report` line.
+    #[arg(value_enum, long, value_name = "FORMAT", default_value_t, requires = "report")]
+    pub format: ReportFormat,
+
+    /// Start `--format tsv` or `--format csv` output with a row naming the columns, `count` and
+    /// `line`.
+    #[arg(long, requires = "report")]
+    pub header_row: bool,
+
+    /// Right-align `--report` counts to WIDTH characters, up to 64. WIDTH is 7 by default, as in
+    /// `uniq -c`, or 0 with `--count-last`. Counts wider than WIDTH are not truncated. With `auto`, counts are
+    /// aligned to the width of the largest count, so every line lines up however large the counts
+    /// are.
+    #[arg(long, value_name = "WIDTH", value_parser = parse_count_width, requires = "report")]
+    pub count_width: Option<CountWidth>,
+
+    /// Don't pad `--report` counts, so each line is the count, a space, then the line. Shorthand for
+    /// `--count-width 0`.
+    #[arg(long, requires = "report", conflicts_with = "count_width")]
+    pub no_pad: bool,
+
+    /// Separate each `--report` count from its line with STR instead of a single space, for example
+    /// `--output-delimiter '\t'`. Backslash escapes such as `\t`, `\0`, and `\xFF` are supported.
+    #[arg(long, value_name = "STR", value_parser = parse_output_delimiter, requires = "report", conflicts_with_all = ["only_in", "report_unseen", "assume_sorted", "adjacent", "tabulate"])]
+    pub output_delimiter: Option<Box<[u8]>>,
+
+    /// Print each `--report` line before its count, rather than after it. Counts are only padded if
+    /// `--count-width` is given, so `--output-delimiter '\t'` gives `line<TAB>count` output.
+    #[arg(long, requires = "report", conflicts_with_all = ["only_in", "report_unseen", "assume_sorted", "adjacent", "tabulate"])]
+    pub count_last: bool,
+
+    /// Group `--report` lines that are the same when lowercased, without merging them. Each group is
+    /// printed as its total count and lowercase form, followed by one tab-indented line for each
+    /// variant with its own count. Groups with the largest total are printed first, and each group's
+    /// variants are printed most common first. This is not supported with `--format ndjson`.
+    #[arg(long, requires = "report", conflicts_with_all = ["lowercase", "fold", "fold_case", "sort"])]
+    pub group_case: bool,
+
+    /// Instead of printing total unique lines, print each distinct line once. Without `--stream`
+    /// this is only compatible with "exact" mode (the default).
+    #[arg(long, conflicts_with = "report")]
+    pub unique: bool,
+
+    /// Instead of printing total unique lines, print only the lines that occurred more than once,
+    /// like `uniq -d`. Each is printed once, without its count unless `--report` is also given, in
+    /// which case the report is limited to these lines. This is only compatible with "exact" mode
+    /// (the default).
+    #[arg(short = 'd', long, conflicts_with_all = ["unique", "only_in", "report_unseen", "inconsistencies", "assume_sorted", "adjacent", "cap", "tabulate", "watch", "expect", "stats", "checksum", "hot_cache", "metrics_file", "statsd"])]
+    pub duplicates: bool,
+
+    /// Instead of printing total unique lines, print only the lines that occurred exactly once, like
+    /// `uniq -u`. Unlike `--unique`, lines that occurred more than once are left out entirely. Lines
+    /// are printed without their count, which is always 1, unless `--report` is also given. This is
+    /// only compatible with "exact" mode (the default).
+    #[arg(short = 'U', long, conflicts_with_all = ["duplicates", "unique", "only_in", "report_unseen", "inconsistencies", "assume_sorted", "adjacent", "cap", "tabulate", "watch", "expect", "stats", "checksum", "hot_cache", "metrics_file", "statsd"])]
+    pub unique_only: bool,
+
+    /// Only print distinct lines that first occurred on line N or later, such as the new values in
+    /// rows appended to a file since it was last processed. All of the input is still read, so a line
+    /// that repeats one from before line N is not printed. Counts printed by `--report` are of every
+    /// occurrence, including those before line N. Lines are numbered from 1, across all inputs as if
+    /// they were concatenated. Requires `--report`, `--duplicates`, or `--unique-only`, and an
+    /// additional 8 bytes of memory per distinct line. This is only compatible with "exact" mode (the
+    /// default).
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), conflicts_with_all = ["words", "match_regex", "decode", "translate_only", "strip_ansi", "ignore_bytes", "max_distinct", "export"])]
+    pub since_line: Option<u64>,
+
+    /// Only print `--report` lines that occurred at least N times. Together with `--max-count`, this
+    /// limits the report to lines whose count falls within a range. This is only compatible with
+    /// "exact" mode (the default).
+    #[arg(long, value_name = "N", requires = "report", conflicts_with_all = ["only_in", "report_unseen", "inconsistencies", "assume_sorted", "adjacent", "tabulate"])]
+    pub min_count: Option<u64>,
+
+    /// Only print `--report` lines that occurred at most N times. This is only compatible with
+    /// "exact" mode (the default).
+    #[arg(long, value_name = "N", requires = "report", conflicts_with_all = ["only_in", "report_unseen", "inconsistencies", "assume_sorted", "adjacent", "tabulate"])]
+    pub max_count: Option<u64>,
+
+    /// With `--unique`, print each line as soon as it is first seen, in input order, instead of
+    /// waiting until all input has been read. Lines are printed as they appeared in the input,
+    /// before `--trim` and `--lower` are applied. This works in "exact" and "near-exact" modes:
+    /// "near-exact" uses less memory, but will omit a line whose hash collides with an earlier line.
+    /// Memmap is not used in this mode.
+    #[arg(long, requires = "unique", conflicts_with_all = ["sort", "output"])]
+    pub stream: bool,
+
+    /// Instead of printing total unique lines, print the lines that were found in exactly one of the
+    /// inputs. Stdin counts as an input. This is only compatible with "exact" mode (the default), and
+    /// requires memory for each distinct line plus 8 bytes to track which input it was found in.
+    #[arg(long, conflicts_with_all = ["report", "unique"])]
+    pub only_in: bool,
+
+    /// Assume every input is already sorted bytewise (as by `LC_ALL=C sort`), and count unique lines
+    /// by merging the inputs instead of hashing. This uses constant memory regardless of input
+    /// cardinality. All inputs must be sorted with the same ordering, after `--trim` and `--lower`
+    /// are applied. If unsorted input is found cuniq exits with an error instead of printing an
+    /// incorrect count. Memmap is not used in this mode.
+    #[arg(long, conflicts_with_all = ["report", "unique", "only_in"])]
+    pub assume_sorted: bool,
+
+    /// Count runs of identical consecutive lines instead of distinct lines, like `uniq | wc -l`. As
+    /// with `uniq`, a line is only merged with the line immediately before it, so a line that
+    /// reappears after a different line is counted again: `a a b a` counts 3, where the default
+    /// counts 2. Inputs are treated as one stream, as if they were concatenated. Only the previous
+    /// line is kept, so this uses constant memory however many distinct lines there are. `--trim`,
+    /// `--lower`, and the other input options apply before lines are compared. This is only
+    /// compatible with "exact" mode (the default).
+    #[arg(long, conflicts_with_all = ["report", "unique", "only_in", "report_unseen", "inconsistencies", "assume_sorted", "cap", "max_distinct", "stats", "hot_cache", "watch"])]
+    pub adjacent: bool,
+
+    /// Stop reading input as soon as N distinct lines have been found, and print `>=N` instead of the
+    /// total. If the input has fewer than N distinct lines the total is printed as usual. This is not
+    /// supported in "estimate" mode, and memmap is not used.
+    #[arg(long, value_name = "N", conflicts_with_all = ["report", "unique", "only_in", "assume_sorted"])]
+    pub cap: Option<usize>,
+
+    /// Stop tracking new distinct lines once N have been found, which bounds memory use on untrusted
+    /// input. Lines that are already tracked are still counted, so their occurrence counts remain
+    /// exact. If the limit is exceeded the total is printed as `>=N`, and an estimate of how many
+    /// distinct lines were not tracked is printed to stderr. With `--report`, only the first N
+    /// distinct lines are reported. This is not supported in "estimate" mode.
+    #[arg(long, alias = "limit-distinct-report", value_name = "N", conflicts_with_all = ["unique", "only_in", "report_unseen", "assume_sorted", "cap", "stats"])]
+    pub max_distinct: Option<usize>,
+
+    /// Exit with status 4 if `--max-distinct` was exceeded.
+    #[arg(long, requires = "max_distinct")]
+    pub strict_max_distinct: bool,
+
+    /// Read only the first N lines of each input, to preview a long run. The usual output is
+    /// printed, but it only covers those lines. A warning is printed to stderr, along with the number
+    /// of distinct lines seen per line read and a projection of the total distinct count. The
+    /// projection assumes new lines keep appearing at the same rate through the rest of the input,
+    /// which usually makes it an overestimate, and it is only possible when the size of the input is
+    /// known, so not when reading stdin. With `--header`, the header says the output is a preview.
+    /// Files are not read via memmap.
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), conflicts_with_all = ["cap", "assume_sorted", "only_in", "report_unseen", "stream", "listen", "expect", "delimiter_str", "zero_terminated", "memmap"])]
+    pub preview: Option<usize>,
+
+    /// Count lines as they arrive, such as from `tail -f`, and every SECONDS print a line to stdout
+    /// with a UTC timestamp and the distinct count so far, like `2024-09-16T08:30:00Z total 42`. A
+    /// final line is printed once the input ends. Stdin is read first, then each file.
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..), conflicts_with_all = ["report", "unique", "only_in", "report_unseen", "inconsistencies", "assume_sorted", "listen", "preview", "cap", "max_distinct", "expect", "checksum", "stats", "delimiter_str", "zero_terminated", "words", "match_regex"])]
+    pub watch: Option<u64>,
+
+    /// With `--watch`, also print the distinct count of only the lines seen during each interval,
+    /// like `2024-09-16T08:30:00Z interval 7 total 42`. The interval count is always near-exact,
+    /// whatever the `--mode`, to keep its memory low.
+    #[arg(long, requires = "watch")]
+    pub rolling: bool,
+
+    /// After printing total unique lines, check the total against SPEC and exit with status 3 if it
+    /// does not match. SPEC may be an exact count (`123456`), an inclusive range (`100..200`), or a
+    /// count with a percentage tolerance (`5000000±2%` or `5000000+-2%`).
+    #[arg(long, value_name = "SPEC", value_parser = parse_expectation, conflicts_with_all = ["report", "unique", "only_in", "report_unseen", "cap", "max_distinct"])]
+    pub expect: Option<Expectation>,
+
+    /// In "estimate" mode, widen the `--expect` range by three times the estimate's standard error,
+    /// so that a correct estimate is very unlikely to fail the check.
+    #[arg(long, requires = "expect")]
+    pub expect_conservative: bool,
+
+    /// Instead of printing total unique lines, print each line from the `--expected` file that was
+    /// never found in the input, as it is written there. `--trim` and `--lower` apply to the
+    /// expected lines as well when matching them to the input.
+    #[arg(long, requires = "expected", conflicts_with_all = ["report", "unique", "only_in", "assume_sorted", "cap"])]
+    pub report_unseen: bool,
+
+    /// File containing expected lines, for use with `--report-unseen`.
+    #[arg(long, value_name = "FILE", requires = "report_unseen")]
+    pub expected: Option<PathBuf>,
+
+    /// Instead of printing total unique lines, print each line that was found in more than one
+    /// original form before `--trim`, `--lower`, `--fold`, and `--strip-ansi` were applied. Each
+    /// such line is printed on its own line, followed by one tab-indented line per form with its
+    /// occurrence count. Lines with the most forms are printed first. Only the first
+    /// `--max-variants` forms of each line are kept, and the occurrences of any others are counted
+    /// on a final `...` line. This is only compatible with "exact" mode (the default).
+    #[arg(long, conflicts_with_all = ["report", "unique", "only_in", "report_unseen", "assume_sorted", "cap", "max_distinct", "stats", "expect", "sort"])]
+    pub inconsistencies: bool,
+
+    /// The most original forms `--inconsistencies` keeps for each line, which bounds the memory it
+    /// uses.
+    #[arg(long, value_name = "N", default_value_t = 16, requires = "inconsistencies", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub max_variants: usize,
+
+    /// Instead of printing total unique lines, split each line into fields and print the number of
+    /// distinct values of each of the two `--field`s, and the number of distinct pairs of them. Lines
+    /// with fewer fields than the larger `--field` are skipped, so all three counts describe the
+    /// same lines. `--trim`, `--lower`, and the other input options apply to each field.
+    #[arg(long, requires = "fields", conflicts_with_all = ["report", "unique", "only_in", "report_unseen", "inconsistencies", "assume_sorted", "adjacent", "cap", "max_distinct", "stats", "expect", "export", "hot_cache", "watch", "checksum", "precision"])]
+    pub tabulate: bool,
+
+    /// A field to `--tabulate`, numbered from 1. Must be given exactly twice.
+    #[arg(short = 'f', long = "field", value_name = "N", requires = "tabulate", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub fields: Vec<usize>,
+
+    /// The byte that separates `--tabulate` fields. Backslash escapes such as `\t` and `\x1F` are
+    /// supported.
+    #[arg(short = 'F', long, value_name = "BYTE", default_value = "\\t", value_parser = parse_field_delimiter, requires = "tabulate")]
+    pub field_delimiter: u8,
+
+    /// Sort report output by line. Lines are sorted bytewise unless `--sort=numeric` is given. Has
+    /// no effect unless used with `--report`, `--unique`, `--only-in`, or `--report-unseen`.
+    #[arg(value_enum, short = 's', long, value_name = "ORDER", num_args = 0..=1, require_equals = true, default_missing_value = "bytes")]
+    pub sort: Option<SortOrder>,
+
+    /// Sort `--report` output by count, with the most common lines first. Lines with equal counts
+    /// are sorted bytewise.
+    #[arg(short = 'S', long, requires = "report", conflicts_with_all = ["sort", "group_case"])]
+    pub sort_by_count: bool,
+
+    /// Reverse the order given by `--sort`, `--sort-by-count`, or `--top`, so that lines sort in
+    /// descending order, or the least common lines come first.
+    #[arg(short = 'r', long)]
+    pub reverse: bool,
+
+    /// Only print the N most common `--report` lines, most common first. Lines with equal counts
+    /// are chosen and ordered bytewise, as with `--sort-by-count`. With `--sort`, the N lines are
+    /// sorted by line instead. This is only compatible with "exact" mode (the default).
+    #[arg(long, value_name = "N", requires = "report", conflicts_with_all = ["group_case", "only_in", "report_unseen", "inconsistencies", "assume_sorted", "adjacent", "tabulate"], value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub top: Option<usize>,
+
+    /// Write the report to a file instead of stdout. The file is only created once the report is
+    /// complete, so an interrupted run never leaves a truncated report behind. Output is compressed
+    /// if the file name ends in `.gz` or `.zst`. Has no effect unless used with `--report`,
+    /// `--unique`, `--only-in`, `--report-unseen`, or `--inconsistencies`.
+    #[arg(short = 'o', long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    /// Compress the `--output` file, overriding compression detected from the file name.
+    #[arg(value_enum, long, value_name = "FORMAT", requires = "output")]
+    pub output_compression: Option<Compression>,
+
+    /// Compression level to use for `--output`. Valid levels are 0-9 for gzip and 1-22 for zstd.
+    /// Uncompressed output has no level, so this is an error without compression.
+    #[arg(long, value_name = "LEVEL", requires = "output")]
+    pub compression_level: Option<u32>,
+
+    /// Don't end the last line of output with a newline, for consumers that treat a final newline
+    /// as an extra empty line. Output lines are still separated by newlines.
+    #[arg(long)]
+    pub no_trailing_newline: bool,
 
     /// Remove leading and trailing whitespace from input
     #[arg(short, long)]
@@ -33,28 +317,280 @@ pub struct CliArgs {
     #[arg(short, long = "lower")]
     pub lowercase: bool,
 
+    /// Apply Unicode case folding to input, so that lines which only differ in case are counted as
+    /// the same line. This is more thorough than `--lower`: see the folding descriptions for the
+    /// guarantees each provides.
+    #[arg(value_enum, long, value_name = "FOLDING", conflicts_with = "lowercase")]
+    pub fold: Option<Fold>,
+
+    /// Shorthand for `--fold full`.
+    #[arg(long, conflicts_with_all = ["lowercase", "fold"])]
+    pub fold_case: bool,
+
+    /// Remove ANSI escape sequences, such as color codes, from input before comparing lines. With
+    /// `--report`, each line is printed as it first appeared in the input, escape sequences
+    /// included.
+    #[arg(long)]
+    pub strip_ansi: bool,
+
+    /// Split input into records on BYTES instead of on newlines, for example `--delimiter-str '\r\n'`
+    /// or a multi-byte marker. Backslash escapes such as `\r`, `\n`, `\t`, `\0`, and `\xFF` are
+    /// supported. Each record is counted like a line. Consecutive delimiters produce empty records,
+    /// and a final record without a trailing delimiter is counted. Output is still newline
+    /// delimited.
+    #[arg(long, value_name = "BYTES", value_parser = parse_delimiter, conflicts_with_all = ["stream", "assume_sorted"])]
+    pub delimiter_str: Option<Box<[u8]>>,
+
+    /// Lines end with NUL bytes instead of newlines, in both input and output, as with
+    /// `find -print0`. Input is split like `--delimiter-str '\0'`, so lines may contain newlines.
+    /// Each line printed by `--report`, `--unique`, and the other options that print lines ends with
+    /// a NUL byte, but counts are still printed on newline-terminated lines.
+    #[arg(short = 'z', long, conflicts_with_all = ["delimiter_str", "stream", "assume_sorted", "no_trailing_newline"])]
+    pub zero_terminated: bool,
+
+    /// Count distinct words instead of distinct lines. Each line is split on runs of ASCII
+    /// whitespace, and each word is counted as if it were a line of its own: `--trim`, `--lower`,
+    /// and the other input options apply to each word, and reports list words. Empty words are
+    /// ignored.
+    #[arg(long, conflicts_with_all = ["stream", "assume_sorted"])]
+    pub words: bool,
+
+    /// Split words on any of these characters instead of on whitespace. Only ASCII characters are
+    /// supported.
+    #[arg(long, value_name = "CHARS", requires = "words")]
+    pub word_delimiters: Option<String>,
+
+    /// Only count lines that match REGEX. Lines that don't match are skipped entirely, and are not
+    /// included in reports. The regex is matched against each line as it appears in the input,
+    /// before `--trim`, `--lower`, or any other input option is applied. Running a regex on every
+    /// line is much slower than counting alone. Only available if cuniq was compiled with the
+    /// `regex` feature.
+    #[arg(long = "match", value_name = "REGEX", conflicts_with_all = ["stream", "assume_sorted", "words"])]
+    pub match_regex: Option<String>,
+
+    /// Count the text captured by group N of the `--match` regex instead of the whole line, for
+    /// example `--match 'user=(\w+)' --key-group 1` to count distinct users. Group 0 is the whole
+    /// match. Lines where the group is not part of the match, such as one side of an alternation,
+    /// are skipped. Input options such as `--lower` apply to the captured text.
+    #[arg(long, value_name = "N", requires = "match_regex")]
+    pub key_group: Option<usize>,
+
+    /// Decode each line from ENCODING and count the decoded bytes, so that binary records encoded
+    /// for transport can be deduplicated. The whole line must be encoded: whitespace, other than a
+    /// `\r` before the newline, makes it invalid. Lines are decoded before `--match`, `--words`,
+    /// `--trim`, `--lower`, and the other input options, which apply to the decoded bytes. Output
+    /// shows the decoded bytes, unless `--reencode` is given. Decoding copies every line, so counting
+    /// is slower than without `--decode`.
+    #[arg(value_enum, long, value_name = "ENCODING", conflicts_with_all = ["stream", "assume_sorted", "cap", "watch", "report_unseen", "inconsistencies", "group_case"])]
+    pub decode: Option<Encoding>,
+
+    /// What to do with a line that `--decode` can't decode. With `error`, cuniq still reads all of
+    /// the input, then exits with an error quoting the first invalid line instead of printing
+    /// results. With `skip`, invalid lines are not counted, and the number skipped is printed to
+    /// stderr as a warning.
+    #[arg(value_enum, long, value_name = "ACTION", default_value_t, requires = "decode")]
+    pub invalid_encoding: InvalidEncoding,
+
+    /// Encode lines again with the `--decode` encoding when printing them, instead of printing the
+    /// decoded bytes. Lines are printed in canonical form, which may differ from how they appeared in
+    /// the input: hex is lowercase, and base64 uses the standard alphabet with padding. With
+    /// `--sort`, lines are sorted by their decoded bytes.
+    #[arg(long, requires = "decode")]
+    pub reencode: bool,
+
+    /// Count lines by the canonical forms given in FILE, so that aliases of the same value are
+    /// counted as one line. Each line of FILE is a raw value, a tab, and its canonical form. Raw
+    /// values are matched after `--trim`, `--lower`, `--fold`, and `--strip-ansi`, which are applied to
+    /// FILE too, and the canonical form is then counted like any other line. Lines without a mapping
+    /// are counted as they are. If a raw value is mapped more than once, the last mapping is used.
+    /// Lines are translated after `--decode`, `--match`, and `--words`, and only once: a canonical
+    /// form that is also a raw value is not translated again.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["stream", "assume_sorted", "cap", "watch"])]
+    pub translate: Option<PathBuf>,
+
+    /// Skip lines that have no mapping in the `--translate` file, instead of counting them as they
+    /// are.
+    #[arg(long, requires = "translate")]
+    pub translate_only: bool,
+
+    /// Compare lines without the bytes at RANGES, for fixed-width records with volatile columns such
+    /// as timestamps. RANGES is a comma separated list of zero-based byte positions, each either a
+    /// single position `N`, an inclusive range `N-M`, or `N-` for the rest of the line: for example
+    /// `0-19,40-48`. Positions are of the line as it was read, before `--trim` or case mapping.
+    /// Ranges past the end of a line are ignored. Reports and `--unique` show each line as it first
+    /// appeared, including the ignored bytes. This is only compatible with "exact" mode (the
+    /// default).
+    #[arg(long, value_name = "RANGES", value_parser = parse_byte_ranges, conflicts_with_all = ["only_in", "report_unseen", "inconsistencies", "assume_sorted", "adjacent", "tabulate", "watch", "stream", "cap", "max_distinct", "stats", "export", "hot_cache"])]
+    pub ignore_bytes: Option<ByteRanges>,
+
+    /// After printing total unique lines, print statistics about the input, one `name: value` pair
+    /// per line. `singletons` is the number of distinct lines that appeared exactly once, and
+    /// `bytes_read` is the total size of all inputs. This is only compatible with "exact" mode (the
+    /// default), and requires an additional 8 bytes of memory per distinct line.
+    #[arg(long, conflicts_with_all = ["report", "unique", "only_in", "report_unseen", "assume_sorted", "cap"])]
+    pub stats: bool,
+
+    /// After printing total unique lines, print how much of the input was repeated, one
+    /// `name: value` pair per line: `distinct` lines, `total` lines read, `duplicates`, which is
+    /// total minus distinct, and `ratio`, the fraction of lines that were duplicates. This works in
+    /// "exact" and "near-exact" modes. "estimate" mode only estimates the distinct count, so it can't
+    /// say exactly how many lines were duplicates.
+    #[arg(long, conflicts_with_all = ["report", "duplicates", "unique_only", "unique", "only_in", "report_unseen", "inconsistencies", "tabulate", "watch", "cap", "max_distinct", "assume_sorted", "adjacent", "words", "match_regex", "delimiter_str", "decode", "preview"])]
+    pub dup_stats: bool,
+
+    /// Also write every distinct line and its occurrence count to FILE, in the binary format read by
+    /// `line_cardinality::export`. Lines are written byte for byte, so they can contain anything,
+    /// including newlines and invalid UTF-8. Like `--output`, the file is only created once it is
+    /// complete. This is only compatible with "exact" mode (the default), and requires an additional
+    /// 8 bytes of memory per distinct line when counting.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["unique", "only_in", "report_unseen", "inconsistencies", "assume_sorted", "adjacent", "cap", "max_distinct", "hot_cache", "watch", "stream", "listen"])]
+    pub export: Option<PathBuf>,
+
+    /// Write `--export` entries sorted bytewise by line, instead of in hash table order. Sorting
+    /// requires an additional 16 bytes of memory per distinct line.
+    #[arg(long, requires = "export")]
+    pub export_sorted: bool,
+
+    /// Once counting finishes, write a summary of the run to FILE in the Prometheus text format read
+    /// by node_exporter's textfile collector: the number of distinct lines, the lines and bytes read,
+    /// and how long counting took, each as a gauge labelled with the mode and the inputs. Lines
+    /// aren't counted with `--delimiter-str`, and neither lines nor bytes with `--assume-sorted`, so
+    /// those metrics are left out. Like `--output`, the file is only created once it is complete.
+    #[arg(long, value_name = "FILE", conflicts_with_all = METRICS_CONFLICTS)]
+    pub metrics_file: Option<PathBuf>,
+
+    /// Once counting finishes, send the same metrics as `--metrics-file` to the StatsD server at
+    /// HOST:PORT as UDP gauges. Plain StatsD has no labels, so `--metrics-label` is not sent.
+    #[arg(long, value_name = "HOST:PORT", value_parser = parse_statsd_address, conflicts_with_all = METRICS_CONFLICTS)]
+    pub statsd: Option<String>,
+
+    /// Start the name of every `--metrics-file` and `--statsd` metric with PREFIX, followed by `_`.
+    #[arg(long, value_name = "PREFIX", default_value = "cuniq", value_parser = parse_metrics_prefix)]
+    pub metrics_prefix: String,
+
+    /// Add a NAME=VALUE label to every `--metrics-file` metric. Can be given more than once.
+    #[arg(long, value_name = "NAME=VALUE", value_parser = parse_metric_label)]
+    pub metrics_label: Vec<MetricLabel>,
+
+    /// Check a small cache of recently seen lines before the main hash table. This is faster for
+    /// input where repeated lines are close together, such as clustered log output, and about the
+    /// same speed otherwise. SLOTS is the number of lines cached, up to 1048576, and defaults to
+    /// 1024. This is only compatible with "exact" mode (the default), and can't be used with reports.
+    #[arg(long, value_name = "SLOTS", num_args = 0..=1, require_equals = true, default_missing_value = "1024", value_parser = parse_hot_cache_slots, conflicts_with_all = ["report", "only_in", "report_unseen", "assume_sorted", "stats"])]
+    pub hot_cache: Option<usize>,
+
+    /// Once counting finishes, print statistics about the hash table to stderr: how full it is, how
+    /// many times it grew, how far a sample of lines are from the slot their hash points at, and the
+    /// distribution of line lengths. This helps tell whether counting is slow because the hasher is
+    /// clustering lines, or because a larger `--expected-cardinality` would avoid growing. This works
+    /// when counting and with `--report` in "exact" mode (the default), except with `--strip-ansi`.
+    /// Only available if cuniq was compiled with the `diagnostics` feature.
+    #[arg(long, conflicts_with_all = ["unique", "only_in", "report_unseen", "inconsistencies", "assume_sorted", "adjacent", "tabulate", "watch", "ignore_bytes", "since_line"])]
+    pub hash_diagnostics: bool,
+
+    /// After printing total unique lines, print a CRC-32 of all input bytes as `crc32: XXXXXXXX`.
+    /// The checksum is computed in the same pass as the count, over stdin followed by each file in
+    /// the order given, so it matches the CRC-32 of the inputs concatenated together.
+    #[arg(long, conflicts_with_all = ["report", "unique", "only_in", "report_unseen", "assume_sorted", "cap"])]
+    pub checksum: bool,
+
+    /// Start the report with `#` comment lines recording how it was made: the cuniq version and
+    /// commit, the command line, the size and CRC-32 of each input, and when the report was
+    /// generated. Report lines never start with `#`, so these are easy to skip when parsing. Only the
+    /// first 1 MiB of each input is checksummed, unless `--header-full-checksum` is given.
+    #[arg(long, requires = "report")]
+    pub header: bool,
+
+    /// Checksum all of each input for `--header`, rather than just the first 1 MiB.
+    #[arg(long, requires = "header")]
+    pub header_full_checksum: bool,
+
     /// Sets the algorithm used to count (or estimate) cardinality.
     #[arg(value_enum, short = 'm', long, default_value_t)]
     pub mode: Mode,
 
-    /// Set the size used by the selected counting mode. See the `--mode` documentation for how this
-    /// affects each counting mode.
+    /// How many distinct lines to expect. Each mode sizes itself for this: "exact" and "near-exact"
+    /// mode start with a hash table that can hold N lines without reallocating, and "estimate" mode
+    /// uses the fewest registers that keep the expected error within 1% for N lines, which is at
+    /// most 16384. `--verbose` prints the size chosen. A hint that is too high only costs memory,
+    /// and one that is too low only costs speed.
+    #[arg(long, value_name = "N", conflicts_with_all = ["size", "size_memory"])]
+    pub expected_cardinality: Option<usize>,
+
+    /// Deprecated: use `--expected-cardinality`, or `--size-memory` in "estimate" mode. Set the size
+    /// used by the selected counting mode. See the `--mode` documentation for how this affects each
+    /// counting mode.
     #[arg(short = 'n', long)]
     pub size: Option<usize>,
 
-    /// Set the number of threads used to perform the count. By default, the number of logical cores
-    /// is used.
-    /// Not all counting modes support parallelism: see `--mode` for details.
+    /// Set the memory budget for "estimate" mode, for example `64KiB` or `1MiB`. The largest number
+    /// of registers that fits in this budget is used. This is an alternative to
+    /// `--expected-cardinality`.
+    #[arg(long, value_name = "BYTES", value_parser = parse_byte_size, conflicts_with = "size")]
+    pub size_memory: Option<usize>,
+
+    /// How to print the count in "estimate" mode. The estimate is only approximate, so printing it
+    /// in full suggests more precision than it has.
+    #[arg(value_enum, long, value_name = "PRECISION", default_value_t)]
+    pub precision: Precision,
+
+    /// Seed hashing with fresh random seeds on each run. By default the seeds are fixed, so
+    /// "near-exact" and "estimate" modes give the same result every time for the same input. But
+    /// fixed seeds let anyone who knows them craft lines whose hashes collide, which makes
+    /// "near-exact" undercount and slows down "exact". Random seeds resist this, but the results of
+    /// "near-exact" and "estimate" may then differ slightly between runs. "exact" results are the
+    /// same either way.
+    #[arg(long)]
+    pub random_seed: bool,
+
+    /// Not implemented yet: counting is always single-threaded, and this has no effect other than a
+    /// warning. It is accepted so that scripts passing it keep working once it is implemented.
     #[arg(long)]
     pub threads: Option<usize>,
 
+    /// Instead of reading stdin and files, listen on a Unix domain socket at SOCKET and count the
+    /// lines sent by each connection, so that several producer processes can contribute to one count.
+    /// Connections are read one at a time, and each counts as a separate input. Listening stops and
+    /// results are printed on SIGINT or SIGTERM, or when a connection sends only `SHUTDOWN`, for
+    /// example with `printf SHUTDOWN | nc -U SOCKET`. The socket file is removed afterwards. A failed
+    /// connection is logged and does not stop the listener. Only available on unix platforms.
+    #[arg(long, value_name = "SOCKET", conflicts_with_all = ["files", "stream", "cap", "assume_sorted"])]
+    pub listen: Option<PathBuf>,
+
     /// Disable checking stdin for input. May yield a small performance improvement when only
     /// reading input from files.
     #[arg(long)]
     pub no_stdin: bool,
 
+    /// Check that the first input that isn't empty looks like text before counting it, and exit with
+    /// an error if it looks like binary data instead, such as an image passed by mistake. Its first
+    /// 4 KiB are checked. Input is treated as binary if it contains a NUL byte, or if more than 30%
+    /// of its bytes are control characters or invalid UTF-8.
+    #[arg(long, conflicts_with_all = ["zero_terminated", "listen"])]
+    pub text_only: bool,
+
+    /// Count input even if `--text-only` would reject it as binary, for example to override a
+    /// `--text-only` given in an @FILE.
+    #[arg(long)]
+    pub binary_ok: bool,
+
+    /// Treat any of the `--forbidden-bytes` in the input, which are `\r` and NUL by default, as an
+    /// error, for pipelines that should only ever see clean newline-delimited text. The first one is
+    /// reported with its input, line number, and byte offset from the start of that input, and
+    /// cuniq exits without printing results. With `--strict-input=warn`, all input is counted as
+    /// usual, and the number of lines containing forbidden bytes is printed to stderr as a warning.
+    #[arg(value_enum, long, value_name = "ACTION", num_args = 0..=1, require_equals = true, default_missing_value = "error", conflicts_with_all = ["zero_terminated", "delimiter_str", "listen", "stream", "assume_sorted", "cap", "watch"])]
+    pub strict_input: Option<StrictInput>,
+
+    /// The bytes `--strict-input` looks for. Backslash escapes such as `\r`, `\0`, `\t`, and `\xFF`
+    /// are supported.
+    #[arg(long, value_name = "BYTES", default_value = "\\r\\0", value_parser = parse_forbidden_bytes, requires = "strict_input")]
+    pub forbidden_bytes: Box<[u8]>,
+
     /// Force reading files via memmap. This may yield improved performance for large files. If the
-    /// binary was built without memmap support, using this flag will result in an error.
+    /// binary was built without memmap support, using this flag will result in an error. Files
+    /// larger than the platform's limit, which is 2 GiB on 32-bit platforms, can't be memmapped: see
+    /// `max_memmap_bytes` in `--version-json`.
     #[arg(long)]
     pub memmap: bool,
 
@@ -63,33 +599,269 @@ pub struct CliArgs {
     /// performance for small files.
     #[arg(long)]
     pub no_memmap: bool,
+
+    /// Do not print warnings to stderr. Errors are still printed.
+    #[arg(short, long, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Print additional information to stderr. Pass once to print the effective parameters and each
+    /// input, or twice to also print how long each input took to process.
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Print version information as a single line of JSON, for tools that inventory installed
+    /// versions. This has the version and git commit, the enabled feature flags, and the version,
+    /// feature flags, and hasher of the line_cardinality library cuniq was built with.
+    #[arg(long, exclusive = true)]
+    pub version_json: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Time each counting mode on FILE, with and without memmap, and print a table comparing them.
+    /// This helps pick the fastest mode for your data, and shows how much memory each mode needs.
+    /// Lines are counted as-is: options such as `--trim` don't apply.
+    Bench(BenchArgs),
+}
+
+#[derive(Args)]
+pub struct BenchArgs {
+    /// File to count.
+    pub file: PathBuf,
+
+    /// Number of times to count the file in each mode. The fastest run is reported, which reduces
+    /// noise from other processes and a cold page cache.
+    #[arg(long, value_name = "N", default_value_t = 3, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub runs: usize,
+}
+
+/// Parse a byte count with an optional unit suffix. `K`, `M`, and `G` (optionally followed by `iB`)
+/// are binary units, while `KB`, `MB`, and `GB` are decimal units.
+fn parse_byte_size(arg: &str) -> Result<usize, String> {
+    let arg = arg.trim();
+    let split = arg.find(|c: char| !c.is_ascii_digit()).unwrap_or(arg.len());
+    let (number, unit) = arg.split_at(split);
+    let number: usize = number.parse().map_err(|_| format!("expected a number of bytes, but got \"{arg}\""))?;
+    let multiplier: usize = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        _ => return Err(format!("unknown unit \"{unit}\"")),
+    };
+    number.checked_mul(multiplier).ok_or_else(|| format!("\"{arg}\" is too large"))
+}
+
+/// Parse a `--count-width`, which is either a number or `auto`
+fn parse_hot_cache_slots(arg: &str) -> Result<usize, String> {
+    let slots: usize = arg.parse().map_err(|_| format!("expected a number, but got \"{arg}\""))?;
+    if slots > LineCounter::<()>::MAX_HOT_CACHE_SLOTS {
+        return Err(format!("the hot cache can hold at most {} lines, but {slots} were requested", LineCounter::<()>::MAX_HOT_CACHE_SLOTS));
+    }
+    Ok(slots)
+}
+
+fn parse_count_width(arg: &str) -> Result<CountWidth, String> {
+    if arg.eq_ignore_ascii_case("auto") {
+        return Ok(CountWidth::Auto);
+    }
+    let width: usize = arg.parse().map_err(|_| format!("expected a number or \"auto\", but got \"{arg}\""))?;
+    if width > MAX_COUNT_WIDTH {
+        return Err(format!("the count width must be at most {MAX_COUNT_WIDTH}, but was {width}"));
+    }
+    Ok(CountWidth::Fixed(width))
+}
+
+/// Parse a field delimiter, which must be a single byte after backslash escapes are applied
+fn parse_field_delimiter(arg: &str) -> Result<u8, String> {
+    match Vec::unescape_bytes(arg).as_slice() {
+        [byte] => Ok(*byte),
+        _ => Err("the field delimiter must be a single byte".to_string()),
+    }
+}
+
+/// Parse a record delimiter, which may contain backslash escapes
+fn parse_delimiter(arg: &str) -> Result<Box<[u8]>, String> {
+    let delimiter = Vec::unescape_bytes(arg);
+    if delimiter.is_empty() {
+        return Err("the delimiter must not be empty".to_string());
+    }
+    Ok(delimiter.into_boxed_slice())
+}
+
+/// Parse the `--forbidden-bytes` set, which may contain backslash escapes
+fn parse_forbidden_bytes(arg: &str) -> Result<Box<[u8]>, String> {
+    let mut bytes = Vec::unescape_bytes(arg);
+    if bytes.is_empty() {
+        return Err("at least one byte must be forbidden".to_string());
+    }
+    if bytes.contains(&b'\n') {
+        return Err("newlines separate lines, so they can't be forbidden".to_string());
+    }
+    bytes.sort_unstable();
+    bytes.dedup();
+    Ok(bytes.into_boxed_slice())
+}
+
+/// Parse a `--report` output delimiter, which may contain backslash escapes and may be empty
+fn parse_output_delimiter(arg: &str) -> Result<Box<[u8]>, String> {
+    Ok(Vec::unescape_bytes(arg).into_boxed_slice())
+}
+
+/// Unicode case folding to apply to input. Neither kind of folding uses the Turkic-specific
+/// mappings, and neither performs Unicode normalization.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Fold {
+    /// Simple case folding, which maps each character to exactly one character. Unlike `--lower`,
+    /// this merges characters with several lowercase forms, such as "ς" and "σ". Characters that
+    /// only fold to multiple characters are left unchanged, so "ß" and "ss" remain distinct.
+    Simple,
+    /// Full case folding, as used for Unicode caseless matching. Lines are counted as the same line
+    /// exactly when they match caselessly, so "straße" and "STRASSE" are the same line. Lines that
+    /// are entirely ASCII are folded about as quickly as with `--lower`, but other lines are slower
+    /// to fold than with simple folding.
+    Full,
+}
+
+/// Encoding of input lines, for `--decode`
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    /// Base64, in the standard alphabet (`+/`) or the URL-safe alphabet (`-_`), with or without
+    /// `=` padding. Different encodings of the same bytes are counted as the same line.
+    Base64,
+    /// Hexadecimal, two digits per byte, in either case.
+    Hex,
+}
+
+/// What to do with lines that can't be decoded
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum InvalidEncoding {
+    /// Exit with an error once all input has been read
+    #[default]
+    Error,
+    /// Don't count the line, and warn about how many were skipped
+    Skip,
+}
+
+/// How wide the column of `--report` counts is
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CountWidth {
+    /// Pad counts to this many characters
+    Fixed(usize),
+    /// Pad counts to the width of the largest count
+    Auto,
+}
+
+/// What `--strict-input` does when it finds a forbidden byte
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StrictInput {
+    /// Stop reading, and exit with an error pointing out the first forbidden byte
+    Error,
+    /// Count all of the input, then warn how many lines contained forbidden bytes
+    Warn,
+}
+
+/// Order to sort lines in
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SortOrder {
+    /// Sort bytewise, as by `LC_ALL=C sort`.
+    Bytes,
+    /// Sort by the number at the start of each line, as by `LC_ALL=C sort -n`. A number is an
+    /// optional `-`, digits, and an optional decimal part, after any leading spaces or tabs. Lines
+    /// that don't start with a number sort as zero, and lines with equal numbers are sorted
+    /// bytewise.
+    Numeric,
+}
+
+/// How an estimated count is printed
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Precision {
+    /// The estimate as a whole number, such as `1234567`
+    #[default]
+    ExactInteger,
+    /// The estimate rounded to 2 significant digits, such as `1200000`. Estimates below 1000 are
+    /// not rounded.
+    Sig2,
+    /// The estimate rounded to 3 significant digits, such as `1230000`. Estimates below 1000 are
+    /// not rounded.
+    Sig3,
+    /// The range within three standard errors of the estimate, such as `1211000..1258000`. A
+    /// correct estimate is within this range over 99% of the time.
+    Interval,
+}
+
+/// Format of report lines
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ReportFormat {
+    /// The occurrence count, right-aligned, then the line. This matches `uniq -c`.
+    #[default]
+    Text,
+    /// One JSON object per line, such as `{"line":"foo","count":3}`. Lines that are not valid UTF-8
+    /// can't be JSON strings, so they are written as an array of byte values instead, such as
+    /// `{"line_bytes":[102,111,255],"count":3}`.
+    Ndjson,
+    /// The count, a tab, then the line. Tabs, newlines, carriage returns, and backslashes in the
+    /// line are escaped as `\t`, `\n`, `\r`, and `\\`.
+    Tsv,
+    /// The count, a comma, then the line, as RFC 4180 CSV. A line containing a comma, double quote,
+    /// or line break is enclosed in double quotes, with each double quote in it doubled.
+    Csv,
 }
 
 /// Mode used to calculate cardinality
-#[derive(ValueEnum, Clone, Default)]
+#[derive(ValueEnum, Clone, Copy, Default)]
 pub enum Mode {
     /// Uses a hash table to exactly count cardinality.
     /// The size of the hash table is proportional to the cardinality of the input.
-    /// You may use the `--size` flag to set the initial capacity of the internal hash table. For
-    /// very large inputs `--size` may help reduce expensive hash table reallocations. Avoid setting
-    /// `--size` for small datasets.
+    /// You may use `--expected-cardinality` to set the initial capacity of the internal hash table.
+    /// For very large inputs this may help reduce expensive hash table reallocations. Avoid setting
+    /// it for small datasets.
     #[default]
     Exact,
     /// Uses a hash table to exactly count cardinality, but does not store the original line.
     /// This mode is faster than "exact" mode, but hash collision will result in under-counting the
     /// cardinality by one. However, hash collisions for a 64-bit hash are exceedingly unlikely.
     /// The size of the hash table is proportional to the cardinality of the input.
-    /// You may use the `--size` flag to set the initial capacity of the internal hash table. For
-    /// very large inputs `--size` may help reduce expensive hash table reallocations. Avoid setting
-    /// `--size` for small datasets. This mode is not compatible with `--report`.
+    /// You may use `--expected-cardinality` to set the initial capacity of the internal hash table.
+    /// For very large inputs this may help reduce expensive hash table reallocations. Avoid setting
+    /// it for small datasets. This mode is not compatible with `--report`.
     NearExact,
     /// Uses the HyperLogLog algorithm to estimate cardinality with fixed memory.
-    /// Use the `--size` flag to specify the number of 1-byte registers to use. More registers will
-    /// increase estimate accuracy. By default, 65536 is used. This mode is not compatible with
-    /// `--report`.
+    /// Use `--expected-cardinality` to choose the number of 1-byte registers from the number of
+    /// distinct lines you expect, or `--size-memory` to specify a memory budget instead. More
+    /// registers will increase estimate accuracy. By default, 65536 is used. This mode is not
+    /// compatible with `--report`.
     Estimate,
 }
 
+/// Compression applied to output files
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Compression {
+    /// Do not compress output
+    None,
+    /// Compress output with gzip. Only available if cuniq was compiled with the `gzip` feature.
+    Gzip,
+    /// Compress output with zstd. Only available if cuniq was compiled with the `zstd` feature.
+    Zstd,
+}
+
+impl Compression {
+    /// Guess compression from a file extension
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
 impl Display for Mode {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let str = match self {
@@ -100,3 +872,131 @@ impl Display for Mode {
         f.write_str(str)
     }
 }
+
+impl Display for ReportFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            ReportFormat::Text => "text",
+            ReportFormat::Ndjson => "ndjson",
+            ReportFormat::Tsv => "tsv",
+            ReportFormat::Csv => "csv",
+        };
+        f.write_str(str)
+    }
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            Encoding::Base64 => "base64",
+            Encoding::Hex => "hex",
+        };
+        f.write_str(str)
+    }
+}
+
+/// The command line arguments in `args`, with each `@FILE` argument replaced by the lines of FILE.
+/// The first argument is the program name, which is never expanded.
+pub fn expand_argfiles<I: IntoIterator<Item = OsString>>(args: I) -> Result<Vec<OsString>, Error> {
+    let mut args = args.into_iter();
+    let mut expanded: Vec<OsString> = args.next().into_iter().collect();
+    let mut literal = false;
+    for arg in args {
+        let argfile = if literal { None } else { arg.to_str().and_then(|arg| arg.strip_prefix('@')).filter(|path| !path.is_empty()) };
+        match argfile {
+            Some(path) => read_argfile(Path::new(path), &mut expanded)?,
+            None => {
+                literal = arg == "--";
+                expanded.push(arg);
+            }
+        }
+    }
+    Ok(expanded)
+}
+
+/// Append each non-empty line of the file at `path` to `args`
+fn read_argfile(path: &Path, args: &mut Vec<OsString>) -> Result<(), Error> {
+    let contents = std::fs::read(path).map_err(|e| Error::io(format!("error reading argument file \"{}\"", escaped(path)), e))?;
+    for line in contents.lines().filter(|line| !line.is_empty()) {
+        let arg = line.to_vec().into_os_string().map_err(|_| Error::message(format!("argument file \"{}\" is not valid UTF-8", escaped(path))))?;
+        args.push(arg);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn os_strings(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn test_expand_argfiles() {
+        let path = std::env::temp_dir().join(format!("cuniq-test-{}-argfile.txt", std::process::id()));
+        std::fs::write(&path, "--report\r\n\na file.txt\n@nested\n").unwrap();
+        let argfile = format!("@{}", path.display());
+        let expanded = expand_argfiles(os_strings(&["@cuniq", "first", &argfile, "@", "--", &argfile])).unwrap();
+        assert_eq!(expanded, os_strings(&["@cuniq", "first", "--report", "a file.txt", "@nested", "@", "--", &argfile]));
+        std::fs::remove_file(&path).unwrap();
+        assert!(expand_argfiles(os_strings(&["cuniq", &argfile])).is_err());
+    }
+
+    #[test]
+    fn test_parse_byte_size() {
+        assert_eq!(parse_byte_size("0"), Ok(0));
+        assert_eq!(parse_byte_size("1000"), Ok(1000));
+        assert_eq!(parse_byte_size("16B"), Ok(16));
+        assert_eq!(parse_byte_size("64K"), Ok(65536));
+        assert_eq!(parse_byte_size("64KiB"), Ok(65536));
+        assert_eq!(parse_byte_size("1MiB"), Ok(1048576));
+        assert_eq!(parse_byte_size("1 mib"), Ok(1048576));
+        assert_eq!(parse_byte_size("2G"), Ok(2 << 30));
+        assert_eq!(parse_byte_size("1MB"), Ok(1_000_000));
+        assert!(parse_byte_size("").is_err());
+        assert!(parse_byte_size("MiB").is_err());
+        assert!(parse_byte_size("1TiB").is_err());
+        assert!(parse_byte_size("-1").is_err());
+    }
+
+    #[test]
+    fn test_parse_field_delimiter() {
+        assert_eq!(parse_field_delimiter(","), Ok(b','));
+        assert_eq!(parse_field_delimiter("\\t"), Ok(b'\t'));
+        assert_eq!(parse_field_delimiter("\\xFF"), Ok(0xFF));
+        assert!(parse_field_delimiter("").is_err());
+        assert!(parse_field_delimiter(", ").is_err());
+    }
+
+    #[test]
+    fn test_parse_delimiter() {
+        assert_eq!(parse_delimiter("\\r\\n"), Ok(b"\r\n".as_slice().into()));
+        assert_eq!(parse_delimiter("\\0"), Ok(b"\0".as_slice().into()));
+        assert_eq!(parse_delimiter("--\\xFF"), Ok(b"--\xFF".as_slice().into()));
+        assert_eq!(parse_delimiter("\r\n"), Ok(b"\r\n".as_slice().into()));
+        assert!(parse_delimiter("").is_err());
+    }
+
+    #[test]
+    fn test_parse_count_width() {
+        assert_eq!(parse_count_width("7"), Ok(CountWidth::Fixed(7)));
+        assert_eq!(parse_count_width("0"), Ok(CountWidth::Fixed(0)));
+        assert_eq!(parse_count_width("auto"), Ok(CountWidth::Auto));
+        assert_eq!(parse_count_width("AUTO"), Ok(CountWidth::Auto));
+        assert!(parse_count_width("-1").is_err());
+        assert!(parse_count_width("wide").is_err());
+        assert_eq!(parse_count_width("64"), Ok(CountWidth::Fixed(64)));
+        assert!(parse_count_width("65").is_err());
+        assert!(parse_count_width("70000").is_err());
+    }
+
+    #[test]
+    fn test_parse_hot_cache_slots() {
+        assert_eq!(parse_hot_cache_slots("1024"), Ok(1024));
+        assert_eq!(parse_hot_cache_slots("1048576"), Ok(1048576));
+        assert!(parse_hot_cache_slots("1048577").is_err());
+        assert!(parse_hot_cache_slots(&usize::MAX.to_string()).is_err());
+        assert!(parse_hot_cache_slots("many").is_err());
+    }
+}
@@ -0,0 +1,367 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Decoding lines with `--decode` before they are counted, and encoding them again for `--reencode`
+
+use std::mem::size_of;
+
+use bstr::ByteSlice;
+use line_cardinality::{CountUnique, Error};
+
+use crate::cli_args::{Encoding, InvalidEncoding};
+use crate::logging::log_warn;
+
+/// The most bytes of an invalid line that are quoted in the error about it
+const QUOTED_LINE_LIMIT: usize = 64;
+
+/// Wraps a [`CountUnique`] to count the bytes each line decodes to, rather than the line itself.
+///
+/// Lines are decoded before the wrapped counter's line mapper, so `--trim`, `--lower`, and the other
+/// input options apply to the decoded bytes. Each line is decoded into a buffer that is reused for
+/// every line, so decoding never allocates once the buffer has grown to fit the longest line.
+pub struct DecodingCounter<C> {
+    inner: C,
+    encoding: Encoding,
+    on_invalid: InvalidEncoding,
+    /// the decoded line, reused between lines
+    buffer: Vec<u8>,
+    /// how many lines could not be decoded
+    invalid_lines: u64,
+    /// the first line that could not be decoded, shortened to quote in the error about it
+    first_invalid: Option<Vec<u8>>,
+}
+
+impl<C: CountUnique> DecodingCounter<C> {
+    pub fn new(inner: C, encoding: Encoding, on_invalid: InvalidEncoding) -> Self {
+        DecodingCounter {
+            inner,
+            encoding,
+            on_invalid,
+            buffer: Vec::new(),
+            invalid_lines: 0,
+            first_invalid: None,
+        }
+    }
+
+    /// The wrapped counter, mutably
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    /// Report any lines that could not be decoded: as an error with `--invalid-encoding error`, or
+    /// as a warning if they were skipped.
+    pub fn finish(&self) -> Result<(), Error> {
+        if self.invalid_lines == 0 {
+            return Ok(());
+        }
+        match self.on_invalid {
+            InvalidEncoding::Error => {
+                let first = self.first_invalid.as_deref().unwrap_or_default();
+                Err(Error::input(format!(
+                    "{} lines are not valid {}, the first being \"{}\". Use --invalid-encoding skip to skip them",
+                    self.invalid_lines,
+                    self.encoding,
+                    first.as_bstr(),
+                )))
+            }
+            InvalidEncoding::Skip => {
+                log_warn!("skipped {} lines that are not valid {}", self.invalid_lines, self.encoding);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<C: CountUnique> CountUnique for DecodingCounter<C> {
+    #[inline(always)]
+    fn count_line(&mut self, line: &[u8]) {
+        // memmapped input keeps the `\r` of a CRLF line ending, which buffered reads remove
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if decode(self.encoding, line, &mut self.buffer) {
+            self.inner.count_line(&self.buffer);
+        } else {
+            self.invalid_lines += 1;
+            if self.on_invalid == InvalidEncoding::Error && self.first_invalid.is_none() {
+                let mut quoted = line[..line.len().min(QUOTED_LINE_LIMIT)].to_vec();
+                if line.len() > QUOTED_LINE_LIMIT {
+                    quoted.extend_from_slice(b"...");
+                }
+                self.first_invalid = Some(quoted);
+            }
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.inner.count()
+    }
+
+    fn estimated_memory(&self) -> usize {
+        size_of::<Self>() - size_of::<C>() + self.buffer.capacity() + self.inner.estimated_memory()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+}
+
+/// Encodes lines for output with `--reencode`, or passes them through unchanged without it
+pub struct OutputEncoder {
+    encoding: Option<Encoding>,
+    /// the encoded line, reused between lines
+    buffer: Vec<u8>,
+}
+
+impl OutputEncoder {
+    pub fn new(encoding: Option<Encoding>) -> Self {
+        OutputEncoder { encoding, buffer: Vec::new() }
+    }
+
+    /// Whether lines are changed before they are written
+    pub fn is_enabled(&self) -> bool {
+        self.encoding.is_some()
+    }
+
+    /// `line` as it should be written to output
+    #[inline(always)]
+    pub fn encode<'a>(&'a mut self, line: &'a [u8]) -> &'a [u8] {
+        match self.encoding {
+            Some(encoding) => {
+                encode(encoding, line, &mut self.buffer);
+                &self.buffer
+            }
+            None => line,
+        }
+    }
+}
+
+/// Decode `line` into `buffer`, replacing its contents. Returns `false` if `line` is not valid.
+fn decode(encoding: Encoding, line: &[u8], buffer: &mut Vec<u8>) -> bool {
+    buffer.clear();
+    match encoding {
+        Encoding::Base64 => decode_base64(line, buffer),
+        Encoding::Hex => decode_hex(line, buffer),
+    }
+}
+
+/// Encode `bytes` into `buffer`, replacing its contents. This always produces the canonical form:
+/// lowercase hex, or base64 with the standard alphabet and padding.
+fn encode(encoding: Encoding, bytes: &[u8], buffer: &mut Vec<u8>) {
+    buffer.clear();
+    match encoding {
+        Encoding::Base64 => encode_base64(bytes, buffer),
+        Encoding::Hex => encode_hex(bytes, buffer),
+    }
+}
+
+fn decode_hex(line: &[u8], buffer: &mut Vec<u8>) -> bool {
+    if line.len() % 2 != 0 {
+        return false;
+    }
+    buffer.reserve(line.len() / 2);
+    for pair in line.chunks_exact(2) {
+        let (Some(high), Some(low)) = (hex_value(pair[0]), hex_value(pair[1])) else {
+            return false;
+        };
+        buffer.push(high << 4 | low);
+    }
+    true
+}
+
+#[inline(always)]
+fn hex_value(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
+}
+
+fn encode_hex(bytes: &[u8], buffer: &mut Vec<u8>) {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    buffer.reserve(bytes.len() * 2);
+    for byte in bytes {
+        buffer.push(DIGITS[usize::from(byte >> 4)]);
+        buffer.push(DIGITS[usize::from(byte & 0xF)]);
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Decode base64 in either the standard or the URL-safe alphabet, with or without padding. Bits left
+/// over after the last byte must be zero, so every byte string has only one unpadded encoding in
+/// each alphabet.
+fn decode_base64(line: &[u8], buffer: &mut Vec<u8>) -> bool {
+    let unpadded = match line {
+        [rest @ .., b'=', b'='] | [rest @ .., b'='] if line.len() % 4 == 0 => rest,
+        _ => line,
+    };
+    // one leftover character doesn't even hold a whole byte
+    if unpadded.len() % 4 == 1 {
+        return false;
+    }
+    buffer.reserve(unpadded.len() / 4 * 3 + 2);
+    let mut chunks = unpadded.chunks_exact(4);
+    for chunk in &mut chunks {
+        let Some(bits) = base64_bits(chunk) else {
+            return false;
+        };
+        buffer.extend_from_slice(&bits.to_be_bytes()[1..]);
+    }
+    let remainder = chunks.remainder();
+    if remainder.is_empty() {
+        return true;
+    }
+    let Some(bits) = base64_bits(remainder) else {
+        return false;
+    };
+    // 2 characters hold 1 byte, and 3 hold 2
+    let len = remainder.len() - 1;
+    let bytes = bits.to_be_bytes();
+    if bytes[1 + len..].iter().any(|byte| *byte != 0) {
+        return false;
+    }
+    buffer.extend_from_slice(&bytes[1..1 + len]);
+    true
+}
+
+/// The bits of up to 4 base64 characters, as the low 24 bits of the result. Missing characters are
+/// treated as zeros.
+#[inline(always)]
+fn base64_bits(chars: &[u8]) -> Option<u32> {
+    let mut bits = 0;
+    for index in 0..4 {
+        let value = match chars.get(index) {
+            Some(&c) => base64_value(c)?,
+            None => 0,
+        };
+        bits = bits << 6 | u32::from(value);
+    }
+    Some(bits)
+}
+
+#[inline(always)]
+fn base64_value(c: u8) -> Option<u8> {
+    match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' | b'-' => Some(62),
+        b'/' | b'_' => Some(63),
+        _ => None,
+    }
+}
+
+fn encode_base64(bytes: &[u8], buffer: &mut Vec<u8>) {
+    buffer.reserve(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let mut group = [0; 4];
+        group[1..1 + chunk.len()].copy_from_slice(chunk);
+        let bits = u32::from_be_bytes(group);
+        for index in 0..4 {
+            if index <= chunk.len() {
+                buffer.push(BASE64_ALPHABET[(bits >> (18 - 6 * index) & 0x3F) as usize]);
+            } else {
+                buffer.push(b'=');
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use line_cardinality::{EmitLines, LineCounter};
+
+    use super::*;
+
+    fn decoded(encoding: Encoding, line: &[u8]) -> Option<Vec<u8>> {
+        let mut buffer = Vec::new();
+        decode(encoding, line, &mut buffer).then_some(buffer)
+    }
+
+    fn encoded(encoding: Encoding, bytes: &[u8]) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        encode(encoding, bytes, &mut buffer);
+        buffer
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(decoded(Encoding::Hex, b""), Some(Vec::new()));
+        assert_eq!(decoded(Encoding::Hex, b"00ff7F"), Some(vec![0x00, 0xFF, 0x7F]));
+        assert_eq!(decoded(Encoding::Hex, b"0"), None);
+        assert_eq!(decoded(Encoding::Hex, b"0g"), None);
+        assert_eq!(decoded(Encoding::Hex, b" 00"), None);
+        assert_eq!(encoded(Encoding::Hex, &[0x00, 0xFF, 0x7F]), b"00ff7f");
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(decoded(Encoding::Base64, b""), Some(Vec::new()));
+        assert_eq!(decoded(Encoding::Base64, b"Zm9vYmFy"), Some(b"foobar".to_vec()));
+        assert_eq!(decoded(Encoding::Base64, b"Zm9vYg=="), Some(b"foob".to_vec()));
+        assert_eq!(decoded(Encoding::Base64, b"Zm9vYg"), Some(b"foob".to_vec()));
+        assert_eq!(decoded(Encoding::Base64, b"Zm9vYmE="), Some(b"fooba".to_vec()));
+        assert_eq!(decoded(Encoding::Base64, b"Zm9vYmE"), Some(b"fooba".to_vec()));
+        // URL-safe alphabet
+        assert_eq!(decoded(Encoding::Base64, b"-_8"), Some(vec![0xFB, 0xFF]));
+        assert_eq!(decoded(Encoding::Base64, b"+/8="), Some(vec![0xFB, 0xFF]));
+    }
+
+    #[test]
+    fn test_invalid_base64() {
+        for line in [
+            b"Z".as_slice(),
+            b"Zm9vY",
+            b"Zm9v!mFy",
+            b"Zm9vYg=",
+            b"Zm9vY===",
+            b"Zm=vYg==",
+            b"====",
+            // non-zero leftover bits
+            b"Zm9vYh",
+            b"Zm9vYmF",
+            b"Zm9v YmFy",
+        ] {
+            assert_eq!(decoded(Encoding::Base64, line), None, "{:?}", line.as_bstr());
+        }
+    }
+
+    /// Encoding and then decoding gives back the original bytes, for every length of remainder
+    #[test]
+    fn test_round_trip() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        for len in 0..8 {
+            for encoding in [Encoding::Base64, Encoding::Hex] {
+                let original = &bytes[256 - len..];
+                assert_eq!(decoded(encoding, &encoded(encoding, original)).as_deref(), Some(original), "{encoding} of {original:?}");
+            }
+        }
+        assert_eq!(encoded(Encoding::Base64, b"foob"), b"Zm9vYg==");
+        assert_eq!(encoded(Encoding::Base64, b"fooba"), b"Zm9vYmE=");
+    }
+
+    fn count(on_invalid: InvalidEncoding, input: &[u8]) -> (Vec<Vec<u8>>, Result<(), Error>) {
+        let mut counter = DecodingCounter::new(LineCounter::new(), Encoding::Hex, on_invalid);
+        counter.count_unique_in_bytes(input);
+        let result = counter.finish();
+        let mut lines = counter.inner.into_vec();
+        lines.sort();
+        (lines, result)
+    }
+
+    #[test]
+    fn test_counter() {
+        // different encodings of the same bytes are counted once, and a CRLF ending is ignored
+        let (lines, result) = count(InvalidEncoding::Error, b"6869\r\n6869\n4869\n");
+        assert_eq!(lines, [b"Hi".to_vec(), b"hi".to_vec()]);
+        assert!(result.is_ok());
+
+        let (lines, result) = count(InvalidEncoding::Skip, b"6869\nnot hex\n");
+        assert_eq!(lines, [b"hi".to_vec()]);
+        assert!(result.is_ok());
+
+        let (_lines, result) = count(InvalidEncoding::Error, b"6869\nnot hex\n");
+        assert!(result.unwrap_err().to_string().contains("\"not hex\""));
+    }
+}
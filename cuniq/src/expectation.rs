@@ -0,0 +1,153 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Checking the final count against `--expect`
+
+use std::fmt::{Display, Formatter};
+
+/// An inclusive range of acceptable distinct line counts
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Expectation {
+    min: u64,
+    max: u64,
+}
+
+impl Expectation {
+    /// Whether `count` meets this expectation
+    pub fn contains(&self, count: u64) -> bool {
+        (self.min..=self.max).contains(&count)
+    }
+
+    /// Widen both bounds by `relative_error`, a fraction of each bound
+    pub fn widened(&self, relative_error: f64) -> Self {
+        Expectation {
+            min: (self.min as f64 * (1.0 - relative_error)).floor().max(0.0) as u64,
+            max: (self.max as f64 * (1.0 + relative_error)).ceil() as u64,
+        }
+    }
+}
+
+impl Display for Expectation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}", self.min)
+        } else {
+            write!(f, "{}..{}", self.min, self.max)
+        }
+    }
+}
+
+/// Parse an `--expect` spec: an exact count (`N`), an inclusive range (`N..M`), or a count with a
+/// percentage tolerance (`N±P%` or `N+-P%`). The tolerance may have up to two decimal places, and is
+/// rounded down to a whole number of lines.
+pub fn parse_expectation(spec: &str) -> Result<Expectation, String> {
+    let spec = spec.trim();
+    if let Some((min, max)) = spec.split_once("..") {
+        let min = parse_count(min)?;
+        let max = parse_count(max)?;
+        if min > max {
+            return Err(format!("range start {min} is greater than range end {max}"));
+        }
+        Ok(Expectation { min, max })
+    } else if let Some((count, tolerance)) = spec.split_once('±').or_else(|| spec.split_once("+-")) {
+        let count = parse_count(count)?;
+        let hundredths = parse_percentage(tolerance)?;
+        // integer math, so large counts don't lose precision. This can't overflow a u128.
+        let tolerance = (count as u128 * hundredths as u128 / 10_000) as u64;
+        Ok(Expectation {
+            min: count.saturating_sub(tolerance),
+            max: count.saturating_add(tolerance),
+        })
+    } else {
+        let count = parse_count(spec)?;
+        Ok(Expectation { min: count, max: count })
+    }
+}
+
+fn parse_count(count: &str) -> Result<u64, String> {
+    let count = count.trim();
+    count.parse().map_err(|_| format!("expected a count, but got \"{count}\""))
+}
+
+/// Parse a percentage such as `2%` or `0.25%` into hundredths of a percent
+fn parse_percentage(percentage: &str) -> Result<u64, String> {
+    let percentage = percentage.trim();
+    let invalid = || format!("expected a percentage with at most two decimal places, such as \"2.5%\", but got \"{percentage}\"");
+    let number = percentage.strip_suffix('%').ok_or_else(invalid)?;
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if whole.is_empty() || fraction.len() > 2 || !whole.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let whole: u64 = whole.parse().map_err(|_| invalid())?;
+    // pad the fraction to two digits, so ".5" is 50 hundredths
+    let fraction: u64 = format!("{fraction:0<2}").parse().map_err(|_| invalid())?;
+    whole.checked_mul(100)
+        .and_then(|hundredths| hundredths.checked_add(fraction))
+        .filter(|hundredths| *hundredths <= 10_000)
+        .ok_or_else(|| format!("percentage must be at most 100%, but was \"{percentage}\""))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn range(min: u64, max: u64) -> Expectation {
+        Expectation { min, max }
+    }
+
+    #[test]
+    fn test_parse_exact() {
+        assert_eq!(parse_expectation("123456"), Ok(range(123456, 123456)));
+        assert_eq!(parse_expectation(" 0 "), Ok(range(0, 0)));
+        assert!(parse_expectation("-1").is_err());
+        assert!(parse_expectation("lots").is_err());
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_expectation("10..20"), Ok(range(10, 20)));
+        assert_eq!(parse_expectation("10..10"), Ok(range(10, 10)));
+        assert!(parse_expectation("20..10").is_err());
+        assert!(parse_expectation("10..").is_err());
+    }
+
+    #[test]
+    fn test_parse_percentage() {
+        assert_eq!(parse_expectation("5000000±2%"), Ok(range(4900000, 5100000)));
+        assert_eq!(parse_expectation("5000000+-2%"), Ok(range(4900000, 5100000)));
+        assert_eq!(parse_expectation("1000±0.5%"), Ok(range(995, 1005)));
+        assert_eq!(parse_expectation("1000±.5%").ok(), None);
+        assert_eq!(parse_expectation("1000±2.25%"), Ok(range(978, 1022)));
+        // tolerance rounds down to whole lines
+        assert_eq!(parse_expectation("99±1%"), Ok(range(99, 99)));
+        assert_eq!(parse_expectation("10±100%"), Ok(range(0, 20)));
+        // no precision loss for counts beyond f64's integer range
+        assert_eq!(parse_expectation("18446744073709551615±0%"), Ok(range(u64::MAX, u64::MAX)));
+        assert_eq!(parse_expectation("18446744073709551615±1%").unwrap().max, u64::MAX);
+        assert!(parse_expectation("1000±2").is_err());
+        assert!(parse_expectation("1000±2.125%").is_err());
+        assert!(parse_expectation("1000±101%").is_err());
+    }
+
+    #[test]
+    fn test_contains() {
+        let expectation = parse_expectation("100±5%").unwrap();
+        assert!(!expectation.contains(94));
+        assert!(expectation.contains(95));
+        assert!(expectation.contains(105));
+        assert!(!expectation.contains(106));
+    }
+
+    #[test]
+    fn test_widened() {
+        assert_eq!(range(1000, 1000).widened(0.1), range(900, 1100));
+        assert_eq!(range(10, 20).widened(0.05), range(9, 21));
+        assert_eq!(range(10, 20).widened(2.0), range(0, 60));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(range(5, 5).to_string(), "5");
+        assert_eq!(range(5, 7).to_string(), "5..7");
+    }
+}
@@ -0,0 +1,129 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Checking input against a list of expected lines
+
+use std::io::BufRead;
+
+use bstr::io::BufReadExt;
+use line_cardinality::{CountUnique, Error, LineMap};
+
+/// A [`CountUnique`] that only tracks which of a set of expected lines have been seen. Lines that
+/// were not expected are ignored, so memory use is proportional to the number of expected lines
+/// rather than the cardinality of the input. Each expected line costs its [`LineMap`] key and a
+/// 32-byte value, plus a copy of the line as it was written if the line mapper changed it.
+pub struct ExpectedLines<M> {
+    /// expected lines after the line mapper, each with the line as it was written in the expected
+    /// file if the mapper changed it, and whether it has been seen
+    map: LineMap<(Option<Vec<u8>>, bool)>,
+    string_buffer: Vec<u8>,
+    count: usize,
+    line_mapper: M,
+}
+
+impl<M> ExpectedLines<M>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    /// Load newline-delimited expected lines. `line_mapper` is applied to both the expected lines
+    /// and the input lines, so that they are compared in the same form.
+    pub fn from_read<R: BufRead>(mut reader: R, mut line_mapper: M) -> Result<Self, Error> {
+        let mut map = LineMap::new();
+        let mut string_buffer = Vec::new();
+        reader.for_byte_line(|original| {
+            let line = line_mapper(original, &mut string_buffer);
+            if !map.contains_line(line) {
+                let original = (line != original).then(|| original.to_vec());
+                map.insert(line, (original, false));
+            }
+            Ok(true)
+        }).map_err(|e| Error::io_static("failed to read expected lines", e))?;
+        Ok(ExpectedLines {
+            map,
+            string_buffer,
+            count: 0,
+            line_mapper,
+        })
+    }
+}
+
+impl<M> ExpectedLines<M> {
+    /// Iterate over expected lines that have not been seen, as they were written in the expected
+    /// file. Of several expected lines that map to the same line, the first is used.
+    pub fn unseen_lines(&self) -> impl Iterator<Item = &[u8]> {
+        self.map.iter()
+            .filter(|(_line, (_original, seen))| !*seen)
+            .map(|(line, (original, _seen))| original.as_deref().unwrap_or(line))
+    }
+}
+
+impl<M> CountUnique for ExpectedLines<M>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    #[inline(always)]
+    fn count_line(&mut self, line: &[u8]) {
+        let line = (self.line_mapper)(line, &mut self.string_buffer);
+        if let Some((_original, seen)) = self.map.get_mut(line) {
+            if !*seen {
+                *seen = true;
+                self.count += 1;
+            }
+        }
+    }
+
+    /// Number of distinct expected lines that have been seen
+    fn count(&self) -> usize {
+        self.count
+    }
+
+    fn reset(&mut self) {
+        self.count = 0;
+        self.map.values_mut().for_each(|(_original, seen)| *seen = false);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bstr::ByteSlice;
+
+    use super::*;
+
+    #[test]
+    fn test_unseen_lines() {
+        let mut expected = ExpectedLines::from_read(b"a\nb\nc\nd\nb".as_slice(), |line, _buffer| line).unwrap();
+        expected.count_unique_in_bytes(b"x\nb\nd\nd\ny");
+
+        let mut unseen: Vec<&[u8]> = expected.unseen_lines().collect();
+        unseen.sort_unstable();
+        assert_eq!(unseen, [b"a".as_slice(), b"c"]);
+        assert_eq!(expected.count(), 2);
+
+        expected.reset();
+        assert_eq!(expected.unseen_lines().count(), 4);
+    }
+
+    #[test]
+    fn test_line_mapper_applies_to_expected() {
+        let mut expected = ExpectedLines::from_read(b" A \nb".as_slice(), |line, buffer| {
+            buffer.clear();
+            line.trim().to_lowercase_into(buffer);
+            buffer
+        }).unwrap();
+        expected.count_unique_in_bytes(b"a\n B");
+        assert_eq!(expected.unseen_lines().count(), 0);
+    }
+
+    #[test]
+    fn test_unseen_lines_are_original() {
+        let mut expected = ExpectedLines::from_read(b" A \nb\na\nC".as_slice(), |line, buffer| {
+            buffer.clear();
+            line.trim().to_lowercase_into(buffer);
+            buffer
+        }).unwrap();
+        expected.count_unique_in_bytes(b"b");
+        let mut unseen: Vec<&[u8]> = expected.unseen_lines().collect();
+        unseen.sort_unstable();
+        assert_eq!(unseen, [b" A ".as_slice(), b"C"]);
+    }
+}
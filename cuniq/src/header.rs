@@ -0,0 +1,137 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! The `--header` written before reports. Each header line starts with `#`, which can't be the start
+//! of a report line, as report lines start with a right-aligned count.
+
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::checksum::InputChecksum;
+use crate::constants;
+use crate::paths::os_bytes;
+
+/// Write the header. `args` is the full command line, including the program name. `preview` is the
+/// number of lines read from each input by `--preview`, if it was given. Arguments and input names
+/// are written as the OS gave them, even if they aren't UTF-8.
+pub fn write_header<W: Write>(writer: &mut W, args: impl IntoIterator<Item = OsString>, inputs: &[InputChecksum], preview: Option<usize>, generated: SystemTime) -> io::Result<()> {
+    writeln!(writer, "# cuniq {} {}", env!("CARGO_PKG_VERSION"), constants::GIT_COMMIT_HASH)?;
+    let args: Vec<Vec<u8>> = args.into_iter().map(|arg| quote_arg(os_bytes(&arg))).collect();
+    writer.write_all(b"# args: ")?;
+    writer.write_all(&args.join(&b' '))?;
+    writeln!(writer)?;
+    for input in inputs {
+        writer.write_all(b"# input: ")?;
+        writer.write_all(os_bytes(&input.name))?;
+        write!(writer, " ({} bytes, crc32 {:08x}", input.bytes, input.crc32)?;
+        if input.hashed_bytes < input.bytes {
+            write!(writer, " of first {} bytes", input.hashed_bytes)?;
+        }
+        writeln!(writer, ")")?;
+    }
+    if let Some(lines) = preview {
+        writeln!(writer, "# preview: only the first {lines} lines of each input were read")?;
+    }
+    writeln!(writer, "# generated: {}", format_timestamp(generated))
+}
+
+/// Quote an argument for a POSIX shell if it contains anything other than common safe characters
+fn quote_arg(arg: &[u8]) -> Vec<u8> {
+    let safe = |byte: &u8| byte.is_ascii_alphanumeric() || b"-_=+.,/:@%".contains(byte);
+    if !arg.is_empty() && arg.iter().all(safe) {
+        arg.to_vec()
+    } else {
+        let mut quoted = vec![b'\''];
+        for &byte in arg {
+            match byte {
+                b'\'' => quoted.extend_from_slice(br"'\''"),
+                byte => quoted.push(byte),
+            }
+        }
+        quoted.push(b'\'');
+        quoted
+    }
+}
+
+/// Format a time as an RFC 3339 UTC timestamp with second precision, such as
+/// `2024-09-16T08:30:00Z`
+pub fn format_timestamp(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+    let (year, month, day) = civil_from_days(seconds / 86400);
+    let seconds_of_day = seconds % 86400;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+    )
+}
+
+/// Convert days since the Unix epoch into a (year, month, day) date in the proleptic Gregorian
+/// calendar. This is Howard Hinnant's `civil_from_days` algorithm, restricted to dates after the
+/// epoch.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // shift the epoch to 0000-03-01, so leap days fall at the end of each year
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn timestamp(seconds: u64) -> String {
+        format_timestamp(UNIX_EPOCH + Duration::from_secs(seconds))
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(timestamp(1_726_475_400), "2024-09-16T08:30:00Z");
+        assert_eq!(timestamp(4_107_542_399), "2100-02-28T23:59:59Z");
+        assert_eq!(timestamp(4_107_542_400), "2100-03-01T00:00:00Z");
+    }
+
+    #[test]
+    fn test_quote_arg() {
+        assert_eq!(quote_arg(b"--size=10MiB"), b"--size=10MiB");
+        assert_eq!(quote_arg(b"my file.txt"), b"'my file.txt'");
+        assert_eq!(quote_arg(b"it's"), br"'it'\''s'");
+        assert_eq!(quote_arg(b""), b"''");
+        assert_eq!(quote_arg(b"caf\xe9.txt"), b"'caf\xe9.txt'");
+    }
+
+    #[test]
+    fn test_write_header() {
+        let inputs = [
+            InputChecksum { name: "stdin".into(), bytes: 4, hashed_bytes: 4, crc32: 0x1234abcd },
+            InputChecksum { name: "big.txt".into(), bytes: 2000, hashed_bytes: 1000, crc32: 0xff },
+        ];
+        let args = ["cuniq", "-c", "big.txt"].map(OsString::from);
+        let mut output = Vec::new();
+        write_header(&mut output, args.clone(), &inputs, None, UNIX_EPOCH).unwrap();
+        let expected = format!(
+            "# cuniq {} {}\n# args: cuniq -c big.txt\n# input: stdin (4 bytes, crc32 1234abcd)\n# input: big.txt (2000 bytes, crc32 000000ff of first 1000 bytes)\n# generated: 1970-01-01T00:00:00Z\n",
+            env!("CARGO_PKG_VERSION"),
+            constants::GIT_COMMIT_HASH,
+        );
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+
+        let mut output = Vec::new();
+        write_header(&mut output, args, &inputs[..1], Some(1000), UNIX_EPOCH).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("# input: stdin (4 bytes, crc32 1234abcd)\n# preview: only the first 1000 lines of each input were read\n# generated: "), "{output}");
+    }
+}
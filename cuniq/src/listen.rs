@@ -0,0 +1,143 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! `--listen`: count lines sent to a Unix domain socket by any number of producer processes
+
+use std::io::{self, BufReader, Cursor, ErrorKind, Read};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use line_cardinality::{CountUnique, Error};
+
+use crate::checksum::{ChecksumReader, Checksums};
+use crate::log_elapsed;
+use crate::logging::{log_debug, log_info, log_warn};
+use crate::paths::escaped;
+
+/// A connection that sends exactly this, and nothing else, stops the listener instead of being
+/// counted. The trailing newline is optional.
+pub const SHUTDOWN_MESSAGE: &[u8] = b"SHUTDOWN\n";
+
+/// How long to wait between checks for a new connection or a shutdown signal
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Removes the socket file when listening stops, so the next run can bind the same path
+struct SocketGuard {
+    path: PathBuf,
+}
+
+impl Drop for SocketGuard {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Listen on a Unix domain socket at `path`, and count each connection's lines into `processor` as
+/// a separate input. Connections are handled one at a time, in the order they were accepted. This
+/// returns once SIGINT or SIGTERM is received, or a connection sends [`SHUTDOWN_MESSAGE`]. A
+/// connection that fails is logged and dropped without stopping the listener, but lines it sent
+/// before failing are still counted.
+pub fn process_connections<T, F>(path: &Path, processor: &mut T, next_source: &mut F, checksums: &mut Checksums) -> Result<(), Error>
+where
+    T: CountUnique,
+    F: FnMut(&mut T),
+{
+    let stop = Arc::new(AtomicBool::new(false));
+    for signal in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        signal_hook::flag::register(signal, Arc::clone(&stop)).map_err(|e| Error::io_static("failed to register signal handler", e))?;
+    }
+
+    let listener = UnixListener::bind(path).map_err(|e| Error::io(format!("failed to listen on \"{}\"", escaped(path)), e))?;
+    let _guard = SocketGuard { path: path.to_path_buf() };
+    // accept without blocking, so a signal is noticed even if no producer ever connects
+    listener.set_nonblocking(true).map_err(|e| Error::io_static("failed to configure socket", e))?;
+    log_info!("listening on \"{}\"", escaped(path));
+
+    let mut connections: u64 = 0;
+    while !stop.load(Ordering::Relaxed) {
+        let stream = match listener.accept() {
+            Ok((stream, _address)) => stream,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => {
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(e) => {
+                // such as running out of file descriptors, which may resolve once a connection closes
+                log_warn!("failed to accept connection: {e}");
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+        connections += 1;
+        let name = format!("connection {connections}");
+        match process_connection(stream, &name, processor, next_source, checksums) {
+            Ok(true) => {
+                log_info!("{name}: received shutdown");
+                break;
+            }
+            Ok(false) => {}
+            Err(e) => log_warn!("{name}: {e}"),
+        }
+    }
+    log_info!("stopped listening after {connections} connections");
+    Ok(())
+}
+
+/// Count the lines from one connection. Returns `true` if it was a shutdown request instead.
+fn process_connection<T, F>(stream: UnixStream, name: &str, processor: &mut T, next_source: &mut F, checksums: &mut Checksums) -> Result<bool, Error>
+where
+    T: CountUnique,
+    F: FnMut(&mut T),
+{
+    // accepted sockets inherit non-blocking mode on some platforms
+    stream.set_nonblocking(false).map_err(|e| Error::io_static("failed to configure connection", e))?;
+    let prefix = read_prefix(&stream).map_err(|e| Error::io_static("failed to read from connection", e))?;
+    if is_shutdown(&prefix) {
+        return Ok(true);
+    }
+
+    log_debug!("{name}: accepted");
+    next_source(processor);
+    let start = Instant::now();
+    let reader = BufReader::new(Cursor::new(prefix).chain(stream));
+    if checksums.is_enabled() {
+        checksums.start_input(name.into());
+        processor.count_unique_in_read(ChecksumReader::new(reader, checksums))?;
+    } else {
+        processor.count_unique_in_read(reader)?;
+    }
+    log_elapsed(&name, start);
+    Ok(false)
+}
+
+/// Read just enough of a connection to tell whether it is a shutdown request. This stops early at
+/// the end of the connection, so a shutdown request is recognized as soon as it has been sent.
+fn read_prefix(mut stream: &UnixStream) -> io::Result<Vec<u8>> {
+    let mut prefix = Vec::with_capacity(SHUTDOWN_MESSAGE.len() + 1);
+    (&mut stream).take(SHUTDOWN_MESSAGE.len() as u64 + 1).read_to_end(&mut prefix)?;
+    Ok(prefix)
+}
+
+/// Whether the whole of a connection, or at least the start of it returned by [`read_prefix`], is a
+/// shutdown request. A connection with more data after the shutdown message is counted as normal.
+fn is_shutdown(prefix: &[u8]) -> bool {
+    prefix == SHUTDOWN_MESSAGE || prefix == &SHUTDOWN_MESSAGE[..SHUTDOWN_MESSAGE.len() - 1]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_shutdown() {
+        assert!(is_shutdown(b"SHUTDOWN\n"));
+        assert!(is_shutdown(b"SHUTDOWN"));
+        assert!(!is_shutdown(b"SHUTDOWN\nx"));
+        assert!(!is_shutdown(b"SHUTDOWN\r\n"));
+        assert!(!is_shutdown(b"shutdown\n"));
+        assert!(!is_shutdown(b""));
+    }
+}
@@ -0,0 +1,109 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Leveled logging to stderr. Every write to stderr must go through the macros in this module, so
+//! that `--quiet` and `--verbose` are respected everywhere.
+
+use std::fmt::Arguments;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Severity of a log message. A message is printed if its level is at or below the current level.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[repr(u8)]
+pub enum Level {
+    /// Errors that affect the exit code. These are always printed.
+    Error = 0,
+    /// Something the user probably wants to know about, but that does not stop cuniq.
+    Warn = 1,
+    /// Details about what cuniq is doing, such as the effective parameters.
+    Info = 2,
+    /// Details useful for debugging, such as timings.
+    Debug = 3,
+}
+
+impl Level {
+    /// Get the level implied by the `--quiet` and `--verbose` flags
+    pub fn from_flags(quiet: bool, verbose: u8) -> Self {
+        if quiet {
+            Level::Error
+        } else {
+            match verbose {
+                0 => Level::Warn,
+                1 => Level::Info,
+                _ => Level::Debug,
+            }
+        }
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            Level::Error => "",
+            Level::Warn => "warning: ",
+            Level::Info => "info: ",
+            Level::Debug => "debug: ",
+        }
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+
+/// Set the most verbose level that will be printed
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Whether messages at `level` are printed. Useful to skip expensive work that only feeds a log message.
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Print a message to stderr if `level` is enabled. Use the macros instead of calling this directly.
+pub fn log(level: Level, args: Arguments) {
+    if level == Level::Error || enabled(level) {
+        // there's nowhere left to report a failure to write to stderr, so it is ignored
+        let _ = writeln!(io::stderr().lock(), "{}{}", level.prefix(), args);
+    }
+}
+
+/// Print requested output to stderr whatever the level, like an error but without implying one.
+/// This is for diagnostics the user explicitly asked for, such as `--hash-diagnostics`.
+#[cfg(feature = "diagnostics")]
+macro_rules! log_always {
+    ($($arg:tt)*) => { $crate::logging::log($crate::logging::Level::Error, format_args!($($arg)*)) };
+}
+
+macro_rules! log_error {
+    ($($arg:tt)*) => { $crate::logging::log($crate::logging::Level::Error, format_args!($($arg)*)) };
+}
+
+macro_rules! log_warn {
+    ($($arg:tt)*) => { $crate::logging::log($crate::logging::Level::Warn, format_args!($($arg)*)) };
+}
+
+macro_rules! log_info {
+    ($($arg:tt)*) => { $crate::logging::log($crate::logging::Level::Info, format_args!($($arg)*)) };
+}
+
+macro_rules! log_debug {
+    ($($arg:tt)*) => { $crate::logging::log($crate::logging::Level::Debug, format_args!($($arg)*)) };
+}
+
+pub(crate) use {log_debug, log_error, log_info, log_warn};
+#[cfg(feature = "diagnostics")]
+pub(crate) use log_always;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_flags() {
+        assert_eq!(Level::from_flags(true, 0), Level::Error);
+        assert_eq!(Level::from_flags(true, 2), Level::Error);
+        assert_eq!(Level::from_flags(false, 0), Level::Warn);
+        assert_eq!(Level::from_flags(false, 1), Level::Info);
+        assert_eq!(Level::from_flags(false, 2), Level::Debug);
+        assert_eq!(Level::from_flags(false, 5), Level::Debug);
+    }
+}
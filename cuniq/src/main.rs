@@ -2,17 +2,80 @@
 // cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
 
 use std::fs::File;
-use std::io::{self, BufWriter, ErrorKind, IsTerminal, Write};
+use std::fmt::Display;
+use std::io::{self, BufRead, BufReader, ErrorKind, IsTerminal, Read, Write};
+use std::path::Path;
 use std::process::ExitCode;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
 
+use bstr::io::BufReadExt;
 use bstr::ByteSlice;
 use clap::Parser;
 
-use line_cardinality::{CountUnique, Error, ErrorCause, HashingLineCounter, HyperLogLog, InexactHashingLineCounter, LineCounter, ReportUnique};
+use line_cardinality::export::ExportWriter;
+use line_cardinality::{hll_size_for_memory, strip_ansi, strip_ansi_in_place, use_random_seeds, ContainsLine, CountUnique, DelimitedCounter, DistinctLimit, EmitLines, Error, ErrorCause, HashingLineCounter, HyperLogLog, InexactHashingLineCounter, LineCounter, LineMapper, ReportUnique, SortedLineCounter, TokenizingCounter, ASCII_WHITESPACE};
 
-use crate::cli_args::{CliArgs, Mode};
+use crate::adjacent::AdjacentLineCounter;
+use crate::case_groups::group_by_case;
+use crate::checksum::{ChecksumReader, ChecksumResults, Checksums};
+use crate::decoding::{DecodingCounter, OutputEncoder};
+use crate::cli_args::{CliArgs, Command, CountWidth, Mode, Precision, ReportFormat, SortOrder};
+use crate::expected::ExpectedLines;
+use crate::header::write_header;
+#[cfg(feature = "diagnostics")]
+use crate::logging::log_always;
+use crate::logging::{log_debug, log_error, log_info, log_warn, Level};
+use crate::metrics::RunSummary;
+use crate::ndjson::write_ndjson_line;
+use crate::number::{digits, format_estimate};
+use crate::original::OriginalLineCounter;
+use crate::output::{AtomicFileWriter, Output, TrailingNewline};
+use crate::paths::escaped;
+use crate::presence::PresenceCounter;
+use crate::preview::Preview;
+use crate::since::FirstLineCounter;
+use crate::strict_input::ForbiddenBytes;
+use crate::sort::{retain_top_by_count, sort_lines, sort_report, sort_report_by_count};
+use crate::tabular::{write_csv_row, write_tsv_row, COLUMNS};
+use crate::tabulate::{FieldCounter, TabulatingCounter};
+use crate::translate::{TranslatingCounter, Translation};
+use crate::variants::VariantCounter;
 
+mod adjacent;
+mod bench;
+mod binary;
+mod case_groups;
+mod checksum;
 mod cli_args;
+mod decoding;
+mod expectation;
+mod expected;
+mod header;
+#[cfg(unix)]
+mod listen;
+mod logging;
+mod masking;
+#[cfg(feature = "regex")]
+mod matching;
+mod metrics;
+mod ndjson;
+mod number;
+mod original;
+mod output;
+mod paths;
+mod presence;
+mod preview;
+mod since;
+mod sizing;
+mod sort;
+mod strict_input;
+mod tabular;
+mod tabulate;
+mod translate;
+mod variants;
+mod version;
+mod watch;
 
 /// constants generated in build.rs
 pub mod constants {
@@ -22,142 +85,1554 @@ pub mod constants {
 type Count = u64;
 
 /// This can happen if someone pipes our stdout into `head` or some such
-static STDOUT_ERROR_MESSAGE: &str = "failed to write to stdout";
+static OUTPUT_ERROR_MESSAGE: &str = "failed to write output";
+
+/// Exit code used when reading input or writing output fails
+const EXIT_CODE_IO_ERROR: u8 = 1;
+
+/// Exit code used when the user provided invalid arguments, such as an unsupported `--size`.
+/// This matches the exit code clap uses for argument parsing errors.
+const EXIT_CODE_USAGE_ERROR: u8 = 2;
+
+/// Exit code used when the count did not meet `--expect`
+const EXIT_CODE_EXPECTATION_FAILED: u8 = 3;
+
+/// Exit code used when `--max-distinct` was exceeded with `--strict-max-distinct`
+const EXIT_CODE_MAX_DISTINCT_EXCEEDED: u8 = 4;
+
+/// Exit code used when the input was read, but rejected, such as by `--strict-input`
+const EXIT_CODE_INPUT_REJECTED: u8 = 5;
+
+/// Number of registers used to estimate how many distinct lines `--max-distinct` rejected. This
+/// takes 4KiB, and has a standard error of under 2%.
+const MAX_DISTINCT_OVERFLOW_REGISTERS: usize = 4096;
+
+/// How many bytes at the start of each input are checksummed for `--header`, unless
+/// `--header-full-checksum` is given
+const HEADER_CHECKSUM_BYTES: u64 = 1024 * 1024;
+
+/// How many standard errors `--expect-conservative` widens the expected range by. A correct
+/// estimate is within three standard errors of the true count over 99% of the time.
+const EXPECT_CONSERVATIVE_STANDARD_ERRORS: f64 = 3.0;
+
+/// How many lines `--hash-diagnostics` looks up again to measure probe distances. Spread through
+/// the whole table, this is plenty to see clustering, while keeping the lookups quick.
+#[cfg(feature = "diagnostics")]
+const HASH_DIAGNOSTICS_SAMPLE: usize = 10_000;
+
+// Case mappings that can be applied by preprocess_line. These are used as a const generic parameter,
+// which can't be an enum.
+/// Case is left unchanged
+const CASE_NONE: u8 = 0;
+/// `--lower`
+const CASE_LOWER: u8 = 1;
+/// `--fold simple`
+const CASE_FOLD_SIMPLE: u8 = 2;
+/// `--fold full`
+const CASE_FOLD_FULL: u8 = 3;
+
+/// The line mapper for `--trim`, the case options, and `--strip-ansi`. Only [`preprocess_line`] is
+/// compiled for each combination of them. The modes take it as a function pointer, so each mode is
+/// only compiled once.
+type Preprocess = for<'a> fn(&'a [u8], &'a mut Vec<u8>) -> &'a [u8];
 
 fn main() -> ExitCode {
-    let args = CliArgs::parse();
-    match (args.trim, args.lowercase) {
-        (false, false) => run_with_const_parameters::<false, false>(args),
-        (false, true) => run_with_const_parameters::<false, true>(args),
-        (true, false) => run_with_const_parameters::<true, false>(args),
-        (true, true) => run_with_const_parameters::<true, true>(args),
+    let args = match cli_args::expand_argfiles(std::env::args_os()) {
+        Ok(args) => CliArgs::parse_from(args),
+        Err(e) => return exit_code_for(Err(e)),
+    };
+    logging::set_level(Level::from_flags(args.quiet, args.verbose));
+    if args.version_json {
+        let result = version::write_version_json(&mut io::stdout().lock()).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e));
+        return exit_code_for(result.map(|()| Outcome::Success));
     }
+    if let Some(Command::Bench(bench_args)) = &args.command {
+        return exit_code_for(bench::run(bench_args).map(|()| Outcome::Success));
+    }
+    let preprocess = preprocessor(&args);
+    run(args, preprocess)
 }
 
-fn run_with_const_parameters<const TRIM: bool, const LOWERCASE: bool>(args: CliArgs) -> ExitCode {
-    let result = if args.report {
-        report::<TRIM, LOWERCASE>(args)
-    } else {
-        count::<TRIM, LOWERCASE>(args)
-    };
-    if let Err(e) = result {
-        match e.get_cause() {
-            ErrorCause::Io(cause) => {
-                match cause.kind() {
-                    ErrorKind::BrokenPipe => (),
-                    _ => eprintln!("{e}: {cause:?}"),
+fn run(args: CliArgs, preprocess: Preprocess) -> ExitCode {
+    let result = validate_args(&args).and_then(|()| {
+        warn_ignored_args(&args);
+        log_info!("mode: {}, trim: {}, case: {}, strip ansi: {}", args.mode, args.trim, case_name(case(&args)), args.strip_ansi);
+        // estimate mode logs its register count when it is created
+        if let (Some(expected), Mode::Exact | Mode::NearExact) = (args.expected_cardinality, args.mode) {
+            log_info!("--expected-cardinality {expected}: initial hash table capacity of {}", sizing::hash_table_capacity(expected));
+        }
+        if args.random_seed {
+            use_random_seeds();
+            if !matches!(args.mode, Mode::Exact) {
+                log_warn!("--random-seed: {} mode results may differ between runs on the same input", args.mode);
+            }
+        }
+        if args.text_only && !args.binary_ok {
+            check_text_input(&args)?;
+        }
+        if args.only_in {
+            only_in(args, preprocess).map(|()| Outcome::Success)
+        } else if args.report_unseen {
+            report_unseen(args, preprocess).map(|()| Outcome::Success)
+        } else if args.inconsistencies {
+            inconsistencies(args, preprocess).map(|()| Outcome::Success)
+        } else if args.assume_sorted {
+            count_sorted(args, preprocess)
+        } else if args.adjacent {
+            count_adjacent(args, preprocess)
+        } else if args.tabulate {
+            tabulate(args, preprocess).map(|()| Outcome::Success)
+        } else if args.report || args.duplicates || args.unique_only {
+            report(args, preprocess)
+        } else if args.unique {
+            unique(args, preprocess).map(|()| Outcome::Success)
+        } else if let Some(seconds) = args.watch {
+            watch(args, preprocess, Duration::from_secs(seconds)).map(|()| Outcome::Success)
+        } else {
+            count(args, preprocess)
+        }
+    });
+    exit_code_for(result)
+}
+
+/// Report how a run ended, and choose the process exit code
+fn exit_code_for(result: Result<Outcome, Error>) -> ExitCode {
+    match result {
+        Ok(Outcome::Success) => ExitCode::SUCCESS,
+        Ok(Outcome::ExpectationFailed) => ExitCode::from(EXIT_CODE_EXPECTATION_FAILED),
+        Ok(Outcome::MaxDistinctExceeded) => ExitCode::from(EXIT_CODE_MAX_DISTINCT_EXCEEDED),
+        Err(e) => {
+            match e.get_cause() {
+                ErrorCause::Io(cause) => {
+                    match cause.kind() {
+                        ErrorKind::BrokenPipe => (),
+                        _ => log_error!("{e}: {cause:?}"),
+                    }
                 }
+                ErrorCause::Size(_) | ErrorCause::User | ErrorCause::Input => log_error!("{e}"),
             }
-            ErrorCause::Size(_) | ErrorCause::User => eprintln!("{e}"),
+            ExitCode::from(exit_code(e.get_cause()))
         }
-        ExitCode::FAILURE
-    } else {
-        ExitCode::SUCCESS
     }
 }
 
-fn report<const TRIM: bool, const LOWERCASE: bool>(args: CliArgs) -> Result<(), Error> {
+/// How a run that did not fail with an [`Error`] ended
+enum Outcome {
+    Success,
+    /// the count did not meet `--expect`
+    ExpectationFailed,
+    /// `--max-distinct` was exceeded, with `--strict-max-distinct`
+    MaxDistinctExceeded,
+}
+
+/// Map an error cause to the exit code documented in [`cli_args::EXIT_STATUS_HELP`]
+fn exit_code(cause: &ErrorCause) -> u8 {
+    match cause {
+        ErrorCause::Io(_) => EXIT_CODE_IO_ERROR,
+        ErrorCause::Size(_) | ErrorCause::User => EXIT_CODE_USAGE_ERROR,
+        ErrorCause::Input => EXIT_CODE_INPUT_REJECTED,
+    }
+}
+
+/// Check for argument combinations that clap can't validate for us
+fn validate_args(args: &CliArgs) -> Result<(), Error> {
+    if args.precision != Precision::ExactInteger && !matches!(args.mode, Mode::Estimate) {
+        return Err(Error::message(format!("--precision is not supported in {} mode", args.mode)));
+    }
+    if args.size_memory.is_some() && !matches!(args.mode, Mode::Estimate) {
+        return Err(Error::message(format!("--size-memory is not supported in {} mode", args.mode)));
+    }
+    if args.unique && !args.stream && !matches!(args.mode, Mode::Exact) {
+        return Err(Error::message(format!("--unique is not supported in {} mode without --stream", args.mode)));
+    }
+    if args.stream && matches!(args.mode, Mode::Estimate) {
+        return Err(Error::message(format!("--stream is not supported in {} mode", args.mode)));
+    }
+    if args.only_in && !matches!(args.mode, Mode::Exact) {
+        return Err(Error::message(format!("--only-in is not supported in {} mode", args.mode)));
+    }
+    if args.assume_sorted && !matches!(args.mode, Mode::Exact) {
+        return Err(Error::message(format!("--assume-sorted is not supported in {} mode", args.mode)));
+    }
+    if args.adjacent && !matches!(args.mode, Mode::Exact) {
+        return Err(Error::message(format!("--adjacent is not supported in {} mode", args.mode)));
+    }
+    if args.report_unseen && !matches!(args.mode, Mode::Exact) {
+        return Err(Error::message(format!("--report-unseen is not supported in {} mode", args.mode)));
+    }
+    if args.inconsistencies && !matches!(args.mode, Mode::Exact) {
+        return Err(Error::message(format!("--inconsistencies is not supported in {} mode", args.mode)));
+    }
+    if args.hot_cache.is_some() && !matches!(args.mode, Mode::Exact) {
+        return Err(Error::message(format!("--hot-cache is not supported in {} mode", args.mode)));
+    }
+    if args.stats && !matches!(args.mode, Mode::Exact) {
+        return Err(Error::message(format!("--stats is not supported in {} mode", args.mode)));
+    }
+    if args.export.is_some() && !matches!(args.mode, Mode::Exact) {
+        return Err(Error::message(format!("--export is not supported in {} mode", args.mode)));
+    }
+    if args.max_distinct.is_some() && matches!(args.mode, Mode::Estimate) {
+        return Err(Error::message(format!("--max-distinct is not supported in {} mode", args.mode)));
+    }
+    if args.cap.is_some() && matches!(args.mode, Mode::Estimate) {
+        return Err(Error::message(format!("--cap is not supported in {} mode", args.mode)));
+    }
+    if args.ignore_bytes.is_some() && !matches!(args.mode, Mode::Exact) {
+        return Err(Error::message(format!("--ignore-bytes is not supported in {} mode", args.mode)));
+    }
+    if args.duplicates && !matches!(args.mode, Mode::Exact) {
+        return Err(Error::message(format!("--duplicates is not supported in {} mode", args.mode)));
+    }
+    if args.unique_only && !matches!(args.mode, Mode::Exact) {
+        return Err(Error::message(format!("--unique-only is not supported in {} mode", args.mode)));
+    }
+    if args.since_line.is_some() && !(args.report || args.duplicates || args.unique_only) {
+        return Err(Error::message_static("--since-line requires --report, --duplicates, or --unique-only"));
+    }
+    if args.since_line.is_some() && !matches!(args.mode, Mode::Exact) {
+        return Err(Error::message(format!("--since-line is not supported in {} mode", args.mode)));
+    }
+    if (args.min_count.is_some() || args.max_count.is_some()) && !matches!(args.mode, Mode::Exact) {
+        return Err(Error::message(format!("--min-count and --max-count are not supported in {} mode", args.mode)));
+    }
+    if args.text_only && record_delimiter(args).is_some_and(|delimiter| delimiter.contains(&0)) {
+        return Err(Error::message_static("--text-only treats NUL bytes as binary, so it can't be used with a --delimiter-str containing one"));
+    }
+    if args.top.is_some() && !matches!(args.mode, Mode::Exact) {
+        return Err(Error::message(format!("--top is not supported in {} mode", args.mode)));
+    }
+    if let (Some(min), Some(max)) = (args.min_count, args.max_count) {
+        if min > max {
+            return Err(Error::message(format!("--min-count {min} is greater than --max-count {max}, so no lines would be reported")));
+        }
+    }
+    if args.hash_diagnostics && !matches!(args.mode, Mode::Exact) {
+        return Err(Error::message(format!("--hash-diagnostics is not supported in {} mode", args.mode)));
+    }
+    if args.hash_diagnostics && args.strip_ansi && (args.report || args.duplicates || args.unique_only) {
+        return Err(Error::message_static("--hash-diagnostics is not supported with --strip-ansi when printing lines"));
+    }
+    if args.dup_stats && matches!(args.mode, Mode::Estimate) {
+        return Err(Error::message(format!("--dup-stats is not supported in {} mode, which can't count distinct lines exactly", args.mode)));
+    }
+    if args.tabulate && args.fields.len() != 2 {
+        return Err(Error::message(format!("--tabulate requires exactly two --field arguments, but {} were given", args.fields.len())));
+    }
+    if args.group_case && args.format != ReportFormat::Text {
+        return Err(Error::message(format!("--group-case is not supported with --format {}", args.format)));
+    }
+    if args.zero_terminated && args.format == ReportFormat::Ndjson {
+        return Err(Error::message_static("--zero-terminated is not supported with --format ndjson, which is always newline delimited"));
+    }
+    if args.header && args.format == ReportFormat::Ndjson {
+        return Err(Error::message_static("--header is not supported with --format ndjson"));
+    }
+    if args.header_row && !matches!(args.format, ReportFormat::Tsv | ReportFormat::Csv) {
+        return Err(Error::message(format!("--header-row is only supported with --format tsv or --format csv, not --format {}", args.format)));
+    }
+    if args.word_delimiters.as_ref().is_some_and(|delimiters| !delimiters.is_ascii()) {
+        return Err(Error::message_static("--word-delimiters only supports ASCII characters"));
+    }
+    if args.listen.is_some() && cfg!(not(unix)) {
+        return Err(Error::message_static("--listen is only supported on unix platforms"));
+    }
+    if (args.fold.is_some() || args.fold_case) && cfg!(not(feature = "unicode")) {
+        return Err(Error::message_static("This cuniq binary was compiled without unicode support, which is required for --fold and --fold-case"));
+    }
+    if args.match_regex.is_some() && cfg!(not(feature = "regex")) {
+        return Err(Error::message_static("This cuniq binary was compiled without regex support, which is required for --match"));
+    }
+    if args.hash_diagnostics && cfg!(not(feature = "diagnostics")) {
+        return Err(Error::message_static("This cuniq binary was compiled without diagnostics support, which is required for --hash-diagnostics"));
+    }
+    Ok(())
+}
+
+/// Warn about arguments that are valid but will not do anything, or are deprecated
+fn warn_ignored_args(args: &CliArgs) {
+    if args.size.is_some() {
+        log_warn!("--size is deprecated, use --expected-cardinality or --size-memory instead");
+    }
+    let lines_output = args.report || args.duplicates || args.unique_only || args.unique || args.only_in || args.report_unseen || args.inconsistencies;
+    if args.sort.is_some() && !lines_output {
+        log_warn!("--sort has no effect without --report, --duplicates, --unique-only, --unique, --only-in, or --report-unseen");
+    }
+    if args.reverse && !sorted_report(args) {
+        log_warn!("--reverse has no effect without --sort, --sort-by-count, or --top");
+    }
+    if args.output.is_some() && !lines_output {
+        log_warn!("--output has no effect without --report, --duplicates, --unique-only, --unique, --only-in, --report-unseen, or --inconsistencies");
+    }
+    if args.reencode && !lines_output {
+        log_warn!("--reencode has no effect without --report, --duplicates, --unique-only, --unique, or --only-in");
+    }
+    if args.inconsistencies && !args.trim && !args.lowercase && args.fold.is_none() && !args.fold_case && !args.strip_ansi {
+        log_warn!("--inconsistencies has nothing to find without --trim, --lower, --fold, or --strip-ansi");
+    }
+    if args.expect_conservative && !matches!(args.mode, Mode::Estimate) {
+        log_warn!("--expect-conservative has no effect in {} mode", args.mode);
+    }
+    if args.threads.is_some() {
+        log_warn!("--threads is not implemented yet, so counting is single-threaded");
+    }
+    if args.hot_cache.is_some() && args.unique && !args.stream && args.strip_ansi {
+        log_warn!("--hot-cache has no effect with --unique and --strip-ansi");
+    }
+    if args.count_width.is_some() && args.format != ReportFormat::Text {
+        log_warn!("--count-width has no effect with --format {}", args.format);
+    }
+    if args.no_pad && args.format != ReportFormat::Text {
+        log_warn!("--no-pad has no effect with --format {}", args.format);
+    }
+    if args.output_delimiter.is_some() && args.format != ReportFormat::Text {
+        log_warn!("--output-delimiter has no effect with --format {}", args.format);
+    }
+    if args.count_last && args.format != ReportFormat::Text {
+        log_warn!("--count-last has no effect with --format {}", args.format);
+    }
+    if args.binary_ok && !args.text_only {
+        log_warn!("--binary-ok has no effect without --text-only");
+    }
+    if args.metrics_prefix != "cuniq" && !metrics_requested(args) {
+        log_warn!("--metrics-prefix has no effect without --metrics-file or --statsd");
+    }
+    if !args.metrics_label.is_empty() && args.metrics_file.is_none() {
+        log_warn!("--metrics-label has no effect without --metrics-file");
+    }
+}
+
+/// The initial hash table capacity for "exact" and "near-exact" mode, from `--expected-cardinality`
+/// or the deprecated `--size`
+fn hash_table_capacity(args: &CliArgs) -> usize {
+    match args.expected_cardinality {
+        Some(expected) => sizing::hash_table_capacity(expected),
+        None => args.size.unwrap_or(0),
+    }
+}
+
+/// Apply `--hot-cache` to a counter that doesn't track occurrence counts
+fn with_hot_cache<M: LineMapper>(args: &CliArgs, processor: LineCounter<M>) -> LineCounter<M> {
+    match args.hot_cache {
+        Some(slots) => processor.with_hot_cache(slots),
+        None => processor,
+    }
+}
+
+fn report(args: CliArgs, preprocess: Preprocess) -> Result<Outcome, Error> {
     match args.mode {
+        #[cfg(feature = "memmap")]
+        Mode::Exact if can_borrow_keys(&args) => {
+            report_borrowed(&args, &args.files[0]).map(|()| Outcome::Success)
+        }
+        Mode::Exact if args.since_line.is_some() => report_since(args, preprocess),
+        Mode::Exact if args.strip_ansi || args.ignore_bytes.is_some() => report_original(args, preprocess),
         Mode::Exact => {
-            let mut processor = HashingLineCounter::<Count, _>::with_line_mapper_and_capacity(preprocess_line::<TRIM, LOWERCASE>, args.size.unwrap_or(0));
-            process_input(&args, &mut processor)?;
-            let stdout = io::stdout().lock();
-            let mut writer = BufWriter::new(stdout);
-            if args.sort {
+            let processor = HashingLineCounter::<Count, _>::with_line_mapper_and_capacity(preprocess, hash_table_capacity(&args));
+            // opened before processing input so that we can abort early if the output is not writable
+            let mut writer = open_output(&args)?;
+            let terminator = line_terminator(&args);
+            let export = open_export(&args)?;
+            let Processed { processor, limit_exceeded, checksums } = process_input_limited(&args, preprocess, processor)?;
+            maybe_log_hash_diagnostics(&args, &processor);
+            write_export(&args, export, processor.count(), processor.iter().map(|(line, count)| (line, *count)))?;
+            maybe_write_header(&args, &mut writer, &checksums)?;
+            maybe_write_header_row(&args, &mut writer, terminator)?;
+            let width = count_width(&args, || processor.iter().map(|(_line, count)| *count).max());
+            let mut encoder = output_encoder(&args);
+            if args.group_case {
+                write_case_groups(&args, &mut writer, processor.iter().map(|(line, count)| (line, *count)).filter(|(_line, count)| reported(&args, *count)))?;
+                writer.finish()?;
+                std::mem::forget(processor); // same explanation as below
+            } else if sorted_report(&args) {
                 let mut report = processor.to_report_vec();
-                report.sort_unstable_by(|(a, _), (b, _)| a.as_slice().as_bstr().cmp(b.as_slice().as_bstr()));
+                report.retain(|(_line, count)| reported(&args, *count));
+                sort_report_entries(&args, &mut report);
                 for (line, count) in report.iter() {
-                    write_line(&mut writer, line, count)?;
+                    write_entry(&args, &mut writer, width, encoder.encode(line), count, terminator)?;
                 }
-                writer.flush().map_err(|e| Error::io_static(STDOUT_ERROR_MESSAGE, e))?;
+                writer.finish()?;
                 std::mem::forget(report); // same explanation as below
             } else {
-                for (line, count) in &processor {
-                    write_line(&mut writer, line, count)?;
+                for (line, count) in processor.iter().filter(|(_line, count)| reported(&args, **count)) {
+                    write_entry(&args, &mut writer, width, encoder.encode(line), count, terminator)?;
                 }
-                writer.flush().map_err(|e| Error::io_static(STDOUT_ERROR_MESSAGE, e))?;
+                writer.finish()?;
 
                 // leak the hash map and prevent Drop (and its destructor) from being run.
                 // This is useful because cleaning up the hash set takes a significant amount of time, and the
                 // OS is going to do it for us regardless.
                 std::mem::forget(processor);
             }
-            Ok(())
+            Ok(max_distinct_outcome(&args, limit_exceeded))
         }
         _ => Err(Error::message(format!("{} mode cannot generate cardinality reports", args.mode))),
     }
 }
 
-#[inline(always)]
-fn write_line<T: Write>(writer: &mut T, line: &[u8], count: &Count) -> Result<(), Error> {
-    write!(writer, "{count:7} ").map_err(|e| Error::io_static(STDOUT_ERROR_MESSAGE, e))?;
-    writer.write_all(line).map_err(|e| Error::io_static(STDOUT_ERROR_MESSAGE, e))?;
-    writeln!(writer).map_err(|e| Error::io_static(STDOUT_ERROR_MESSAGE, e))
+/// Report on lines as they first appeared in the input, rather than after line mapping. This is
+/// used for `--strip-ansi` and `--ignore-bytes`, where the mapped line has lost the escape sequences
+/// or columns the user expects to see.
+fn report_original(args: CliArgs, preprocess: Preprocess) -> Result<Outcome, Error> {
+    let processor = OriginalLineCounter::with_line_mapper_and_capacity(preprocess, hash_table_capacity(&args))
+        .with_ignored_bytes(args.ignore_bytes.clone());
+    let mut writer = open_output(&args)?;
+    let terminator = line_terminator(&args);
+    let export = open_export(&args)?;
+    let Processed { processor, limit_exceeded, checksums } = process_input_limited(&args, preprocess, processor)?;
+    write_export(&args, export, processor.count(), processor.iter())?;
+    maybe_write_header(&args, &mut writer, &checksums)?;
+    maybe_write_header_row(&args, &mut writer, terminator)?;
+    let width = count_width(&args, || processor.iter().map(|(_line, count)| count).max());
+    let mut encoder = output_encoder(&args);
+    let entries = processor.iter().filter(|(_line, count)| reported(&args, *count));
+    if args.group_case {
+        write_case_groups(&args, &mut writer, entries)?;
+    } else if sorted_report(&args) {
+        let mut report: Vec<(&[u8], Count)> = entries.collect();
+        sort_report_entries(&args, &mut report);
+        for (line, count) in report.iter() {
+            write_entry(&args, &mut writer, width, encoder.encode(line), count, terminator)?;
+        }
+    } else {
+        for (line, count) in entries {
+            write_entry(&args, &mut writer, width, encoder.encode(line), &count, terminator)?;
+        }
+    }
+    writer.finish()?;
+    std::mem::forget(processor); // same explanation as in report()
+    Ok(max_distinct_outcome(&args, limit_exceeded))
 }
 
-fn count<const TRIM: bool, const LOWERCASE: bool>(args: CliArgs) -> Result<(), Error> {
+/// Report on only the lines first seen on or after `--since-line`, with their counts over all of
+/// the input
+fn report_since(args: CliArgs, preprocess: Preprocess) -> Result<Outcome, Error> {
+    let since = args.since_line.expect("checked by report()");
+    let mut processor = FirstLineCounter::with_line_mapper_and_capacity(preprocess, hash_table_capacity(&args));
+    let mut writer = open_output(&args)?;
+    let terminator = line_terminator(&args);
+    let checksums = process_input(&args, preprocess, &mut processor)?;
+    maybe_write_header(&args, &mut writer, &checksums)?;
+    maybe_write_header_row(&args, &mut writer, terminator)?;
+    let width = count_width(&args, || processor.iter_since(since).map(|(_line, count)| count).max());
+    let mut encoder = output_encoder(&args);
+    let entries = processor.iter_since(since).filter(|(_line, count)| reported(&args, *count));
+    if args.group_case {
+        write_case_groups(&args, &mut writer, entries)?;
+    } else if sorted_report(&args) {
+        let mut report: Vec<(&[u8], Count)> = entries.collect();
+        sort_report_entries(&args, &mut report);
+        for (line, count) in report.iter() {
+            write_entry(&args, &mut writer, width, encoder.encode(line), count, terminator)?;
+        }
+    } else {
+        for (line, count) in entries {
+            write_entry(&args, &mut writer, width, encoder.encode(line), &count, terminator)?;
+        }
+    }
+    writer.finish()?;
+    std::mem::forget(processor); // same explanation as in report()
+    Ok(Outcome::Success)
+}
+
+/// Print each distinct line once
+fn unique(args: CliArgs, preprocess: Preprocess) -> Result<(), Error> {
+    if args.stream {
+        return match args.mode {
+            Mode::Exact => {
+                let mut processor = with_hot_cache(&args, LineCounter::with_line_mapper_and_capacity(preprocess, hash_table_capacity(&args)));
+                stream_unique(&args, &mut processor)?;
+                std::mem::forget(processor); // same explanation as in report()
+                Ok(())
+            }
+            Mode::NearExact => {
+                let mut processor = InexactHashingLineCounter::with_line_mapper_and_capacity(preprocess, hash_table_capacity(&args));
+                stream_unique(&args, &mut processor)?;
+                std::mem::forget(processor); // same explanation as in report()
+                Ok(())
+            }
+            Mode::Estimate => unreachable!("rejected by validate_args"),
+        };
+    }
+
+    let mut writer = open_output(&args)?;
+
+    let terminator = line_terminator(&args);
+    let mut encoder = output_encoder(&args);
+    if args.strip_ansi || args.ignore_bytes.is_some() {
+        // print lines as they first appeared, as in report_original()
+        let mut processor = OriginalLineCounter::with_line_mapper_and_capacity(preprocess, hash_table_capacity(&args))
+            .with_ignored_bytes(args.ignore_bytes.clone());
+        process_input(&args, preprocess, &mut processor)?;
+        let mut lines: Vec<&[u8]> = processor.iter().map(|(line, _count)| line).collect();
+        if let Some(order) = args.sort {
+            sort_output_lines(&args, &mut lines, order);
+        }
+        for line in lines {
+            write_bare_line(&mut writer, encoder.encode(line), terminator)?;
+        }
+        std::mem::forget(processor); // same explanation as in report()
+    } else {
+        let mut processor = with_hot_cache(&args, LineCounter::with_line_mapper_and_capacity(preprocess, hash_table_capacity(&args)));
+        process_input(&args, preprocess, &mut processor)?;
+        if args.sort == Some(SortOrder::Bytes) && !args.reverse && !encoder.is_enabled() {
+            processor.write_sorted_lines(&mut writer, terminator).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+        } else if let Some(order) = args.sort {
+            let mut lines = processor.into_vec();
+            sort_output_lines(&args, &mut lines, order);
+            for line in lines {
+                write_bare_line(&mut writer, encoder.encode(&line), terminator)?;
+            }
+        } else {
+            let mut result = Ok(());
+            processor.for_each_line(|line| {
+                if result.is_ok() {
+                    result = write_bare_line(&mut writer, encoder.encode(line), terminator);
+                }
+            });
+            result?;
+            std::mem::forget(processor); // same explanation as in report()
+        }
+    }
+    writer.finish()
+}
+
+/// Print each distinct line to stdout as soon as it is first seen. Files are read with buffered
+/// reads, so that output begins before a file has been completely read.
+fn stream_unique<T: CountUnique>(args: &CliArgs, processor: &mut T) -> Result<(), Error> {
+    check_files(args)?;
+    // stdout is line buffered, so each line is visible downstream as soon as it is written. Without
+    // a trailing newline each line only becomes visible once the next one starts.
+    let mut writer = TrailingNewline::new(io::stdout().lock(), !args.no_trailing_newline);
+    if stdin_used(args) {
+        log_info!("input: stdin");
+        let start = Instant::now();
+        stream_unique_in_read(processor, stdin_reader(), &mut writer)?;
+        log_elapsed(&"stdin", start);
+    }
+    for path in &args.files {
+        let file = open_file(path)?;
+        let start = Instant::now();
+        stream_unique_in_read(processor, BufReader::new(file), &mut writer)?;
+        log_elapsed(&escaped(path), start);
+    }
+    writer.flush().map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))
+}
+
+/// Count lines from `reader`, writing each new line to `writer`. Reading stops as soon as a write
+/// fails, which is how we stop early when downstream closes the pipe.
+fn stream_unique_in_read<T: CountUnique, R: BufRead, W: Write>(processor: &mut T, mut reader: R, writer: &mut W) -> Result<(), Error> {
+    let mut output_result = Ok(());
+    reader.for_byte_line(|line| {
+        processor.count_line_with(line, |line| output_result = write_bare_line(writer, line, b'\n'));
+        Ok(output_result.is_ok())
+    }).map_err(|e| Error::io_static("failed to read from buffer", e))?;
+    output_result
+}
+
+/// Print the distinct count every `interval` while counting
+fn watch(args: CliArgs, preprocess: Preprocess, interval: Duration) -> Result<(), Error> {
+    check_files(&args)?;
+    let read_stdin = stdin_used(&args);
+    let mut interval_counter = args.rolling.then(|| InexactHashingLineCounter::with_line_mapper(preprocess));
+    let mut writer = io::stdout().lock();
     match args.mode {
         Mode::Exact => {
-            let mut processor = LineCounter::with_line_mapper_and_capacity(preprocess_line::<TRIM, LOWERCASE>, args.size.unwrap_or(0));
-            process_input(&args, &mut processor)?;
-            println!("{}", processor.count());
-            std::mem::forget(processor); // same explanation as above
+            let mut processor = with_hot_cache(&args, LineCounter::with_line_mapper_and_capacity(preprocess, hash_table_capacity(&args)));
+            watch::watch(read_stdin, args.files, interval, &mut processor, interval_counter.as_mut(), &mut writer)?;
+            std::mem::forget(processor); // same explanation as in report()
         }
         Mode::NearExact => {
-            let mut processor = InexactHashingLineCounter::with_line_mapper_and_capacity(preprocess_line::<TRIM, LOWERCASE>, args.size.unwrap_or(0));
-            process_input(&args, &mut processor)?;
-            println!("{}", processor.count());
+            let mut processor = InexactHashingLineCounter::with_line_mapper_and_capacity(preprocess, hash_table_capacity(&args));
+            watch::watch(read_stdin, args.files, interval, &mut processor, interval_counter.as_mut(), &mut writer)?;
+            std::mem::forget(processor); // same explanation as in report()
+        }
+        Mode::Estimate => {
+            let mut processor = estimator(&args, preprocess)?;
+            watch::watch(read_stdin, args.files, interval, &mut processor, interval_counter.as_mut(), &mut writer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Print lines that were found in exactly one input
+fn only_in(args: CliArgs, preprocess: Preprocess) -> Result<(), Error> {
+    let mut processor = PresenceCounter::with_line_mapper_and_capacity(preprocess, hash_table_capacity(&args));
+    let mut writer = open_output(&args)?;
+    let terminator = line_terminator(&args);
+    process_input_with_sources(&args, preprocess, &mut processor, PresenceCounter::next_source)?;
+    let mut encoder = output_encoder(&args);
+    if let Some(order) = args.sort {
+        let mut lines: Vec<&[u8]> = processor.lines_in_single_source().collect();
+        sort_output_lines(&args, &mut lines, order);
+        for line in lines {
+            write_bare_line(&mut writer, encoder.encode(line), terminator)?;
+        }
+    } else {
+        for line in processor.lines_in_single_source() {
+            write_bare_line(&mut writer, encoder.encode(line), terminator)?;
+        }
+    }
+    writer.finish()?;
+    std::mem::forget(processor); // same explanation as in report()
+    Ok(())
+}
+
+/// Print expected lines that were not found in the input
+fn report_unseen(args: CliArgs, preprocess: Preprocess) -> Result<(), Error> {
+    let path = args.expected.as_deref().expect("clap requires --expected with --report-unseen");
+    let expected_file = open_file(path)?;
+    let mut processor = ExpectedLines::from_read(BufReader::new(expected_file), preprocess)?;
+    let mut writer = open_output(&args)?;
+    let terminator = line_terminator(&args);
+    process_input(&args, preprocess, &mut processor)?;
+    if let Some(order) = args.sort {
+        let mut lines: Vec<&[u8]> = processor.unseen_lines().collect();
+        sort_output_lines(&args, &mut lines, order);
+        for line in lines {
+            write_bare_line(&mut writer, line, terminator)?;
+        }
+    } else {
+        for line in processor.unseen_lines() {
+            write_bare_line(&mut writer, line, terminator)?;
+        }
+    }
+    writer.finish()?;
+    std::mem::forget(processor); // same explanation as in report()
+    Ok(())
+}
+
+/// Print lines that were found in more than one original form, with each form and its count
+fn inconsistencies(args: CliArgs, preprocess: Preprocess) -> Result<(), Error> {
+    let mut processor = VariantCounter::with_line_mapper_and_capacity(preprocess, args.max_variants, hash_table_capacity(&args));
+    let mut writer = open_output(&args)?;
+    let terminator = line_terminator(&args);
+    process_input(&args, preprocess, &mut processor)?;
+    let inconsistencies = processor.inconsistencies();
+    let width = count_width(&args, || {
+        inconsistencies.iter()
+            .flat_map(|(_line, variants)| variants.forms().into_iter().map(|(_form, count)| count).chain([variants.other()]))
+            .max()
+    });
+    for (line, variants) in &inconsistencies {
+        write_bare_line(&mut writer, line, terminator)?;
+        for (form, count) in variants.forms() {
+            writer.write_all(b"\t").map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+            write_line(&mut writer, ReportLayout::TEXT, width, form, &count, terminator)?;
+        }
+        if variants.other() != 0 {
+            writer.write_all(b"\t").map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+            write_line(&mut writer, ReportLayout::TEXT, width, b"...", &variants.other(), terminator)?;
+        }
+    }
+    writer.finish()?;
+    std::mem::forget(processor); // same explanation as in report()
+    Ok(())
+}
+
+/// Write a `--group-case` report of `entries`: each group's total count and lowercase form, followed
+/// by a tab-indented report line for each of its variants
+fn write_case_groups<'a, T, I>(args: &CliArgs, writer: &mut T, entries: I) -> Result<(), Error>
+where
+    T: Write,
+    I: IntoIterator<Item = (&'a [u8], Count)>,
+{
+    let groups = group_by_case(entries);
+    let terminator = line_terminator(args);
+    let width = count_width(args, || groups.iter().map(|group| group.subtotal).max());
+    let layout = report_layout(args);
+    for group in &groups {
+        write_line(writer, layout, width, &group.key, &group.subtotal, terminator)?;
+        for (line, count) in &group.variants {
+            writer.write_all(b"\t").map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+            write_line(writer, layout, width, line, count, terminator)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether `--report` entries are collected and sorted before they are printed, rather than printed
+/// in hash table order
+fn sorted_report(args: &CliArgs) -> bool {
+    args.sort.is_some() || args.sort_by_count || args.top.is_some()
+}
+
+/// Keep only the `--top` entries if it was given, then sort report entries by `--sort`, or by count
+/// otherwise, then apply `--reverse`
+fn sort_report_entries<L: AsRef<[u8]>>(args: &CliArgs, report: &mut Vec<(L, Count)>) {
+    if let Some(n) = args.top {
+        retain_top_by_count(report, n);
+    }
+    match args.sort {
+        Some(order) => sort_report(report, order),
+        None => sort_report_by_count(report),
+    }
+    if args.reverse {
+        report.reverse();
+    }
+}
+
+/// Sort lines printed without counts by `order`, then apply `--reverse`
+fn sort_output_lines<L: AsRef<[u8]>>(args: &CliArgs, lines: &mut [L], order: SortOrder) {
+    sort_lines(lines, order);
+    if args.reverse {
+        lines.reverse();
+    }
+}
+
+#[inline(always)]
+fn write_bare_line<T: Write>(writer: &mut T, line: &[u8], terminator: u8) -> Result<(), Error> {
+    writer.write_all(line).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+    writer.write_all(&[terminator]).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))
+}
+
+/// The byte each line of output ends with
+fn line_terminator(args: &CliArgs) -> u8 {
+    if args.zero_terminated { b'\0' } else { b'\n' }
+}
+
+/// The bytes input is split into records on, if not newlines
+fn record_delimiter(args: &CliArgs) -> Option<&[u8]> {
+    if args.zero_terminated { Some(b"\0") } else { args.delimiter_str.as_deref() }
+}
+
+/// Report on a single memmapped file without copying each line. This is only possible when no line
+/// mapping is done, as otherwise the map keys would not be slices of the file.
+#[cfg(feature = "memmap")]
+fn report_borrowed(args: &CliArgs, path: &Path) -> Result<(), Error> {
+    use line_cardinality::BorrowedLineCounter;
+
+    let file = open_file(path)?;
+    let mut writer = open_output(args)?;
+    let terminator = line_terminator(args);
+    // the map borrows from this, so it must stay alive until output is complete
+    log_input_file(path, &file);
+    log_info!("reading files via memmap");
+    let start = Instant::now();
+    let mapped_file = map_file(&file)?;
+    let mut processor = BorrowedLineCounter::<Count>::with_capacity(hash_table_capacity(args));
+    processor.count_unique_in_slice(&mapped_file);
+    log_elapsed(&escaped(path), start);
+    maybe_write_header_row(args, &mut writer, terminator)?;
+    let width = count_width(args, || processor.iter().map(|(_line, count)| count).max());
+    if args.group_case {
+        write_case_groups(args, &mut writer, processor.iter().filter(|(_line, count)| reported(args, *count)))?;
+        writer.finish()?;
+        std::mem::forget(processor); // same explanation as in report()
+    } else if sorted_report(args) {
+        let mut report = processor.to_report_vec();
+        report.retain(|(_line, count)| reported(args, *count));
+        sort_report_entries(args, &mut report);
+        for (line, count) in report.iter() {
+            write_entry(args, &mut writer, width, line, count, terminator)?;
+        }
+        writer.finish()?;
+        std::mem::forget(report); // same explanation as in report()
+    } else {
+        for (line, count) in processor.iter().filter(|(_line, count)| reported(args, *count)) {
+            write_entry(args, &mut writer, width, line, &count, terminator)?;
+        }
+        writer.finish()?;
+        std::mem::forget(processor); // same explanation as in report()
+    }
+    Ok(())
+}
+
+/// Whether [`report_borrowed`] can make this report. It only supports a single memmapped file whose
+/// lines are counted exactly as they are, with nothing else taken from the input along the way.
+#[cfg(feature = "memmap")]
+fn can_borrow_keys(args: &CliArgs) -> bool {
+    let single_mapped_file = args.files.len() == 1 && !stdin_used(args) && use_memmap(args);
+    let lines_as_they_are = !args.trim && case(args) == CASE_NONE && !args.strip_ansi
+        && record_delimiter(args).is_none()
+        && !args.words
+        && args.match_regex.is_none()
+        && args.decode.is_none()
+        && args.translate.is_none()
+        && args.ignore_bytes.is_none()
+        && args.since_line.is_none();
+    let counts_only = args.max_distinct.is_none()
+        && args.export.is_none()
+        && args.strict_input.is_none()
+        && !args.hash_diagnostics
+        && !args.header;
+    single_mapped_file && lines_as_they_are && counts_only
+}
+
+/// Encodes lines before they are printed, if `--reencode` asks for it
+fn output_encoder(args: &CliArgs) -> OutputEncoder {
+    OutputEncoder::new(args.decode.filter(|_| args.reencode))
+}
+
+/// Open the `--export` file if there is one. Like report output, this is done before processing
+/// input so that we can abort early if it is not writable.
+fn open_export(args: &CliArgs) -> Result<Option<AtomicFileWriter>, Error> {
+    args.export.as_deref().map(AtomicFileWriter::create).transpose()
+}
+
+/// Write `entries` distinct lines and their counts to the file opened by [`open_export`]. Unsorted
+/// exports are written straight from the iterator, so they take no additional memory.
+fn write_export<'a, I>(args: &CliArgs, file: Option<AtomicFileWriter>, entries: usize, lines: I) -> Result<(), Error>
+where
+    I: Iterator<Item = (&'a [u8], Count)>,
+{
+    let Some(file) = file else {
+        return Ok(());
+    };
+    let mut writer = ExportWriter::new(file, entries as u64, args.export_sorted)?;
+    if args.export_sorted {
+        let mut sorted: Vec<(&[u8], Count)> = lines.collect();
+        sorted.sort_unstable_by_key(|(line, _count)| *line);
+        for (line, count) in sorted {
+            writer.write_entry(line, count)?;
+        }
+    } else {
+        for (line, count) in lines {
+            writer.write_entry(line, count)?;
+        }
+    }
+    writer.finish()?.finish()
+}
+
+/// Open the `--output` destination for a report
+fn open_output(args: &CliArgs) -> Result<Output, Error> {
+    Ok(Output::open(args.output.as_deref(), args.output_compression, args.compression_level)?.trailing_newline(!args.no_trailing_newline))
+}
+
+/// Stdout, for printing counts. This is buffered like report output, so [`Output::finish`] must be
+/// called to write it and to check for errors.
+fn count_output(args: &CliArgs) -> Result<Output, Error> {
+    Ok(Output::open(None, None, None)?.trailing_newline(!args.no_trailing_newline))
+}
+
+/// Write the `--header`, if requested
+fn maybe_write_header<T: Write>(args: &CliArgs, writer: &mut T, checksums: &ChecksumResults) -> Result<(), Error> {
+    if args.header {
+        write_header(writer, std::env::args_os(), &checksums.inputs, args.preview, SystemTime::now()).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+    }
+    Ok(())
+}
+
+/// Write the `--header-row` naming the `--format tsv` or `--format csv` columns, if requested
+fn maybe_write_header_row<T: Write>(args: &CliArgs, writer: &mut T, terminator: u8) -> Result<(), Error> {
+    if args.header_row {
+        let separator = if args.format == ReportFormat::Csv { "," } else { "\t" };
+        writer.write_all(COLUMNS.join(separator).as_bytes()).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+        writer.write_all(&[terminator]).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+    }
+    Ok(())
+}
+
+/// The width to right-align `--report` counts to. Without `--count-width`, this is 7 as in `uniq -c`,
+/// except that counts after their lines aren't padded. `max_count` is only called for
+/// `--count-width auto`, as finding the largest count takes a pass over every distinct line.
+fn count_width<F: FnOnce() -> Option<Count>>(args: &CliArgs, max_count: F) -> usize {
+    if args.no_pad {
+        return 0;
+    }
+    let default = CountWidth::Fixed(if args.count_last { 0 } else { 7 });
+    match args.count_width.unwrap_or(default) {
+        CountWidth::Fixed(width) => width,
+        CountWidth::Auto if args.format != ReportFormat::Text => 0,
+        CountWidth::Auto => digits(max_count().unwrap_or(0)),
+    }
+}
+
+/// Whether a line with this occurrence count is reported, given `--duplicates`, `--unique-only`,
+/// `--min-count`, and `--max-count`
+#[inline(always)]
+fn reported(args: &CliArgs, count: Count) -> bool {
+    (!args.duplicates || count > 1)
+        && (!args.unique_only || count == 1)
+        && args.min_count.map_or(true, |min| count >= min)
+        && args.max_count.map_or(true, |max| count <= max)
+}
+
+/// Write one reported line: with its count for `--report`, or on its own for `--duplicates` or
+/// `--unique-only` without `--report`
+#[inline(always)]
+fn write_entry<T: Write>(args: &CliArgs, writer: &mut T, width: usize, line: &[u8], count: &Count, terminator: u8) -> Result<(), Error> {
+    if args.report {
+        write_line(writer, report_layout(args), width, line, count, terminator)
+    } else {
+        write_bare_line(writer, line, terminator)
+    }
+}
+
+/// How `--report` lines are written: their `--format`, and for text lines, what separates the count
+/// from the line and which comes first
+#[derive(Clone, Copy)]
+struct ReportLayout<'a> {
+    format: ReportFormat,
+    delimiter: &'a [u8],
+    count_last: bool,
+}
+
+impl ReportLayout<'static> {
+    /// Text lines with the count first, as printed by `uniq -c`
+    const TEXT: Self = ReportLayout { format: ReportFormat::Text, delimiter: b" ", count_last: false };
+}
+
+/// The layout of `--report` lines given `--format`, `--output-delimiter`, and `--count-last`
+#[inline(always)]
+fn report_layout(args: &CliArgs) -> ReportLayout<'_> {
+    ReportLayout {
+        format: args.format,
+        delimiter: args.output_delimiter.as_deref().unwrap_or(b" "),
+        count_last: args.count_last,
+    }
+}
+
+/// Write one `--report` line in the given layout, with a text count right-aligned to `width`
+#[inline(always)]
+fn write_line<T: Write>(writer: &mut T, layout: ReportLayout, width: usize, line: &[u8], count: &Count, terminator: u8) -> Result<(), Error> {
+    match layout.format {
+        ReportFormat::Text => {}
+        ReportFormat::Ndjson => return write_ndjson_line(writer, line, *count).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e)),
+        ReportFormat::Tsv => return write_tsv_row(writer, line, *count, terminator).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e)),
+        ReportFormat::Csv => return write_csv_row(writer, line, *count, terminator).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e)),
+    }
+    if layout.count_last {
+        writer.write_all(line).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+        writer.write_all(layout.delimiter).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+        write!(writer, "{count:width$}").map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+    } else {
+        write!(writer, "{count:width$}").map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+        writer.write_all(layout.delimiter).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+        writer.write_all(line).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+    }
+    writer.write_all(&[terminator]).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))
+}
+
+fn count(args: CliArgs, preprocess: Preprocess) -> Result<Outcome, Error> {
+    let start = Instant::now();
+    let outcome = match args.mode {
+        Mode::Exact if args.stats || args.export.is_some() => {
+            // singletons and exports need each line's occurrence count, which LineCounter doesn't keep
+            let mut processor = HashingLineCounter::<Count, _>::with_line_mapper_and_capacity(preprocess, hash_table_capacity(&args));
+            let export = open_export(&args)?;
+            let checksums = process_input(&args, preprocess, &mut processor)?;
+            maybe_log_hash_diagnostics(&args, &processor);
+            write_export(&args, export, processor.count(), processor.iter().map(|(line, count)| (line, *count)))?;
+            let mut writer = count_output(&args)?;
+            let outcome = finish_count(&args, &mut writer, processor.count(), None, checksums.total)?;
+            write_dup_stats(&args, &mut writer, processor.count(), &checksums)?;
+            if args.stats {
+                writeln!(writer, "singletons: {}", processor.singleton_count()).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+                writeln!(writer, "bytes_read: {}", checksums.bytes.unwrap_or(0)).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+            }
+            writer.finish()?;
+            write_metrics(&args, processor.count(), &checksums, start)?;
             std::mem::forget(processor); // same explanation as above
+            outcome
+        }
+        Mode::Exact if args.ignore_bytes.is_some() => {
+            // only OriginalLineCounter can ignore bytes, as it maps lines itself, and it is already
+            // used by reports
+            let processor = OriginalLineCounter::with_line_mapper_and_capacity(preprocess, hash_table_capacity(&args))
+                .with_ignored_bytes(args.ignore_bytes.clone());
+            let Processed { processor, checksums, .. } = process_input_limited(&args, preprocess, processor)?;
+            let mut writer = count_output(&args)?;
+            let outcome = finish_count(&args, &mut writer, processor.count(), None, checksums.total)?;
+            write_dup_stats(&args, &mut writer, processor.count(), &checksums)?;
+            writer.finish()?;
+            write_metrics(&args, processor.count(), &checksums, start)?;
+            std::mem::forget(processor); // same explanation as above
+            outcome
+        }
+        Mode::Exact => {
+            let processor = with_hot_cache(&args, LineCounter::with_line_mapper_and_capacity(preprocess, hash_table_capacity(&args)));
+            count_hashing(&args, preprocess, processor, |processor| maybe_log_hash_diagnostics(&args, processor))?
+        }
+        Mode::NearExact => {
+            let processor = InexactHashingLineCounter::with_line_mapper_and_capacity(preprocess, hash_table_capacity(&args));
+            count_hashing(&args, preprocess, processor, |_processor| ())?
         }
         Mode::Estimate => {
-            let mut processor = if let Some(size) = args.size {
-                let size = usize::max(16, size); // make size at least 16
-                let size = previous_power_of_2(size); // reduce size to nearest power of 2
-                HyperLogLog::with_line_mapper_and_capacity(preprocess_line::<TRIM, LOWERCASE>, size)?
-            } else {
-                HyperLogLog::with_line_mapper(preprocess_line::<TRIM, LOWERCASE>)
-            };
-            process_input(&args, &mut processor)?;
-            println!("{}", processor.count());
+            let mut processor = estimator(&args, preprocess)?;
+            let checksums = process_input(&args, preprocess, &mut processor)?;
+            let mut writer = count_output(&args)?;
+            let outcome = finish_count(&args, &mut writer, processor.count(), Some(processor.standard_error()), checksums.total)?;
+            writer.finish()?;
+            write_metrics(&args, processor.count(), &checksums, start)?;
             std::mem::forget(processor); // same explanation as above
+            outcome
+        }
+    };
+    Ok(outcome)
+}
+
+/// Print the number of distinct values of two fields, and of their pairs
+fn tabulate(args: CliArgs, preprocess: Preprocess) -> Result<(), Error> {
+    let counter = || match args.mode {
+        Mode::Exact => Ok(FieldCounter::Exact(LineCounter::with_line_mapper_and_capacity(tabulate::identity, hash_table_capacity(&args)))),
+        Mode::NearExact => Ok(FieldCounter::NearExact(InexactHashingLineCounter::with_line_mapper_and_capacity(tabulate::identity, hash_table_capacity(&args)))),
+        Mode::Estimate => estimator(&args, tabulate::identity).map(|counter| FieldCounter::Estimate(Box::new(counter))),
+    };
+    let fields = [args.fields[0] - 1, args.fields[1] - 1];
+    let mut processor = TabulatingCounter::new([counter()?, counter()?, counter()?], fields, args.field_delimiter, preprocess);
+    process_input(&args, preprocess, &mut processor)?;
+    if processor.skipped() != 0 {
+        log_warn!("{} lines had fewer than {} fields and were skipped", processor.skipped(), args.fields[0].max(args.fields[1]));
+    }
+    let mut writer = count_output(&args)?;
+    writeln!(writer, "field {}: {}", args.fields[0], processor.first().count()).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+    writeln!(writer, "field {}: {}", args.fields[1], processor.second().count()).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+    writeln!(writer, "pairs: {}", processor.pairs().count()).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+    writer.finish()?;
+    std::mem::forget(processor); // same explanation as in report()
+    Ok(())
+}
+
+/// Create the [`HyperLogLog`] for estimate mode, sized by `--size-memory` or `--size`
+fn estimator<M>(args: &CliArgs, line_mapper: M) -> Result<HyperLogLog<M>, Error>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    let processor = if let Some(bytes) = args.size_memory {
+        let size = hll_size_for_memory(bytes);
+        HyperLogLog::with_line_mapper_and_capacity(line_mapper, size)
+            .map_err(|e| Error::message(format!("--size-memory {bytes} is too small: {e}")))?
+    } else if let Some(expected) = args.expected_cardinality {
+        HyperLogLog::with_line_mapper_and_capacity(line_mapper, sizing::estimate_registers(expected))?
+    } else if let Some(requested_size) = args.size {
+        let size = usize::max(16, requested_size); // make size at least 16
+        let size = previous_power_of_2(size); // reduce size to nearest power of 2
+        if size != requested_size {
+            log_warn!("--size {requested_size} was rounded to {size}, as estimate mode requires a power of 2 that is at least 16");
         }
+        HyperLogLog::with_line_mapper_and_capacity(line_mapper, size)?
+    } else {
+        HyperLogLog::with_line_mapper(line_mapper)
+    };
+    log_info!("estimating with {} registers", processor.size());
+    Ok(processor)
+}
+
+/// Count with an exact or near-exact counter, applying `--cap` or `--max-distinct` if given.
+/// `counted` is called with the counter once all input has been counted.
+fn count_hashing<T, F>(args: &CliArgs, preprocess: Preprocess, mut processor: T, counted: F) -> Result<Outcome, Error>
+where
+    T: CountUnique + ContainsLine,
+    F: FnOnce(&T),
+{
+    if let Some(cap) = args.cap {
+        #[cfg(feature = "regex")]
+        if let Some(pattern) = &args.match_regex {
+            count_capped(args, &mut matching::MatchingCounter::new(&mut processor, pattern, args.key_group)?, cap)?;
+            counted(&processor);
+            std::mem::forget(processor); // same explanation as in report()
+            return Ok(Outcome::Success);
+        }
+        if args.words {
+            count_capped(args, &mut TokenizingCounter::with_delimiters(&mut processor, word_delimiters(args)), cap)?;
+        } else {
+            count_capped(args, &mut processor, cap)?;
+        }
+        counted(&processor);
+        std::mem::forget(processor); // same explanation as in report()
+        return Ok(Outcome::Success);
+    }
+    let start = Instant::now();
+    let Processed { processor, limit_exceeded, checksums } = process_input_limited(args, preprocess, processor)?;
+    counted(&processor);
+    let mut writer = count_output(args)?;
+    let outcome = if limit_exceeded {
+        writeln!(writer, ">={}", processor.count()).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+        write_checksum(&mut writer, checksums.total)?;
+        max_distinct_outcome(args, limit_exceeded)
+    } else {
+        let outcome = finish_count(args, &mut writer, processor.count(), None, checksums.total)?;
+        write_dup_stats(args, &mut writer, processor.count(), &checksums)?;
+        outcome
+    };
+    writer.finish()?;
+    write_metrics(args, processor.count(), &checksums, start)?;
+    std::mem::forget(processor); // same explanation as in report()
+    Ok(outcome)
+}
+
+/// Print `--hash-diagnostics` for an exact counter's hash table, if requested
+fn maybe_log_hash_diagnostics<T, M: LineMapper>(args: &CliArgs, processor: &HashingLineCounter<T, M>) {
+    #[cfg(feature = "diagnostics")]
+    if args.hash_diagnostics {
+        log_always!("hash diagnostics:\n{}", processor.diagnostics(HASH_DIAGNOSTICS_SAMPLE).to_string().trim_end());
+    }
+    #[cfg(not(feature = "diagnostics"))]
+    let _ = (args, processor);
+}
+
+/// The result of [`process_input_limited`]
+struct Processed<T> {
+    processor: T,
+    /// whether `--max-distinct` was exceeded
+    limit_exceeded: bool,
+    /// checksums of the input requested by `--checksum` or `--header`
+    checksums: ChecksumResults,
+}
+
+/// Like [`process_input`], but if `--max-distinct` was given new lines stop being added to
+/// `processor` once it is reached.
+fn process_input_limited<T>(args: &CliArgs, preprocess: Preprocess, mut processor: T) -> Result<Processed<T>, Error>
+where
+    T: CountUnique + ContainsLine,
+{
+    let Some(max_distinct) = args.max_distinct else {
+        let checksums = process_input(args, preprocess, &mut processor)?;
+        return Ok(Processed { processor, limit_exceeded: false, checksums });
+    };
+    let overflow = HyperLogLog::with_line_mapper_and_capacity(preprocess, MAX_DISTINCT_OVERFLOW_REGISTERS)?;
+    let mut limited = DistinctLimit::new(processor, max_distinct, overflow);
+    let checksums = process_input(args, preprocess, &mut limited)?;
+    let limit_exceeded = limited.limit_exceeded();
+    if limit_exceeded {
+        let message = format!(
+            "stopped tracking new lines at --max-distinct {max_distinct}: about {} more distinct lines ({} lines in total) were not tracked",
+            limited.rejected_distinct_estimate(),
+            limited.rejected_lines(),
+        );
+        if args.strict_max_distinct {
+            log_error!("{message}");
+        } else {
+            log_warn!("{message}");
+        }
+    }
+    Ok(Processed { processor: limited.into_inner(), limit_exceeded, checksums })
+}
+
+/// The outcome of a run that may have exceeded `--max-distinct`
+fn max_distinct_outcome(args: &CliArgs, limit_exceeded: bool) -> Outcome {
+    if limit_exceeded && args.strict_max_distinct {
+        Outcome::MaxDistinctExceeded
+    } else {
+        Outcome::Success
+    }
+}
+
+/// Write the total and the `--checksum` if there is one, and check the total against `--expect`.
+/// `standard_error` is the relative standard error of the count if it is an estimate, which is
+/// printed with `--precision`. The check always uses the count as it was estimated.
+fn finish_count<W: Write>(args: &CliArgs, writer: &mut W, count: usize, standard_error: Option<f64>, checksum: Option<u32>) -> Result<Outcome, Error> {
+    match standard_error {
+        Some(standard_error) => writeln!(writer, "{}", format_estimate(count as u64, standard_error, args.precision)),
+        None => writeln!(writer, "{count}"),
+    }.map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+    write_checksum(writer, checksum)?;
+    let Some(expectation) = args.expect else {
+        return Ok(Outcome::Success);
+    };
+    let expectation = match standard_error {
+        Some(standard_error) if args.expect_conservative => expectation.widened(EXPECT_CONSERVATIVE_STANDARD_ERRORS * standard_error),
+        _ => expectation,
+    };
+    if expectation.contains(count as u64) {
+        Ok(Outcome::Success)
+    } else {
+        log_error!("expected {expectation} distinct lines, but counted {count}");
+        Ok(Outcome::ExpectationFailed)
+    }
+}
+
+/// Write `--dup-stats`, from the distinct count and the lines counted while input was read
+fn write_dup_stats<W: Write>(args: &CliArgs, writer: &mut W, distinct: usize, checksums: &ChecksumResults) -> Result<(), Error> {
+    if !args.dup_stats {
+        return Ok(());
+    }
+    let total = checksums.lines.unwrap_or(0);
+    let duplicates = total.saturating_sub(distinct as u64);
+    let ratio = if total == 0 { 0.0 } else { duplicates as f64 / total as f64 };
+    writeln!(writer, "distinct: {distinct}\ntotal: {total}\nduplicates: {duplicates}\nratio: {ratio:.4}").map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))
+}
+
+fn write_checksum<W: Write>(writer: &mut W, checksum: Option<u32>) -> Result<(), Error> {
+    if let Some(checksum) = checksum {
+        writeln!(writer, "crc32: {checksum:08x}").map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+    }
+    Ok(())
+}
+
+/// Whether a summary of the run was requested with `--metrics-file` or `--statsd`
+fn metrics_requested(args: &CliArgs) -> bool {
+    args.metrics_file.is_some() || args.statsd.is_some()
+}
+
+/// Write `--metrics-file` and send `--statsd`, if requested. `start` is when counting started.
+fn write_metrics(args: &CliArgs, distinct: usize, checksums: &ChecksumResults, start: Instant) -> Result<(), Error> {
+    if !metrics_requested(args) {
+        return Ok(());
+    }
+    let summary = RunSummary {
+        mode: args.mode,
+        inputs: input_names(args),
+        distinct,
+        lines: checksums.lines,
+        bytes: checksums.bytes,
+        duration: start.elapsed(),
+    };
+    if let Some(path) = &args.metrics_file {
+        let mut file = AtomicFileWriter::create(path)?;
+        metrics::write_textfile(&mut file, &args.metrics_prefix, &args.metrics_label, &summary)
+            .map_err(|e| Error::io(format!("error writing metrics file \"{}\"", escaped(path)), e))?;
+        file.finish()?;
+    }
+    if let Some(address) = &args.statsd {
+        metrics::send_statsd(address, &args.metrics_prefix, &summary)?;
     }
     Ok(())
 }
 
-fn process_input<T>(args: &CliArgs, processor: &mut T) -> Result<(), Error>
+/// The name of every input in the order they are read, separated by commas
+fn input_names(args: &CliArgs) -> String {
+    let mut names = Vec::with_capacity(args.files.len() + 1);
+    if let Some(socket) = &args.listen {
+        names.push(escaped(socket).to_string());
+    }
+    if stdin_used(args) {
+        names.push("stdin".to_string());
+    }
+    names.extend(args.files.iter().map(|path| escaped(path).to_string()));
+    names.join(",")
+}
+
+/// Count unique lines, but stop reading input as soon as `cap` distinct lines have been found. Files
+/// are read with buffered reads, as memmap can't stop partway through a file.
+fn count_capped<T: CountUnique>(args: &CliArgs, processor: &mut T, cap: usize) -> Result<(), Error> {
+    if let Some(delimiter) = record_delimiter(args) {
+        return count_capped_records(args, &mut DelimitedCounter::new(processor, delimiter), cap);
+    }
+    count_capped_records(args, processor, cap)
+}
+
+/// [`count_capped`], after wrapping `processor` to split input into records on `--delimiter-str`
+/// if needed
+fn count_capped_records<T: CountUnique>(args: &CliArgs, processor: &mut T, cap: usize) -> Result<(), Error> {
+    check_files(args)?;
+
+    let mut cap_reached = false;
+    if stdin_used(args) {
+        log_info!("input: stdin");
+        let start = Instant::now();
+        cap_reached = processor.count_until(stdin_reader(), cap)?;
+        log_elapsed(&"stdin", start);
+    }
+    for path in &args.files {
+        if cap_reached {
+            break;
+        }
+        let file = open_file(path)?;
+        let start = Instant::now();
+        cap_reached = processor.count_until(BufReader::new(file), cap)?;
+        log_elapsed(&escaped(path), start);
+    }
+
+    let mut writer = count_output(args)?;
+    if cap_reached {
+        writeln!(writer, ">={cap}")
+    } else {
+        writeln!(writer, "{}", processor.count())
+    }.map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))?;
+    writer.finish()
+}
+
+/// Count unique lines by merging inputs that are already sorted
+fn count_sorted(args: CliArgs, preprocess: Preprocess) -> Result<Outcome, Error> {
+    // every input is merged at once, so unlike other modes this needs them all open together
+    let mut readers: Vec<Box<dyn BufRead>> = Vec::with_capacity(args.files.len() + 1);
+    for path in &args.files {
+        let file = open_file(path)?;
+        log_input_file(path, &file);
+        readers.push(Box::new(BufReader::new(file)));
+    }
+    if stdin_used(&args) {
+        log_info!("input: stdin");
+        readers.insert(0, Box::new(stdin_reader()));
+    }
+
+    let mut processor = SortedLineCounter::with_line_mapper(preprocess);
+    let start = Instant::now();
+    processor.count_unique_in_sorted_reads(readers)?;
+    log_elapsed(&"all inputs", start);
+    let mut writer = count_output(&args)?;
+    let outcome = finish_count(&args, &mut writer, processor.count(), None, None)?;
+    writer.finish()?;
+    // the inputs are merged without passing through Checksums, so there are no line or byte counts
+    write_metrics(&args, processor.count(), &ChecksumResults::default(), start)?;
+    Ok(outcome)
+}
+
+/// Count runs of identical consecutive lines, as `uniq` would collapse them
+fn count_adjacent(args: CliArgs, preprocess: Preprocess) -> Result<Outcome, Error> {
+    let start = Instant::now();
+    let mut processor = AdjacentLineCounter::with_line_mapper(preprocess);
+    let checksums = process_input(&args, preprocess, &mut processor)?;
+    let mut writer = count_output(&args)?;
+    let outcome = finish_count(&args, &mut writer, processor.count(), None, checksums.total)?;
+    writer.finish()?;
+    write_metrics(&args, processor.count(), &checksums, start)?;
+    Ok(outcome)
+}
+
+/// Process all inputs. Returns the checksums requested by `--checksum` or `--header`, if any.
+fn process_input<T>(args: &CliArgs, preprocess: Preprocess, processor: &mut T) -> Result<ChecksumResults, Error>
 where
     T: line_cardinality::CountUniqueFromReadFile,
 {
+    process_input_with_sources(args, preprocess, processor, |_processor| ())
+}
+
+/// Like [`process_input`], but `next_source` is called before each input (stdin and each file) is
+/// processed.
+fn process_input_with_sources<T, F>(args: &CliArgs, preprocess: Preprocess, processor: &mut T, mut next_source: F) -> Result<ChecksumResults, Error>
+where
+    T: line_cardinality::CountUniqueFromReadFile,
+    F: FnMut(&mut T),
+{
+    let translation = match &args.translate {
+        Some(path) => {
+            let file = open_file(path)?;
+            let size = file.metadata().ok().map(|metadata| metadata.len());
+            Some(Translation::from_read(BufReader::new(file), size, preprocess, args.translate_only)?)
+        }
+        None => None,
+    };
+    // applied even without --translate, see TranslatingCounter
+    let mut translator = TranslatingCounter::new(processor, translation);
+    process_matched(args, &mut translator, |translator| next_source(translator.inner_mut()))
+}
+
+/// [`process_input_with_sources`], after wrapping `processor` to translate lines with
+/// `--translate` if needed
+fn process_matched<T, F>(args: &CliArgs, processor: &mut T, mut next_source: F) -> Result<ChecksumResults, Error>
+where
+    T: line_cardinality::CountUniqueFromReadFile,
+    F: FnMut(&mut T),
+{
+    #[cfg(feature = "regex")]
+    if let Some(pattern) = &args.match_regex {
+        let mut matcher = matching::MatchingCounter::new(processor, pattern, args.key_group)?;
+        return process_decoded(args, &mut matcher, |matcher| next_source(matcher.inner_mut()));
+    }
+    if args.words {
+        let mut tokenizer = TokenizingCounter::with_delimiters(processor, word_delimiters(args));
+        return process_decoded(args, &mut tokenizer, |tokenizer| next_source(tokenizer.inner_mut()));
+    }
+    process_decoded(args, processor, next_source)
+}
+
+/// The bytes `--words` splits lines on
+fn word_delimiters(args: &CliArgs) -> &[u8] {
+    args.word_delimiters.as_deref().map_or(ASCII_WHITESPACE, str::as_bytes)
+}
+
+/// [`process_input_with_sources`], after wrapping `processor` to split lines into words or match
+/// them against `--match` if needed
+fn process_decoded<T, F>(args: &CliArgs, processor: &mut T, mut next_source: F) -> Result<ChecksumResults, Error>
+where
+    T: line_cardinality::CountUniqueFromReadFile,
+    F: FnMut(&mut T),
+{
+    if let Some(encoding) = args.decode {
+        let mut decoder = DecodingCounter::new(processor, encoding, args.invalid_encoding);
+        let checksums = process_records(args, &mut decoder, |decoder| next_source(decoder.inner_mut()))?;
+        decoder.finish()?;
+        return Ok(checksums);
+    }
+    process_records(args, processor, next_source)
+}
+
+/// [`process_input_with_sources`], after wrapping `processor` to decode lines with `--decode` if
+/// needed
+fn process_records<T, F>(args: &CliArgs, processor: &mut T, mut next_source: F) -> Result<ChecksumResults, Error>
+where
+    T: line_cardinality::CountUniqueFromReadFile,
+    F: FnMut(&mut T),
+{
+    if let Some(delimiter) = record_delimiter(args) {
+        let mut splitter = DelimitedCounter::new(processor, delimiter);
+        return process_all_inputs(args, &mut splitter, |splitter| next_source(splitter.inner_mut()));
+    }
+    process_all_inputs(args, processor, next_source)
+}
+
+/// [`process_input_with_sources`], after wrapping `processor` to split input into records on
+/// `--delimiter-str` if needed
+fn process_all_inputs<T, F>(args: &CliArgs, processor: &mut T, mut next_source: F) -> Result<ChecksumResults, Error>
+where
+    T: line_cardinality::CountUniqueFromReadFile,
+    F: FnMut(&mut T),
+{
+    check_files(args)?;
+    if !args.files.is_empty() {
+        log_info!("reading files via {}", if use_memmap(args) { "memmap" } else { "buffered reads" });
+    }
+
+    let mut checksums = Checksums::new(args.checksum);
+    if args.stats || args.preview.is_some() || metrics_requested(args) {
+        checksums = checksums.with_byte_count();
+    }
+    // records split on a multi-byte --delimiter-str can't be counted a buffer at a time
+    if (metrics_requested(args) || args.dup_stats) && args.delimiter_str.is_none() {
+        checksums = checksums.with_line_count(line_terminator(args));
+    }
+    if args.header {
+        let limit = if args.header_full_checksum { None } else { Some(HEADER_CHECKSUM_BYTES) };
+        checksums = checksums.with_per_input(limit);
+    }
+    if let Some(action) = args.strict_input {
+        checksums = checksums.with_forbidden_bytes(ForbiddenBytes::new(&args.forbidden_bytes, action));
+    }
+
+    #[cfg(unix)]
+    if let Some(socket) = &args.listen {
+        listen::process_connections(socket, processor, &mut next_source, &mut checksums)?;
+        return Ok(checksums.finish());
+    }
+
+    let mut preview = args.preview.map(Preview::new);
+    process_stdin(args, processor, &mut next_source, &mut checksums, preview.as_mut())?;
+    check_forbidden_bytes(&checksums)?;
 
-    // pre-open all files so that we can display any errors and abort *before* doing work
-    let mut files: Vec<File> = Vec::with_capacity(args.files.len());
     for path in &args.files {
-        let file = File::open(path).map_err(|e| Error::io(format!("error opening file \"{}\"", path.display()), e))?;
-        files.push(file);
+        next_source(processor);
+        let file = open_file(path)?;
+        let start = Instant::now();
+        if checksums.is_enabled() {
+            checksums.start_input(path.as_os_str().to_owned());
+            process_file_with_checksum(args, processor, &file, &mut checksums, preview.as_mut())?;
+        } else {
+            process_file(args, processor, &file)?;
+        }
+        log_elapsed(&escaped(path), start);
+        check_forbidden_bytes(&checksums)?;
+    }
+    if let Some(forbidden) = checksums.forbidden_bytes() {
+        forbidden.log_summary();
     }
+    let checksums = checksums.finish();
+    if let Some(preview) = preview {
+        preview.log_summary(processor.count(), checksums.bytes.unwrap_or(0), total_input_bytes(args));
+    }
+    Ok(checksums)
+}
 
-    process_stdin(args, processor)?;
+/// Check that the first input that isn't empty looks like text, for `--text-only`. Only a sample
+/// from its start is read. The sample read from stdin is kept in [`STDIN_SAMPLE`], so that
+/// [`stdin_reader`] still counts stdin in full.
+fn check_text_input(args: &CliArgs) -> Result<(), Error> {
+    let reject = |input: String, sample: &[u8]| match binary::check_sample(sample) {
+        Some(binary) => Err(Error::input(format!("{input} looks like binary data rather than text: {binary}. Use --binary-ok to count it anyway."))),
+        None => Ok(()),
+    };
+    if stdin_used(args) {
+        let sample = binary::read_sample(io::stdin().lock()).map_err(|e| Error::io_static("failed to read from stdin", e))?;
+        let result = reject("stdin".to_string(), &sample);
+        let empty = sample.is_empty();
+        *STDIN_SAMPLE.lock().unwrap() = sample;
+        if !empty {
+            return result;
+        }
+    }
+    for path in &args.files {
+        let sample = binary::read_sample(open_file(path)?).map_err(|e| Error::io(format!("error reading file \"{}\"", escaped(path)), e))?;
+        if !sample.is_empty() {
+            return reject(format!("\"{}\"", escaped(path)), &sample);
+        }
+    }
+    Ok(())
+}
+
+/// Fail once `--strict-input` has found a forbidden byte, unless it only warns. This is checked
+/// after each input, as reading stops early once one is found.
+fn check_forbidden_bytes(checksums: &Checksums) -> Result<(), Error> {
+    checksums.forbidden_bytes().map_or(Ok(()), ForbiddenBytes::check)
+}
+
+/// The total size of every input, or `None` if it can't be known in advance because stdin is read
+fn total_input_bytes(args: &CliArgs) -> Option<u64> {
+    if stdin_used(args) {
+        return None;
+    }
+    // a total too large for a u64 is treated as unknown
+    args.files.iter().try_fold(0u64, |total, path| total.checked_add(std::fs::metadata(path).ok()?.len()))
+}
+
+/// Check that every file can be opened, so that we can display any errors and abort *before* doing
+/// work. Files are closed again and reopened as they are read, so that only one is open at a time
+/// however many inputs there are.
+fn check_files(args: &CliArgs) -> Result<(), Error> {
+    for path in &args.files {
+        let file = open_file(path)?;
+        log_input_file(path, &file);
+    }
+    Ok(())
+}
+
+fn open_file(path: &Path) -> Result<File, Error> {
+    File::open(path).map_err(|e| Error::io(format!("error opening file \"{}\"", escaped(path)), e))
+}
+
+/// Log an input file and its size, as part of the table of inputs printed by `--verbose`
+fn log_input_file(path: &Path, file: &File) {
+    if logging::enabled(Level::Info) {
+        match file.metadata() {
+            Ok(metadata) => log_info!("input: \"{}\" ({} bytes)", escaped(path), metadata.len()),
+            Err(_) => log_info!("input: \"{}\"", escaped(path)),
+        }
+    }
+}
+
+/// Log how long it took to process an input
+fn log_elapsed(input: &dyn Display, start: Instant) {
+    log_debug!("processed {input} in {:.3?}", start.elapsed());
+}
+
+/// Whether files will be read via memmap. `--preview` needs buffered reads to stop partway through
+/// each file.
+fn use_memmap(args: &CliArgs) -> bool {
+    if args.no_memmap || args.preview.is_some() || cfg!(not(feature = "memmap")) {
+        false
+    } else {
+        // by default, process with memmap on unix platforms
+        args.memmap || cfg!(unix)
+    }
+}
+
+/// Whether stdin will be read for input
+fn stdin_used(args: &CliArgs) -> bool {
+    !args.no_stdin && args.listen.is_none() && !io::stdin().is_terminal()
+}
 
+/// The start of stdin, if `--text-only` has already read it to check that it looks like text
+static STDIN_SAMPLE: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+/// Stdin for counting, starting with anything already read into [`STDIN_SAMPLE`]
+fn stdin_reader() -> io::Chain<io::Cursor<Vec<u8>>, io::StdinLock<'static>> {
+    let sample = std::mem::take(&mut *STDIN_SAMPLE.lock().unwrap());
+    io::Cursor::new(sample).chain(io::stdin().lock())
+}
+
+#[inline(always)]
+fn process_file<T>(args: &CliArgs, processor: &mut T, file: &File) -> Result<(), Error>
+where
+    T: line_cardinality::CountUniqueFromReadFile,
+{
     use cfg_if::cfg_if;
     cfg_if! {
         if #[cfg(feature = "memmap")] {
             if args.no_memmap {
                 // process without memmap
-                processor.count_unique_in_files(&files)?;
+                processor.count_unique_in_file(file)?;
             } else if args.memmap {
                 // use memmap forced by user
-                use line_cardinality::CountUniqueFromMemmapFile;
-                processor.count_unique_in_memmap_files(&files)?;
+                processor.count_unique_in_bytes(&map_file(file)?);
             } else {
                 cfg_if! {
                     if #[cfg(unix)] {
                         // by default, process with memmap on unix platforms
-                        use line_cardinality::CountUniqueFromMemmapFile;
-                        processor.count_unique_in_memmap_files(&files)?;
+                        processor.count_unique_in_bytes(&map_file(file)?);
                     } else {
                         // by default, process without memmap on non-unix platforms
-                        processor.count_unique_in_files(&files)?;
+                        processor.count_unique_in_file(file)?;
                     }
                 }
             }
@@ -166,40 +1641,189 @@ where
                 Err(Error::message_static("This cuniq binary was compiled without memmap support"))?;
             } else {
                 // process without memmap
-                processor.count_unique_in_files(&files)?;
+                processor.count_unique_in_file(file)?;
             }
         }
     }
     Ok(())
 }
 
+/// Memory-map `file`, warning if the OS rejected the hints about how it will be read
+#[cfg(feature = "memmap")]
+fn map_file(file: &File) -> Result<line_cardinality::MappedFile, Error> {
+    let len = file.metadata().map_err(|e| Error::io_static("failed to read file metadata", e))?.len();
+    check_memmap_len(len)?;
+    let mapped_file = line_cardinality::MappedFile::new(file)?;
+    if let Some(e) = mapped_file.advice_error() {
+        log_warn!("failed to advise the OS how a memmapped file will be read, so reading it may be slower: {e}");
+    }
+    Ok(mapped_file)
+}
+
+/// Check that a file of `len` bytes can be memmapped, with an error that suggests reading it
+/// without memmap if it can't
+#[cfg(feature = "memmap")]
+fn check_memmap_len(len: u64) -> Result<(), Error> {
+    if len > line_cardinality::MAX_MAPPED_LEN {
+        Err(Error::message(format!(
+            "file is {len} bytes, which is too large to memmap on this platform (the limit is {} bytes). Use --no-memmap to read it with normal reads, which have no size limit",
+            line_cardinality::MAX_MAPPED_LEN,
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Like [`process_file`], but also feeds the file's bytes into `checksums`, and reads only the
+/// first lines of the file if there is a `preview`
+fn process_file_with_checksum<T>(args: &CliArgs, processor: &mut T, file: &File, checksums: &mut Checksums, preview: Option<&mut Preview>) -> Result<(), Error>
+where
+    T: CountUnique,
+{
+    if args.memmap && cfg!(not(feature = "memmap")) {
+        Err(Error::message_static("This cuniq binary was compiled without memmap support"))?;
+    }
+    #[cfg(feature = "memmap")]
+    if use_memmap(args) {
+        let mapped_file = map_file(file)?;
+        checksums.update(&mapped_file);
+        // the run is going to fail, so there's no need to count the file
+        if !checksums.stopped() {
+            processor.count_unique_in_bytes(&mapped_file);
+        }
+        return Ok(());
+    }
+    let reader = ChecksumReader::new(BufReader::new(file), checksums);
+    match preview {
+        Some(preview) => preview.count(processor, reader),
+        None => processor.count_unique_in_read(reader),
+    }
+}
+
 #[inline(always)]
-fn process_stdin<T>(args: &CliArgs, processor: &mut T) -> Result<(), Error>
+fn process_stdin<T, F>(args: &CliArgs, processor: &mut T, next_source: &mut F, checksums: &mut Checksums, preview: Option<&mut Preview>) -> Result<(), Error>
 where
     T: CountUnique,
+    F: FnMut(&mut T),
 {
-    if !args.no_stdin {
-        let stdin_handle = io::stdin().lock();
-        if !stdin_handle.is_terminal() {
-            processor.count_unique_in_read(stdin_handle)?;
+    if stdin_used(args) {
+        log_info!("input: stdin");
+        next_source(processor);
+        let start = Instant::now();
+        if checksums.is_enabled() {
+            checksums.start_input("stdin".into());
+            let reader = ChecksumReader::new(stdin_reader(), checksums);
+            match preview {
+                Some(preview) => preview.count(processor, reader)?,
+                None => processor.count_unique_in_read(reader)?,
+            }
+        } else {
+            processor.count_unique_in_read(stdin_reader())?;
         }
+        log_elapsed(&"stdin", start);
     }
     Ok(())
 }
 
+/// Choose the [`preprocess_line`] for these arguments
+fn preprocessor(args: &CliArgs) -> Preprocess {
+    match case(args) {
+        CASE_NONE => preprocessor_with_case::<CASE_NONE>(args),
+        CASE_LOWER => preprocessor_with_case::<CASE_LOWER>(args),
+        #[cfg(feature = "unicode")]
+        CASE_FOLD_SIMPLE => preprocessor_with_case::<CASE_FOLD_SIMPLE>(args),
+        #[cfg(feature = "unicode")]
+        CASE_FOLD_FULL => preprocessor_with_case::<CASE_FOLD_FULL>(args),
+        // rejected by validate_args
+        #[cfg(not(feature = "unicode"))]
+        _ => preprocessor_with_case::<CASE_NONE>(args),
+        #[cfg(feature = "unicode")]
+        case => unreachable!("unsupported case mapping {case}"),
+    }
+}
+
+fn preprocessor_with_case<const CASE: u8>(args: &CliArgs) -> Preprocess {
+    match (args.trim, args.strip_ansi) {
+        (false, false) => preprocess_line::<false, CASE, false>,
+        (true, false) => preprocess_line::<true, CASE, false>,
+        (false, true) => preprocess_line::<false, CASE, true>,
+        (true, true) => preprocess_line::<true, CASE, true>,
+    }
+}
+
+/// The case mapping requested by `--lower`, `--fold`, or `--fold-case`
+fn case(args: &CliArgs) -> u8 {
+    let fold = if args.fold_case { Some(cli_args::Fold::Full) } else { args.fold };
+    match (args.lowercase, fold) {
+        (false, None) => CASE_NONE,
+        (true, _) => CASE_LOWER,
+        (false, Some(cli_args::Fold::Simple)) => CASE_FOLD_SIMPLE,
+        (false, Some(cli_args::Fold::Full)) => CASE_FOLD_FULL,
+    }
+}
+
 #[inline(always)]
-fn preprocess_line<'a, const TRIM: bool, const LOWERCASE: bool>(line: &'a [u8], buffer: &'a mut Vec<u8>) -> &'a [u8] {
-    let trimmed = if TRIM {
-        line.trim()
-    } else {
-        line
-    };
-    if LOWERCASE {
-        buffer.clear();
-        trimmed.to_lowercase_into(buffer);
-        buffer
+fn preprocess_line<'a, const TRIM: bool, const CASE: u8, const STRIP_ANSI: bool>(line: &'a [u8], buffer: &'a mut Vec<u8>) -> &'a [u8] {
+    if STRIP_ANSI {
+        // escape sequences must be removed before trimming, as they may be hiding whitespace at
+        // the ends of the line. Lowercasing can't turn a CSI sequence into something else, so it's
+        // done first to allow stripping in place.
+        let line = if CASE == CASE_NONE {
+            strip_ansi(line, buffer)
+        } else {
+            map_case::<CASE>(line, buffer);
+            strip_ansi_in_place(buffer);
+            buffer.as_slice()
+        };
+        if TRIM {
+            line.trim()
+        } else {
+            line
+        }
     } else {
-        trimmed
+        let trimmed = if TRIM {
+            line.trim()
+        } else {
+            line
+        };
+        if CASE == CASE_NONE {
+            trimmed
+        } else {
+            map_case::<CASE>(trimmed, buffer);
+            buffer
+        }
+    }
+}
+
+/// Write `line` into `buffer` with the `CASE` mapping applied
+#[inline(always)]
+fn map_case<const CASE: u8>(line: &[u8], buffer: &mut Vec<u8>) {
+    match CASE {
+        CASE_LOWER => {
+            buffer.clear();
+            line.to_lowercase_into(buffer);
+        }
+        #[cfg(feature = "unicode")]
+        CASE_FOLD_SIMPLE => {
+            line_cardinality::fold_case_simple(line, buffer);
+        }
+        #[cfg(feature = "unicode")]
+        CASE_FOLD_FULL => {
+            // same result as fold_case_full, but with a fast path for ASCII lines
+            line_cardinality::fold_case(line, buffer);
+        }
+        _ => unreachable!("unsupported case mapping {CASE}"),
+    }
+}
+
+/// Human-readable name of a case mapping, for logging
+fn case_name(case: u8) -> &'static str {
+    match case {
+        CASE_NONE => "unchanged",
+        CASE_LOWER => "lowercase",
+        CASE_FOLD_SIMPLE => "simple fold",
+        CASE_FOLD_FULL => "full fold",
+        _ => unreachable!("unsupported case mapping {case}"),
     }
 }
 
@@ -242,4 +1866,23 @@ mod test {
         assert_eq!(previous_power_of_2(65537), 65536, "case 65537");
         assert_eq!(previous_power_of_2(usize::MAX), 1usize.rotate_right(1), "case max");
     }
+
+    #[cfg(feature = "memmap")]
+    #[test]
+    fn test_check_memmap_len() {
+        assert!(check_memmap_len(0).is_ok());
+        assert!(check_memmap_len(line_cardinality::MAX_MAPPED_LEN).is_ok());
+        let error = check_memmap_len(u64::MAX).unwrap_err();
+        assert!(error.to_string().contains("--no-memmap"), "{error}");
+        // lengths past 4 GiB only fit in the address space of 64-bit platforms
+        assert_eq!(check_memmap_len(u64::from(u32::MAX) + 1).is_ok(), cfg!(target_pointer_width = "64"));
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let io_error = Error::io_static("io", io::Error::from(ErrorKind::NotFound));
+        assert_eq!(exit_code(io_error.get_cause()), 1);
+        assert_eq!(exit_code(Error::message_static("user").get_cause()), 2);
+        assert_eq!(exit_code(HyperLogLog::with_capacity(17).err().unwrap().get_cause()), 2);
+    }
 }
@@ -0,0 +1,103 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Comparing lines without the byte ranges given to `--ignore-bytes`
+
+use std::ops::Range;
+
+/// Sorted, non-overlapping ranges of byte positions within a line
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ByteRanges {
+    ranges: Vec<Range<usize>>,
+}
+
+impl ByteRanges {
+    /// Write `line` to `key` with every byte in these ranges removed. Ranges that start past the
+    /// end of the line remove nothing, and ranges that end past it remove the rest of the line.
+    pub fn remove(&self, line: &[u8], key: &mut Vec<u8>) {
+        key.clear();
+        let mut start = 0;
+        for range in &self.ranges {
+            if range.start >= line.len() {
+                break;
+            }
+            key.extend_from_slice(&line[start..range.start]);
+            start = range.end.min(line.len());
+        }
+        key.extend_from_slice(&line[start..]);
+    }
+}
+
+/// Parse `--ignore-bytes`: a comma separated list of zero-based byte positions, each either a single
+/// position `N`, an inclusive range `N-M`, or `N-` for everything from `N` to the end of the line
+pub fn parse_byte_ranges(arg: &str) -> Result<ByteRanges, String> {
+    let parse_position = |position: &str| position.trim().parse::<usize>()
+        .map_err(|_| format!("expected a byte position, but got \"{position}\""));
+    let mut ranges = Vec::new();
+    for range in arg.split(',') {
+        let range = match range.split_once('-') {
+            Some((start, "")) => parse_position(start)?..usize::MAX,
+            Some((start, end)) => {
+                let (start, end) = (parse_position(start)?, parse_position(end)?);
+                if start > end {
+                    return Err(format!("range start {start} is greater than range end {end}"));
+                }
+                start..end.saturating_add(1)
+            }
+            None => {
+                let position = parse_position(range)?;
+                position..position.saturating_add(1)
+            }
+        };
+        ranges.push(range);
+    }
+    // merge overlapping and adjacent ranges, so that remove() can step through them in order
+    ranges.sort_unstable_by_key(|range| range.start);
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    Ok(ByteRanges { ranges: merged })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn removed(ranges: &str, line: &[u8]) -> Vec<u8> {
+        let mut key = Vec::new();
+        parse_byte_ranges(ranges).unwrap().remove(line, &mut key);
+        key
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_byte_ranges("0-19,40-48"), Ok(ByteRanges { ranges: vec![0..20, 40..49] }));
+        assert_eq!(parse_byte_ranges("7,2-3,60-"), Ok(ByteRanges { ranges: vec![2..4, 7..8, 60..usize::MAX] }));
+        assert_eq!(parse_byte_ranges("3-5,0-3,6"), Ok(ByteRanges { ranges: vec![Range { start: 0, end: 7 }] }));
+        assert!(parse_byte_ranges("").is_err());
+        assert!(parse_byte_ranges("5-2").is_err());
+        assert!(parse_byte_ranges("1,,2").is_err());
+        assert!(parse_byte_ranges("-5").is_err());
+        assert!(parse_byte_ranges("a-b").is_err());
+    }
+
+    #[test]
+    fn test_remove() {
+        assert_eq!(removed("0-3,6-7", b"2024ab12cd"), b"abcd");
+        assert_eq!(removed("4", b"01234567"), b"0123567");
+        assert_eq!(removed("2-", b"01234567"), b"01");
+    }
+
+    /// Ranges past the end of a line only remove the bytes the line has
+    #[test]
+    fn test_remove_past_end() {
+        assert_eq!(removed("2-4,10-20", b"0123"), b"01");
+        assert_eq!(removed("10-20", b"0123"), b"0123");
+        assert_eq!(removed("0-20", b"0123"), b"");
+        assert_eq!(removed("0", b""), b"");
+    }
+}
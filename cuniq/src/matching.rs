@@ -0,0 +1,136 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Filtering lines with `--match`, and counting a capture group with `--key-group`
+
+use std::mem::size_of;
+
+use line_cardinality::{CountUnique, Error};
+use regex::bytes::{CaptureLocations, Regex};
+
+/// Wraps a [`CountUnique`] to only count lines that match a regex. With a key group, the text that
+/// group captured is counted in place of the whole line.
+///
+/// The regex is run on each line before the wrapped counter's line mapper, so `--trim`, `--lower`,
+/// and the other input options apply to the counted key but not to the text the regex sees. Running
+/// a regex costs far more than hashing a line, so expect counting to be several times slower than
+/// without `--match`, depending on the pattern.
+pub struct MatchingCounter<C> {
+    inner: C,
+    regex: Regex,
+    /// the capture group to count instead of the whole line, if any
+    key_group: Option<usize>,
+    /// reused between lines, so that finding a capture group doesn't allocate
+    locations: CaptureLocations,
+}
+
+impl<C: CountUnique> MatchingCounter<C> {
+    /// Creates a new [`MatchingCounter`] counting the lines that match `pattern`, or the text of
+    /// capture group `key_group` in those lines if it is given. Fails if `pattern` is not a valid
+    /// regex or does not have a group numbered `key_group`.
+    pub fn new(inner: C, pattern: &str, key_group: Option<usize>) -> Result<Self, Error> {
+        let regex = Regex::new(pattern).map_err(|e| Error::message(format!("invalid --match regex: {e}")))?;
+        if let Some(group) = key_group {
+            let groups = regex.captures_len() - 1;
+            if group > groups {
+                return Err(Error::message(format!("--key-group {group} is out of range, as the --match regex has {groups} capture groups")));
+            }
+        }
+        let locations = regex.capture_locations();
+        Ok(MatchingCounter { inner, regex, key_group, locations })
+    }
+
+    /// The wrapped counter, mutably
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    /// The part of `line` to count, or `None` if it should be skipped. A key group that did not
+    /// take part in the match, such as one side of an alternation, also skips the line.
+    #[inline(always)]
+    fn key<'a>(regex: &Regex, key_group: Option<usize>, locations: &mut CaptureLocations, line: &'a [u8]) -> Option<&'a [u8]> {
+        match key_group {
+            None => regex.is_match(line).then_some(line),
+            Some(0) => regex.find(line).map(|found| found.as_bytes()),
+            Some(group) => {
+                regex.captures_read(locations, line)?;
+                let (start, end) = locations.get(group)?;
+                Some(&line[start..end])
+            }
+        }
+    }
+}
+
+impl<C: CountUnique> CountUnique for MatchingCounter<C> {
+    #[inline(always)]
+    fn count_line(&mut self, line: &[u8]) {
+        if let Some(key) = Self::key(&self.regex, self.key_group, &mut self.locations, line) {
+            self.inner.count_line(key);
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.inner.count()
+    }
+
+    /// The memory of the wrapped counter, plus this wrapper's own fields. Memory used by the
+    /// compiled regex is not included.
+    fn estimated_memory(&self) -> usize {
+        size_of::<Self>() - size_of::<C>() + self.inner.estimated_memory()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use line_cardinality::{EmitLines, LineCounter};
+
+    use super::*;
+
+    /// The distinct keys counted in `input`, sorted
+    fn keys(pattern: &str, key_group: Option<usize>, input: &[u8]) -> Vec<Vec<u8>> {
+        let mut counter = MatchingCounter::new(LineCounter::new(), pattern, key_group).unwrap();
+        counter.count_unique_in_bytes(input);
+        let mut keys = counter.inner.into_vec();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn test_match_filters_lines() {
+        assert_eq!(keys("ERROR", None, b"ERROR a\nINFO b\nERROR a\nERROR c\n"), [b"ERROR a".to_vec(), b"ERROR c".to_vec()]);
+        assert!(keys("^$", None, b"a\nb\n").is_empty());
+    }
+
+    #[test]
+    fn test_key_group() {
+        let input = b"user=alice op=read\nuser=bob op=read\nanonymous op=read\nuser=alice op=write\n";
+        assert_eq!(keys(r"user=(\w+)", Some(1), input), [b"alice".to_vec(), b"bob".to_vec()]);
+        assert_eq!(keys(r"op=(?<op>\w+)", Some(1), input), [b"read".to_vec(), b"write".to_vec()]);
+        // group 0 is the whole match
+        assert_eq!(keys(r"op=\w+", Some(0), input), [b"op=read".to_vec(), b"op=write".to_vec()]);
+    }
+
+    #[test]
+    fn test_key_group_not_in_match() {
+        assert_eq!(keys(r"id=(\d+)|anonymous", Some(1), b"id=1\nanonymous\nid=2\n"), [b"1".to_vec(), b"2".to_vec()]);
+        // an empty capture is still counted
+        assert_eq!(keys(r"id=(\d*)", Some(1), b"id=\nid=3\n"), [b"".to_vec(), b"3".to_vec()]);
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        assert!(MatchingCounter::new(LineCounter::new(), "(", None).is_err());
+        assert!(MatchingCounter::new(LineCounter::new(), r"a(b)", Some(2)).is_err());
+        assert!(MatchingCounter::new(LineCounter::new(), r"ab", Some(1)).is_err());
+        assert!(MatchingCounter::new(LineCounter::new(), r"ab", Some(0)).is_ok());
+    }
+
+    #[test]
+    fn test_non_utf8_lines() {
+        assert_eq!(keys(r"(?-u)k=(\S+)", Some(1), b"k=\xFF\xFE\nk=ok\n"), [b"ok".to_vec(), b"\xFF\xFE".to_vec()]);
+    }
+}
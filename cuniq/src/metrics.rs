@@ -0,0 +1,213 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Writing a summary of a count to `--metrics-file`, in the Prometheus textfile collector format,
+//! and sending it to `--statsd`
+
+use std::io::{self, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+use line_cardinality::Error;
+
+use crate::cli_args::Mode;
+
+/// A `key=value` label added to every metric by `--metrics-label`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MetricLabel {
+    pub name: String,
+    pub value: String,
+}
+
+/// Labels cuniq sets itself, which `--metrics-label` can't override
+pub const RESERVED_LABELS: [&str; 2] = ["mode", "input"];
+
+/// What a finished count found, and what it read to find it
+pub struct RunSummary {
+    pub mode: Mode,
+    /// names of the inputs, separated by commas
+    pub inputs: String,
+    pub distinct: usize,
+    /// records read, or `None` if they weren't counted
+    pub lines: Option<u64>,
+    /// bytes read, or `None` if they weren't counted
+    pub bytes: Option<u64>,
+    pub duration: Duration,
+}
+
+/// One value from a [`RunSummary`]
+struct Metric {
+    /// name, without the prefix
+    name: &'static str,
+    help: &'static str,
+    value: String,
+}
+
+impl RunSummary {
+    /// Every metric this summary has a value for. Metrics without a value are left out, rather
+    /// than reported as zero.
+    fn metrics(&self) -> Vec<Metric> {
+        let mut metrics = vec![Metric { name: "distinct_lines", help: "Distinct lines counted by the last run.", value: self.distinct.to_string() }];
+        if let Some(lines) = self.lines {
+            metrics.push(Metric { name: "input_lines", help: "Lines read by the last run.", value: lines.to_string() });
+        }
+        if let Some(bytes) = self.bytes {
+            metrics.push(Metric { name: "input_bytes", help: "Bytes read by the last run.", value: bytes.to_string() });
+        }
+        metrics.push(Metric { name: "duration_seconds", help: "How long the last run took to read and count its input.", value: format!("{:.6}", self.duration.as_secs_f64()) });
+        metrics
+    }
+}
+
+/// Write `summary` in the Prometheus text exposition format, as read by node_exporter's textfile
+/// collector. Every metric is a gauge with the `mode` and `input` labels followed by `labels`.
+pub fn write_textfile<W: Write>(writer: &mut W, prefix: &str, labels: &[MetricLabel], summary: &RunSummary) -> io::Result<()> {
+    let mut label_set = format!("mode=\"{}\",input=\"{}\"", summary.mode, escape_label_value(&summary.inputs));
+    for label in labels {
+        label_set.push_str(&format!(",{}=\"{}\"", label.name, escape_label_value(&label.value)));
+    }
+    for metric in summary.metrics() {
+        writeln!(writer, "# HELP {prefix}_{} {}", metric.name, metric.help)?;
+        writeln!(writer, "# TYPE {prefix}_{} gauge", metric.name)?;
+        writeln!(writer, "{prefix}_{}{{{label_set}}} {}", metric.name, metric.value)?;
+    }
+    Ok(())
+}
+
+/// Send each metric in `summary` to the StatsD server at `address` as a gauge, one per datagram.
+/// Plain StatsD has no labels, so only the prefix distinguishes runs.
+pub fn send_statsd(address: &str, prefix: &str, summary: &RunSummary) -> Result<(), Error> {
+    let error = || format!("failed to send metrics to StatsD at \"{address}\"");
+    let target = address.to_socket_addrs()
+        .map_err(|e| Error::io(error(), e))?
+        .next()
+        .ok_or_else(|| Error::message(format!("--statsd \"{address}\" did not resolve to any address")))?;
+    let local: SocketAddr = match target {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local).map_err(|e| Error::io(error(), e))?;
+    for metric in summary.metrics() {
+        let datagram = format!("{prefix}_{}:{}|g", metric.name, metric.value);
+        socket.send_to(datagram.as_bytes(), target).map_err(|e| Error::io(error(), e))?;
+    }
+    Ok(())
+}
+
+/// Escape a label value for the text exposition format, which only escapes backslashes, double
+/// quotes, and newlines
+fn escape_label_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Parse a `--metrics-prefix`, which must be a valid Prometheus metric name
+pub fn parse_metrics_prefix(arg: &str) -> Result<String, String> {
+    let mut chars = arg.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':');
+    if valid {
+        Ok(arg.to_string())
+    } else {
+        Err("the prefix must start with a letter, '_', or ':', followed by letters, digits, '_', or ':'".to_string())
+    }
+}
+
+/// Parse a `--statsd` address. The host is only resolved when the metrics are sent, but the port is
+/// checked here so that a typo fails before counting rather than after.
+pub fn parse_statsd_address(arg: &str) -> Result<String, String> {
+    match arg.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(arg.to_string()),
+        _ => Err(format!("expected HOST:PORT, but got \"{arg}\"")),
+    }
+}
+
+/// Parse a `--metrics-label` of the form `name=value`. The name must be a valid Prometheus label
+/// name, and the value can be anything.
+pub fn parse_metric_label(arg: &str) -> Result<MetricLabel, String> {
+    let (name, value) = arg.split_once('=').ok_or_else(|| format!("expected name=value, but got \"{arg}\""))?;
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!("label name \"{name}\" must start with a letter or '_', followed by letters, digits, or '_'"));
+    }
+    if name.starts_with("__") {
+        return Err(format!("label name \"{name}\" is reserved, as it starts with \"__\""));
+    }
+    if RESERVED_LABELS.contains(&name) {
+        return Err(format!("label name \"{name}\" is already set by cuniq"));
+    }
+    Ok(MetricLabel { name: name.to_string(), value: value.to_string() })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn summary() -> RunSummary {
+        RunSummary {
+            mode: Mode::Exact,
+            inputs: "stdin".to_string(),
+            distinct: 3,
+            lines: Some(10),
+            bytes: None,
+            duration: Duration::from_millis(1500),
+        }
+    }
+
+    #[test]
+    fn test_write_textfile() {
+        let mut output = Vec::new();
+        let labels = [MetricLabel { name: "job".to_string(), value: "nightly".to_string() }];
+        write_textfile(&mut output, "cuniq", &labels, &summary()).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("# TYPE cuniq_distinct_lines gauge\ncuniq_distinct_lines{mode=\"exact\",input=\"stdin\",job=\"nightly\"} 3\n"), "{output}");
+        assert!(output.contains("cuniq_input_lines{mode=\"exact\",input=\"stdin\",job=\"nightly\"} 10\n"), "{output}");
+        assert!(output.contains("cuniq_duration_seconds{mode=\"exact\",input=\"stdin\",job=\"nightly\"} 1.500000\n"), "{output}");
+        assert!(!output.contains("input_bytes"), "{output}");
+    }
+
+    #[test]
+    fn test_escape_label_value() {
+        assert_eq!(escape_label_value("plain"), "plain");
+        assert_eq!(escape_label_value("C:\\logs\\\"a\"\nb"), "C:\\\\logs\\\\\\\"a\\\"\\nb");
+    }
+
+    #[test]
+    fn test_parse_metrics_prefix() {
+        assert_eq!(parse_metrics_prefix("cuniq"), Ok("cuniq".to_string()));
+        assert_eq!(parse_metrics_prefix("job:cuniq_2"), Ok("job:cuniq_2".to_string()));
+        assert!(parse_metrics_prefix("").is_err());
+        assert!(parse_metrics_prefix("2cuniq").is_err());
+        assert!(parse_metrics_prefix("cu-niq").is_err());
+    }
+
+    #[test]
+    fn test_parse_statsd_address() {
+        assert_eq!(parse_statsd_address("localhost:8125"), Ok("localhost:8125".to_string()));
+        assert_eq!(parse_statsd_address("[::1]:8125"), Ok("[::1]:8125".to_string()));
+        assert!(parse_statsd_address("localhost").is_err());
+        assert!(parse_statsd_address(":8125").is_err());
+        assert!(parse_statsd_address("localhost:statsd").is_err());
+    }
+
+    #[test]
+    fn test_parse_metric_label() {
+        assert_eq!(parse_metric_label("env=prod=eu"), Ok(MetricLabel { name: "env".to_string(), value: "prod=eu".to_string() }));
+        assert_eq!(parse_metric_label("_empty="), Ok(MetricLabel { name: "_empty".to_string(), value: String::new() }));
+        assert!(parse_metric_label("env").is_err());
+        assert!(parse_metric_label("=prod").is_err());
+        assert!(parse_metric_label("my-env=prod").is_err());
+        assert!(parse_metric_label("__name__=x").is_err());
+        assert!(parse_metric_label("mode=x").is_err());
+    }
+}
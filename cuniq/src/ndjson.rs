@@ -0,0 +1,80 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Report entries as newline-delimited JSON, for `--format ndjson`
+
+use std::io::{self, Write};
+
+/// Write one report entry as a JSON object on its own line. A line that is valid UTF-8 is written as
+/// a string: `{"line":"foo","count":3}`. JSON strings can't hold arbitrary bytes, so any other line
+/// is written as an array of its bytes instead: `{"line_bytes":[102,111,255],"count":3}`.
+pub fn write_ndjson_line<W: Write>(writer: &mut W, line: &[u8], count: u64) -> io::Result<()> {
+    if std::str::from_utf8(line).is_ok() {
+        writer.write_all(b"{\"line\":\"")?;
+        write_escaped(writer, line)?;
+        writer.write_all(b"\"")?;
+    } else {
+        writer.write_all(b"{\"line_bytes\":[")?;
+        for (index, byte) in line.iter().enumerate() {
+            if index != 0 {
+                writer.write_all(b",")?;
+            }
+            write!(writer, "{byte}")?;
+        }
+        writer.write_all(b"]")?;
+    }
+    writeln!(writer, ",\"count\":{count}}}")
+}
+
+/// Write the contents of a JSON string. `line` must be valid UTF-8, which is passed through as-is
+/// apart from the characters JSON requires to be escaped.
+pub fn write_escaped<W: Write>(writer: &mut W, line: &[u8]) -> io::Result<()> {
+    let mut start = 0;
+    for (index, byte) in line.iter().enumerate() {
+        if !matches!(byte, b'"' | b'\\' | 0x00..=0x1F) {
+            continue;
+        }
+        writer.write_all(&line[start..index])?;
+        match byte {
+            b'"' => writer.write_all(b"\\\"")?,
+            b'\\' => writer.write_all(b"\\\\")?,
+            b'\n' => writer.write_all(b"\\n")?,
+            b'\r' => writer.write_all(b"\\r")?,
+            b'\t' => writer.write_all(b"\\t")?,
+            0x08 => writer.write_all(b"\\b")?,
+            0x0C => writer.write_all(b"\\f")?,
+            _ => write!(writer, "\\u{byte:04x}")?,
+        }
+        start = index + 1;
+    }
+    writer.write_all(&line[start..])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ndjson(line: &[u8], count: u64) -> String {
+        let mut output = Vec::new();
+        write_ndjson_line(&mut output, line, count).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_utf8() {
+        assert_eq!(ndjson(b"foo", 3), "{\"line\":\"foo\",\"count\":3}\n");
+        assert_eq!(ndjson(b"", 1), "{\"line\":\"\",\"count\":1}\n");
+        assert_eq!(ndjson("straße ✓".as_bytes(), 12), "{\"line\":\"straße ✓\",\"count\":12}\n");
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert_eq!(ndjson(b"a\"b\\c", 1), r#"{"line":"a\"b\\c","count":1}"#.to_string() + "\n");
+        assert_eq!(ndjson(b"\t\r\x08\x0c\x00\x1f\x7f", 1), r#"{"line":"\t\r\b\f\u0000\u001f"#.to_string() + "\x7f\",\"count\":1}\n");
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        assert_eq!(ndjson(b"fo\xff", 2), "{\"line_bytes\":[102,111,255],\"count\":2}\n");
+    }
+}
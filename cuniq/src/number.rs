@@ -0,0 +1,153 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Formatting numbers for display
+
+use crate::cli_args::Precision;
+
+/// How many standard errors either side of an estimate `--precision interval` covers. A correct
+/// estimate is within three standard errors of the true count over 99% of the time.
+const INTERVAL_STANDARD_ERRORS: f64 = 3.0;
+
+/// Format an estimated count with `precision`. `standard_error` is the estimate's relative
+/// standard error.
+pub fn format_estimate(estimate: u64, standard_error: f64, precision: Precision) -> String {
+    match precision {
+        Precision::ExactInteger => estimate.to_string(),
+        Precision::Sig2 => round_significant(estimate, 2).to_string(),
+        Precision::Sig3 => round_significant(estimate, 3).to_string(),
+        Precision::Interval => {
+            let (low, high) = interval(estimate, INTERVAL_STANDARD_ERRORS * standard_error);
+            format!("{low}..{high}")
+        }
+    }
+}
+
+/// Round `value` to `digits` significant digits, rounding halves up. Values below 1000 are
+/// returned unchanged, as small estimates are precise enough to show in full.
+pub fn round_significant(value: u64, digits: u32) -> u64 {
+    if value < 1000 {
+        return value;
+    }
+    let value_digits = value.ilog10() + 1;
+    if value_digits <= digits {
+        return value;
+    }
+    let divisor = 10u64.pow(value_digits - digits);
+    let quotient = value / divisor;
+    let rounded_up = value % divisor >= divisor / 2;
+    // rounding up can only overflow for values within a rounding step of u64::MAX
+    match rounded_up.then(|| (quotient + 1).checked_mul(divisor)).flatten() {
+        Some(rounded) => rounded,
+        None => quotient * divisor,
+    }
+}
+
+/// The range within `relative_error` of `value`, rounded to whole numbers. Rounding rather than
+/// widening keeps a small estimate, which is almost always exact, from being shown as a range
+/// around it, such as `0..2` for an estimate of 1.
+pub fn interval(value: u64, relative_error: f64) -> (u64, u64) {
+    // float to integer casts saturate, so neither bound can overflow
+    let low = (value as f64 * (1.0 - relative_error)).round().max(0.0) as u64;
+    let high = (value as f64 * (1.0 + relative_error)).round() as u64;
+    (low.min(value), high.max(value))
+}
+
+/// The number of decimal digits in `value`
+pub fn digits(value: u64) -> usize {
+    value.checked_ilog10().map_or(1, |log| log as usize + 1)
+}
+
+/// Format a byte count with a binary unit, such as `1.5 MiB`
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_significant() {
+        // small values are never rounded
+        assert_eq!(round_significant(0, 2), 0);
+        assert_eq!(round_significant(7, 2), 7);
+        assert_eq!(round_significant(999, 2), 999);
+        assert_eq!(round_significant(1000, 2), 1000);
+        assert_eq!(round_significant(1049, 2), 1000);
+        assert_eq!(round_significant(1050, 2), 1100);
+        assert_eq!(round_significant(1234, 3), 1230);
+        assert_eq!(round_significant(1235, 3), 1240);
+        assert_eq!(round_significant(9999, 2), 10000);
+        assert_eq!(round_significant(1_234_567, 2), 1_200_000);
+        assert_eq!(round_significant(1_234_567, 3), 1_230_000);
+        assert_eq!(round_significant(1_250_000, 2), 1_300_000);
+        assert_eq!(round_significant(12_345_678_901_234, 3), 12_300_000_000_000);
+        // already short enough
+        assert_eq!(round_significant(1200, 2), 1200);
+        assert_eq!(round_significant(4321, 4), 4321);
+        assert_eq!(round_significant(4321, 5), 4321);
+    }
+
+    #[test]
+    fn test_round_significant_near_max() {
+        assert_eq!(round_significant(u64::MAX, 2), 18_000_000_000_000_000_000);
+        assert_eq!(round_significant(u64::MAX, 3), 18_400_000_000_000_000_000);
+        // rounding up would overflow, so this rounds down instead
+        assert_eq!(round_significant(u64::MAX, 19), 18_446_744_073_709_551_610);
+        assert_eq!(round_significant(u64::MAX, 20), u64::MAX);
+    }
+
+    #[test]
+    fn test_format_estimate() {
+        assert_eq!(format_estimate(1_234_567, 0.01, Precision::ExactInteger), "1234567");
+        assert_eq!(format_estimate(1_234_567, 0.01, Precision::Sig2), "1200000");
+        assert_eq!(format_estimate(1_234_567, 0.01, Precision::Sig3), "1230000");
+        assert_eq!(format_estimate(10_000, 0.01, Precision::Interval), "9700..10300");
+        assert_eq!(format_estimate(0, 0.01, Precision::Interval), "0..0");
+        assert_eq!(format_estimate(100, 0.5, Precision::Interval), "0..250");
+        assert!(format_estimate(u64::MAX, 0.01, Precision::Interval).ends_with(&format!("..{}", u64::MAX)));
+    }
+
+    #[test]
+    fn test_interval() {
+        assert_eq!(interval(0, 0.05), (0, 0));
+        assert_eq!(interval(1, 0.05), (1, 1));
+        assert_eq!(interval(10, 0.05), (10, 11));
+        assert_eq!(interval(9_999, 0.05), (9_499, 10_499));
+        // an error of over 100% can't go below 0
+        assert_eq!(interval(100, 1.5), (0, 250));
+        assert_eq!(interval(u64::MAX, 0.05), (17_524_406_870_024_073_216, u64::MAX));
+    }
+
+    #[test]
+    fn test_digits() {
+        assert_eq!(digits(0), 1);
+        assert_eq!(digits(9), 1);
+        assert_eq!(digits(10), 2);
+        assert_eq!(digits(9_999_999), 7);
+        assert_eq!(digits(10_000_000), 8);
+        assert_eq!(digits(u64::MAX), 20);
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1024), "1.0 KiB");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(7 << 20), "7.0 MiB");
+        assert_eq!(format_bytes(3 << 40), "3.0 TiB");
+        assert_eq!(format_bytes(u64::MAX), "16777216.0 TiB");
+    }
+}
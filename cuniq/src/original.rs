@@ -0,0 +1,140 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Reporting lines as they originally appeared, rather than after line mapping
+
+use std::collections::HashMap;
+
+use line_cardinality::{ContainsLine, CountUnique};
+
+use crate::masking::ByteRanges;
+use crate::sizing::hash_map_with_capacity;
+use crate::Count;
+
+/// The first original form of a mapped line, and how many times the mapped line was seen
+struct Entry {
+    /// `None` if the original line is identical to the mapped line, which avoids storing it twice
+    original: Option<Box<[u8]>>,
+    count: Count,
+}
+
+/// A [`CountUnique`] that counts occurrences of each mapped line, but remembers the first original
+/// line that mapped to it so that reports can show the line as it appeared in the input.
+pub struct OriginalLineCounter<M> {
+    map: HashMap<Vec<u8>, Entry>,
+    string_buffer: Vec<u8>,
+    line_mapper: M,
+    /// removed from each line before it is mapped, for `--ignore-bytes`
+    ignored_bytes: Option<ByteRanges>,
+    /// the line with `ignored_bytes` removed
+    key_buffer: Vec<u8>,
+}
+
+impl<M> OriginalLineCounter<M>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    pub fn with_line_mapper_and_capacity(line_mapper: M, capacity: usize) -> Self {
+        OriginalLineCounter {
+            map: hash_map_with_capacity(capacity),
+            string_buffer: Vec::new(),
+            line_mapper,
+            ignored_bytes: None,
+            key_buffer: Vec::new(),
+        }
+    }
+
+    /// Compare lines with the bytes in `ranges` removed, before the line mapper is applied. This
+    /// is done here rather than by the line mapper, so that the original line is still known.
+    pub fn with_ignored_bytes(mut self, ranges: Option<ByteRanges>) -> Self {
+        self.ignored_bytes = ranges;
+        self
+    }
+}
+
+impl<M> OriginalLineCounter<M> {
+    /// Iterate over the first original form of each distinct line and its occurrence count
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], Count)> {
+        self.map.iter()
+            .map(|(line, entry)| (entry.original.as_deref().unwrap_or(line), entry.count))
+    }
+}
+
+impl<M> CountUnique for OriginalLineCounter<M>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    #[inline(always)]
+    fn count_line(&mut self, line: &[u8]) {
+        let key = match &self.ignored_bytes {
+            Some(ranges) => {
+                ranges.remove(line, &mut self.key_buffer);
+                self.key_buffer.as_slice()
+            }
+            None => line,
+        };
+        let mapped = (self.line_mapper)(key, &mut self.string_buffer);
+        match self.map.get_mut(mapped) {
+            Some(entry) => entry.count += 1,
+            None => {
+                let original = (mapped != line).then(|| line.into());
+                self.map.insert(mapped.to_vec(), Entry { original, count: 1 });
+            }
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.map.len()
+    }
+
+    fn reset(&mut self) {
+        self.map.clear();
+    }
+}
+
+impl<M> ContainsLine for OriginalLineCounter<M>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    fn contains_line(&mut self, line: &[u8]) -> bool {
+        let key = match &self.ignored_bytes {
+            Some(ranges) => {
+                ranges.remove(line, &mut self.key_buffer);
+                self.key_buffer.as_slice()
+            }
+            None => line,
+        };
+        let mapped = (self.line_mapper)(key, &mut self.string_buffer);
+        self.map.contains_key(mapped)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use line_cardinality::strip_ansi;
+
+    use super::*;
+
+    #[test]
+    fn test_first_original_is_reported() {
+        let mut counter = OriginalLineCounter::with_line_mapper_and_capacity(strip_ansi, 0);
+        counter.count_unique_in_bytes(b"\x1B[31mred\x1B[0m\nred\nplain\n\x1B[1mplain");
+
+        let mut report: Vec<(&[u8], Count)> = counter.iter().collect();
+        report.sort_unstable();
+        assert_eq!(report, [(b"\x1B[31mred\x1B[0m".as_slice(), 2), (b"plain", 2)]);
+    }
+
+    #[test]
+    fn test_ignored_bytes() {
+        let ranges = crate::masking::parse_byte_ranges("0-2").unwrap();
+        let mut counter = OriginalLineCounter::with_line_mapper_and_capacity(strip_ansi, 0).with_ignored_bytes(Some(ranges));
+        counter.count_unique_in_bytes(b"01:a\n02:a\n03:\x1B[1mb\n04:b\n");
+        assert!(counter.contains_line(b"99:a"));
+        assert!(!counter.contains_line(b"a"));
+
+        let mut report: Vec<(&[u8], Count)> = counter.iter().collect();
+        report.sort_unstable();
+        assert_eq!(report, [(b"01:a".as_slice(), 2), (b"03:\x1B[1mb", 2)]);
+    }
+}
@@ -0,0 +1,399 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Destinations that output can be written to
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, StdoutLock, Write};
+use std::path::{Path, PathBuf};
+
+use line_cardinality::Error;
+
+use crate::cli_args::Compression;
+use crate::paths::escaped;
+
+static OUTPUT_FILE_ERROR_MESSAGE: &str = "failed to finish writing output file";
+
+/// Writes to a temporary file next to the destination path, which is only moved into place once
+/// [`AtomicFileWriter::finish`] is called. If dropped before then the temporary file is deleted, so
+/// a partially written (or truncated) output file is never left behind.
+pub struct AtomicFileWriter {
+    writer: Option<BufWriter<File>>,
+    temp_path: PathBuf,
+    path: PathBuf,
+}
+
+impl AtomicFileWriter {
+    pub fn create(path: &Path) -> Result<Self, Error> {
+        let temp_path = temp_path_for(path);
+        let file = File::create(&temp_path)
+            .map_err(|e| Error::io(format!("error creating output file \"{}\"", escaped(path)), e))?;
+        Ok(Self {
+            writer: Some(BufWriter::new(file)),
+            temp_path,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Flush all remaining data and move the file to its final destination
+    pub fn finish(mut self) -> Result<(), Error> {
+        let writer = self.writer.take().expect("AtomicFileWriter already finished");
+        let file = writer.into_inner()
+            .map_err(|e| Error::io_static(OUTPUT_FILE_ERROR_MESSAGE, e.into_error()))?;
+        file.sync_all().map_err(|e| Error::io_static(OUTPUT_FILE_ERROR_MESSAGE, e))?;
+        drop(file);
+        fs::rename(&self.temp_path, &self.path)
+            .map_err(|e| Error::io(format!("error moving output file into place at \"{}\"", escaped(&self.path)), e))
+    }
+
+    fn writer(&mut self) -> &mut BufWriter<File> {
+        self.writer.as_mut().expect("AtomicFileWriter already finished")
+    }
+}
+
+impl Write for AtomicFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer().write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.writer().write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer().flush()
+    }
+}
+
+impl Drop for AtomicFileWriter {
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.take() {
+            // never finished, so the contents may be incomplete
+            drop(writer);
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+/// Hidden sibling of `path` used while the output is being written
+fn temp_path_for(path: &Path) -> PathBuf {
+    let mut file_name = OsString::from(".");
+    file_name.push(path.file_name().unwrap_or_default());
+    file_name.push(format!(".{}.tmp", std::process::id()));
+    path.with_file_name(file_name)
+}
+
+/// Passes writes through to `W`, optionally holding back a newline at the end of a write until
+/// more output follows it. A newline that is still held back when writing stops is never written,
+/// so the last line of output has no trailing newline.
+pub struct TrailingNewline<W> {
+    inner: W,
+    /// whether newlines at the end of a write are held back
+    hold: bool,
+    /// whether a newline was held back and not yet written
+    pending: bool,
+}
+
+impl<W: Write> TrailingNewline<W> {
+    /// Wrap `inner`, which only has the final newline removed if `trailing_newline` is false
+    pub fn new(inner: W, trailing_newline: bool) -> Self {
+        Self { inner, hold: !trailing_newline, pending: false }
+    }
+
+    /// Unwrap the writer, discarding any held back newline
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for TrailingNewline<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        if !self.hold || buf.is_empty() {
+            return self.inner.write_all(buf);
+        }
+        if self.pending {
+            self.inner.write_all(b"\n")?;
+            self.pending = false;
+        }
+        match buf.split_last() {
+            Some((b'\n', rest)) => {
+                self.inner.write_all(rest)?;
+                self.pending = true;
+                Ok(())
+            }
+            _ => self.inner.write_all(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Where output is being sent to, and whether the last line ends in a newline
+pub struct Output {
+    writer: TrailingNewline<Destination>,
+}
+
+/// Compressed output is buffered before the encoder as well as after it, as encoders have a high
+/// per-call overhead and report lines are written in several small pieces.
+enum Destination {
+    Stdout(BufWriter<StdoutLock<'static>>),
+    File(AtomicFileWriter),
+    #[cfg(feature = "gzip")]
+    Gzip(BufWriter<flate2::write::GzEncoder<AtomicFileWriter>>),
+    #[cfg(feature = "zstd")]
+    Zstd(BufWriter<zstd::Encoder<'static, AtomicFileWriter>>),
+}
+
+impl Output {
+    /// Open an output destination. If no path is given stdout is used. If `compression` is not
+    /// specified it is guessed from the file extension.
+    pub fn open(path: Option<&Path>, compression: Option<Compression>, level: Option<u32>) -> Result<Self, Error> {
+        let destination = Destination::open(path, compression, level)?;
+        Ok(Output { writer: TrailingNewline::new(destination, true) })
+    }
+
+    /// Set whether the last line of output ends in a newline, which it does by default
+    pub fn trailing_newline(self, trailing_newline: bool) -> Self {
+        Output { writer: TrailingNewline::new(self.writer.into_inner(), trailing_newline) }
+    }
+
+    /// Flush all output. For files this also writes any compression trailer and moves the file
+    /// into place, so this must be called exactly once after all output has been written.
+    pub fn finish(self) -> Result<(), Error> {
+        self.writer.into_inner().finish()
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.writer.write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl Destination {
+    fn open(path: Option<&Path>, compression: Option<Compression>, level: Option<u32>) -> Result<Self, Error> {
+        let Some(path) = path else {
+            return Ok(Destination::Stdout(BufWriter::new(io::stdout().lock())));
+        };
+        let compression = compression.unwrap_or_else(|| Compression::from_path(path));
+        match compression {
+            Compression::None => {
+                if let Some(level) = level {
+                    return Err(Error::message(format!("--compression-level {level} was given, but \"{}\" is not compressed. Use a .gz or .zst file name, or --output-compression.", escaped(path))));
+                }
+                Ok(Destination::File(AtomicFileWriter::create(path)?))
+            }
+            Compression::Gzip => {
+                cfg_if::cfg_if! {
+                    if #[cfg(feature = "gzip")] {
+                        let level = match level {
+                            Some(level @ 0..=9) => flate2::Compression::new(level),
+                            Some(level) => return Err(Error::message(format!("gzip compression level must be between 0 and 9, but was {level}"))),
+                            None => flate2::Compression::default(),
+                        };
+                        Ok(Destination::Gzip(BufWriter::new(flate2::write::GzEncoder::new(AtomicFileWriter::create(path)?, level))))
+                    } else {
+                        let _ = level;
+                        Err(Error::message_static("This cuniq binary was compiled without gzip support"))
+                    }
+                }
+            }
+            Compression::Zstd => {
+                cfg_if::cfg_if! {
+                    if #[cfg(feature = "zstd")] {
+                        let level = match level {
+                            Some(level @ 1..=22) => level as i32,
+                            Some(level) => return Err(Error::message(format!("zstd compression level must be between 1 and 22, but was {level}"))),
+                            None => zstd::DEFAULT_COMPRESSION_LEVEL,
+                        };
+                        let encoder = zstd::Encoder::new(AtomicFileWriter::create(path)?, level)
+                            .map_err(|e| Error::io_static("failed to initialize zstd compression", e))?;
+                        Ok(Destination::Zstd(BufWriter::new(encoder)))
+                    } else {
+                        let _ = level;
+                        Err(Error::message_static("This cuniq binary was compiled without zstd support"))
+                    }
+                }
+            }
+        }
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        match self {
+            Destination::Stdout(mut writer) => writer.flush().map_err(|e| Error::io_static(crate::OUTPUT_ERROR_MESSAGE, e)),
+            Destination::File(writer) => writer.finish(),
+            // the encoders must be finished to write their trailers, and only then can the file be
+            // moved into place
+            #[cfg(feature = "gzip")]
+            Destination::Gzip(writer) => writer.into_inner()
+                .map_err(|e| Error::io_static(OUTPUT_FILE_ERROR_MESSAGE, e.into_error()))?
+                .finish()
+                .map_err(|e| Error::io_static(OUTPUT_FILE_ERROR_MESSAGE, e))?
+                .finish(),
+            #[cfg(feature = "zstd")]
+            Destination::Zstd(writer) => writer.into_inner()
+                .map_err(|e| Error::io_static(OUTPUT_FILE_ERROR_MESSAGE, e.into_error()))?
+                .finish()
+                .map_err(|e| Error::io_static(OUTPUT_FILE_ERROR_MESSAGE, e))?
+                .finish(),
+        }
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Destination::Stdout(writer) => writer,
+            Destination::File(writer) => writer,
+            #[cfg(feature = "gzip")]
+            Destination::Gzip(writer) => writer,
+            #[cfg(feature = "zstd")]
+            Destination::Zstd(writer) => writer,
+        }
+    }
+}
+
+impl Write for Destination {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer().write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.writer().write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer().flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static REPORT: &[u8] = b"      3 three\n      2 two\n      1 one\n";
+
+    /// A unique path in the system temp directory for a test to write to
+    fn test_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("cuniq-test-{}-{name}", std::process::id()))
+    }
+
+    fn write_output(path: &Path, compression: Option<Compression>) {
+        let mut output = Output::open(Some(path), compression, None).unwrap();
+        output.write_all(REPORT).unwrap();
+        output.finish().unwrap();
+    }
+
+    #[test]
+    fn test_compression_from_path() {
+        assert_eq!(Compression::from_path(Path::new("report.txt")), Compression::None);
+        assert_eq!(Compression::from_path(Path::new("report")), Compression::None);
+        assert_eq!(Compression::from_path(Path::new("report.gz")), Compression::Gzip);
+        assert_eq!(Compression::from_path(Path::new("report.zst")), Compression::Zstd);
+    }
+
+    #[test]
+    fn test_no_trailing_newline() {
+        let mut writer = TrailingNewline::new(Vec::new(), false);
+        writer.write_all(REPORT).unwrap();
+        writeln!(writer, "{:7} zero", 0).unwrap();
+        writer.write_all(b"").unwrap();
+        assert_eq!(writer.into_inner(), b"      3 three\n      2 two\n      1 one\n      0 zero");
+
+        let mut writer = TrailingNewline::new(Vec::new(), true);
+        writer.write_all(REPORT).unwrap();
+        assert_eq!(writer.into_inner(), REPORT);
+    }
+
+    #[test]
+    fn test_uncompressed() {
+        let path = test_path("uncompressed.txt");
+        write_output(&path, None);
+        assert_eq!(fs::read(&path).unwrap(), REPORT);
+        assert!(!temp_path_for(&path).exists());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_unfinished_file_is_removed() {
+        let path = test_path("unfinished.txt");
+        let mut output = Output::open(Some(&path), None, None).unwrap();
+        output.write_all(REPORT).unwrap();
+        output.flush().unwrap();
+        assert!(temp_path_for(&path).exists());
+        drop(output);
+        assert!(!temp_path_for(&path).exists());
+        assert!(!path.exists());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip() {
+        use std::io::Read;
+
+        let path = test_path("report.gz");
+        write_output(&path, None);
+        let mut decompressed = Vec::new();
+        flate2::read::GzDecoder::new(File::open(&path).unwrap()).read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, REPORT);
+        fs::remove_file(path).unwrap();
+    }
+
+    /// Enough small writes to flush the buffers in front of and behind the encoder many times
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip_large() {
+        use std::io::Read;
+
+        let path = test_path("large-report.gz");
+        let mut output = Output::open(Some(&path), None, None).unwrap();
+        for i in 0..100_000 {
+            writeln!(output, "{i:7} line").unwrap();
+        }
+        output.finish().unwrap();
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(File::open(&path).unwrap()).read_to_string(&mut decompressed).unwrap();
+        assert_eq!(decompressed.lines().count(), 100_000);
+        assert_eq!(decompressed.lines().last(), Some("  99999 line"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd() {
+        let path = test_path("report.out");
+        write_output(&path, Some(Compression::Zstd));
+        let decompressed = zstd::decode_all(File::open(&path).unwrap()).unwrap();
+        assert_eq!(decompressed, REPORT);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_level_without_compression() {
+        let path = test_path("uncompressed-level.txt");
+        assert!(Output::open(Some(&path), None, Some(5)).is_err());
+        assert!(!temp_path_for(&path).exists());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_invalid_gzip_level() {
+        let path = test_path("invalid-level.gz");
+        assert!(Output::open(Some(&path), None, Some(10)).is_err());
+        assert!(!temp_path_for(&path).exists());
+    }
+}
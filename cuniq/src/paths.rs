@@ -0,0 +1,80 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Formatting paths for output. A path can be any bytes on unix, or contain unpaired surrogates on
+//! Windows, and [`Path::display`] replaces anything that isn't UTF-8, so two different paths can be
+//! printed the same. Instead, paths are written one of two ways:
+//!
+//! - output that can hold any bytes, such as a report `--header`, gets the path's bytes as the OS
+//!   gave them, with [`os_bytes`]
+//! - output that must be UTF-8, such as messages on stderr and metrics labels, gets [`escaped`],
+//!   which keeps UTF-8 as it is and escapes everything else, so that no two paths look the same
+//!
+//! A format with its own escaping, such as JSON, should hold the escaped form rather than a lossy one.
+
+use std::ffi::OsStr;
+use std::fmt::{self, Display, Formatter};
+
+use bstr::ByteSlice;
+
+/// The bytes of `path` as the OS gave them. On Windows these are WTF-8, which is UTF-8 unless the
+/// path contains unpaired surrogates.
+pub fn os_bytes<P: AsRef<OsStr> + ?Sized>(path: &P) -> &[u8] {
+    path.as_ref().as_encoded_bytes()
+}
+
+/// Display `path` as UTF-8 without losing anything: each byte that isn't part of valid UTF-8 is
+/// written as `\xNN`, and so is a backslash that would otherwise be read as the start of one.
+pub fn escaped<P: AsRef<OsStr> + ?Sized>(path: &P) -> Escaped<'_> {
+    Escaped(os_bytes(path))
+}
+
+/// A path displayed by [`escaped`]
+pub struct Escaped<'a>(&'a [u8]);
+
+impl Display for Escaped<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for chunk in ByteSlice::utf8_chunks(self.0) {
+            let mut valid = chunk.valid();
+            while let Some(index) = valid.find("\\x") {
+                f.write_str(&valid[..index])?;
+                f.write_str("\\x5c")?;
+                valid = &valid[index + 1..];
+            }
+            f.write_str(valid)?;
+            for byte in chunk.invalid() {
+                write!(f, "\\x{byte:02x}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn test_escaped() {
+        assert_eq!(escaped("plain/file.txt").to_string(), "plain/file.txt");
+        assert_eq!(escaped(Path::new("日本/ファイル")).to_string(), "日本/ファイル");
+        assert_eq!(escaped(r"C:\logs\today.txt").to_string(), r"C:\logs\today.txt");
+        // a literal `\x` is escaped, so it can't be mistaken for an escaped byte
+        assert_eq!(escaped(r"a\x41\\x").to_string(), r"a\x5cx41\\x5cx");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_escaped_non_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = OsStr::from_bytes(b"caf\xe9/\xff\xfe.txt");
+        assert_eq!(os_bytes(path), b"caf\xe9/\xff\xfe.txt");
+        assert_eq!(escaped(path).to_string(), r"caf\xe9/\xff\xfe.txt");
+        // only the invalid byte of a truncated sequence is escaped
+        assert_eq!(escaped(OsStr::from_bytes(b"\xe6\x97a")).to_string(), r"\xe6\x97a");
+        assert_ne!(escaped(OsStr::from_bytes(b"\xff")).to_string(), escaped(OsStr::from_bytes(b"\xfe")).to_string());
+    }
+}
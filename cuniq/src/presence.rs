@@ -0,0 +1,107 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tracking which input each distinct line was found in
+
+use line_cardinality::{CountUnique, LineMap};
+
+/// Which input a line was found in.
+///
+/// Only one input is remembered, as the only question we need to answer is whether a line was
+/// found in exactly one input. This keeps the memory cost at 8 bytes per distinct line regardless
+/// of how many inputs there are, on top of the line's [`LineMap`] key.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Presence {
+    Single(u32),
+    Multiple,
+}
+
+/// A [`CountUnique`] that tracks which input each distinct line was found in. Call
+/// [`PresenceCounter::next_source`] before counting each input.
+pub struct PresenceCounter<M> {
+    map: LineMap<Presence>,
+    string_buffer: Vec<u8>,
+    source: u32,
+    line_mapper: M,
+}
+
+impl<M> PresenceCounter<M>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    pub fn with_line_mapper_and_capacity(line_mapper: M, capacity: usize) -> Self {
+        PresenceCounter {
+            map: LineMap::with_capacity(capacity),
+            string_buffer: Vec::new(),
+            source: 0,
+            line_mapper,
+        }
+    }
+}
+
+impl<M> PresenceCounter<M> {
+    /// Lines counted after this call are attributed to a new input
+    pub fn next_source(&mut self) {
+        self.source += 1;
+    }
+
+    /// Iterate over lines that were found in exactly one input
+    pub fn lines_in_single_source(&self) -> impl Iterator<Item = &[u8]> {
+        self.map.iter()
+            .filter(|(_line, presence)| matches!(presence, Presence::Single(_)))
+            .map(|(line, _presence)| line)
+    }
+}
+
+impl<M> CountUnique for PresenceCounter<M>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    #[inline(always)]
+    fn count_line(&mut self, line: &[u8]) {
+        let line = (self.line_mapper)(line, &mut self.string_buffer);
+        match self.map.get_mut(line) {
+            Some(presence) => {
+                if *presence != Presence::Single(self.source) {
+                    *presence = Presence::Multiple;
+                }
+            }
+            None => {
+                self.map.insert(line, Presence::Single(self.source));
+            }
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.map.len()
+    }
+
+    fn reset(&mut self) {
+        self.map.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn identity<'a>(line: &'a [u8], _buffer: &'a mut Vec<u8>) -> &'a [u8] {
+        line
+    }
+
+    #[test]
+    fn test_lines_in_single_source() {
+        let mut counter = PresenceCounter::with_line_mapper_and_capacity(identity, 0);
+        counter.next_source();
+        counter.count_unique_in_bytes(b"a\nshared\nb\na\nall");
+        counter.next_source();
+        counter.count_unique_in_bytes(b"shared\nc\nall\nc");
+        counter.next_source();
+        counter.count_unique_in_bytes(b"d\nall");
+
+        let mut lines: Vec<&[u8]> = counter.lines_in_single_source().collect();
+        lines.sort_unstable();
+        assert_eq!(lines, [b"a".as_slice(), b"b", b"c", b"d"]);
+        assert_eq!(counter.count(), 6);
+    }
+}
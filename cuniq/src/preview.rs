@@ -0,0 +1,189 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! `--preview`, which reads only the first lines of each input and projects the full distinct count
+//! from them
+
+use std::io::{self, BufRead, Read};
+
+use line_cardinality::{CountUnique, Error};
+
+use crate::logging::log_warn;
+
+/// Reads the first lines of each input, and keeps track of how many were read
+pub struct Preview {
+    lines_per_input: usize,
+    lines: u64,
+}
+
+impl Preview {
+    pub fn new(lines_per_input: usize) -> Self {
+        Preview { lines_per_input, lines: 0 }
+    }
+
+    /// Count the first lines of `reader` into `processor`
+    pub fn count<T: CountUnique, R: BufRead>(&mut self, processor: &mut T, reader: R) -> Result<(), Error> {
+        let mut limited = LineLimit::new(reader, self.lines_per_input);
+        processor.count_unique_in_read(&mut limited)?;
+        self.lines += limited.lines_read();
+        Ok(())
+    }
+
+    /// Print what the preview saw, and the projection for the whole input, to stderr. `total_bytes`
+    /// is the size of every input, if it is known.
+    pub fn log_summary(&self, distinct: usize, bytes_read: u64, total_bytes: Option<u64>) {
+        log_warn!("preview: only the first {} lines of each input were read, so this output is incomplete", self.lines_per_input);
+        log_warn!("preview: {distinct} distinct in {} lines read ({:.4} distinct per line)", self.lines, distinct_per_line(distinct, self.lines));
+        match total_bytes.and_then(|total_bytes| project(distinct, bytes_read, total_bytes)) {
+            Some(projected) => log_warn!(
+                "preview: projected about {projected} distinct over all {} bytes of input. This assumes new lines keep appearing at the rate they did in the preview, which usually makes it an overestimate.",
+                total_bytes.unwrap_or(0),
+            ),
+            None => log_warn!("preview: no projection is possible, as the size of the input is unknown"),
+        }
+    }
+}
+
+/// The fraction of lines read that were distinct, which is 0 if no lines were read
+fn distinct_per_line(distinct: usize, lines: u64) -> f64 {
+    if lines == 0 {
+        0.0
+    } else {
+        distinct as f64 / lines as f64
+    }
+}
+
+/// Project a distinct count seen in the first `bytes_read` bytes of the input over all
+/// `total_bytes`, assuming distinct lines keep appearing at the same rate. Distinct lines appear
+/// less often the more of the input has been seen, so this tends to overestimate. Returns `None` if
+/// nothing was read from a non-empty input, as there is no rate to project from.
+fn project(distinct: usize, bytes_read: u64, total_bytes: u64) -> Option<u64> {
+    if bytes_read >= total_bytes {
+        Some(distinct as u64)
+    } else if bytes_read == 0 {
+        None
+    } else {
+        // float to integer casts saturate, so this can't overflow
+        Some((distinct as f64 * (total_bytes as f64 / bytes_read as f64)).round() as u64)
+    }
+}
+
+/// Wraps a reader, and ends its input after a number of lines. A final line without a newline
+/// counts as a line.
+pub struct LineLimit<R> {
+    inner: R,
+    /// newlines left before the input ends
+    remaining: usize,
+    /// newlines consumed so far
+    newlines: u64,
+    /// whether bytes were consumed after the last newline
+    partial_line: bool,
+}
+
+impl<R: BufRead> LineLimit<R> {
+    pub fn new(inner: R, lines: usize) -> Self {
+        LineLimit { inner, remaining: lines, newlines: 0, partial_line: false }
+    }
+
+    /// The number of lines consumed so far
+    pub fn lines_read(&self) -> u64 {
+        self.newlines + u64::from(self.partial_line)
+    }
+}
+
+impl<R: BufRead> Read for LineLimit<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let read = available.len().min(buf.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for LineLimit<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.remaining == 0 {
+            return Ok(&[]);
+        }
+        let buf = self.inner.fill_buf()?;
+        // end the buffer after the last newline still allowed
+        let end = buf.iter()
+            .enumerate()
+            .filter(|(_index, byte)| **byte == b'\n')
+            .nth(self.remaining - 1)
+            .map_or(buf.len(), |(index, _byte)| index + 1);
+        Ok(&buf[..end])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // the buffer is already filled, so this doesn't read anything and can't fail
+        if let Ok(buf) = self.inner.fill_buf() {
+            let consumed = &buf[..amt.min(buf.len())];
+            let newlines = consumed.iter().filter(|byte| **byte == b'\n').count();
+            self.remaining = self.remaining.saturating_sub(newlines);
+            self.newlines += newlines as u64;
+            if let Some(last) = consumed.last() {
+                self.partial_line = *last != b'\n';
+            }
+        }
+        self.inner.consume(amt);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::BufReader;
+
+    use line_cardinality::{EmitLines, LineCounter};
+
+    use super::*;
+
+    fn read_limited(input: &[u8], lines: usize, capacity: usize) -> (Vec<u8>, u64) {
+        let mut limited = LineLimit::new(BufReader::with_capacity(capacity, input), lines);
+        let mut output = Vec::new();
+        limited.read_to_end(&mut output).unwrap();
+        (output, limited.lines_read())
+    }
+
+    #[test]
+    fn test_line_limit() {
+        for capacity in [1, 2, 3, 64] {
+            assert_eq!(read_limited(b"a\nb\nc\n", 2, capacity), (b"a\nb\n".to_vec(), 2), "capacity {capacity}");
+            assert_eq!(read_limited(b"a\nb\nc\n", 3, capacity), (b"a\nb\nc\n".to_vec(), 3), "capacity {capacity}");
+            assert_eq!(read_limited(b"a\nb\nc\n", 10, capacity), (b"a\nb\nc\n".to_vec(), 3), "capacity {capacity}");
+            // a final line without a newline still counts
+            assert_eq!(read_limited(b"a\n\nc", 10, capacity), (b"a\n\nc".to_vec(), 3), "capacity {capacity}");
+            assert_eq!(read_limited(b"", 10, capacity), (Vec::new(), 0), "capacity {capacity}");
+        }
+    }
+
+    #[test]
+    fn test_count() {
+        let mut preview = Preview::new(3);
+        let mut counter = LineCounter::new();
+        preview.count(&mut counter, b"a\nb\na\nc\nd\n".as_slice()).unwrap();
+        preview.count(&mut counter, b"e\n".as_slice()).unwrap();
+        let mut lines = counter.into_vec();
+        lines.sort_unstable();
+        assert_eq!(lines, [b"a".to_vec(), b"b".to_vec(), b"e".to_vec()]);
+        assert_eq!(preview.lines, 4);
+    }
+
+    #[test]
+    fn test_project() {
+        assert_eq!(project(10, 100, 1000), Some(100));
+        assert_eq!(project(3, 7, 10), Some(4));
+        // the whole input was read, so there is nothing to project
+        assert_eq!(project(10, 1000, 1000), Some(10));
+        assert_eq!(project(0, 0, 0), Some(0));
+        assert_eq!(project(0, 0, 1000), None);
+        assert_eq!(project(usize::MAX, 1, u64::MAX), Some(u64::MAX));
+    }
+
+    #[test]
+    fn test_distinct_per_line() {
+        assert_eq!(distinct_per_line(0, 0), 0.0);
+        assert_eq!(distinct_per_line(5, 20), 0.25);
+    }
+}
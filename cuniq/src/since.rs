@@ -0,0 +1,121 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tracking the line number each distinct line first occurred on, for `--since-line`
+
+use std::collections::HashMap;
+
+use line_cardinality::{ContainsLine, CountUnique};
+
+use crate::sizing::hash_map_with_capacity;
+use crate::Count;
+
+/// How many times a mapped line was seen, and the line number it was first seen on
+struct Entry {
+    count: Count,
+    first_line: u64,
+}
+
+/// A [`CountUnique`] that counts occurrences of each mapped line, and remembers the number of the
+/// line each was first seen on. Lines are numbered from 1 across every input, as if the inputs were
+/// concatenated. This requires an additional 8 bytes of memory per distinct line over
+/// [`line_cardinality::HashingLineCounter`].
+pub struct FirstLineCounter<M> {
+    map: HashMap<Vec<u8>, Entry>,
+    string_buffer: Vec<u8>,
+    line_mapper: M,
+    /// the number of lines counted so far
+    lines: u64,
+}
+
+impl<M> FirstLineCounter<M>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    pub fn with_line_mapper_and_capacity(line_mapper: M, capacity: usize) -> Self {
+        FirstLineCounter {
+            map: hash_map_with_capacity(capacity),
+            string_buffer: Vec::new(),
+            line_mapper,
+            lines: 0,
+        }
+    }
+}
+
+impl<M> FirstLineCounter<M> {
+    /// Iterate over each distinct line first seen on line `since` or later, with its occurrence
+    /// count over all lines
+    pub fn iter_since(&self, since: u64) -> impl Iterator<Item = (&[u8], Count)> {
+        self.map.iter()
+            .filter(move |(_line, entry)| entry.first_line >= since)
+            .map(|(line, entry)| (line.as_slice(), entry.count))
+    }
+}
+
+impl<M> CountUnique for FirstLineCounter<M>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    #[inline(always)]
+    fn count_line(&mut self, line: &[u8]) {
+        self.lines += 1;
+        let line = (self.line_mapper)(line, &mut self.string_buffer);
+        match self.map.get_mut(line) {
+            Some(entry) => entry.count += 1,
+            None => {
+                self.map.insert(line.to_vec(), Entry { count: 1, first_line: self.lines });
+            }
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.map.len()
+    }
+
+    fn reset(&mut self) {
+        self.map.clear();
+        self.lines = 0;
+    }
+}
+
+impl<M> ContainsLine for FirstLineCounter<M>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    fn contains_line(&mut self, line: &[u8]) -> bool {
+        let line = (self.line_mapper)(line, &mut self.string_buffer);
+        self.map.contains_key(line)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bstr::ByteSlice;
+
+    use super::*;
+
+    fn lowercase<'a>(line: &'a [u8], buffer: &'a mut Vec<u8>) -> &'a [u8] {
+        buffer.clear();
+        line.to_lowercase_into(buffer);
+        buffer
+    }
+
+    fn since(counter: &FirstLineCounter<impl Sized>, line: u64) -> Vec<(&[u8], Count)> {
+        let mut report: Vec<(&[u8], Count)> = counter.iter_since(line).collect();
+        report.sort_unstable();
+        report
+    }
+
+    #[test]
+    fn test_first_line() {
+        let mut counter = FirstLineCounter::with_line_mapper_and_capacity(lowercase, 0);
+        counter.count_unique_in_bytes(b"a\nb\nA\n");
+        counter.count_unique_in_bytes(b"c\nb\nd\nC\n");
+        assert_eq!(counter.count(), 4);
+        assert_eq!(since(&counter, 1), [(b"a".as_slice(), 2), (b"b", 2), (b"c", 2), (b"d", 1)]);
+        assert_eq!(since(&counter, 3), [(b"c".as_slice(), 2), (b"d", 1)]);
+        // the repeat of "b" on line 5 doesn't make it new
+        assert_eq!(since(&counter, 5), [(b"d".as_slice(), 1)]);
+        assert_eq!(since(&counter, 8), []);
+    }
+}
@@ -0,0 +1,103 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Translating `--expected-cardinality` into the size each counting mode uses
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// The relative standard error that "estimate" mode aims for
+const TARGET_ERROR: f64 = 0.01;
+
+/// The fewest registers a [`HyperLogLog`](line_cardinality::HyperLogLog) can have
+const MIN_REGISTERS: usize = 16;
+
+/// The most registers [`estimate_registers`] chooses. Its standard error is within
+/// [`TARGET_ERROR`] whatever the cardinality, so more would only cost memory.
+const MAX_REGISTERS: usize = 16384;
+
+/// The initial hash table capacity for "exact" and "near-exact" mode, which is enough to hold
+/// `expected` distinct lines without reallocating. The two modes store different entries, but both
+/// tables reserve room by entry count, so the hint needs no adjusting.
+pub fn hash_table_capacity(expected: usize) -> usize {
+    expected
+}
+
+/// An empty map with room for `capacity` entries, if that much can be allocated. The capacity is
+/// only a hint, so one too large to allocate is ignored rather than panicking or aborting.
+pub fn hash_map_with_capacity<K: Eq + Hash, V>(capacity: usize) -> HashMap<K, V> {
+    let mut map = HashMap::new();
+    let _ = map.try_reserve(capacity);
+    map
+}
+
+/// The register count for "estimate" mode: the smallest power of 2 whose relative standard error
+/// after `expected` distinct lines is within 1%, up to [`MAX_REGISTERS`].
+pub fn estimate_registers(expected: usize) -> usize {
+    let mut registers = MIN_REGISTERS;
+    while registers < MAX_REGISTERS && relative_error(registers, expected) > TARGET_ERROR {
+        registers *= 2;
+    }
+    registers
+}
+
+/// The relative standard error of a HyperLogLog with `registers` after `cardinality` distinct
+/// lines. Below 2.5 lines per register the estimate falls back to linear counting, whose error
+/// depends on how many registers are still empty rather than only on the register count.
+fn relative_error(registers: usize, cardinality: usize) -> f64 {
+    if cardinality == 0 {
+        return 0.0;
+    }
+    let registers = registers as f64;
+    let cardinality = cardinality as f64;
+    let load = cardinality / registers;
+    if load < 2.5 {
+        (registers * (load.exp() - load - 1.0)).sqrt() / cardinality
+    } else {
+        1.04 / registers.sqrt()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hash_table_capacity() {
+        assert_eq!(hash_table_capacity(0), 0);
+        assert_eq!(hash_table_capacity(1_000_000), 1_000_000);
+    }
+
+    #[test]
+    fn test_hash_map_with_capacity() {
+        assert!(hash_map_with_capacity::<u64, u64>(1000).capacity() >= 1000);
+        assert_eq!(hash_map_with_capacity::<u64, u64>(usize::MAX).capacity(), 0);
+    }
+
+    #[test]
+    fn test_estimate_registers() {
+        assert_eq!(estimate_registers(0), MIN_REGISTERS);
+        // linear counting needs fewer registers while most of them stay empty
+        assert_eq!(estimate_registers(100), 8192);
+        assert_eq!(estimate_registers(1000), 8192);
+        assert_eq!(estimate_registers(1_000_000), MAX_REGISTERS);
+        assert_eq!(estimate_registers(usize::MAX), MAX_REGISTERS);
+        for expected in [1, 10, 100, 1000, 10_000, 100_000, 1_000_000] {
+            let registers = estimate_registers(expected);
+            assert!(registers.is_power_of_two(), "{expected}");
+            assert!(relative_error(registers, expected) <= TARGET_ERROR, "{expected}");
+            if registers > MIN_REGISTERS {
+                assert!(relative_error(registers / 2, expected) > TARGET_ERROR, "{expected}");
+            }
+        }
+    }
+
+    /// Any cardinality is within the target error at the cap, so the cap never loosens it
+    #[test]
+    fn test_max_registers() {
+        for cardinality in [1, 1000, 40_960, 40_961, 1_000_000, usize::MAX] {
+            assert!(relative_error(MAX_REGISTERS, cardinality) <= TARGET_ERROR, "{cardinality}");
+        }
+        assert!(relative_error(MAX_REGISTERS / 2, usize::MAX) > TARGET_ERROR);
+    }
+}
@@ -0,0 +1,250 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Orderings for `--sort`, `--sort-by-count`, and `--top`
+
+use std::cmp::Ordering;
+
+use bstr::ByteSlice;
+
+use crate::cli_args::SortOrder;
+use crate::Count;
+
+/// Sort lines by `order`
+pub fn sort_lines<L: AsRef<[u8]>>(lines: &mut [L], order: SortOrder) {
+    lines.sort_unstable_by(|a, b| compare(a.as_ref(), b.as_ref(), order));
+}
+
+/// Sort report entries by line, according to `order`
+pub fn sort_report<L: AsRef<[u8]>>(report: &mut [(L, Count)], order: SortOrder) {
+    report.sort_unstable_by(|(a, _), (b, _)| compare(a.as_ref(), b.as_ref(), order));
+}
+
+/// Sort report entries by count, largest first. Lines with equal counts are sorted bytewise, so
+/// the order doesn't depend on the hash table.
+pub fn sort_report_by_count<L: AsRef<[u8]>>(report: &mut [(L, Count)]) {
+    report.sort_unstable_by(compare_by_count);
+}
+
+/// Keep only the `n` entries that [`sort_report_by_count`] would put first, in no particular order.
+/// This only partially sorts the report, so it is much cheaper than sorting all of it when `n` is
+/// small. Ties are broken the same way, so the entries kept don't depend on the hash table either.
+pub fn retain_top_by_count<L: AsRef<[u8]>>(report: &mut Vec<(L, Count)>, n: usize) {
+    if n < report.len() {
+        if n > 0 {
+            report.select_nth_unstable_by(n - 1, compare_by_count);
+        }
+        report.truncate(n);
+    }
+}
+
+fn compare_by_count<L: AsRef<[u8]>>((a, a_count): &(L, Count), (b, b_count): &(L, Count)) -> Ordering {
+    b_count.cmp(a_count).then_with(|| a.as_ref().cmp(b.as_ref()))
+}
+
+fn compare(a: &[u8], b: &[u8], order: SortOrder) -> Ordering {
+    match order {
+        SortOrder::Bytes => a.as_bstr().cmp(b.as_bstr()),
+        SortOrder::Numeric => compare_numeric(a, b),
+    }
+}
+
+/// Compare lines like `LC_ALL=C sort -n`: by the number at the start of each line, and then
+/// bytewise if the numbers are equal. Lines that don't start with a number compare as zero. Numbers
+/// are parsed without converting them to a fixed-size type, so they may have any number of digits
+/// without losing precision, and nothing is allocated.
+pub fn compare_numeric(a: &[u8], b: &[u8]) -> Ordering {
+    Number::parse(a).cmp(&Number::parse(b))
+        .then_with(|| a.cmp(b))
+}
+
+/// A number at the start of a line, as understood by `sort -n`: optional leading blanks, an
+/// optional `-`, digits, and an optional `.` followed by more digits.
+#[derive(PartialEq, Eq, Debug)]
+struct Number<'a> {
+    negative: bool,
+    /// integer digits, without leading zeros
+    integer: &'a [u8],
+    /// fractional digits, without trailing zeros
+    fraction: &'a [u8],
+}
+
+impl<'a> Number<'a> {
+    fn parse(line: &'a [u8]) -> Self {
+        let line = trim_start(line, |byte| byte == b' ' || byte == b'\t');
+        let (negative, line) = match line.strip_prefix(b"-") {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let integer = prefix(line, |byte| byte.is_ascii_digit());
+        let fraction = match line[integer.len()..].strip_prefix(b".") {
+            Some(rest) => prefix(rest, |byte| byte.is_ascii_digit()),
+            None => &[],
+        };
+        let integer = trim_start(integer, |byte| byte == b'0');
+        let fraction = trim_end(fraction, |byte| byte == b'0');
+        // negative zero is zero
+        let negative = negative && !(integer.is_empty() && fraction.is_empty());
+        Number { negative, integer, fraction }
+    }
+
+    /// Compare absolute values. Without leading zeros a longer integer part is always larger, and
+    /// without trailing zeros the fractional parts compare bytewise.
+    fn cmp_magnitude(&self, other: &Self) -> Ordering {
+        self.integer.len().cmp(&other.integer.len())
+            .then_with(|| self.integer.cmp(other.integer))
+            .then_with(|| self.fraction.cmp(other.fraction))
+    }
+}
+
+impl Ord for Number<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, false) => self.cmp_magnitude(other),
+            (true, true) => other.cmp_magnitude(self),
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+        }
+    }
+}
+
+impl PartialOrd for Number<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn prefix(bytes: &[u8], predicate: impl Fn(u8) -> bool) -> &[u8] {
+    let end = bytes.iter().position(|byte| !predicate(*byte)).unwrap_or(bytes.len());
+    &bytes[..end]
+}
+
+fn trim_start(bytes: &[u8], predicate: impl Fn(u8) -> bool) -> &[u8] {
+    &bytes[prefix(bytes, predicate).len()..]
+}
+
+fn trim_end(bytes: &[u8], predicate: impl Fn(u8) -> bool) -> &[u8] {
+    let end = bytes.iter().rposition(|byte| !predicate(*byte)).map_or(0, |index| index + 1);
+    &bytes[..end]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sorted(lines: &[&str]) -> Vec<String> {
+        let mut lines: Vec<&[u8]> = lines.iter().map(|line| line.as_bytes()).collect();
+        sort_lines(&mut lines, SortOrder::Numeric);
+        lines.into_iter().map(|line| String::from_utf8(line.to_vec()).unwrap()).collect()
+    }
+
+    /// The expected order is the output of `LC_ALL=C sort -n` (GNU coreutils)
+    #[test]
+    fn test_matches_gnu_sort() {
+        let lines = ["abc", "-5", "0", "5", "-0", " 3", "+4", "1.50", "1.5", ".5", "-.5", "zz", "", "10", "9", "1e3", "007", "7"];
+        let expected = ["-5", "-.5", "", "+4", "-0", "0", "abc", "zz", ".5", "1e3", "1.5", "1.50", " 3", "5", "007", "7", "9", "10"];
+        assert_eq!(sorted(&lines), expected);
+    }
+
+    #[test]
+    fn test_sort_by_count() {
+        let mut report: Vec<(&[u8], Count)> = vec![(b"b", 2), (b"c", 5), (b"a", 2), (b"d", 1)];
+        sort_report_by_count(&mut report);
+        assert_eq!(report, [(b"c".as_slice(), 5), (b"a", 2), (b"b", 2), (b"d", 1)]);
+    }
+
+    #[test]
+    fn test_retain_top_by_count() {
+        let report: Vec<(&[u8], Count)> = vec![(b"b", 2), (b"c", 5), (b"a", 2), (b"d", 1)];
+        for (n, expected) in [
+            (0, &[][..]),
+            (1, &[(b"c".as_slice(), 5)]),
+            (2, &[(b"c", 5), (b"a", 2)]),
+            (3, &[(b"c", 5), (b"a", 2), (b"b", 2)]),
+            (5, &[(b"c", 5), (b"a", 2), (b"b", 2), (b"d", 1)]),
+        ] {
+            let mut top = report.clone();
+            retain_top_by_count(&mut top, n);
+            sort_report_by_count(&mut top);
+            assert_eq!(top, expected, "top {n}");
+        }
+    }
+
+    #[test]
+    fn test_long_numbers() {
+        let lines = ["100000000000000000000000000001", "99999999999999999999999999999", "100000000000000000000000000000.5", "-100000000000000000000000000000"];
+        let expected = ["-100000000000000000000000000000", "99999999999999999999999999999", "100000000000000000000000000000.5", "100000000000000000000000000001"];
+        assert_eq!(sorted(&lines), expected);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Number::parse(b" \t-0012.3400xyz"), Number { negative: true, integer: b"12", fraction: b"34" });
+        assert_eq!(Number::parse(b"-0.000"), Number { negative: false, integer: b"", fraction: b"" });
+        assert_eq!(Number::parse(b"12."), Number { negative: false, integer: b"12", fraction: b"" });
+        assert_eq!(Number::parse(b"\n5"), Number { negative: false, integer: b"", fraction: b"" });
+    }
+
+    /// xorshift64, so the property test is reproducible without a dependency on a random number crate
+    fn next_random(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    /// A random line that may or may not start with a number
+    fn random_line(state: &mut u64) -> String {
+        let mut line = String::new();
+        for _ in 0..next_random(state) % 2 {
+            line.push(' ');
+        }
+        if next_random(state) % 3 == 0 {
+            line.push('-');
+        }
+        for _ in 0..next_random(state) % 5 {
+            line.push(char::from(b'0' + (next_random(state) % 10) as u8));
+        }
+        if next_random(state) % 2 == 0 {
+            line.push('.');
+            for _ in 0..next_random(state) % 4 {
+                line.push(char::from(b'0' + (next_random(state) % 10) as u8));
+            }
+        }
+        if next_random(state) % 4 == 0 {
+            line.push(['x', '-', '.', ' '][(next_random(state) % 4) as usize]);
+        }
+        line
+    }
+
+    /// Reference key: the value of the leading number in thousandths. Random lines have at most 3
+    /// fractional digits and few integer digits, so this can't overflow or lose precision.
+    fn reference_value(line: &str) -> i64 {
+        let line = line.trim_start_matches([' ', '\t']);
+        let (sign, line) = match line.strip_prefix('-') {
+            Some(line) => (-1, line),
+            None => (1, line),
+        };
+        let integer: String = line.chars().take_while(char::is_ascii_digit).collect();
+        let rest = &line[integer.len()..];
+        let fraction: String = match rest.strip_prefix('.') {
+            Some(rest) => rest.chars().take_while(char::is_ascii_digit).collect(),
+            None => String::new(),
+        };
+        let integer: i64 = integer.parse().unwrap_or(0);
+        let fraction: i64 = format!("{fraction:0<3}").parse().unwrap();
+        sign * (integer * 1000 + fraction)
+    }
+
+    #[test]
+    fn test_against_reference() {
+        let mut state = 0x2545F4914F6CDD1D;
+        for _ in 0..100 {
+            let lines: Vec<String> = (0..50).map(|_| random_line(&mut state)).collect();
+            let mut expected = lines.clone();
+            expected.sort_by(|a, b| reference_value(a).cmp(&reference_value(b)).then_with(|| a.cmp(b)));
+            let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+            assert_eq!(sorted(&lines), expected);
+        }
+    }
+}
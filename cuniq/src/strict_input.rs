@@ -0,0 +1,184 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! `--strict-input`, which looks for bytes that clean newline-delimited input shouldn't contain, such
+//! as `\r` and NUL, while input is read
+
+use std::ffi::OsString;
+use std::fmt::{Display, Formatter};
+
+use bstr::ByteSlice;
+use line_cardinality::Error;
+
+use crate::cli_args::StrictInput;
+use crate::logging::log_warn;
+use crate::paths::escaped;
+
+/// Scans input for forbidden bytes, keeping track of the line and byte offset within each input so
+/// that the first one found can be pointed out
+pub struct ForbiddenBytes {
+    forbidden: Box<[u8]>,
+    action: StrictInput,
+    /// the input being read
+    input: OsString,
+    /// bytes of the current input scanned so far
+    offset: u64,
+    /// newlines in the current input scanned so far
+    newlines: u64,
+    /// the line number of the last line found to contain a forbidden byte, so each line is counted
+    /// once however many it contains
+    last_line: Option<u64>,
+    /// lines containing forbidden bytes, in all inputs
+    lines: u64,
+    first: Option<Offense>,
+}
+
+/// Where a forbidden byte was found
+struct Offense {
+    input: OsString,
+    /// line number, counted from 1
+    line: u64,
+    /// offset from the start of the input, counted from 0
+    offset: u64,
+    byte: u8,
+}
+
+impl ForbiddenBytes {
+    pub fn new(forbidden: &[u8], action: StrictInput) -> Self {
+        ForbiddenBytes {
+            forbidden: forbidden.into(),
+            action,
+            input: OsString::new(),
+            offset: 0,
+            newlines: 0,
+            last_line: None,
+            lines: 0,
+            first: None,
+        }
+    }
+
+    /// Start a new input called `name`. Line numbers and offsets restart from its beginning.
+    pub fn start_input(&mut self, name: OsString) {
+        self.input = name;
+        self.offset = 0;
+        self.newlines = 0;
+        self.last_line = None;
+    }
+
+    /// Scan the next `bytes` of the current input
+    pub fn scan(&mut self, bytes: &[u8]) {
+        if self.stopped() {
+            return;
+        }
+        let mut position = 0;
+        while let Some(found) = bytes[position..].find_byteset(&self.forbidden) {
+            let found = position + found;
+            self.newlines += newlines(&bytes[position..found]);
+            let line = self.newlines + 1;
+            if self.last_line != Some(line) {
+                self.last_line = Some(line);
+                self.lines += 1;
+                self.first.get_or_insert_with(|| Offense {
+                    input: self.input.clone(),
+                    line,
+                    offset: self.offset + found as u64,
+                    byte: bytes[found],
+                });
+                if self.stopped() {
+                    return;
+                }
+            }
+            position = found + 1;
+        }
+        self.newlines += newlines(&bytes[position..]);
+        self.offset += bytes.len() as u64;
+    }
+
+    /// Whether there's no point reading any more input, as a forbidden byte has been found and it
+    /// is an error
+    pub fn stopped(&self) -> bool {
+        self.action == StrictInput::Error && self.first.is_some()
+    }
+
+    /// Fail if a forbidden byte has been found and it is an error
+    pub fn check(&self) -> Result<(), Error> {
+        match &self.first {
+            Some(first) if self.action == StrictInput::Error => Err(Error::input(format!("--strict-input: {first}"))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Print how many lines contained forbidden bytes as a warning, with `--strict-input=warn`
+    pub fn log_summary(&self) {
+        if let (StrictInput::Warn, Some(first)) = (self.action, &self.first) {
+            log_warn!("--strict-input: {} lines contain forbidden bytes, the first being: {first}", self.lines);
+        }
+    }
+}
+
+impl Display for Offense {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\"{}\" contains forbidden byte 0x{:02X} on line {}, at byte offset {}", escaped(&self.input), self.byte, self.line, self.offset)
+    }
+}
+
+fn newlines(bytes: &[u8]) -> u64 {
+    bytes.iter().filter(|&&byte| byte == b'\n').count() as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn scan(chunks: &[&[u8]], action: StrictInput) -> ForbiddenBytes {
+        let mut forbidden = ForbiddenBytes::new(b"\r\0", action);
+        forbidden.start_input("input".into());
+        for chunk in chunks {
+            forbidden.scan(chunk);
+        }
+        forbidden
+    }
+
+    fn first(forbidden: &ForbiddenBytes) -> Option<(u64, u64, u8)> {
+        forbidden.first.as_ref().map(|first| (first.line, first.offset, first.byte))
+    }
+
+    #[test]
+    fn test_clean() {
+        let forbidden = scan(&[b"a\nb\n", b"c\td"], StrictInput::Error);
+        assert!(forbidden.check().is_ok());
+        assert_eq!(forbidden.lines, 0);
+    }
+
+    #[test]
+    fn test_position() {
+        let forbidden = scan(&[b"ab\ncd\n", b"ef\r\n"], StrictInput::Error);
+        assert_eq!(first(&forbidden), Some((3, 8, b'\r')));
+        assert!(forbidden.stopped());
+        let error = forbidden.check().unwrap_err().to_string();
+        assert_eq!(error, "--strict-input: \"input\" contains forbidden byte 0x0D on line 3, at byte offset 8");
+    }
+
+    /// Warnings count each offending line once, over every input
+    #[test]
+    fn test_warn() {
+        let mut forbidden = scan(&[b"a\0\0\nb\n\r", b"\r\nc\n\0"], StrictInput::Warn);
+        assert_eq!(first(&forbidden), Some((1, 1, 0)));
+        assert_eq!(forbidden.lines, 3);
+        forbidden.start_input("other".into());
+        forbidden.scan(b"\0\n\0");
+        assert_eq!(forbidden.lines, 5);
+        assert!(!forbidden.stopped());
+        assert!(forbidden.check().is_ok());
+    }
+
+    /// Positions restart with each input
+    #[test]
+    fn test_inputs() {
+        let mut forbidden = scan(&[b"a\nb\n"], StrictInput::Error);
+        forbidden.start_input("second".into());
+        forbidden.scan(b"x\0");
+        assert_eq!(first(&forbidden), Some((1, 1, 0)));
+        assert_eq!(forbidden.first.unwrap().input, "second");
+    }
+}
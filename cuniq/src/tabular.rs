@@ -0,0 +1,89 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Report entries as tab- or comma-separated rows, for `--format tsv` and `--format csv`
+
+use std::io::{self, Write};
+
+/// The columns of a row, written by `--header-row`
+pub const COLUMNS: [&str; 2] = ["count", "line"];
+
+/// Write one report entry as `count<TAB>line`. A line can't hold a tab or a row terminator
+/// unescaped, so as in the "linear TSV" convention, tab, newline, carriage return, and backslash are
+/// written as `\t`, `\n`, `\r`, and `\\`.
+pub fn write_tsv_row<W: Write>(writer: &mut W, line: &[u8], count: u64, terminator: u8) -> io::Result<()> {
+    write!(writer, "{count}\t")?;
+    let mut start = 0;
+    for (index, byte) in line.iter().enumerate() {
+        let escaped: &[u8] = match byte {
+            b'\t' => b"\\t",
+            b'\n' => b"\\n",
+            b'\r' => b"\\r",
+            b'\\' => b"\\\\",
+            _ => continue,
+        };
+        writer.write_all(&line[start..index])?;
+        writer.write_all(escaped)?;
+        start = index + 1;
+    }
+    writer.write_all(&line[start..])?;
+    writer.write_all(&[terminator])
+}
+
+/// Write one report entry as `count,line`. As in RFC 4180, a line containing a comma, a double
+/// quote, a newline, or a carriage return is enclosed in double quotes, with each double quote in it
+/// doubled. Rows end with `terminator` rather than the CRLF of the RFC, as most readers accept either.
+pub fn write_csv_row<W: Write>(writer: &mut W, line: &[u8], count: u64, terminator: u8) -> io::Result<()> {
+    write!(writer, "{count},")?;
+    if line.iter().any(|&byte| matches!(byte, b',' | b'"' | b'\n' | b'\r')) {
+        writer.write_all(b"\"")?;
+        for chunk in line.split_inclusive(|&byte| byte == b'"') {
+            writer.write_all(chunk)?;
+            if chunk.ends_with(b"\"") {
+                writer.write_all(b"\"")?;
+            }
+        }
+        writer.write_all(b"\"")?;
+    } else {
+        writer.write_all(line)?;
+    }
+    writer.write_all(&[terminator])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tsv(line: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        write_tsv_row(&mut output, line, 3, b'\n').unwrap();
+        output
+    }
+
+    fn csv(line: &[u8], terminator: u8) -> Vec<u8> {
+        let mut output = Vec::new();
+        write_csv_row(&mut output, line, 12, terminator).unwrap();
+        output
+    }
+
+    #[test]
+    fn test_tsv() {
+        assert_eq!(tsv(b"foo bar"), b"3\tfoo bar\n");
+        assert_eq!(tsv(b""), b"3\t\n");
+        assert_eq!(tsv(b"a\tb\\c\r\nd"), b"3\ta\\tb\\\\c\\r\\nd\n");
+        assert_eq!(tsv(b"\xff,\"x\""), b"3\t\xff,\"x\"\n");
+    }
+
+    #[test]
+    fn test_csv() {
+        assert_eq!(csv(b"foo bar", b'\n'), b"12,foo bar\n");
+        assert_eq!(csv(b"", b'\n'), b"12,\n");
+        assert_eq!(csv(b"a,b", b'\n'), b"12,\"a,b\"\n");
+        assert_eq!(csv(b"say \"hi\"", b'\n'), b"12,\"say \"\"hi\"\"\"\n");
+        assert_eq!(csv(b"\"", b'\n'), b"12,\"\"\"\"\n");
+        assert_eq!(csv(b"two\r\nlines", b'\n'), b"12,\"two\r\nlines\"\n");
+        assert_eq!(csv(b"tab\tand \xff", b'\n'), b"12,tab\tand \xff\n");
+        // with -z, lines can hold newlines, which are quoted the same way
+        assert_eq!(csv(b"a\nb", b'\0'), b"12,\"a\nb\"\0");
+    }
+}
@@ -0,0 +1,250 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Counting the distinct values of two fields, and of their combinations, for `--tabulate`
+
+use std::mem::size_of;
+
+use line_cardinality::{CountUnique, HyperLogLog, InexactHashingLineCounter, LineCounter};
+
+/// Passes a field through unchanged. Fields are mapped by [`TabulatingCounter`] before they are
+/// counted, so the counters it wraps don't need to map them again.
+pub fn identity<'a>(field: &'a [u8], _buffer: &'a mut Vec<u8>) -> &'a [u8] {
+    field
+}
+
+/// A counter for whichever mode was selected. Tabulating counts with three counters of the same
+/// type, so choosing the mode per line costs less than compiling every input path once per mode.
+pub enum FieldCounter<M>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    Exact(LineCounter<M>),
+    NearExact(InexactHashingLineCounter<M>),
+    /// boxed, as it is much larger than the other counters
+    Estimate(Box<HyperLogLog<M>>),
+}
+
+impl<M> CountUnique for FieldCounter<M>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    #[inline(always)]
+    fn count_line(&mut self, line: &[u8]) {
+        match self {
+            FieldCounter::Exact(counter) => counter.count_line(line),
+            FieldCounter::NearExact(counter) => counter.count_line(line),
+            FieldCounter::Estimate(counter) => counter.count_line(line),
+        }
+    }
+
+    fn count(&self) -> usize {
+        match self {
+            FieldCounter::Exact(counter) => counter.count(),
+            FieldCounter::NearExact(counter) => counter.count(),
+            FieldCounter::Estimate(counter) => counter.count(),
+        }
+    }
+
+    fn estimated_memory(&self) -> usize {
+        size_of::<Self>() + match self {
+            FieldCounter::Exact(counter) => counter.estimated_memory() - size_of::<LineCounter<M>>(),
+            FieldCounter::NearExact(counter) => counter.estimated_memory() - size_of::<InexactHashingLineCounter<M>>(),
+            FieldCounter::Estimate(counter) => counter.estimated_memory(),
+        }
+    }
+
+    fn reset(&mut self) {
+        match self {
+            FieldCounter::Exact(counter) => counter.reset(),
+            FieldCounter::NearExact(counter) => counter.reset(),
+            FieldCounter::Estimate(counter) => counter.reset(),
+        }
+    }
+}
+
+/// Splits each line into fields, and counts the distinct values of two of them and of the pairs
+/// they form, each with its own counter. Lines are split once, and each field is mapped with
+/// `line_mapper` before it is counted. Lines with too few fields are skipped, so that all three
+/// counts describe the same lines.
+pub struct TabulatingCounter<C, M> {
+    first: C,
+    second: C,
+    pairs: C,
+    /// zero-based indexes of the two fields
+    fields: [usize; 2],
+    delimiter: u8,
+    line_mapper: M,
+    first_buffer: Vec<u8>,
+    second_buffer: Vec<u8>,
+    /// the two mapped fields, joined by `delimiter`. Neither field can contain the delimiter, so
+    /// different pairs are never joined into the same bytes.
+    pair_buffer: Vec<u8>,
+    /// lines skipped because they had too few fields
+    skipped: u64,
+}
+
+impl<C, M> TabulatingCounter<C, M>
+where
+    C: CountUnique,
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    /// Count the distinct values of the fields at zero-based indexes `fields`, and of their pairs,
+    /// with each of `counters` in that order.
+    pub fn new(counters: [C; 3], fields: [usize; 2], delimiter: u8, line_mapper: M) -> Self {
+        let [first, second, pairs] = counters;
+        TabulatingCounter {
+            first,
+            second,
+            pairs,
+            fields,
+            delimiter,
+            line_mapper,
+            first_buffer: Vec::new(),
+            second_buffer: Vec::new(),
+            pair_buffer: Vec::new(),
+            skipped: 0,
+        }
+    }
+
+    /// The counter of the first field's values
+    pub fn first(&self) -> &C {
+        &self.first
+    }
+
+    /// The counter of the second field's values
+    pub fn second(&self) -> &C {
+        &self.second
+    }
+
+    /// The counter of the pairs of values
+    pub fn pairs(&self) -> &C {
+        &self.pairs
+    }
+
+    /// How many lines were skipped because they had too few fields
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+}
+
+impl<C, M> CountUnique for TabulatingCounter<C, M>
+where
+    C: CountUnique,
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    #[inline(always)]
+    fn count_line(&mut self, line: &[u8]) {
+        let [first_index, second_index] = self.fields;
+        let mut first = None;
+        let mut second = None;
+        let fields = line.split(|byte| *byte == self.delimiter).take(first_index.max(second_index) + 1);
+        for (index, field) in fields.enumerate() {
+            if index == first_index {
+                first = Some(field);
+            }
+            if index == second_index {
+                second = Some(field);
+            }
+        }
+        let (Some(first), Some(second)) = (first, second) else {
+            self.skipped += 1;
+            return;
+        };
+        let first = (self.line_mapper)(first, &mut self.first_buffer);
+        let second = (self.line_mapper)(second, &mut self.second_buffer);
+        self.pair_buffer.clear();
+        self.pair_buffer.extend_from_slice(first);
+        self.pair_buffer.push(self.delimiter);
+        self.pair_buffer.extend_from_slice(second);
+        self.first.count_line(first);
+        self.second.count_line(second);
+        self.pairs.count_line(&self.pair_buffer);
+    }
+
+    /// The number of distinct pairs
+    fn count(&self) -> usize {
+        self.pairs.count()
+    }
+
+    fn estimated_memory(&self) -> usize {
+        size_of::<Self>() - 3 * size_of::<C>()
+            + self.first_buffer.capacity() + self.second_buffer.capacity() + self.pair_buffer.capacity()
+            + self.first.estimated_memory() + self.second.estimated_memory() + self.pairs.estimated_memory()
+    }
+
+    fn reset(&mut self) {
+        self.first.reset();
+        self.second.reset();
+        self.pairs.reset();
+        self.skipped = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::array::from_fn;
+
+    use bstr::ByteSlice;
+
+    use super::*;
+
+    fn lowercase<'a>(line: &'a [u8], buffer: &'a mut Vec<u8>) -> &'a [u8] {
+        buffer.clear();
+        line.to_lowercase_into(buffer);
+        buffer
+    }
+
+    fn counts<C: CountUnique, M>(counter: &TabulatingCounter<C, M>) -> [usize; 3]
+    where
+        M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+    {
+        [counter.first().count(), counter.second().count(), counter.pairs().count()]
+    }
+
+    #[test]
+    fn test_counts() {
+        let mut counter = TabulatingCounter::new(from_fn(|_| LineCounter::with_line_mapper(identity)), [0, 2], b',', identity);
+        counter.count_unique_in_bytes(b"a,x,1\na,y,2\nb,x,1\nb,y,1\na,z,1\n");
+        assert_eq!(counts(&counter), [2, 2, 3]);
+        assert_eq!(counter.count(), 3);
+    }
+
+    /// Joining the fields must not let two different pairs collide
+    #[test]
+    fn test_pairs_are_unambiguous() {
+        let mut counter = TabulatingCounter::new(from_fn(|_| LineCounter::with_line_mapper(identity)), [0, 1], b'\t', identity);
+        counter.count_unique_in_bytes(b"a b\tc\na\tb c\n");
+        assert_eq!(counts(&counter), [2, 2, 2]);
+    }
+
+    #[test]
+    fn test_skips_short_lines() {
+        let mut counter = TabulatingCounter::new(from_fn(|_| LineCounter::with_line_mapper(identity)), [1, 0], b',', identity);
+        counter.count_unique_in_bytes(b"a,b\nc\n\nd,e,f\n");
+        assert_eq!(counts(&counter), [2, 2, 2]);
+        assert_eq!(counter.skipped(), 2);
+    }
+
+    /// The same field can be given twice, and fields are mapped before they are counted
+    #[test]
+    fn test_same_field_and_mapping() {
+        let mut counter = TabulatingCounter::new(from_fn(|_| LineCounter::with_line_mapper(identity)), [0, 0], b',', lowercase);
+        counter.count_unique_in_bytes(b"A,1\na,2\nB\n");
+        assert_eq!(counts(&counter), [2, 2, 2]);
+        assert_eq!(counter.skipped(), 0);
+    }
+
+    #[test]
+    fn test_field_counter_modes() {
+        for mode in 0..3 {
+            let mut counter = TabulatingCounter::new(from_fn(|_| match mode {
+                0 => FieldCounter::Exact(LineCounter::with_line_mapper(identity)),
+                1 => FieldCounter::NearExact(InexactHashingLineCounter::with_line_mapper(identity)),
+                _ => FieldCounter::Estimate(Box::new(HyperLogLog::with_line_mapper(identity))),
+            }), [0, 1], b',', identity);
+            counter.count_unique_in_bytes(b"a,1\na,2\nb,1\n");
+            assert_eq!(counts(&counter), [2, 2, 3], "mode {mode}");
+        }
+    }
+}
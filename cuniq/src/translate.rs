@@ -0,0 +1,275 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Counting lines by a canonical form read from a mapping file, for `--translate`
+
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::mem::size_of;
+
+use bstr::io::BufReadExt;
+use bstr::ByteSlice;
+use line_cardinality::{CountUnique, Error};
+
+use crate::logging::log_warn;
+
+/// How many mappings are read before the rest of the map is reserved, based on their average
+/// length. This avoids both growing the map many times over and over-allocating for long lines.
+const SAMPLE_MAPPINGS: usize = 1024;
+
+/// The most bytes of a line that are quoted in a message about it
+const QUOTED_LINE_LIMIT: usize = 64;
+
+/// A mapping of raw values to their canonical forms, loaded from a `--translate` file
+pub struct Translation<M> {
+    /// raw value, after line mapping, to canonical form
+    map: HashMap<Vec<u8>, Vec<u8>>,
+    /// skip lines without a canonical form, instead of counting them as they are
+    translate_only: bool,
+    string_buffer: Vec<u8>,
+    line_mapper: M,
+    /// heap memory used by the keys and values of `map`
+    mapping_memory: usize,
+}
+
+impl<M> Translation<M>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    /// Load a mapping of raw values to canonical forms from `reader`, one per line, with a tab
+    /// between them. Only the first tab separates the two, so a canonical form can contain tabs.
+    /// Empty lines are skipped. If a raw value appears more than once, the last mapping for it is
+    /// used, and a warning is printed. `size` is the total size of `reader` if it is known, which is
+    /// used to size the map up front.
+    ///
+    /// Raw values are mapped with `line_mapper` as they are loaded, and lines are mapped with it
+    /// before they are looked up, so that `--trim`, `--lower`, and the other input options decide
+    /// which lines match.
+    pub fn from_read<R: BufRead>(mut reader: R, size: Option<u64>, mut line_mapper: M, translate_only: bool) -> Result<Self, Error> {
+        let mut map: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+        let mut string_buffer = Vec::new();
+        let mut line_number: u64 = 0;
+        let mut bytes_read: u64 = 0;
+        let mut invalid_line: Option<u64> = None;
+        let mut duplicates: u64 = 0;
+        let mut first_duplicate: Option<(u64, Vec<u8>)> = None;
+        reader.for_byte_line(|line| {
+            line_number += 1;
+            bytes_read += line.len() as u64 + 1;
+            if line.is_empty() {
+                return Ok(true);
+            }
+            let Some((raw, canonical)) = line.split_once_str(b"\t") else {
+                invalid_line = Some(line_number);
+                return Ok(false);
+            };
+            let raw = line_mapper(raw, &mut string_buffer);
+            match map.get_mut(raw) {
+                Some(existing) => {
+                    duplicates += 1;
+                    first_duplicate.get_or_insert_with(|| (line_number, quote(raw)));
+                    existing.clear();
+                    existing.extend_from_slice(canonical);
+                }
+                None => {
+                    map.insert(raw.to_vec(), canonical.to_vec());
+                }
+            }
+            if map.len() == SAMPLE_MAPPINGS {
+                if let Some(size) = size {
+                    let expected = size / (bytes_read / SAMPLE_MAPPINGS as u64).max(1);
+                    map.reserve(usize::try_from(expected).unwrap_or(usize::MAX).saturating_sub(map.len()));
+                }
+            }
+            Ok(true)
+        }).map_err(|e| Error::io_static("failed to read --translate file", e))?;
+        if let Some(line_number) = invalid_line {
+            return Err(Error::message(format!("line {line_number} of the --translate file has no tab between the raw value and its canonical form")));
+        }
+        if let Some((line_number, raw)) = first_duplicate {
+            log_warn!(
+                "{duplicates} lines of the --translate file repeat a raw value that was already mapped, the first being \"{}\" on line {line_number}. The last mapping of each is used.",
+                raw.as_bstr(),
+            );
+        }
+        let mapping_memory = map.iter().map(|(raw, canonical)| raw.capacity() + canonical.capacity()).sum();
+        Ok(Translation {
+            map,
+            translate_only,
+            string_buffer,
+            line_mapper,
+            mapping_memory,
+        })
+    }
+
+    /// The form of `line` to count, or `None` if it should be skipped
+    #[inline(always)]
+    fn translate<'a>(&'a mut self, line: &'a [u8]) -> Option<&'a [u8]> {
+        let key = (self.line_mapper)(line, &mut self.string_buffer);
+        match self.map.get(key) {
+            Some(canonical) => Some(canonical),
+            None if self.translate_only => None,
+            // the line as it was, not as it was mapped, so that original forms are kept
+            None => Some(line),
+        }
+    }
+
+    /// Heap memory used by the mapping. The map's own table is estimated from its capacity.
+    fn heap_memory(&self) -> usize {
+        self.map.capacity() * (size_of::<(Vec<u8>, Vec<u8>)>() + 1) + self.mapping_memory + self.string_buffer.capacity()
+    }
+}
+
+/// Wraps a [`CountUnique`] to count the canonical form of each line that has one, so that aliases
+/// of the same value are counted as one line. The canonical form is counted like any other line, so
+/// the wrapped counter's line mapper applies to it as well. With a translation, each line is mapped
+/// twice, once to look it up and once by the wrapped counter, so counting is somewhat slower.
+///
+/// Without a translation every line is passed straight through. This lets the wrapper be applied
+/// whether or not `--translate` was given: checking for a translation once per line costs far less
+/// than compiling every input path a second time for a different counter type.
+pub struct TranslatingCounter<C, M> {
+    inner: C,
+    translation: Option<Translation<M>>,
+}
+
+impl<C, M> TranslatingCounter<C, M>
+where
+    C: CountUnique,
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    pub fn new(inner: C, translation: Option<Translation<M>>) -> Self {
+        TranslatingCounter { inner, translation }
+    }
+
+    /// The wrapped counter, mutably
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+}
+
+/// `line`, shortened to quote in a message
+fn quote(line: &[u8]) -> Vec<u8> {
+    let mut quoted = line[..line.len().min(QUOTED_LINE_LIMIT)].to_vec();
+    if line.len() > QUOTED_LINE_LIMIT {
+        quoted.extend_from_slice(b"...");
+    }
+    quoted
+}
+
+impl<C, M> CountUnique for TranslatingCounter<C, M>
+where
+    C: CountUnique,
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    #[inline(always)]
+    fn count_line(&mut self, line: &[u8]) {
+        let Some(translation) = &mut self.translation else {
+            return self.inner.count_line(line);
+        };
+        if let Some(line) = translation.translate(line) {
+            self.inner.count_line(line);
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.inner.count()
+    }
+
+    fn estimated_memory(&self) -> usize {
+        size_of::<Self>() - size_of::<C>() + self.translation.as_ref().map_or(0, Translation::heap_memory) + self.inner.estimated_memory()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use line_cardinality::{EmitLines, LineCounter};
+
+    use super::*;
+
+    fn identity<'a>(line: &'a [u8], _buffer: &'a mut Vec<u8>) -> &'a [u8] {
+        line
+    }
+
+    fn lowercase<'a>(line: &'a [u8], buffer: &'a mut Vec<u8>) -> &'a [u8] {
+        buffer.clear();
+        line.to_lowercase_into(buffer);
+        buffer
+    }
+
+    fn translating<M>(mapping: &[u8], line_mapper: M, translate_only: bool) -> TranslatingCounter<LineCounter<M>, M>
+    where
+        M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8] + Clone,
+    {
+        let translation = Translation::from_read(mapping, None, line_mapper.clone(), translate_only).unwrap();
+        TranslatingCounter::new(LineCounter::with_line_mapper(line_mapper), Some(translation))
+    }
+
+    fn counted<M>(counter: TranslatingCounter<LineCounter<M>, M>) -> Vec<Vec<u8>>
+    where
+        M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+    {
+        let mut lines = counter.inner.into_vec();
+        lines.sort_unstable();
+        lines
+    }
+
+    #[test]
+    fn test_hits_and_misses() {
+        let mapping = b"web-1\tweb\nweb-2\tweb\n\ndb-1\tdb\twith a tab\n";
+        let mut counter = translating(mapping, identity, false);
+        counter.count_unique_in_bytes(b"web-1\nweb-2\nweb\ndb-1\ncache-1\n");
+        assert_eq!(counter.count(), 3);
+        assert_eq!(counted(counter), [b"cache-1".as_slice(), b"db\twith a tab", b"web"]);
+
+        let mut counter = translating(mapping, identity, true);
+        counter.count_unique_in_bytes(b"web-1\nweb-2\ndb-1\ncache-1\n");
+        assert_eq!(counter.count(), 2);
+    }
+
+    #[test]
+    fn test_duplicates_use_the_last_mapping() {
+        let mapping = b"a\tfirst\nb\tother\na\tsecond\n";
+        let mut counter = translating(mapping, identity, false);
+        counter.count_unique_in_bytes(b"a\n");
+        assert_eq!(counted(counter), [b"second".as_slice()]);
+    }
+
+    /// Raw values are matched after the line mapper, and canonical forms are counted through it
+    #[test]
+    fn test_line_mapper() {
+        let mapping = b"Web-1\tWEB\n";
+        let mut counter = translating(mapping, lowercase, false);
+        counter.count_unique_in_bytes(b"WEB-1\nweb-1\nweb\n");
+        assert_eq!(counted(counter), [b"web".as_slice()]);
+    }
+
+    #[test]
+    fn test_invalid_mapping() {
+        let result = Translation::from_read(b"a\tb\nno tab\n".as_slice(), None, identity, false);
+        assert!(result.err().unwrap().to_string().contains("line 2"));
+    }
+
+    /// The size hint only reserves capacity, which doesn't change the mapping
+    #[test]
+    fn test_size_hint() {
+        let mapping: Vec<u8> = (0..SAMPLE_MAPPINGS * 2).flat_map(|i| format!("{i}\t{}\n", i % 10).into_bytes()).collect();
+        let translation = Translation::from_read(mapping.as_slice(), Some(mapping.len() as u64), identity, false).unwrap();
+        assert!(translation.map.capacity() >= SAMPLE_MAPPINGS * 2);
+        let mut counter = TranslatingCounter::new(LineCounter::new(), Some(translation));
+        let input: Vec<u8> = (0..SAMPLE_MAPPINGS * 2).flat_map(|i| format!("{i}\n").into_bytes()).collect();
+        counter.count_unique_in_bytes(&input);
+        assert_eq!(counter.count(), 10);
+    }
+
+    #[test]
+    fn test_without_translation() {
+        let mut counter = TranslatingCounter::new(LineCounter::new(), None::<Translation<for<'a> fn(&'a [u8], &'a mut Vec<u8>) -> &'a [u8]>>);
+        counter.count_unique_in_bytes(b"a\nb\na\n");
+        assert_eq!(counter.count(), 2);
+    }
+}
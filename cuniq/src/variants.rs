@@ -0,0 +1,154 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tracking the different original forms of each mapped line, for `--inconsistencies`
+
+use std::collections::HashMap;
+
+use line_cardinality::CountUnique;
+
+use crate::sizing::hash_map_with_capacity;
+use crate::Count;
+
+/// The original forms a mapped line was seen in, with how many times each was seen
+#[derive(Default)]
+pub struct Variants {
+    /// at most `max_variants` forms, in the order they were first seen. There are rarely more than
+    /// a few, so a linear search is faster than an inner map.
+    forms: Vec<(Box<[u8]>, Count)>,
+    /// occurrences of forms that were not stored because `forms` was full
+    other: Count,
+}
+
+impl Variants {
+    /// The stored forms and their occurrence counts, most common first. Forms with the same count
+    /// are sorted bytewise.
+    pub fn forms(&self) -> Vec<(&[u8], Count)> {
+        let mut forms: Vec<(&[u8], Count)> = self.forms.iter().map(|(form, count)| (form.as_ref(), *count)).collect();
+        forms.sort_unstable_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+        forms
+    }
+
+    /// Occurrences of forms that were not stored because there were already `max_variants` of them
+    pub fn other(&self) -> Count {
+        self.other
+    }
+
+    /// Whether more than one distinct form was seen
+    pub fn is_inconsistent(&self) -> bool {
+        self.forms.len() + usize::from(self.other != 0) > 1
+    }
+}
+
+/// A [`CountUnique`] that counts each distinct original form of each mapped line. Only the first
+/// `max_variants` forms of a mapped line are stored, which bounds the memory used by a line with
+/// many forms.
+pub struct VariantCounter<M> {
+    map: HashMap<Vec<u8>, Variants>,
+    string_buffer: Vec<u8>,
+    max_variants: usize,
+    line_mapper: M,
+}
+
+impl<M> VariantCounter<M>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    pub fn with_line_mapper_and_capacity(line_mapper: M, max_variants: usize, capacity: usize) -> Self {
+        VariantCounter {
+            map: hash_map_with_capacity(capacity),
+            string_buffer: Vec::new(),
+            max_variants,
+            line_mapper,
+        }
+    }
+}
+
+impl<M> VariantCounter<M> {
+    /// Mapped lines that were seen in more than one form, with the most forms first. Lines with the
+    /// same number of forms are sorted bytewise.
+    pub fn inconsistencies(&self) -> Vec<(&[u8], &Variants)> {
+        let mut inconsistencies: Vec<(&[u8], &Variants)> = self.map.iter()
+            .filter(|(_line, variants)| variants.is_inconsistent())
+            .map(|(line, variants)| (line.as_slice(), variants))
+            .collect();
+        inconsistencies.sort_unstable_by(|(a, a_variants), (b, b_variants)| {
+            let a_forms = (a_variants.forms.len(), a_variants.other);
+            let b_forms = (b_variants.forms.len(), b_variants.other);
+            b_forms.cmp(&a_forms).then_with(|| a.cmp(b))
+        });
+        inconsistencies
+    }
+}
+
+impl<M> CountUnique for VariantCounter<M>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    #[inline(always)]
+    fn count_line(&mut self, line: &[u8]) {
+        let mapped = (self.line_mapper)(line, &mut self.string_buffer);
+        if !self.map.contains_key(mapped) {
+            self.map.insert(mapped.to_vec(), Variants::default());
+        }
+        let variants = self.map.get_mut(mapped).expect("inserted above");
+        if let Some((_form, count)) = variants.forms.iter_mut().find(|(form, _count)| **form == *line) {
+            *count += 1;
+        } else if variants.forms.len() < self.max_variants {
+            variants.forms.push((line.into(), 1));
+        } else {
+            variants.other += 1;
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.map.len()
+    }
+
+    fn reset(&mut self) {
+        self.map.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lowercase<'a>(line: &'a [u8], buffer: &'a mut Vec<u8>) -> &'a [u8] {
+        buffer.clear();
+        buffer.extend(line.iter().map(u8::to_ascii_lowercase));
+        buffer
+    }
+
+    #[test]
+    fn test_inconsistencies() {
+        let mut counter = VariantCounter::with_line_mapper_and_capacity(lowercase, 16, 0);
+        counter.count_unique_in_bytes(b"b\nA\na\nB\nc\na\nC\nb\nd\nd\n");
+        assert_eq!(counter.count(), 4);
+
+        let inconsistencies = counter.inconsistencies();
+        let keys: Vec<&[u8]> = inconsistencies.iter().map(|(key, _variants)| *key).collect();
+        assert_eq!(keys, [b"a".as_slice(), b"b", b"c"]);
+        assert_eq!(inconsistencies[0].1.forms(), [(b"a".as_slice(), 2), (b"A", 1)]);
+        assert_eq!(inconsistencies[1].1.forms(), [(b"b".as_slice(), 2), (b"B", 1)]);
+        assert_eq!(inconsistencies[2].1.forms(), [(b"C".as_slice(), 1), (b"c", 1)]);
+    }
+
+    #[test]
+    fn test_max_variants() {
+        let mut counter = VariantCounter::with_line_mapper_and_capacity(lowercase, 2, 0);
+        counter.count_unique_in_bytes(b"ab\nAb\naB\nAB\naB\nab\nx\nX\n");
+
+        let inconsistencies = counter.inconsistencies();
+        assert_eq!(inconsistencies.len(), 2);
+        let (key, variants) = inconsistencies[0];
+        assert_eq!(key, b"ab");
+        assert_eq!(variants.forms(), [(b"ab".as_slice(), 2), (b"Ab", 1)]);
+        assert_eq!(variants.other(), 3);
+
+        // a single stored form is still inconsistent if other forms were not stored
+        let mut counter = VariantCounter::with_line_mapper_and_capacity(lowercase, 1, 0);
+        counter.count_unique_in_bytes(b"a\nA\n");
+        assert_eq!(counter.inconsistencies().len(), 1);
+    }
+}
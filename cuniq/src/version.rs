@@ -0,0 +1,95 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Machine-readable version information, for `--version-json`
+
+use std::io::{self, Write};
+
+use line_cardinality::BuildInfo;
+
+use crate::constants::{FEATURES, GIT_COMMIT_HASH};
+use crate::ndjson::write_escaped;
+
+/// Write cuniq's version, commit, and feature flags, the largest file it can memmap, and
+/// line_cardinality's build info, as a single line of JSON:
+///
+/// ```json
+/// {"version":"1.0.3","commit":"abc123","features":["memmap","regex"],"max_memmap_bytes":9223372036854775807,"line_cardinality":{"version":"2.0.0","features":["ahash","file"],"hasher":"ahash, randomly seeded"}}
+/// ```
+///
+/// `max_memmap_bytes` is `null` if the binary was built without memmap support. Files of any size
+/// can be read without memmap.
+pub fn write_version_json<W: Write>(writer: &mut W) -> io::Result<()> {
+    write_json(writer, env!("CARGO_PKG_VERSION"), GIT_COMMIT_HASH, FEATURES, max_memmap_bytes(), line_cardinality::build_info())
+}
+
+fn max_memmap_bytes() -> Option<u64> {
+    #[cfg(feature = "memmap")]
+    return Some(line_cardinality::MAX_MAPPED_LEN);
+    #[cfg(not(feature = "memmap"))]
+    return None;
+}
+
+fn write_json<W: Write>(writer: &mut W, version: &str, commit: &str, features: &[&str], max_memmap_bytes: Option<u64>, library: BuildInfo) -> io::Result<()> {
+    writer.write_all(b"{\"version\":")?;
+    write_string(writer, version)?;
+    writer.write_all(b",\"commit\":")?;
+    write_string(writer, commit)?;
+    writer.write_all(b",\"features\":")?;
+    write_strings(writer, features.iter().copied())?;
+    match max_memmap_bytes {
+        Some(bytes) => write!(writer, ",\"max_memmap_bytes\":{bytes}")?,
+        None => writer.write_all(b",\"max_memmap_bytes\":null")?,
+    }
+    writer.write_all(b",\"line_cardinality\":{\"version\":")?;
+    write_string(writer, library.version)?;
+    writer.write_all(b",\"features\":")?;
+    write_strings(writer, library.features())?;
+    writer.write_all(b",\"hasher\":")?;
+    write_string(writer, library.hasher)?;
+    writer.write_all(b"}}\n")
+}
+
+fn write_string<W: Write>(writer: &mut W, string: &str) -> io::Result<()> {
+    writer.write_all(b"\"")?;
+    write_escaped(writer, string.as_bytes())?;
+    writer.write_all(b"\"")
+}
+
+fn write_strings<'a, W: Write, I: Iterator<Item = &'a str>>(writer: &mut W, strings: I) -> io::Result<()> {
+    writer.write_all(b"[")?;
+    for (index, string) in strings.enumerate() {
+        if index != 0 {
+            writer.write_all(b",")?;
+        }
+        write_string(writer, string)?;
+    }
+    writer.write_all(b"]")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_json() {
+        let library = line_cardinality::build_info();
+        let mut output = Vec::new();
+        write_json(&mut output, "1.2.3", "abc\"def", &["gzip", "memmap"], Some(4096), library).unwrap();
+        let library_features: Vec<String> = library.features().map(|feature| format!("\"{feature}\"")).collect();
+        let expected = format!(
+            "{{\"version\":\"1.2.3\",\"commit\":\"abc\\\"def\",\"features\":[\"gzip\",\"memmap\"],\"max_memmap_bytes\":4096,\"line_cardinality\":{{\"version\":\"{}\",\"features\":[{}],\"hasher\":\"{}\"}}}}\n",
+            library.version,
+            library_features.join(","),
+            library.hasher,
+        );
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_no_features() {
+        let mut output = Vec::new();
+        write_json(&mut output, "1.2.3", "", &[], None, line_cardinality::build_info()).unwrap();
+        assert!(String::from_utf8(output).unwrap().starts_with("{\"version\":\"1.2.3\",\"commit\":\"\",\"features\":[],\"max_memmap_bytes\":null,"));
+    }
+}
@@ -0,0 +1,147 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! `--watch`, which prints the distinct count at a fixed interval while input is still arriving
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{sync_channel, RecvTimeoutError, SyncSender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use line_cardinality::{CountUnique, Error};
+
+use crate::header::format_timestamp;
+use crate::logging::log_info;
+use crate::paths::escaped;
+use crate::{log_elapsed, open_file, stdin_reader, OUTPUT_ERROR_MESSAGE};
+
+/// Batches waiting to be counted before the reader blocks
+const CHANNEL_BOUND: usize = 16;
+
+/// Count stdin, if `read_stdin`, then each file into `total`, and every `interval` write a line with
+/// the count so far to `writer`. If there is an `interval_counter` it is also given every line, and
+/// its count is written and then reset each interval.
+pub fn watch<T, I, W>(read_stdin: bool, files: Vec<PathBuf>, interval: Duration, total: &mut T, mut interval_counter: Option<&mut I>, writer: &mut W) -> Result<(), Error>
+where
+    T: CountUnique,
+    I: CountUnique,
+    W: Write,
+{
+    // reading blocks until input arrives, so it happens on another thread to keep the ticks on time
+    let (sender, receiver) = sync_channel(CHANNEL_BOUND);
+    let reader = thread::spawn(move || read_inputs(read_stdin, &files, &sender));
+
+    let mut next_tick = Instant::now() + interval;
+    loop {
+        // checked before waiting, so that ticks aren't delayed by input that never stops arriving
+        if Instant::now() >= next_tick {
+            write_tick(writer, total, interval_counter.as_deref_mut())?;
+            next_tick += interval;
+        }
+        match receiver.recv_timeout(next_tick.saturating_duration_since(Instant::now())) {
+            Ok(batch) => {
+                total.count_unique_in_read(batch.as_slice())?;
+                if let Some(interval_counter) = interval_counter.as_deref_mut() {
+                    interval_counter.count_unique_in_read(batch.as_slice())?;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    // the reader only disconnects once it is done, so this doesn't block
+    reader.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))?;
+    write_tick(writer, total, interval_counter)
+}
+
+/// Write the counts at the end of an interval, and reset the interval count
+fn write_tick<T, I, W>(writer: &mut W, total: &T, interval_counter: Option<&mut I>) -> Result<(), Error>
+where
+    T: CountUnique,
+    I: CountUnique,
+    W: Write,
+{
+    let result = match interval_counter {
+        Some(interval_counter) => {
+            let result = writeln!(writer, "{} interval {} total {}", format_timestamp(SystemTime::now()), interval_counter.count(), total.count());
+            interval_counter.reset();
+            result
+        }
+        None => writeln!(writer, "{} total {}", format_timestamp(SystemTime::now()), total.count()),
+    };
+    result.and_then(|()| writer.flush()).map_err(|e| Error::io_static(OUTPUT_ERROR_MESSAGE, e))
+}
+
+/// Send the lines of each input as batches of whole lines, until every input has been read or the
+/// receiver hangs up
+fn read_inputs(read_stdin: bool, files: &[PathBuf], sender: &SyncSender<Vec<u8>>) -> Result<(), Error> {
+    if read_stdin {
+        log_info!("input: stdin");
+        let start = Instant::now();
+        send_batches(stdin_reader(), sender).map_err(|e| Error::io_static("failed to read from stdin", e))?;
+        log_elapsed(&"stdin", start);
+    }
+    for path in files {
+        let file = open_file(path)?;
+        let start = Instant::now();
+        send_batches(BufReader::new(file), sender).map_err(|e| Error::io(format!("failed to read from \"{}\"", escaped(path)), e))?;
+        log_elapsed(&escaped(path), start);
+    }
+    Ok(())
+}
+
+/// Send everything `reader` reads, split into batches of whole lines. The whole lines from each
+/// read are sent right away, so slow input is counted as soon as it arrives.
+fn send_batches<R: BufRead>(mut reader: R, sender: &SyncSender<Vec<u8>>) -> io::Result<()> {
+    let mut batch = Vec::new();
+    loop {
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            break;
+        }
+        let read = available.len();
+        // a partial line at the end of the batch waits for the rest of it
+        let whole_lines = available.iter().rposition(|byte| *byte == b'\n').map(|index| batch.len() + index + 1);
+        batch.extend_from_slice(available);
+        reader.consume(read);
+        if let Some(whole_lines) = whole_lines {
+            let rest = batch.split_off(whole_lines);
+            if sender.send(std::mem::replace(&mut batch, rest)).is_err() {
+                return Ok(());
+            }
+        }
+    }
+    if !batch.is_empty() {
+        // the receiver may have hung up, in which case there's nothing left to do anyway
+        let _ = sender.send(batch);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc::sync_channel;
+
+    use super::*;
+
+    fn batches(input: &[u8], capacity: usize) -> Vec<Vec<u8>> {
+        let (sender, receiver) = sync_channel(1024);
+        send_batches(BufReader::with_capacity(capacity, input), &sender).unwrap();
+        drop(sender);
+        receiver.into_iter().collect()
+    }
+
+    #[test]
+    fn test_batches_hold_whole_lines() {
+        for capacity in [1, 2, 3, 64] {
+            let batches = batches(b"ab\ncd\nef", capacity);
+            assert_eq!(batches.concat(), b"ab\ncd\nef", "capacity {capacity}");
+            // only the last batch may end without a newline
+            for batch in &batches[..batches.len() - 1] {
+                assert!(batch.ends_with(b"\n"), "capacity {capacity}: {batches:?}");
+            }
+        }
+        assert!(batches(b"", 64).is_empty());
+    }
+}
@@ -0,0 +1,54 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--adjacent`
+
+use common::{run, run_with_input, temp_file};
+
+mod common;
+
+/// 3 distinct lines in 6 runs, as `uniq` would print them
+const INPUT: &[u8] = b"a\na\nb\na\nA\n a\nc\nc";
+
+#[test]
+fn test_runs() {
+    assert_eq!(run(&["--adjacent"], INPUT), "6\n");
+    // the default counts distinct lines wherever they are
+    assert_eq!(run(&[], INPUT), "5\n");
+    assert_eq!(run(&["--adjacent"], b""), "0\n");
+    assert_eq!(run(&["--adjacent"], b"\n\n\n"), "1\n");
+}
+
+#[test]
+fn test_input_options() {
+    assert_eq!(run(&["--adjacent", "--lower"], INPUT), "5\n");
+    assert_eq!(run(&["--adjacent", "--lower", "--trim"], INPUT), "4\n");
+    assert_eq!(run(&["--adjacent", "--words"], b"a a b\nb a\n"), "3\n");
+    assert_eq!(run(&["--adjacent", "--expect", "6"], INPUT), "6\n");
+}
+
+#[test]
+fn test_files_continue_runs() {
+    let first = temp_file("adjacent-1.txt", b"a\nb\n");
+    let second = temp_file("adjacent-2.txt", b"b\nc\n");
+    for memmap in ["--memmap", "--no-memmap"] {
+        let output = run(&["--adjacent", "--no-stdin", memmap, first.to_str().unwrap(), second.to_str().unwrap()], b"");
+        assert_eq!(output, "3\n", "{memmap}");
+    }
+    std::fs::remove_file(first).unwrap();
+    std::fs::remove_file(second).unwrap();
+}
+
+#[test]
+fn test_invalid_arguments() {
+    for args in [
+        ["--adjacent", "--report"].as_slice(),
+        &["--adjacent", "--unique"],
+        &["--adjacent", "--assume-sorted"],
+        &["--adjacent", "--mode", "estimate"],
+        &["--adjacent", "--mode", "near-exact"],
+    ] {
+        let output = run_with_input(args, INPUT);
+        assert_eq!(output.status.code(), Some(2), "cuniq {args:?}");
+    }
+}
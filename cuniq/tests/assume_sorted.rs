@@ -0,0 +1,24 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--assume-sorted`
+
+use common::{run, run_with_input, EXIT_CODE_INPUT_REJECTED};
+
+mod common;
+
+#[test]
+fn test_sorted() {
+    assert_eq!(run(&["--assume-sorted"], b"a\na\nb\nc\nc\n"), "3\n");
+    assert_eq!(run(&["--assume-sorted", "--lower"], b"A\na\nB\n"), "2\n");
+}
+
+/// Unsorted input is rejected as bad input, not as bad arguments
+#[test]
+fn test_not_sorted() {
+    let output = run_with_input(&["--assume-sorted"], b"a\nc\nb\n");
+    assert_eq!(output.status.code(), Some(EXIT_CODE_INPUT_REJECTED));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("is not sorted: \"b\" appears after \"c\""), "{stderr}");
+}
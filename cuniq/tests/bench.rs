@@ -0,0 +1,47 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `cuniq bench`
+
+use std::process::Output;
+
+use test_support::{test_file, HAMLET_WORDS};
+
+use common::cuniq;
+
+mod common;
+
+fn bench(args: &[&str]) -> Output {
+    cuniq().arg("bench").args(args).output().unwrap()
+}
+
+#[test]
+fn test_table() {
+    let file = test_file("hamlet_words.txt");
+    let output = bench(&[file.to_str().unwrap(), "--runs", "1"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines = stdout.lines();
+    assert!(lines.next().unwrap().ends_with("(164.2 KiB), fastest of 1 runs"));
+    assert_eq!(lines.next(), Some(""));
+    assert_eq!(lines.next().unwrap().split_whitespace().collect::<Vec<_>>(), ["mode", "input", "time", "throughput", "distinct", "memory"]);
+
+    let rows: Vec<Vec<&str>> = lines.map(|line| line.split_whitespace().collect()).collect();
+    let inputs: &[&str] = if cfg!(feature = "memmap") { &["read", "memmap"] } else { &["read"] };
+    assert_eq!(rows.len(), 3 * inputs.len());
+    for (row, mode) in rows.iter().zip(["exact", "near-exact", "estimate"].iter().flat_map(|mode| inputs.iter().map(move |_| mode))) {
+        assert_eq!(&row[0], mode, "{row:?}");
+        // only the estimate may be off
+        if *mode != "estimate" {
+            assert_eq!(row[5], HAMLET_WORDS.distinct.plain.to_string(), "{row:?}");
+        }
+    }
+}
+
+#[test]
+fn test_usage_errors() {
+    let file = test_file("small.txt");
+    assert_eq!(bench(&[]).status.code(), Some(2));
+    assert_eq!(bench(&[file.to_str().unwrap(), "--runs", "0"]).status.code(), Some(2));
+    assert_eq!(bench(&["missing.txt"]).status.code(), Some(1));
+}
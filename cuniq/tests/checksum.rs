@@ -0,0 +1,65 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--checksum`
+
+use test_support::test_file;
+
+use common::run;
+
+mod common;
+
+/// CRC-32 of the concatenation of `inputs`
+fn expected_checksum(inputs: &[&[u8]]) -> String {
+    let mut hasher = crc32fast::Hasher::new();
+    for input in inputs {
+        hasher.update(input);
+    }
+    format!("crc32: {:08x}", hasher.finalize())
+}
+
+/// Run cuniq with `stdin` piped in, and return its stdout lines
+fn run_lines(args: &[&str], stdin: &[u8]) -> Vec<String> {
+    run(args, stdin).lines().map(str::to_string).collect()
+}
+
+#[test]
+fn test_files() {
+    let hamlet = std::fs::read(test_file("hamlet_words.txt")).unwrap();
+    let numbers = std::fs::read(test_file("cardinality_1000.txt")).unwrap();
+    let expected = expected_checksum(&[&hamlet, &numbers]);
+    let hamlet_path = test_file("hamlet_words.txt");
+    let numbers_path = test_file("cardinality_1000.txt");
+    let files = [hamlet_path.to_str().unwrap(), numbers_path.to_str().unwrap()];
+    let read_methods: &[&str] = if cfg!(feature = "memmap") { &["--memmap", "--no-memmap"] } else { &["--no-memmap"] };
+    for &read_method in read_methods {
+        let lines = run_lines(&[&["--checksum", "--no-stdin", read_method], files.as_slice()].concat(), b"");
+        assert_eq!(lines, ["6414", expected.as_str()], "{read_method}");
+    }
+}
+
+#[test]
+fn test_stdin_then_files() {
+    let stdin = b"foo\nbar\nfoo";
+    let numbers = std::fs::read(test_file("cardinality_100.txt")).unwrap();
+    let numbers_path = test_file("cardinality_100.txt");
+    let lines = run_lines(&["--checksum", numbers_path.to_str().unwrap()], stdin);
+    assert_eq!(lines, ["102", expected_checksum(&[stdin, &numbers]).as_str()]);
+}
+
+#[test]
+fn test_modes() {
+    let stdin = b"a\nb\nA\n";
+    let expected = expected_checksum(&[stdin]);
+    for mode in ["exact", "near-exact", "estimate"] {
+        let lines = run_lines(&["--checksum", "--lower", "--mode", mode], stdin);
+        assert_eq!(lines, ["2", expected.as_str()], "{mode}");
+    }
+    assert_eq!(run_lines(&["--checksum", "--max-distinct", "1"], stdin), [">=1", expected.as_str()]);
+    assert_eq!(run_lines(&["--checksum", "--stats"], stdin), ["3", expected.as_str(), "singletons: 3", "bytes_read: 6"]);
+}
+
+#[test]
+fn test_without_checksum() {
+    assert_eq!(run_lines(&[], b"a\nb\n"), ["2"]);
+}
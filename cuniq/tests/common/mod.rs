@@ -0,0 +1,98 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Running cuniq from the integration tests. Each test file only uses some of these.
+
+#![allow(dead_code)]
+
+use std::ffi::OsStr;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+use test_support::test_file;
+
+/// Exit code used when the count did not meet `--expect`
+pub const EXIT_CODE_EXPECTATION_FAILED: i32 = 3;
+
+/// Exit code used when `--max-distinct` was exceeded with `--strict-max-distinct`
+pub const EXIT_CODE_MAX_DISTINCT_EXCEEDED: i32 = 4;
+
+/// Exit code used when the input was read, but rejected, such as by `--strict-input`
+pub const EXIT_CODE_INPUT_REJECTED: i32 = 5;
+
+/// A command to run the cuniq binary under test, with nothing on stdin
+pub fn cuniq() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_cuniq"));
+    command.stdin(Stdio::null());
+    command
+}
+
+/// Run cuniq with `input` on stdin. If cuniq exits with an error it may not read all of it, so a
+/// broken pipe is ignored.
+pub fn run_with_input(args: &[&str], input: &[u8]) -> Output {
+    let mut child = cuniq()
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    if let Err(e) = child.stdin.take().unwrap().write_all(input) {
+        assert_eq!(e.kind(), io::ErrorKind::BrokenPipe);
+    }
+    child.wait_with_output().unwrap()
+}
+
+/// Run cuniq on `files` rather than stdin
+pub fn run_on_files<P: AsRef<OsStr>>(args: &[&str], files: &[P]) -> Output {
+    cuniq().args(args).arg("--no-stdin").args(files).output().unwrap()
+}
+
+/// Run cuniq on a test file and return its exit code, stdout, and stderr
+pub fn run_test_file(args: &[&str], file: &str) -> (i32, String, String) {
+    split_output(run_on_files(args, &[test_file(file)]))
+}
+
+/// The stdout of a cuniq run, which must have succeeded
+pub fn success(args: &[&str], output: Output) -> Vec<u8> {
+    assert!(output.status.success(), "cuniq {args:?} failed: {}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+/// Run cuniq with `input` on stdin, which must succeed, and return its output
+pub fn run_bytes(args: &[&str], input: &[u8]) -> Vec<u8> {
+    success(args, run_with_input(args, input))
+}
+
+/// Run cuniq with `input` on stdin, which must succeed, and return its output as text
+pub fn run(args: &[&str], input: &[u8]) -> String {
+    String::from_utf8(run_bytes(args, input)).unwrap()
+}
+
+/// Run cuniq on `files`, which must succeed, and return its output as text
+pub fn run_files<P: AsRef<OsStr>>(args: &[&str], files: &[P]) -> String {
+    String::from_utf8(success(args, run_on_files(args, files))).unwrap()
+}
+
+/// The exit code, stdout, and stderr of a cuniq run
+pub fn split_output(output: Output) -> (i32, String, String) {
+    (output.status.code().unwrap(), String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap())
+}
+
+/// Assert that cuniq rejects `args` as a usage error
+pub fn assert_usage_error(args: &[&str], input: &[u8]) {
+    assert_eq!(run_with_input(args, input).status.code(), Some(2), "{args:?}");
+}
+
+/// A path in the temp directory that is unique to this test process
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("cuniq-test-{}-{name}", std::process::id()))
+}
+
+/// Write `contents` to a new file at [`temp_path`]
+pub fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+    let path = temp_path(name);
+    std::fs::write(&path, contents).unwrap();
+    path
+}
@@ -0,0 +1,72 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--count-width`
+
+use common::{run, run_with_input, temp_file};
+
+mod common;
+
+/// `count` copies of `a`, and one `b`
+fn input(count: usize) -> Vec<u8> {
+    let mut input = b"a\n".repeat(count);
+    input.extend_from_slice(b"b\n");
+    input
+}
+
+#[test]
+fn test_default_width() {
+    assert_eq!(run(&["--report", "--sort"], &input(12)), "     12 a\n      1 b\n");
+    assert_eq!(run(&["--report", "--sort", "--count-width", "7"], &input(12)), "     12 a\n      1 b\n");
+}
+
+#[test]
+fn test_fixed_width() {
+    assert_eq!(run(&["--report", "--sort", "--count-width", "3"], &input(12)), " 12 a\n  1 b\n");
+    // wider counts are not truncated
+    assert_eq!(run(&["--report", "--sort", "--count-width", "0"], &input(12)), "12 a\n1 b\n");
+}
+
+#[test]
+fn test_no_pad() {
+    assert_eq!(run(&["--report", "--sort", "--no-pad"], &input(12345678)), "12345678 a\n1 b\n");
+    assert_eq!(run(&["--report", "--sort", "--no-pad"], b" x \n\ty\n"), "1 \ty\n1  x \n");
+    assert_eq!(run(&["--report", "--sort", "--no-pad", "--strip-ansi"], &input(3)), "3 a\n1 b\n");
+}
+
+#[test]
+fn test_auto_width() {
+    assert_eq!(run(&["--report", "--sort", "--count-width", "auto"], &input(12)), "12 a\n 1 b\n");
+    assert_eq!(run(&["--report", "--sort", "--count-width", "auto"], &input(1234)), "1234 a\n   1 b\n");
+    assert_eq!(run(&["--report", "--sort", "--count-width", "auto"], b"x\n"), "1 x\n");
+    assert_eq!(run(&["--report", "--sort", "--count-width", "auto"], b""), "");
+}
+
+/// Every report path aligns the same way, sorted or not
+#[test]
+fn test_auto_width_report_paths() {
+    let input = input(150);
+    let path = temp_file("count_width.txt", &input);
+    let path = path.to_str().unwrap();
+    let read = ["--no-stdin", "--no-memmap", path];
+    let memmap = ["--no-stdin", "--memmap", path];
+    let mut arg_sets = vec![&read[..], &["--strip-ansi"], &["--lower"], &[]];
+    if cfg!(feature = "memmap") {
+        arg_sets.push(&memmap);
+    }
+    for args in arg_sets {
+        let sorted = run(&[&["--report", "--sort", "--count-width", "auto"], args].concat(), &input);
+        assert_eq!(sorted, "150 a\n  1 b\n", "{args:?}");
+        let mut unsorted: Vec<String> = run(&[&["--report", "--count-width", "auto"], args].concat(), &input).lines().map(str::to_string).collect();
+        unsorted.sort_unstable();
+        assert_eq!(unsorted, ["  1 b", "150 a"], "{args:?}");
+    }
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_invalid_width() {
+    for args in [["--report", "--count-width", "wide"], ["--report", "--count-width", "-1"], ["--count-width", "3", "-m=exact"], ["--no-pad", "-m=exact", "--sort"], ["--report", "--no-pad", "--count-width=3"], ["--report", "--count-width", "65"], ["--report", "--count-width", "70000"]] {
+        assert_eq!(run_with_input(&args, b"").status.code(), Some(2), "{args:?}");
+    }
+}
@@ -0,0 +1,75 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--decode`, `--invalid-encoding`, and `--reencode`
+
+use common::{run, run_with_input, temp_file, EXIT_CODE_INPUT_REJECTED};
+
+mod common;
+
+/// "hi" three ways, "Hi", and the bytes 0xFF 0x00
+const HEX: &[u8] = b"6869\n6869\r\n6869\n4869\nff00\nFF00\n";
+
+/// The same lines as [`HEX`], with differently padded and URL-safe variants
+const BASE64: &[u8] = b"aGk=\naGk\naGk=\r\nSGk=\n/wA=\n_wA\n";
+
+#[test]
+fn test_count() {
+    for (encoding, input) in [("hex", HEX), ("base64", BASE64)] {
+        assert_eq!(run(&["--decode", encoding], input), "3\n", "{encoding}");
+        assert_eq!(run(&["--decode", encoding, "--mode", "near-exact"], input), "3\n", "{encoding}");
+        // input options apply to the decoded bytes
+        assert_eq!(run(&["--decode", encoding, "--lower"], input), "2\n", "{encoding}");
+        assert_eq!(run(&["--decode", encoding, "--no-memmap", "--delimiter-str", "\\n"], input), "3\n", "{encoding}");
+    }
+}
+
+#[test]
+fn test_files_match_stdin() {
+    let path = temp_file("decode.txt", BASE64);
+    let path = path.to_str().unwrap();
+    for memmap in ["--memmap", "--no-memmap"] {
+        assert_eq!(run(&["--no-stdin", "--decode", "base64", memmap, path], b""), "3\n", "{memmap}");
+    }
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_report() {
+    let report = run(&["--decode", "hex", "--report", "--sort", "--format", "ndjson"], HEX);
+    assert_eq!(report, "{\"line\":\"Hi\",\"count\":1}\n{\"line\":\"hi\",\"count\":3}\n{\"line_bytes\":[255,0],\"count\":2}\n");
+    let report = run(&["--decode", "hex", "--report", "--sort", "--reencode"], HEX);
+    assert_eq!(report, "      1 4869\n      3 6869\n      2 ff00\n");
+    // lines are printed in canonical form
+    let unique = run(&["--decode", "base64", "--unique", "--sort", "--reencode"], BASE64);
+    assert_eq!(unique, "SGk=\naGk=\n/wA=\n");
+}
+
+#[test]
+fn test_invalid() {
+    let input = b"6869\nnot hex\n4869\nabc\n";
+    let output = run_with_input(&["--decode", "hex"], input);
+    assert_eq!(output.status.code(), Some(EXIT_CODE_INPUT_REJECTED));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("2 lines are not valid hex") && stderr.contains("\"not hex\""), "{stderr}");
+
+    let output = run_with_input(&["--decode", "hex", "--invalid-encoding", "skip"], input);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"2\n");
+    assert!(String::from_utf8(output.stderr).unwrap().contains("skipped 2 lines"));
+}
+
+#[test]
+fn test_invalid_arguments() {
+    for args in [
+        ["--decode", "base32"].as_slice(),
+        &["--invalid-encoding", "skip"],
+        &["--reencode"],
+        &["--decode", "hex", "--stream", "--unique"],
+        &["--decode", "hex", "--assume-sorted"],
+    ] {
+        let output = run_with_input(args, HEX);
+        assert_eq!(output.status.code(), Some(2), "cuniq {args:?}");
+    }
+}
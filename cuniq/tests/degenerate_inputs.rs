@@ -0,0 +1,100 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! How every mode and output option behaves on degenerate inputs. Each option must print valid
+//! output, which may be empty, for empty input, and must treat a single line the same way whether it
+//! is seen once or many times. The intended behavior is:
+//!
+//! | options                     | modes       | empty input          | `a`                 | `a` three times     |
+//! |-----------------------------|-------------|----------------------|---------------------|---------------------|
+//! | (none)                      | all         | `0`                  | `1`                 | `1`                 |
+//! | `--precision interval`      | estimate    | `0..0`               | `1..1`              | `1..1`              |
+//! | `--precision sig2`          | estimate    | `0`                  | `1`                 | `1`                 |
+//! | `--checksum`                | all         | `0`, crc32 `00000000`| `1`, crc32 of input | `1`, crc32 of input |
+//! | `--expect 0`                | all         | `0`                  | `1`, exit 3         | `1`, exit 3         |
+//! | `--cap 1`                   | exact, near | `0`                  | `>=1`               | `>=1`               |
+//! | `--max-distinct 1`          | exact, near | `0`                  | `1`                 | `1`                 |
+//! | `--unique --stream`         | exact, near | nothing              | `a`                 | `a`                 |
+//! | `--unique`                  | exact       | nothing              | `a`                 | `a`                 |
+//! | `--stats`                   | exact       | `0`, 0 singletons    | `1`, 1 singleton    | `1`, 0 singletons   |
+//! | `-c`                        | exact       | nothing              | `      1 a`         | `      3 a`         |
+//! | `-c --count-width auto`     | exact       | nothing              | `1 a`               | `3 a`               |
+//! | `-c --format ndjson`        | exact       | nothing              | `{..."count":1}`    | `{..."count":3}`    |
+//! | `-c --header`               | exact       | only the header      | header, `      1 a` | header, `      3 a` |
+//! | `--inconsistencies --lower` | exact       | nothing              | nothing             | nothing             |
+//!
+//! Options that a mode does not support are rejected with exit status 2 whatever the input.
+
+use std::path::PathBuf;
+
+use common::{run_with_input, split_output, temp_file, EXIT_CODE_EXPECTATION_FAILED};
+
+mod common;
+
+const ALL_MODES: &[&str] = &["exact", "near-exact", "estimate"];
+const HASHING_MODES: &[&str] = &["exact", "near-exact"];
+const EXACT: &[&str] = &["exact"];
+
+/// The degenerate inputs, in the same order as the expected outputs in [`CASES`]
+const INPUTS: [&[u8]; 3] = [b"", b"a\n", b"a\na\na\n"];
+
+/// Options, the modes they are supported in, and the stdout and exit code expected for each of
+/// [`INPUTS`]
+type Case = (&'static [&'static str], &'static [&'static str], [(&'static str, i32); 3]);
+
+const CASES: &[Case] = &[
+    (&[], ALL_MODES, [("0\n", 0), ("1\n", 0), ("1\n", 0)]),
+    (&["--checksum"], ALL_MODES, [("0\ncrc32: 00000000\n", 0), ("1\ncrc32: ddeaa107\n", 0), ("1\ncrc32: 2ae4c3b5\n", 0)]),
+    (&["--expect", "0"], ALL_MODES, [("0\n", 0), ("1\n", EXIT_CODE_EXPECTATION_FAILED), ("1\n", EXIT_CODE_EXPECTATION_FAILED)]),
+    (&["--cap", "1"], HASHING_MODES, [("0\n", 0), (">=1\n", 0), (">=1\n", 0)]),
+    (&["--max-distinct", "1"], HASHING_MODES, [("0\n", 0), ("1\n", 0), ("1\n", 0)]),
+    (&["--unique", "--stream"], HASHING_MODES, [("", 0), ("a\n", 0), ("a\n", 0)]),
+    (&["--unique"], EXACT, [("", 0), ("a\n", 0), ("a\n", 0)]),
+    (&["--stats"], EXACT, [("0\nsingletons: 0\nbytes_read: 0\n", 0), ("1\nsingletons: 1\nbytes_read: 2\n", 0), ("1\nsingletons: 0\nbytes_read: 6\n", 0)]),
+    (&["-c"], EXACT, [("", 0), ("      1 a\n", 0), ("      3 a\n", 0)]),
+    (&["-c", "--count-width", "auto"], EXACT, [("", 0), ("1 a\n", 0), ("3 a\n", 0)]),
+    (&["-c", "--format", "ndjson"], EXACT, [("", 0), ("{\"line\":\"a\",\"count\":1}\n", 0), ("{\"line\":\"a\",\"count\":3}\n", 0)]),
+    (&["--inconsistencies", "--lower"], EXACT, [("", 0), ("", 0), ("", 0)]),
+    (&["--precision", "interval"], &["estimate"], [("0..0\n", 0), ("1..1\n", 0), ("1..1\n", 0)]),
+    (&["--precision", "sig2"], &["estimate"], [("0\n", 0), ("1\n", 0), ("1\n", 0)]),
+];
+
+#[test]
+fn test_matrix() {
+    let files: Vec<PathBuf> = INPUTS.iter().enumerate().map(|(index, input)| temp_file(&format!("degenerate-{index}.txt"), input)).collect();
+    for (options, supported_modes, expected) in CASES {
+        for mode in ALL_MODES {
+            for ((input, file), (expected_stdout, expected_exit_code)) in INPUTS.iter().zip(&files).zip(expected) {
+                let mut args = vec!["--mode", mode];
+                args.extend_from_slice(options);
+
+                let (exit_code, stdout, stderr) = split_output(run_with_input(&args, input));
+                if supported_modes.contains(mode) {
+                    assert_eq!((exit_code, stdout.as_str()), (*expected_exit_code, *expected_stdout), "{args:?} with {input:?} on stdin: {stderr}");
+                } else {
+                    assert_eq!((exit_code, stdout.as_str()), (2, ""), "{args:?} should be rejected");
+                    continue;
+                }
+
+                args.extend(["--no-stdin", file.to_str().unwrap()]);
+                let (exit_code, stdout, stderr) = split_output(run_with_input(&args, b""));
+                assert_eq!((exit_code, stdout.as_str()), (*expected_exit_code, *expected_stdout), "{args:?} with {input:?} in a file: {stderr}");
+            }
+        }
+    }
+    for file in files {
+        std::fs::remove_file(file).unwrap();
+    }
+}
+
+/// `--header` describes the input, so it differs from run to run, but the report after it does not
+#[test]
+fn test_header() {
+    for (input, expected_report) in INPUTS.iter().zip(["", "      1 a\n", "      3 a\n"]) {
+        let (exit_code, stdout, stderr) = split_output(run_with_input(&["-c", "--header"], input));
+        assert_eq!(exit_code, 0, "{input:?}: {stderr}");
+        let report: String = stdout.split_inclusive('\n').filter(|line| !line.starts_with('#')).collect();
+        assert_eq!(report, expected_report, "{input:?}");
+        assert!(stdout.starts_with("# cuniq "), "{input:?}: {stdout}");
+    }
+}
@@ -0,0 +1,47 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--delimiter-str`
+
+use common::{run, run_with_input, temp_file};
+
+mod common;
+
+static RECORDS: &[u8] = b"one\ntwo\r\n\r\none\ntwo\r\nthree\r\none";
+
+#[test]
+fn test_crlf() {
+    // "one\ntwo" twice, an empty record, "three", and a final "one" without a delimiter
+    assert_eq!(run(&["--delimiter-str", "\\r\\n"], RECORDS), "4\n");
+    assert_eq!(run(&["--delimiter-str", "\\r\\n", "--report", "--sort"], RECORDS), "      1 \n      1 one\n      2 one\ntwo\n      1 three\n");
+    assert_eq!(run(&["--delimiter-str", "\\r\\n", "--cap", "2"], RECORDS), ">=2\n");
+}
+
+#[test]
+fn test_files_match_stdin() {
+    let path = temp_file("delimiter.txt", RECORDS);
+    let read_methods: &[&str] = if cfg!(feature = "memmap") { &["--memmap", "--no-memmap"] } else { &["--no-memmap"] };
+    for &read_method in read_methods {
+        let output = run(&[read_method, "--no-stdin", "--delimiter-str", "\\r\\n", "--report", "--sort", path.to_str().unwrap()], b"");
+        assert_eq!(output, run(&["--delimiter-str", "\\r\\n", "--report", "--sort"], RECORDS), "{read_method}");
+    }
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_single_byte() {
+    assert_eq!(run(&["--delimiter-str", "\\0", "--unique", "--sort"], b"b\na\0a\0b\na\0"), "a\nb\na\n");
+    assert_eq!(run(&["--delimiter-str", ",", "--words"], b"a b,b c,,a"), "3\n");
+}
+
+#[test]
+fn test_multi_byte_marker() {
+    let input = b"first\nrecord\n--\nsecond\n--\nfirst\nrecord\n--\n";
+    assert_eq!(run(&["--delimiter-str", "\\n--\\n", "--report", "--sort"], input), "      2 first\nrecord\n      1 second\n");
+}
+
+#[test]
+fn test_invalid() {
+    assert_eq!(run_with_input(&["--delimiter-str", ""], b"").status.code(), Some(2));
+    assert_eq!(run_with_input(&["--delimiter-str", ",", "--unique", "--stream"], b"").status.code(), Some(2));
+}
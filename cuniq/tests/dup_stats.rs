@@ -0,0 +1,64 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--dup-stats`
+
+use std::collections::HashSet;
+
+use test_support::test_file;
+
+use common::{assert_usage_error, run};
+
+mod common;
+
+#[test]
+fn test_dup_stats() {
+    let expected = "3\ndistinct: 3\ntotal: 5\nduplicates: 2\nratio: 0.4000\n";
+    let input = b"a\nb\na\r\nc\na";
+    assert_eq!(run(&["--dup-stats"], input), expected);
+    assert_eq!(run(&["--dup-stats", "--mode", "near-exact"], input), expected);
+    assert_eq!(run(&["--dup-stats", "--ignore-bytes", "5-"], input), expected);
+    assert_eq!(run(&["--dup-stats", "-z"], b"a\0b\0a\0c\0a\0"), expected);
+    assert_eq!(run(&["--dup-stats"], b""), "0\ndistinct: 0\ntotal: 0\nduplicates: 0\nratio: 0.0000\n");
+}
+
+/// Lines are compared after `--trim` and the other input options, but every line read is counted
+#[test]
+fn test_line_mapping() {
+    assert_eq!(run(&["--dup-stats", "--trim", "--lower"], b"A\n a\nb\n\n"), "3\ndistinct: 3\ntotal: 4\nduplicates: 1\nratio: 0.2500\n");
+}
+
+#[test]
+fn test_file_input() {
+    let contents = std::fs::read_to_string(test_file("hamlet_words.txt")).unwrap();
+    let total = contents.lines().count();
+    let distinct = contents.lines().collect::<HashSet<_>>().len();
+    let output = run(&["--dup-stats", "--no-stdin", test_file("hamlet_words.txt").to_str().unwrap()], b"");
+    let mut lines = output.lines();
+    assert_eq!(lines.next(), Some(distinct.to_string().as_str()));
+    assert_eq!(lines.next(), Some(format!("distinct: {distinct}").as_str()));
+    assert_eq!(lines.next(), Some(format!("total: {total}").as_str()));
+    assert_eq!(lines.next(), Some(format!("duplicates: {}", total - distinct).as_str()));
+    assert_eq!(lines.next(), Some(format!("ratio: {:.4}", (total - distinct) as f64 / total as f64).as_str()));
+}
+
+/// `--checksum` comes first, and `--stats` after
+#[test]
+fn test_with_other_stats() {
+    let output = run(&["--dup-stats", "--checksum", "--stats"], b"a\na\n");
+    let names: Vec<&str> = output.lines().skip(1).map(|line| line.split_once(':').unwrap().0).collect();
+    assert_eq!(names, ["crc32", "distinct", "total", "duplicates", "ratio", "singletons", "bytes_read"]);
+}
+
+#[test]
+fn test_usage_errors() {
+    for args in [
+        &["--mode", "estimate"][..],
+        &["--report"],
+        &["--words"],
+        &["--delimiter-str", "||"],
+        &["--max-distinct", "10"],
+    ] {
+        assert_usage_error(&[&["--dup-stats"][..], args].concat(), b"a\n");
+    }
+}
@@ -0,0 +1,125 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `-d`/`--duplicates` and `-U`/`--unique-only`
+
+use std::collections::BTreeMap;
+use std::process::Output;
+
+use test_support::test_file;
+
+use common::{run_files, run_on_files};
+
+mod common;
+
+fn run_output(args: &[&str]) -> Output {
+    run_on_files(args, &[test_file("hamlet_words.txt")])
+}
+
+fn run(args: &[&str]) -> String {
+    run_files(args, &[test_file("hamlet_words.txt")])
+}
+
+/// Every line of the test file with its count, in bytewise order
+fn counts() -> BTreeMap<String, u64> {
+    let mut counts = BTreeMap::new();
+    for line in std::fs::read_to_string(test_file("hamlet_words.txt")).unwrap().lines() {
+        *counts.entry(line.to_string()).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Every line of the test file that occurs more than once, with its count, in bytewise order
+fn duplicates() -> BTreeMap<String, u64> {
+    let mut counts = counts();
+    counts.retain(|_line, count| *count > 1);
+    counts
+}
+
+/// Every line of the test file that occurs exactly once, in bytewise order
+fn singletons() -> Vec<String> {
+    counts().into_iter().filter(|(_line, count)| *count == 1).map(|(line, _count)| line).collect()
+}
+
+#[test]
+fn test_duplicates() {
+    let expected: String = duplicates().keys().map(|line| format!("{line}\n")).collect();
+    assert!(!expected.is_empty());
+    for args in [
+        &["-d", "-s"][..],
+        &["--duplicates", "--sort", "--no-memmap"],
+        &["-d", "-s", "--memmap"],
+        // reported as they appeared in the input, which is the same here
+        &["-d", "-s", "--strip-ansi"],
+    ] {
+        assert_eq!(run(args), expected, "{args:?}");
+    }
+}
+
+/// Without a sort, duplicates are printed in an unspecified order
+#[test]
+fn test_unsorted() {
+    let mut lines: Vec<String> = run(&["-d"]).lines().map(str::to_string).collect();
+    lines.sort_unstable();
+    assert_eq!(lines, duplicates().into_keys().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_with_report() {
+    let expected: String = duplicates().iter().map(|(line, count)| format!("{count:7} {line}\n")).collect();
+    assert_eq!(run(&["-d", "-c", "-s"]), expected);
+    assert_eq!(run(&["-d", "-c", "-s", "--strip-ansi", "--no-memmap"]), expected);
+
+    let by_count = run(&["-d", "-c", "-S"]);
+    let mut lines: Vec<&str> = expected.lines().collect();
+    lines.sort_by_key(|line| std::cmp::Reverse(line[..7].trim().parse::<u64>().unwrap()));
+    assert_eq!(by_count.lines().count(), lines.len());
+    assert_eq!(by_count.lines().next(), lines.first().copied());
+}
+
+#[test]
+fn test_reverse() {
+    let mut expected: Vec<String> = duplicates().into_keys().map(|line| format!("{line}\n")).collect();
+    expected.reverse();
+    assert_eq!(run(&["-d", "-sr"]), expected.concat());
+}
+
+#[test]
+fn test_unique_only() {
+    let expected: String = singletons().iter().map(|line| format!("{line}\n")).collect();
+    assert!(!expected.is_empty());
+    for args in [
+        &["-U", "-s"][..],
+        &["--unique-only", "--sort", "--no-memmap"],
+        &["-U", "-s", "--memmap"],
+        &["-U", "-s", "--strip-ansi"],
+    ] {
+        assert_eq!(run(args), expected, "{args:?}");
+    }
+    let with_counts: String = singletons().iter().map(|line| format!("      1 {line}\n")).collect();
+    assert_eq!(run(&["-U", "-c", "-s"]), with_counts);
+}
+
+/// Every distinct line is either a duplicate or a singleton
+#[test]
+fn test_unique_only_complements_duplicates() {
+    let mut lines: Vec<String> = [run(&["-d"]), run(&["-U"])].concat().lines().map(str::to_string).collect();
+    lines.sort_unstable();
+    assert_eq!(lines, counts().into_keys().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_usage_errors() {
+    for args in [
+        &["-d", "-U"][..],
+        &["-U", "--mode", "estimate"],
+        &["-U", "--unique"],
+        &["-d", "--mode", "estimate"],
+        &["-d", "--mode", "near-exact"],
+        &["-d", "--unique"],
+        &["-d", "--assume-sorted"],
+        &["-d", "--stats"],
+    ] {
+        assert_eq!(run_output(args).status.code(), Some(2), "{args:?}");
+    }
+}
@@ -0,0 +1,76 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--expect`
+
+use test_support::{test_file, HAMLET_WORDS};
+
+use common::{run_on_files, EXIT_CODE_EXPECTATION_FAILED};
+
+mod common;
+
+/// Run cuniq and return its exit code and the count it printed
+fn run(args: &[&str], file: &str) -> (i32, usize) {
+    let output = run_on_files(args, &[test_file(file)]);
+    let count = String::from_utf8(output.stdout).unwrap().trim().parse().unwrap();
+    (output.status.code().unwrap(), count)
+}
+
+/// Run cuniq on hamlet_words.txt and return only its exit code, for runs that don't print a count
+fn run_for_exit_code(args: &[&str]) -> i32 {
+    run_on_files(args, &[HAMLET_WORDS.path()])
+        .status
+        .code()
+        .unwrap()
+}
+
+#[test]
+fn test_exact() {
+    let exact = HAMLET_WORDS.distinct.plain.to_string();
+    let below = (HAMLET_WORDS.distinct.plain - 1).to_string();
+    assert_eq!(run(&["--expect", &exact], HAMLET_WORDS.filename), (0, HAMLET_WORDS.distinct.plain));
+    assert_eq!(run(&["--expect", &below], HAMLET_WORDS.filename), (EXIT_CODE_EXPECTATION_FAILED, HAMLET_WORDS.distinct.plain));
+    assert_eq!(run(&["--expect", &exact, "--mode", "near-exact"], HAMLET_WORDS.filename), (0, HAMLET_WORDS.distinct.plain));
+}
+
+#[test]
+fn test_range() {
+    let from = format!("{}..6000", HAMLET_WORDS.distinct.plain);
+    let to = format!("5000..{}", HAMLET_WORDS.distinct.plain);
+    let above = format!("{}..6000", HAMLET_WORDS.distinct.plain + 1);
+    assert_eq!(run(&["--expect", &from], HAMLET_WORDS.filename).0, 0);
+    assert_eq!(run(&["--expect", &to], HAMLET_WORDS.filename).0, 0);
+    assert_eq!(run(&["--expect", &above], HAMLET_WORDS.filename).0, EXIT_CODE_EXPECTATION_FAILED);
+}
+
+#[test]
+fn test_percentage() {
+    // 5300±2% is 5194..5406
+    assert_eq!(run(&["--expect", "5300±2%"], HAMLET_WORDS.filename).0, EXIT_CODE_EXPECTATION_FAILED);
+    // 5300±2.25% is 5181..5419
+    assert_eq!(run(&["--expect", "5300±2.25%"], HAMLET_WORDS.filename).0, 0);
+    assert_eq!(run(&["--expect", "5300+-2.25%"], HAMLET_WORDS.filename).0, 0);
+}
+
+#[test]
+fn test_invalid_spec() {
+    assert_eq!(run_for_exit_code(&["--expect", "5300±2"]), 2);
+    assert_eq!(run_for_exit_code(&["--expect", "6000..5000"]), 2);
+}
+
+/// The estimate depends on the hash seed, so instead of expecting a particular result this checks
+/// that the exit code agrees with the printed estimate.
+#[test]
+fn test_estimate_conservative() {
+    // 16 registers have a standard error of 26%, so the conservative range for 1000 is about 220..1780
+    let widening: f64 = 3.0 * 1.04 / 4.0;
+    let conservative_range = (1000.0 * (1.0 - widening)).floor() as usize..=(1000.0 * (1.0 + widening)).ceil() as usize;
+    let args = ["--mode", "estimate", "--size", "16", "--expect", "1000"];
+    let (exit_code, estimate) = run(&args, "cardinality_1000.txt");
+    let expected_exit_code = if estimate == 1000 { 0 } else { EXIT_CODE_EXPECTATION_FAILED };
+    assert_eq!(exit_code, expected_exit_code, "estimate {estimate} without --expect-conservative");
+
+    let (exit_code, estimate) = run(&[args.as_slice(), &["--expect-conservative"]].concat(), "cardinality_1000.txt");
+    let expected_exit_code = if conservative_range.contains(&estimate) { 0 } else { EXIT_CODE_EXPECTATION_FAILED };
+    assert_eq!(exit_code, expected_exit_code, "estimate {estimate} with --expect-conservative");
+}
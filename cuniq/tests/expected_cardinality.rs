@@ -0,0 +1,68 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--expected-cardinality`, and the deprecated `--size` it replaces
+
+use std::process::Output;
+
+use test_support::{test_file, CARDINALITY_1000};
+
+use common::run_on_files;
+
+mod common;
+
+/// Run cuniq verbosely on a test file
+fn run(args: &[&str], file: &str) -> Output {
+    run_on_files(&[&["-v"][..], args].concat(), &[test_file(file)])
+}
+
+/// Run cuniq and return its stdout and stderr
+fn run_ok(args: &[&str], file: &str) -> (String, String) {
+    let output = run(args, file);
+    assert!(output.status.success(), "cuniq {args:?} failed: {}", String::from_utf8_lossy(&output.stderr));
+    (String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap())
+}
+
+#[test]
+fn test_derived_sizes() {
+    for mode in ["exact", "near-exact"] {
+        let (stdout, stderr) = run_ok(&["--mode", mode, "--expected-cardinality", "1000"], "cardinality_1000.txt");
+        assert_eq!(stdout, format!("{}\n", CARDINALITY_1000.distinct.plain), "{mode}");
+        assert!(stderr.contains("info: --expected-cardinality 1000: initial hash table capacity of 1000\n"), "{mode}: {stderr}");
+        assert!(!stderr.contains("warning"), "{mode}: {stderr}");
+    }
+
+    let (_stdout, stderr) = run_ok(&["--mode", "estimate", "--expected-cardinality", "1000"], "cardinality_1000.txt");
+    assert!(stderr.contains("info: estimating with 8192 registers\n"), "{stderr}");
+    let (_stdout, stderr) = run_ok(&["--mode", "estimate", "--expected-cardinality", "1000000000"], "cardinality_1000.txt");
+    assert!(stderr.contains("info: estimating with 16384 registers\n"), "{stderr}");
+}
+
+#[test]
+fn test_size_is_deprecated() {
+    let (stdout, stderr) = run_ok(&["--size", "1000"], "cardinality_1000.txt");
+    assert_eq!(stdout, format!("{}\n", CARDINALITY_1000.distinct.plain));
+    assert!(stderr.contains("warning: --size is deprecated"), "{stderr}");
+}
+
+#[test]
+fn test_conflicts() {
+    for args in [&["--size", "16"][..], &["--size-memory", "1KiB"]] {
+        let output = run(&[&["--expected-cardinality", "1000"][..], args].concat(), "cardinality_10.txt");
+        assert_eq!(output.status.code(), Some(2), "{args:?}");
+    }
+}
+
+/// The expected cardinality is only a hint, so one too large to allocate for is ignored
+#[test]
+fn test_huge_expected_cardinality() {
+    let huge = u64::MAX.to_string();
+    for mode in ["exact", "near-exact"] {
+        let (stdout, _stderr) = run_ok(&["--mode", mode, "--expected-cardinality", &huge], "cardinality_1000.txt");
+        assert_eq!(stdout, format!("{}\n", CARDINALITY_1000.distinct.plain), "{mode}");
+    }
+    for args in [&["-c"][..], &["-c", "--since-line", "1"], &["-c", "--strip-ansi"], &["--inconsistencies"]] {
+        let output = run(&[args, &["--expected-cardinality", &huge]].concat(), "cardinality_10.txt");
+        assert!(output.status.success(), "{args:?}: {}", String::from_utf8_lossy(&output.stderr));
+    }
+}
@@ -0,0 +1,95 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--export` and `--export-sorted`
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use line_cardinality::export::ExportReader;
+use line_cardinality::{CountUnique, HashingLineCounter, ReportUnique};
+
+use test_support::test_file;
+
+use common::{run_files, run_on_files, temp_path};
+
+mod common;
+
+/// Read an export back, checking that each line appears only once, and whether it is sorted
+fn read_export(path: &Path, sorted: bool) -> HashMap<Vec<u8>, u64> {
+    let mut reader = ExportReader::new(BufReader::new(File::open(path).unwrap())).unwrap();
+    assert_eq!(reader.is_sorted(), sorted);
+    let entries = reader.entries();
+    let mut lines = HashMap::new();
+    let mut line = Vec::new();
+    let mut previous: Option<Vec<u8>> = None;
+    while let Some(count) = reader.read_entry(&mut line).unwrap() {
+        if sorted {
+            if let Some(previous) = &previous {
+                assert!(*previous < line, "{previous:?} exported before {line:?}");
+            }
+            previous = Some(line.clone());
+        }
+        assert!(lines.insert(line.clone(), count).is_none(), "{line:?} exported twice");
+    }
+    assert_eq!(lines.len() as u64, entries);
+    lines
+}
+
+/// The exact counts of a file, counted in memory with the library
+fn in_memory_counts(path: &Path) -> HashMap<Vec<u8>, u64> {
+    let mut counter = HashingLineCounter::<u64, ()>::new();
+    counter.count_unique_in_bytes(&std::fs::read(path).unwrap());
+    counter.iter().map(|(line, count)| (line.to_vec(), *count)).collect()
+}
+
+#[test]
+fn test_round_trip() {
+    let input = test_file("hamlet_words.txt");
+    let expected = in_memory_counts(&input);
+    for (args, sorted) in [(&[][..], false), (&["--export-sorted"][..], true), (&["--report"][..], false), (&["--report", "--export-sorted"][..], true)] {
+        let export = temp_path(&format!("round-trip-{sorted}-{}.lcex", args.len()));
+        let export_arg = export.to_str().unwrap();
+        let stdout = run_files(&[&["--export", export_arg][..], args].concat(), &[&input]);
+        if !args.contains(&"--report") {
+            assert_eq!(stdout, format!("{}\n", expected.len()), "{args:?}");
+        }
+        assert_eq!(read_export(&export, sorted), expected, "{args:?}");
+        std::fs::remove_file(&export).unwrap();
+    }
+}
+
+/// Lines are exported as they were counted, after input options are applied
+#[test]
+fn test_input_options() {
+    let input = temp_path("input-options.txt");
+    std::fs::write(&input, b"a\n A\nb\x1b[1m\nb\n").unwrap();
+    let export = temp_path("input-options.lcex");
+    let export_arg = export.to_str().unwrap();
+    assert_eq!(run_files(&["--export", export_arg, "--trim", "--lower"], &[&input]), "3\n");
+    let expected = HashMap::from([(b"a".to_vec(), 2), (b"b\x1b[1m".to_vec(), 1), (b"b".to_vec(), 1)]);
+    assert_eq!(read_export(&export, false), expected);
+
+    // with --report --strip-ansi, the first original form of each line is exported, as it is reported
+    run_files(&["--export", export_arg, "--report", "--strip-ansi"], &[&input]);
+    let expected = HashMap::from([(b"a".to_vec(), 1), (b" A".to_vec(), 1), (b"b\x1b[1m".to_vec(), 2)]);
+    assert_eq!(read_export(&export, false), expected);
+    std::fs::remove_file(&export).unwrap();
+    std::fs::remove_file(&input).unwrap();
+}
+
+#[test]
+fn test_unsupported() {
+    let input = test_file("cardinality_10.txt");
+    let export = temp_path("unsupported.lcex");
+    let export_arg = export.to_str().unwrap();
+    for args in [&["--mode", "near-exact"][..], &["--mode", "estimate"], &["--unique"], &["--cap", "5"], &["--adjacent"]] {
+        let output = run_on_files(&[&["--export", export_arg][..], args].concat(), &[&input]);
+        assert_eq!(output.status.code(), Some(2), "{args:?}");
+    }
+    let output = run_on_files(&["--export-sorted"], &[&input]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(!export.exists());
+}
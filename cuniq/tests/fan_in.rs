@@ -0,0 +1,125 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for passing very many inputs, and for `@FILE` argument files
+
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+use common::{cuniq, temp_path};
+
+mod common;
+
+/// A directory of `count` tiny input files, each with one line of its own and one line shared by
+/// all of them, and an argument file listing them
+struct Inputs {
+    dir: PathBuf,
+    argfile: PathBuf,
+}
+
+impl Inputs {
+    fn new(name: &str, count: usize) -> Self {
+        let dir = temp_path(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut list = String::new();
+        for index in 0..count {
+            let path = dir.join(format!("{index}.txt"));
+            std::fs::write(&path, format!("line {index}\nshared\n")).unwrap();
+            list.push_str(path.to_str().unwrap());
+            list.push('\n');
+        }
+        let argfile = dir.join("inputs.txt");
+        std::fs::write(&argfile, list).unwrap();
+        Inputs { dir, argfile }
+    }
+
+    fn argfile_arg(&self) -> String {
+        format!("@{}", self.argfile.display())
+    }
+}
+
+impl Drop for Inputs {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn stdout(output: Output) -> String {
+    assert!(output.status.success(), "cuniq failed: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_argfile() {
+    let inputs = Inputs::new("argfile", 3);
+    assert_eq!(stdout(cuniq().arg(inputs.argfile_arg()).output().unwrap()), "4\n");
+    // arguments are read from the file as well as files
+    let options = inputs.dir.join("options.txt");
+    std::fs::write(&options, "--report\n--sort\n").unwrap();
+    let output = stdout(cuniq().arg(format!("@{}", options.display())).arg(inputs.argfile_arg()).output().unwrap());
+    assert_eq!(output.lines().next(), Some("      1 line 0"));
+    assert_eq!(output.lines().last(), Some("      3 shared"));
+}
+
+#[test]
+fn test_argfile_errors() {
+    let missing = cuniq().arg("@/nonexistent/cuniq-argfile").output().unwrap();
+    assert_eq!(missing.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&missing.stderr).contains("error reading argument file"));
+    // after `--`, @ is part of a file name
+    let literal = cuniq().args(["--", "@/nonexistent/cuniq-argfile"]).output().unwrap();
+    assert!(String::from_utf8_lossy(&literal.stderr).contains("error opening file \"@/nonexistent/cuniq-argfile\""));
+}
+
+/// Run cuniq on `inputs` with at most `limit` file descriptors
+#[cfg(unix)]
+fn run_with_fd_limit(inputs: &Inputs, limit: usize, args: &[&str]) -> String {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(format!("ulimit -n {limit} && exec \"$0\" \"$@\""))
+        .arg(env!("CARGO_BIN_EXE_cuniq"))
+        .args(args)
+        .arg(inputs.argfile_arg())
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    stdout(output)
+}
+
+/// Inputs are opened one at a time, so there can be far more of them than open files are allowed
+#[cfg(unix)]
+#[test]
+fn test_more_inputs_than_file_descriptors() {
+    let inputs = Inputs::new("fd-limit", 1000);
+    let mut read_methods = vec!["--no-memmap"];
+    if cfg!(feature = "memmap") {
+        read_methods.push("--memmap");
+    }
+    for read_method in read_methods {
+        assert_eq!(run_with_fd_limit(&inputs, 64, &[read_method]), "1001\n", "{read_method}");
+        assert_eq!(run_with_fd_limit(&inputs, 64, &[read_method, "--checksum"]).lines().count(), 2, "{read_method}");
+        assert_eq!(run_with_fd_limit(&inputs, 64, &[read_method, "--unique"]).lines().count(), 1001, "{read_method}");
+    }
+    assert_eq!(run_with_fd_limit(&inputs, 64, &["--cap", "5000"]), "1001\n");
+    assert_eq!(run_with_fd_limit(&inputs, 64, &["--unique", "--stream"]).lines().count(), 1001);
+}
+
+/// Time a count of `inputs`
+fn time_count(inputs: &Inputs, expected: usize) -> Duration {
+    let start = Instant::now();
+    assert_eq!(stdout(cuniq().arg(inputs.argfile_arg()).output().unwrap()), format!("{expected}\n"));
+    start.elapsed()
+}
+
+/// Ten times as many inputs take about ten times as long, not a hundred. Run with `--ignored`, as
+/// creating the inputs is slow.
+#[test]
+#[ignore]
+fn test_wide_fan_in_scales_linearly() {
+    let small = Inputs::new("fan-in-small", 1000);
+    let large = Inputs::new("fan-in-large", 10_000);
+    let small_time = (0..3).map(|_| time_count(&small, 1001)).min().unwrap();
+    let large_time = (0..3).map(|_| time_count(&large, 10_001)).min().unwrap();
+    assert!(large_time < small_time * 30 + Duration::from_millis(500), "10,000 inputs took {large_time:?}, but 1,000 took {small_time:?}");
+}
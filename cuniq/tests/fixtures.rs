@@ -0,0 +1,38 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Check cuniq against every expected result in the test_support fixture registry
+
+use test_support::{report_checksum, Fixture, ALL};
+
+use common::{run_on_files, success};
+
+mod common;
+
+fn run(args: &[&str], fixture: &Fixture) -> Vec<u8> {
+    success(args, run_on_files(args, &[fixture.path()]))
+}
+
+#[test]
+fn test_distinct() {
+    for fixture in ALL {
+        let distinct = fixture.distinct;
+        assert_eq!(run(&[], fixture), format!("{}\n", distinct.plain).into_bytes(), "{}", fixture.filename);
+        // synthetic fixtures are all numbers or a single repeated word, which no mapping changes
+        if fixture.generator.is_none() {
+            for (args, expected) in [(&["--trim"][..], distinct.trim), (&["--lower"], distinct.lower), (&["--trim", "--lower"], distinct.trim_lower)] {
+                assert_eq!(run(args, fixture), format!("{expected}\n").into_bytes(), "{args:?} {}", fixture.filename);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_report_checksums() {
+    for fixture in ALL {
+        if let Some(checksum) = fixture.report_checksum {
+            let report = run(&["--report", "--sort", "--no-pad"], fixture);
+            assert_eq!(report_checksum(&report), checksum, "{}", fixture.filename);
+        }
+    }
+}
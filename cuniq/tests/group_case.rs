@@ -0,0 +1,75 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--group-case`
+
+use std::process::Output;
+
+use test_support::test_file;
+
+use common::{run_on_files, success};
+
+mod common;
+
+/// Run cuniq on `casing_variants.txt`
+fn run_on_fixture(args: &[&str]) -> Output {
+    run_on_files(&[&["-c", "--group-case", "--count-width", "2"][..], args].concat(), &[test_file("casing_variants.txt")])
+}
+
+fn run(args: &[&str]) -> String {
+    String::from_utf8(success(args, run_on_fixture(args))).unwrap()
+}
+
+/// Every variant is listed under its group, including groups with only one variant
+#[test]
+fn test_groups() {
+    let expected = "\
+\x206 elder
+\t 2 elder
+\t 1 ELDER
+\t 1 ElDeR
+\t 1 Elder
+\t 1 eLDER
+\x203 apple
+\t 1 APPLE
+\t 1 Apple
+\t 1 apple
+\x203 banana
+\t 2 banana
+\t 1 Banana
+\x202 cherry
+\t 2 cherry
+\x201   apple
+\t 1   apple
+\x201 date
+\t 1 date
+\x201 date\x20
+\t 1 Date\x20
+\x201 fig
+\t 1 fig
+";
+    // with and without the memmap fast path
+    for args in [&[][..], &["--no-memmap"]] {
+        assert_eq!(run(args), expected, "{args:?}");
+    }
+}
+
+/// Other line mapping happens before grouping
+#[test]
+fn test_trim() {
+    let output = run(&["--trim"]);
+    assert!(output.starts_with(" 6 elder\n"), "{output}");
+    assert!(output.contains("\n 4 apple\n\t 2 apple\n\t 1 APPLE\n\t 1 Apple\n"), "{output}");
+    assert!(output.contains("\n 2 date\n\t 1 Date\n\t 1 date\n"), "{output}");
+}
+
+#[test]
+fn test_invalid() {
+    for args in [&["--format", "ndjson"][..], &["--lower"], &["--fold", "simple"], &["--sort"]] {
+        let output = run_on_fixture(args);
+        assert_eq!(output.status.code(), Some(2), "{args:?}");
+        assert!(output.stdout.is_empty(), "{args:?}");
+    }
+    let output = run_on_files(&["--group-case"], &[test_file("casing_variants.txt")]);
+    assert_eq!(output.status.code(), Some(2), "--group-case requires --report");
+}
@@ -0,0 +1,64 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--hash-diagnostics`
+
+#![cfg(feature = "diagnostics")]
+
+use common::{assert_usage_error, run_with_input, split_output};
+
+mod common;
+
+/// Run cuniq, returning its stdout and stderr
+fn run(args: &[&str], input: &[u8]) -> (String, String) {
+    let (exit_code, stdout, stderr) = split_output(run_with_input(args, input));
+    assert_eq!(exit_code, 0, "cuniq {args:?} failed: {stderr}");
+    (stdout, stderr)
+}
+
+/// 100 distinct lines
+fn distinct_lines() -> Vec<u8> {
+    (0..100).flat_map(|line| format!("line {line}\n").into_bytes()).collect()
+}
+
+#[test]
+fn test_count() {
+    let (stdout, stderr) = run(&["--hash-diagnostics"], &distinct_lines());
+    assert_eq!(stdout, "100\n");
+    assert!(stderr.starts_with("hash diagnostics:\n"), "{stderr}");
+    for line in ["distinct: 100", "capacity: 112", "buckets: 128", "growths: 6", "probe_distance_sample: 100", "key_length 4-7: 100"] {
+        assert!(stderr.lines().any(|l| l == line), "missing {line:?} in {stderr}");
+    }
+}
+
+/// A large enough `--expected-cardinality` means the table never grows
+#[test]
+fn test_expected_cardinality() {
+    let (_stdout, stderr) = run(&["--hash-diagnostics", "--expected-cardinality", "100"], &distinct_lines());
+    assert!(stderr.lines().any(|l| l == "growths: 0"), "{stderr}");
+}
+
+/// The report itself is unchanged, with the diagnostics only on stderr
+#[test]
+fn test_report() {
+    let input = b"a\nb\na\n";
+    let (expected, _stderr) = run(&["-c", "-s"], input);
+    let (stdout, stderr) = run(&["-c", "-s", "--hash-diagnostics"], input);
+    assert_eq!(stdout, expected);
+    assert!(stderr.lines().any(|l| l == "distinct: 2"), "{stderr}");
+
+    let (_stdout, stderr) = run(&["--hash-diagnostics", "--stats"], input);
+    assert!(stderr.lines().any(|l| l == "distinct: 2"), "{stderr}");
+}
+
+#[test]
+fn test_usage_errors() {
+    for args in [
+        &["--hash-diagnostics", "--mode", "near-exact"][..],
+        &["--hash-diagnostics", "--mode", "estimate"],
+        &["--hash-diagnostics", "-c", "--strip-ansi"],
+        &["--hash-diagnostics", "--unique"],
+    ] {
+        assert_usage_error(args, b"a\n");
+    }
+}
@@ -0,0 +1,75 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--header`
+
+use test_support::test_file;
+
+use common::run_files;
+
+mod common;
+
+/// Run cuniq on test files and return its stdout
+fn run(args: &[&str], files: &[&str]) -> String {
+    run_files(args, &files.iter().map(|file| test_file(file)).collect::<Vec<_>>())
+}
+
+/// Split output into its header lines and report lines
+fn split_header(output: &str) -> (Vec<&str>, Vec<&str>) {
+    output.lines().partition(|line| line.starts_with('#'))
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(bytes);
+    hasher.finalize()
+}
+
+#[test]
+fn test_header_content() {
+    let output = run(&["--report", "--header"], &["hamlet_words.txt", "shuffled_numbers.txt"]);
+    let (header, _report) = split_header(&output);
+    assert_eq!(header.len(), 5, "{header:?}");
+    assert!(header[0].starts_with(&format!("# cuniq {} ", env!("CARGO_PKG_VERSION"))), "{}", header[0]);
+    assert!(header[1].starts_with("# args: ") && header[1].ends_with("shuffled_numbers.txt"), "{}", header[1]);
+
+    let hamlet = std::fs::read(test_file("hamlet_words.txt")).unwrap();
+    let expected = format!("# input: {} ({} bytes, crc32 {:08x})", test_file("hamlet_words.txt").display(), hamlet.len(), crc32(&hamlet));
+    assert_eq!(header[2], expected);
+
+    // larger than 1 MiB, so only the start is checksummed
+    let numbers = std::fs::read(test_file("shuffled_numbers.txt")).unwrap();
+    let expected = format!(
+        "# input: {} ({} bytes, crc32 {:08x} of first 1048576 bytes)",
+        test_file("shuffled_numbers.txt").display(),
+        numbers.len(),
+        crc32(&numbers[..1024 * 1024]),
+    );
+    assert_eq!(header[3], expected);
+
+    assert!(header[4].starts_with("# generated: ") && header[4].ends_with('Z'), "{}", header[4]);
+}
+
+#[test]
+fn test_full_checksum() {
+    let output = run(&["--report", "--header", "--header-full-checksum", "--no-memmap"], &["shuffled_numbers.txt"]);
+    let numbers = std::fs::read(test_file("shuffled_numbers.txt")).unwrap();
+    let expected = format!("# input: {} ({} bytes, crc32 {:08x})", test_file("shuffled_numbers.txt").display(), numbers.len(), crc32(&numbers));
+    assert_eq!(split_header(&output).0[2], expected);
+}
+
+/// The report following the header is the same as without `--header`
+#[test]
+fn test_report_unchanged() {
+    let mut arg_sets = vec![&["--report", "--sort"][..], &["--report", "--sort", "--strip-ansi"]];
+    if cfg!(feature = "memmap") {
+        arg_sets.push(&["--report", "--sort", "--memmap"]);
+    }
+    for args in arg_sets {
+        let without_header = run(args, &["hamlet_words.txt"]);
+        let with_header = run(&[args, &["--header"]].concat(), &["hamlet_words.txt"]);
+        let (header, report) = split_header(&with_header);
+        assert_eq!(header.len(), 4);
+        assert_eq!(report, without_header.lines().collect::<Vec<_>>(), "{args:?}");
+    }
+}
@@ -0,0 +1,53 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--hot-cache`
+
+use std::process::Output;
+
+use test_support::test_file;
+
+use common::run_on_files;
+
+mod common;
+
+fn run(args: &[&str], file: &str) -> Output {
+    run_on_files(args, &[test_file(file)])
+}
+
+/// Run cuniq and return its stdout, which must be the same with and without `--hot-cache`
+fn assert_same_output(args: &[&str], file: &str) {
+    let without_cache = run(args, file);
+    assert!(without_cache.status.success());
+    for hot_cache in ["--hot-cache", "--hot-cache=4"] {
+        let with_cache = run(&[args, &[hot_cache]].concat(), file);
+        assert!(with_cache.status.success(), "{hot_cache} {args:?}: {}", String::from_utf8_lossy(&with_cache.stderr));
+        assert_eq!(with_cache.stdout, without_cache.stdout, "{hot_cache} {args:?}");
+    }
+}
+
+#[test]
+fn test_same_results() {
+    assert_same_output(&[], "hamlet_words.txt");
+    assert_same_output(&["--lower"], "hamlet_words.txt");
+    assert_same_output(&["--unique", "--stream"], "hamlet_words.txt");
+    assert_same_output(&["--unique", "--sort"], "hamlet_words.txt");
+    assert_same_output(&[], "same_line.txt");
+}
+
+#[test]
+fn test_exact_only() {
+    let output = run(&["--hot-cache", "--mode", "near-exact"], "hamlet_words.txt");
+    assert_eq!(output.status.code(), Some(2));
+    let output = run(&["--hot-cache", "--report"], "hamlet_words.txt");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_too_many_slots() {
+    assert!(run(&["--hot-cache=1048576"], "hamlet_words.txt").status.success());
+    for slots in ["--hot-cache=1048577", "--hot-cache=18446744073709551615"] {
+        let output = run(&[slots], "hamlet_words.txt");
+        assert_eq!(output.status.code(), Some(2), "{slots}");
+    }
+}
@@ -0,0 +1,66 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--ignore-bytes`
+
+use common::{assert_usage_error, run};
+
+mod common;
+
+/// Fixed-width records: a timestamp at bytes 0-19, an account at 21-26, and a sequence number at
+/// 28-31 that also changes between otherwise identical records
+const RECORDS: &[u8] = b"\
+2024-01-01T00:00:00Z ACC001 0001 DEBIT
+2024-01-01T00:00:05Z ACC001 0002 DEBIT
+2024-01-02T09:30:00Z ACC002 0003 CREDIT
+2024-01-02T09:30:01Z ACC001 0004 CREDIT
+2024-01-03T12:00:00Z ACC001 0005 DEBIT
+";
+
+#[test]
+fn test_count() {
+    assert_eq!(run(&[], RECORDS), "5\n");
+    assert_eq!(run(&["--ignore-bytes", "0-19"], RECORDS), "5\n");
+    assert_eq!(run(&["--ignore-bytes", "0-19,28-31"], RECORDS), "3\n");
+    assert_eq!(run(&["--ignore-bytes", "28-31,0-20"], RECORDS), "3\n");
+    assert_eq!(run(&["--ignore-bytes", "0-32"], RECORDS), "2\n");
+}
+
+/// Reports and `--unique` show the first original form of each line
+#[test]
+fn test_original_lines() {
+    assert_eq!(run(&["-c", "--sort", "--ignore-bytes", "0-19,28-31"], RECORDS), concat!(
+        "      3 2024-01-01T00:00:00Z ACC001 0001 DEBIT\n",
+        "      1 2024-01-02T09:30:00Z ACC002 0003 CREDIT\n",
+        "      1 2024-01-02T09:30:01Z ACC001 0004 CREDIT\n",
+    ));
+    assert_eq!(run(&["--unique", "--sort", "--ignore-bytes", "0-19,28-31"], RECORDS), "\
+2024-01-01T00:00:00Z ACC001 0001 DEBIT
+2024-01-02T09:30:00Z ACC002 0003 CREDIT
+2024-01-02T09:30:01Z ACC001 0004 CREDIT
+");
+}
+
+/// Positions are of the line as read, before `--trim`, and lines shorter than a range are compared
+/// on the bytes they have
+#[test]
+fn test_positions() {
+    let input = b"  x1 a\n  y2 a\n  z3 b\n1\n";
+    assert_eq!(run(&["--ignore-bytes", "0-3", "--trim", "-c", "--sort"], input), "      2   x1 a\n      1   z3 b\n      1 1\n");
+    assert_eq!(run(&["--ignore-bytes", "2-", "--unique", "--sort"], b"ab\nabc\nabcdef\nx\n"), "ab\nx\n");
+}
+
+#[test]
+fn test_usage_errors() {
+    for args in [
+        &["--ignore-bytes", "5-2"][..],
+        &["--ignore-bytes", ""],
+        &["--ignore-bytes", "a-b"],
+        &["--ignore-bytes", "0-3", "--mode", "estimate"],
+        &["--ignore-bytes", "0-3", "-c", "--mode", "near-exact"],
+        &["--ignore-bytes", "0-3", "--adjacent"],
+        &["--ignore-bytes", "0-3", "--stats"],
+    ] {
+        assert_usage_error(args, RECORDS);
+    }
+}
@@ -0,0 +1,96 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--inconsistencies`
+
+use std::process::Output;
+
+use test_support::test_file;
+
+use common::{run_files, run_on_files};
+
+mod common;
+
+/// Run cuniq on `casing_variants.txt`
+fn run_on_fixture(args: &[&str]) -> Output {
+    run_on_files(args, &[test_file("casing_variants.txt")])
+}
+
+fn run(args: &[&str]) -> String {
+    run_files(args, &[test_file("casing_variants.txt")])
+}
+
+#[test]
+fn test_groups() {
+    let expected = "\
+elder
+\t      2 elder
+\t      1 ELDER
+\t      1 ElDeR
+\t      1 Elder
+\t      1 eLDER
+apple
+\t      1   apple
+\t      1 APPLE
+\t      1 Apple
+\t      1 apple
+banana
+\t      2 banana
+\t      1 Banana
+date
+\t      1 Date 
+\t      1 date
+";
+    assert_eq!(run(&["--inconsistencies", "--trim", "--lower"]), expected);
+}
+
+/// Each option that maps lines decides which forms are the same line
+#[test]
+fn test_mappings() {
+    assert_eq!(run(&["--inconsistencies", "--trim"]), "apple\n\t      1   apple\n\t      1 apple\n");
+    let lower = run(&["--inconsistencies", "--lower"]);
+    assert!(lower.starts_with("elder\n"), "{lower}");
+    assert!(!lower.contains("date"), "{lower}");
+    assert_eq!(lower.lines().filter(|line| !line.starts_with('\t')).collect::<Vec<_>>(), ["elder", "apple", "banana"]);
+}
+
+#[test]
+fn test_max_variants() {
+    let expected = "\
+elder
+\t      2 elder
+\t      1 ELDER
+\t      1 Elder
+\t      2 ...
+apple
+\t      1   apple
+\t      1 Apple
+\t      1 apple
+\t      1 ...
+banana
+\t      2 banana
+\t      1 Banana
+date
+\t      1 Date 
+\t      1 date
+";
+    assert_eq!(run(&["--inconsistencies", "--trim", "--lower", "--max-variants", "3"]), expected);
+}
+
+#[test]
+fn test_no_mapping() {
+    let output = run_on_fixture(&["--inconsistencies"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("has nothing to find"));
+}
+
+#[test]
+fn test_exact_mode_only() {
+    for mode in ["near-exact", "estimate"] {
+        let output = run_on_fixture(&["--inconsistencies", "--lower", "--mode", mode]);
+        assert_eq!(output.status.code(), Some(2), "--mode {mode}");
+    }
+    let output = run_on_fixture(&["--inconsistencies", "--lower", "--max-variants", "0"]);
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -0,0 +1,109 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--listen`
+
+#![cfg(unix)]
+
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{cuniq, temp_path};
+
+mod common;
+
+fn socket_path(name: &str) -> PathBuf {
+    temp_path(&format!("{name}.sock"))
+}
+
+/// Start cuniq listening on `socket`, and wait until it accepts connections
+fn listen(socket: &Path, args: &[&str]) -> Child {
+    let _ = std::fs::remove_file(socket);
+    let child = cuniq()
+        .args(args)
+        .arg("--listen")
+        .arg(socket)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let start = Instant::now();
+    while !socket.exists() {
+        assert!(start.elapsed() < Duration::from_secs(10), "cuniq never created its socket");
+        thread::sleep(Duration::from_millis(10));
+    }
+    child
+}
+
+fn send(socket: &Path, data: &[u8]) {
+    let mut stream = UnixStream::connect(socket).unwrap();
+    stream.write_all(data).unwrap();
+}
+
+/// Send lines `start..end` from each of several client threads at once
+fn send_from_threads(socket: &Path, ranges: &[(u32, u32)]) {
+    thread::scope(|scope| {
+        for &(start, end) in ranges {
+            scope.spawn(move || {
+                let lines: String = (start..end).map(|i| format!("{i}\n")).collect();
+                send(socket, lines.as_bytes());
+            });
+        }
+    });
+}
+
+fn finish(child: Child, socket: &Path) -> Output {
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(!socket.exists(), "socket file was not removed");
+    output
+}
+
+#[test]
+fn test_shutdown_connection() {
+    let socket = socket_path("shutdown");
+    let child = listen(&socket, &[]);
+    // overlapping ranges, so the merged count is less than the sum of the connections' counts
+    send_from_threads(&socket, &[(0, 1000), (500, 1500), (1000, 2000), (0, 2000)]);
+    send(&socket, b"SHUTDOWN\n");
+    let output = finish(child, &socket);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2000\n");
+}
+
+#[test]
+fn test_sigterm() {
+    let socket = socket_path("sigterm");
+    let child = listen(&socket, &[]);
+    send_from_threads(&socket, &[(0, 100), (50, 150)]);
+    // connections are only counted once accepted, so wait for the listener to catch up
+    thread::sleep(Duration::from_millis(500));
+    let status = Command::new("kill").arg("-TERM").arg(child.id().to_string()).status().unwrap();
+    assert!(status.success());
+    let output = finish(child, &socket);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "150\n");
+}
+
+#[test]
+fn test_report() {
+    let socket = socket_path("report");
+    let child = listen(&socket, &["--report", "--sort"]);
+    send_from_threads(&socket, &[(0, 3), (1, 3)]);
+    // a SHUTDOWN line followed by more data is counted like any other line
+    send(&socket, b"SHUTDOWN\n2\n");
+    send(&socket, b"SHUTDOWN");
+    let output = finish(child, &socket);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "      1 0\n      2 1\n      3 2\n      1 SHUTDOWN\n");
+}
+
+#[test]
+fn test_rejects_files() {
+    let output = cuniq()
+        .args(["--listen", "unused.sock", "file.txt"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -0,0 +1,66 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--match` and `--key-group`
+
+#![cfg(feature = "regex")]
+
+use std::io::Write;
+
+use common::{run, run_with_input};
+
+mod common;
+
+const LOG: &[u8] = b"\
+2024-01-01 GET /index user=alice
+2024-01-01 GET /about user=bob
+2024-01-01 healthcheck ok
+2024-01-02 POST /login user=Alice
+2024-01-02 GET /index user=alice
+2024-01-02 GET /index user=carol
+";
+
+#[test]
+fn test_match() {
+    assert_eq!(run(&["--match", "GET"], LOG), "4\n");
+    assert_eq!(run(&["--match", "GET", "--mode", "near-exact"], LOG), "4\n");
+    assert_eq!(run(&["--match", "^nothing"], LOG), "0\n");
+}
+
+#[test]
+fn test_key_group() {
+    assert_eq!(run(&["--match", r"user=(\w+)", "--key-group", "1"], LOG), "4\n");
+    // input options apply to the captured key
+    assert_eq!(run(&["--match", r"user=(\w+)", "--key-group", "1", "--lower"], LOG), "3\n");
+    assert_eq!(run(&["--match", r"^(\S+)", "--key-group", "1"], LOG), "2\n");
+    assert_eq!(run(&["--match", r"user=(\w+)", "--key-group", "1", "--cap", "2"], LOG), ">=2\n");
+}
+
+#[test]
+fn test_report() {
+    let report = run(&["--match", r"GET (\S+)", "--key-group", "1", "--report", "--sort"], LOG);
+    assert_eq!(report, "      1 /about\n      3 /index\n");
+}
+
+#[test]
+fn test_estimate() {
+    let mut input = Vec::new();
+    for index in 0..10_000 {
+        writeln!(input, "id={index} noise={}", index % 7).unwrap();
+    }
+    let estimate: f64 = run(&["--match", r"noise=(\d)", "--key-group", "1", "--mode", "estimate"], &input).trim().parse().unwrap();
+    assert!((estimate - 7.0).abs() < 1.0, "estimate was {estimate}");
+}
+
+#[test]
+fn test_invalid_arguments() {
+    for args in [
+        ["--match", "("].as_slice(),
+        &["--match", r"user=(\w+)", "--key-group", "2"],
+        &["--key-group", "1"],
+        &["--match", "a", "--words"],
+    ] {
+        let output = run_with_input(args, LOG);
+        assert_eq!(output.status.code(), Some(2), "cuniq {args:?}");
+    }
+}
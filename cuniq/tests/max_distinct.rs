@@ -0,0 +1,59 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--max-distinct`
+
+use std::io::Write;
+use std::process::Stdio;
+
+use test_support::CARDINALITY_1000;
+
+use common::{cuniq, run_test_file, EXIT_CODE_MAX_DISTINCT_EXCEEDED};
+
+mod common;
+
+#[test]
+fn test_count_exceeded() {
+    for mode in ["exact", "near-exact"] {
+        let (exit_code, stdout, stderr) = run_test_file(&["--mode", mode, "--max-distinct", "10"], "cardinality_1000.txt");
+        assert_eq!((exit_code, stdout.as_str()), (0, ">=10\n"), "{mode} mode");
+        assert!(stderr.starts_with("warning: stopped tracking new lines at --max-distinct 10"), "{mode} mode stderr: {stderr}");
+
+        let (exit_code, stdout, _) = run_test_file(&["--mode", mode, "--max-distinct", "10", "--strict-max-distinct"], "cardinality_1000.txt");
+        assert_eq!((exit_code, stdout.as_str()), (EXIT_CODE_MAX_DISTINCT_EXCEEDED, ">=10\n"), "{mode} mode");
+    }
+}
+
+#[test]
+fn test_not_exceeded() {
+    let distinct = CARDINALITY_1000.distinct.plain;
+    for limit in [distinct, distinct * 2] {
+        let (exit_code, stdout, stderr) = run_test_file(&["--max-distinct", &limit.to_string(), "--strict-max-distinct"], "cardinality_1000.txt");
+        assert_eq!((exit_code, stdout, stderr.as_str()), (0, format!("{distinct}\n"), ""), "--max-distinct {limit}");
+    }
+}
+
+/// Lines that are tracked keep exact counts, even after the limit is reached
+#[test]
+fn test_report_counts_stay_exact() {
+    let mut child = cuniq()
+        .args(["-c", "--sort", "--max-distinct", "2", "-q"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"a\nb\na\nc\na\nb\nd\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "      3 a\n      2 b\n");
+}
+
+/// `--limit-distinct-report` is another name for `--max-distinct`, and still warns when lines
+/// were dropped from the report
+#[test]
+fn test_limit_distinct_report_alias() {
+    let (exit_code, stdout, stderr) = run_test_file(&["-c", "--limit-distinct-report", "10"], "cardinality_1000.txt");
+    assert_eq!(exit_code, 0);
+    assert_eq!(stdout.lines().count(), 10);
+    assert!(stderr.starts_with("warning: stopped tracking new lines at --max-distinct 10"), "stderr: {stderr}");
+}
@@ -0,0 +1,38 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Files give the same results with and without `--memmap`, including empty files
+
+use test_support::{test_file, EMPTY};
+
+use common::{run_files, temp_file};
+
+mod common;
+
+/// Run cuniq on `path` with each way of reading files, checking they all print `expected`
+fn check(args: &[&str], path: &str, expected: &str) {
+    let read_methods: &[&str] = if cfg!(feature = "memmap") { &["--memmap", "--no-memmap"] } else { &["--no-memmap"] };
+    for &read_method in read_methods {
+        assert_eq!(run_files(&[args, &[read_method]].concat(), &[path]), expected, "{args:?} {read_method}");
+    }
+}
+
+#[test]
+fn test_zero_length_file() {
+    let path = temp_file("zero-length.txt", b"");
+    let path_str = path.to_str().unwrap();
+    check(&[], path_str, "0\n");
+    check(&["--report"], path_str, "");
+    check(&["--report", "--sort"], path_str, "");
+    check(&["--checksum"], path_str, "0\ncrc32: 00000000\n");
+    check(&["--mode", "estimate"], path_str, "0\n");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_newline_only_file() {
+    let path = test_file("empty.txt");
+    let path = path.to_str().unwrap();
+    check(&[], path, "1\n");
+    check(&["--report"], path, &format!("{} \n", EMPTY.lines));
+}
@@ -0,0 +1,163 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--metrics-file` and `--statsd`
+
+use std::collections::BTreeMap;
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use common::{assert_usage_error, run, temp_path};
+
+mod common;
+
+/// One sample from a textfile
+#[derive(Debug)]
+struct Sample {
+    labels: BTreeMap<String, String>,
+    value: f64,
+}
+
+fn is_metric_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+fn is_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parse `name="value",...}` up to and including the closing brace, unescaping the values
+fn parse_labels(mut rest: &str) -> (BTreeMap<String, String>, &str) {
+    let mut labels = BTreeMap::new();
+    loop {
+        if let Some(after) = rest.strip_prefix('}') {
+            return (labels, after);
+        }
+        let (name, after) = rest.split_once("=\"").expect("label without a quoted value");
+        assert!(is_label_name(name), "invalid label name {name:?}");
+        let mut value = String::new();
+        let mut chars = after.char_indices();
+        let end = loop {
+            match chars.next().expect("unterminated label value") {
+                (_, '\\') => match chars.next().expect("unterminated escape").1 {
+                    '\\' => value.push('\\'),
+                    '"' => value.push('"'),
+                    'n' => value.push('\n'),
+                    c => panic!("invalid escape \\{c}"),
+                },
+                (_, '\n') => panic!("unescaped newline in label value"),
+                (index, '"') => break index,
+                (_, c) => value.push(c),
+            }
+        };
+        assert!(labels.insert(name.to_string(), value).is_none(), "duplicate label {name}");
+        rest = &after[end + 1..];
+        rest = rest.strip_prefix(',').unwrap_or(rest);
+    }
+}
+
+/// Check that `textfile` follows the text exposition format as the textfile collector reads it:
+/// every sample has a valid name and labels, and is preceded by its `# HELP` and `# TYPE` lines.
+/// Returns the samples by metric name.
+fn validate_textfile(textfile: &str) -> BTreeMap<String, Sample> {
+    assert!(textfile.ends_with('\n'), "textfile must end with a newline");
+    let mut samples = BTreeMap::new();
+    let mut help = None;
+    let mut typed = None;
+    for line in textfile.lines() {
+        if let Some(rest) = line.strip_prefix("# HELP ") {
+            help = Some(rest.split_once(' ').expect("HELP without text").0.to_string());
+        } else if let Some(rest) = line.strip_prefix("# TYPE ") {
+            let (name, metric_type) = rest.split_once(' ').expect("TYPE without a type");
+            assert_eq!(metric_type, "gauge");
+            typed = Some(name.to_string());
+        } else {
+            let (name, rest) = line.split_once('{').expect("sample without labels");
+            assert!(is_metric_name(name), "invalid metric name {name:?}");
+            assert_eq!(help.as_deref(), Some(name), "sample without HELP");
+            assert_eq!(typed.as_deref(), Some(name), "sample without TYPE");
+            let (labels, rest) = parse_labels(rest);
+            let value = rest.strip_prefix(' ').expect("no space before value").parse().expect("invalid value");
+            assert!(samples.insert(name.to_string(), Sample { labels, value }).is_none(), "duplicate metric {name}");
+        }
+    }
+    samples
+}
+
+#[test]
+fn test_metrics_file() {
+    let path = temp_path("metrics.prom");
+    let path_arg = path.to_str().unwrap();
+    assert_eq!(run(&["--metrics-file", path_arg, "--metrics-label", "job=nightly \"logs\"\\\n"], b"a\nb\na\r\nc"), "3\n");
+    let samples = validate_textfile(&std::fs::read_to_string(&path).unwrap());
+    assert_eq!(samples.keys().collect::<Vec<_>>(), ["cuniq_distinct_lines", "cuniq_duration_seconds", "cuniq_input_bytes", "cuniq_input_lines"]);
+    assert_eq!(samples["cuniq_distinct_lines"].value, 3.0);
+    assert_eq!(samples["cuniq_input_lines"].value, 4.0);
+    assert_eq!(samples["cuniq_input_bytes"].value, 8.0);
+    assert!(samples["cuniq_duration_seconds"].value >= 0.0);
+    let labels = &samples["cuniq_distinct_lines"].labels;
+    assert_eq!(labels["mode"], "exact");
+    assert_eq!(labels["input"], "stdin");
+    assert_eq!(labels["job"], "nightly \"logs\"\\\n");
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// Inputs are listed in the order they were read, and metrics that weren't counted are left out
+#[test]
+fn test_metrics_file_inputs() {
+    let input = temp_path("metrics-input.txt");
+    std::fs::write(&input, "x\ny\n").unwrap();
+    let input_arg = input.to_str().unwrap();
+    let path = temp_path("metrics-inputs.prom");
+    let path_arg = path.to_str().unwrap();
+
+    run(&["--metrics-file", path_arg, "--metrics-prefix", "logs:cuniq", "--mode", "estimate", input_arg], b"z\n");
+    let samples = validate_textfile(&std::fs::read_to_string(&path).unwrap());
+    assert_eq!(samples["logs:cuniq_input_lines"].value, 3.0);
+    assert_eq!(samples["logs:cuniq_distinct_lines"].labels["mode"], "estimate");
+    assert_eq!(samples["logs:cuniq_distinct_lines"].labels["input"], format!("stdin,{input_arg}"));
+
+    run(&["--metrics-file", path_arg, "--delimiter-str", "||", "--no-stdin", input_arg], b"");
+    let samples = validate_textfile(&std::fs::read_to_string(&path).unwrap());
+    assert!(!samples.contains_key("cuniq_input_lines"));
+    assert_eq!(samples["cuniq_input_bytes"].value, 4.0);
+
+    std::fs::remove_file(&input).unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_statsd() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    let address = socket.local_addr().unwrap().to_string();
+    assert_eq!(run(&["--statsd", &address, "--metrics-prefix", "batch", "-z"], b"a\0b\0a\0"), "2\n");
+    let mut datagrams = Vec::new();
+    let mut buffer = [0; 512];
+    for _ in 0..4 {
+        let read = socket.recv(&mut buffer).unwrap();
+        datagrams.push(String::from_utf8(buffer[..read].to_vec()).unwrap());
+    }
+    assert_eq!(datagrams[..3], ["batch_distinct_lines:2|g", "batch_input_lines:3|g", "batch_input_bytes:6|g"]);
+    let duration = datagrams[3].strip_prefix("batch_duration_seconds:").unwrap().strip_suffix("|g").unwrap();
+    assert!(duration.parse::<f64>().unwrap() >= 0.0);
+}
+
+#[test]
+fn test_usage_errors() {
+    for args in [
+        &["--metrics-file", "metrics.prom", "--report"][..],
+        &["--statsd", "127.0.0.1:8125", "--unique"],
+        &["--metrics-file", "metrics.prom", "--cap", "10"],
+        &["--metrics-file", "metrics.prom", "--metrics-prefix", "2cuniq"],
+        &["--metrics-file", "metrics.prom", "--metrics-label", "mode=fast"],
+        &["--metrics-file", "metrics.prom", "--metrics-label", "no_value"],
+        &["--statsd", "not an address"],
+    ] {
+        assert_usage_error(args, b"a\n");
+    }
+}
@@ -0,0 +1,54 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--min-count` and `--max-count`
+
+use common::{assert_usage_error, run};
+
+mod common;
+
+/// "a" once, "b" twice, "c" three times, and "d" four times
+const INPUT: &[u8] = b"a\nb\nb\nc\nc\nc\nd\nd\nd\nd\n";
+
+#[test]
+fn test_sorted() {
+    let report = |extra: &[&str]| run(&[&["-c", "-s", "--count-width", "0"][..], extra].concat(), INPUT);
+    assert_eq!(report(&["--min-count", "3"]), "3 c\n4 d\n");
+    assert_eq!(report(&["--max-count", "2"]), "1 a\n2 b\n");
+    assert_eq!(report(&["--min-count", "2", "--max-count", "3"]), "2 b\n3 c\n");
+    assert_eq!(report(&["--min-count", "3", "--max-count", "3"]), "3 c\n");
+    assert_eq!(report(&["--min-count", "5"]), "");
+}
+
+#[test]
+fn test_sort_by_count() {
+    let output = run(&["-c", "--sort-by-count", "--count-width", "0", "--min-count", "2"], INPUT);
+    assert_eq!(output, "4 d\n3 c\n2 b\n");
+}
+
+#[test]
+fn test_unsorted() {
+    let output = run(&["-c", "--count-width", "0", "--min-count", "2", "--max-count", "3"], INPUT);
+    let mut lines: Vec<&str> = output.lines().collect();
+    lines.sort_unstable();
+    assert_eq!(lines, ["2 b", "3 c"]);
+}
+
+/// The thresholds apply on top of `--duplicates`
+#[test]
+fn test_duplicates() {
+    assert_eq!(run(&["-c", "-d", "-s", "--count-width", "0", "--max-count", "2"], INPUT), "2 b\n");
+}
+
+#[test]
+fn test_usage_errors() {
+    for args in [
+        &["--min-count", "2"][..],
+        &["-d", "--max-count", "2"],
+        &["-c", "--min-count", "3", "--max-count", "2"],
+        &["-c", "--min-count", "2", "--mode", "near-exact"],
+        &["-c", "--max-count", "-1"],
+    ] {
+        assert_usage_error(args, INPUT);
+    }
+}
@@ -0,0 +1,63 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--format ndjson`
+
+use std::collections::HashMap;
+
+use test_support::{test_file, HAMLET_WORDS};
+
+use common::run;
+
+mod common;
+
+/// Parse `{"line":"...","count":N}` for lines that don't need escaping
+fn parse_ndjson(output: &str) -> HashMap<String, u64> {
+    output.lines()
+        .map(|line| {
+            let entry = line.strip_prefix("{\"line\":\"").and_then(|entry| entry.strip_suffix('}')).unwrap_or_else(|| panic!("unexpected line {line}"));
+            let (line, count) = entry.split_once("\",\"count\":").unwrap();
+            (line.to_string(), count.parse().unwrap())
+        })
+        .collect()
+}
+
+/// Parse a text report
+fn parse_text(output: &str) -> HashMap<String, u64> {
+    output.lines()
+        .map(|line| {
+            let (count, line) = line.trim_start().split_once(' ').unwrap();
+            (line.to_string(), count.parse().unwrap())
+        })
+        .collect()
+}
+
+/// The same entries as the text report, including the borrowed memmap report and `--strip-ansi`
+#[test]
+fn test_matches_text_report() {
+    let path = test_file("hamlet_words.txt");
+    let path = path.to_str().unwrap();
+    let mut arg_sets = vec![&["--no-memmap"][..], &["--strip-ansi"]];
+    if cfg!(feature = "memmap") {
+        arg_sets.push(&["--memmap"]);
+    }
+    for args in arg_sets {
+        let text = run(&[args, &["--report", "--no-stdin", path]].concat(), b"");
+        let ndjson = run(&[args, &["--report", "--no-stdin", "--format", "ndjson", path]].concat(), b"");
+        let text = parse_text(&text);
+        assert_eq!(text.len(), HAMLET_WORDS.distinct.plain);
+        assert_eq!(parse_ndjson(&ndjson), text, "{args:?}");
+    }
+}
+
+#[test]
+fn test_escaping() {
+    let output = run(&["--report", "--sort", "--format", "ndjson"], b"say \"hi\"\nback\\slash\ntab\there\nbad\xff\nbad\xff\n");
+    let expected = [
+        r#"{"line":"back\\slash","count":1}"#,
+        r#"{"line_bytes":[98,97,100,255],"count":2}"#,
+        r#"{"line":"say \"hi\"","count":1}"#,
+        r#"{"line":"tab\there","count":1}"#,
+    ];
+    assert_eq!(output.lines().collect::<Vec<_>>(), expected);
+}
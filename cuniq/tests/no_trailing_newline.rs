@@ -0,0 +1,75 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--no-trailing-newline`
+
+use test_support::test_file;
+
+use common::{cuniq, run_files, temp_path};
+
+mod common;
+
+/// Run cuniq on `small.txt` with and without `--no-trailing-newline`, and check that the only
+/// difference is the final newline
+fn run(args: &[&str]) -> String {
+    let run_with = |extra: &[&str]| {
+        run_files(&[args, extra].concat(), &[test_file("small.txt")])
+    };
+    let with_newline = run_with(&[]);
+    let without_newline = run_with(&["--no-trailing-newline"]);
+    assert_eq!(with_newline.strip_suffix('\n'), Some(without_newline.as_str()), "cuniq {args:?}");
+    without_newline
+}
+
+#[test]
+fn test_count() {
+    assert_eq!(run(&[]), "3");
+    assert_eq!(run(&["--mode", "estimate"]), "3");
+    assert_eq!(run(&["--cap", "10"]), "3");
+    assert_eq!(run(&["--checksum"]).lines().count(), 2);
+    assert_eq!(run(&["--stats"]).lines().count(), 3);
+}
+
+#[test]
+fn test_report() {
+    assert_eq!(run(&["--report", "--sort"]), "      1 one\n      3 three\n      2 two");
+    assert_eq!(run(&["--report", "--sort", "--format", "ndjson"]).lines().count(), 3);
+}
+
+#[test]
+fn test_unique() {
+    assert_eq!(run(&["--unique", "--sort"]), "one\nthree\ntwo");
+    assert_eq!(run(&["--unique", "--stream"]), "three\ntwo\none");
+}
+
+#[test]
+fn test_output_file() {
+    let path = temp_path("no-trailing-newline.txt");
+    let status = cuniq()
+        .args(["--unique", "--sort", "--no-trailing-newline", "--no-stdin", "--output"])
+        .arg(&path)
+        .arg(test_file("small.txt"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\nthree\ntwo");
+    std::fs::remove_file(path).unwrap();
+}
+
+/// The count is written like a report, so failing to write its last line is an error even when it
+/// has no newline to flush it
+#[cfg(target_os = "linux")]
+#[test]
+fn test_count_write_error() {
+    for args in [&[][..], &["--mode", "estimate"], &["--cap", "10"], &["--stats"]] {
+        let output = cuniq()
+            .args(args)
+            .args(["--no-trailing-newline", "--no-stdin"])
+            .arg(test_file("small.txt"))
+            .stdout(std::fs::File::create("/dev/full").unwrap())
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1), "cuniq {args:?}");
+        assert!(String::from_utf8_lossy(&output.stderr).contains("failed to write output"), "cuniq {args:?}");
+    }
+}
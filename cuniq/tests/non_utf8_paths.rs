@@ -0,0 +1,83 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for paths that aren't valid UTF-8 in every output that names a path. Output that can hold
+//! any bytes gets the path as it is, and output that must be UTF-8 gets it with `\xNN` escapes.
+
+#![cfg(unix)]
+
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::process::Output;
+
+use bstr::ByteSlice;
+
+use common::cuniq;
+
+mod common;
+
+/// A path in the temp directory whose file name ends in `suffix`, which need not be UTF-8
+fn temp_path(suffix: &[u8]) -> PathBuf {
+    let mut name = format!("cuniq-test-{}-", std::process::id()).into_bytes();
+    name.extend_from_slice(suffix);
+    std::env::temp_dir().join(OsStr::from_bytes(&name))
+}
+
+fn run(args: &[&OsStr]) -> Output {
+    cuniq().args(args).output().unwrap()
+}
+
+#[test]
+fn test_error_messages() {
+    let missing = temp_path(b"missing-\xff.txt");
+    let output = run(&["--no-stdin".as_ref(), missing.as_os_str()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!("error opening file \"{}\\xff.txt\"", temp_path(b"missing-").display())), "{stderr}");
+
+    let unwritable = temp_path(b"no-such-dir-\xfe/report.txt");
+    let output = run(&["--report".as_ref(), "--output".as_ref(), unwritable.as_os_str(), "--no-stdin".as_ref()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(r#"no-such-dir-\xfe/report.txt""#), "{stderr}");
+}
+
+#[test]
+fn test_header_and_logs() {
+    let input = temp_path(b"input-caf\xe9.txt");
+    std::fs::write(&input, "a\nb\na\n").unwrap();
+    let report = temp_path(b"report-\xe9.txt");
+    let output = run(&["--report".as_ref(), "--sort".as_ref(), "--header".as_ref(), "-v".as_ref(), "--no-stdin".as_ref(), "-o".as_ref(), report.as_os_str(), input.as_os_str()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // the header holds the raw bytes of the input, and of the arguments it was given
+    let written = std::fs::read(&report).unwrap();
+    let input_line = written.lines().find(|line| line.starts_with(b"# input: ")).unwrap();
+    assert!(input_line.starts_with(&[b"# input: ".as_slice(), input.as_os_str().as_bytes(), b" (6 bytes"].concat()), "{:?}", input_line.as_bstr());
+    let args_line = written.lines().find(|line| line.starts_with(b"# args: ")).unwrap();
+    assert!(args_line.ends_with(&[b"'", input.as_os_str().as_bytes(), b"'"].concat()), "{:?}", args_line.as_bstr());
+    assert!(written.ends_with(b"      2 a\n      1 b\n"));
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(r#"input-caf\xe9.txt" (6 bytes)"#), "{stderr}");
+
+    std::fs::remove_file(&input).unwrap();
+    std::fs::remove_file(&report).unwrap();
+}
+
+#[test]
+fn test_metrics_label() {
+    let input = temp_path(b"metrics-\xff.txt");
+    std::fs::write(&input, "a\nb\n").unwrap();
+    let metrics = temp_path(b"metrics.prom");
+    let output = run(&["--metrics-file".as_ref(), metrics.as_os_str(), "--no-stdin".as_ref(), input.as_os_str()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // label values must be UTF-8, and a backslash in one is escaped again by the text format
+    let textfile = std::fs::read_to_string(&metrics).unwrap();
+    assert!(textfile.contains(r#"metrics-\\xff.txt"}"#), "{textfile}");
+
+    std::fs::remove_file(&input).unwrap();
+    std::fs::remove_file(&metrics).unwrap();
+}
@@ -0,0 +1,75 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--output` and its compression
+
+use std::path::PathBuf;
+
+use test_support::HAMLET_WORDS;
+
+use common::{run_files, run_on_files, temp_path};
+
+mod common;
+
+/// Reports are sorted so that separate runs write them in the same order
+const REPORT_ARGS: &[&str] = &["-c", "--sort"];
+
+/// Write a report of hamlet_words.txt to a temp file named `name`, and return its path
+fn report_to(name: &str, extra_args: &[&str]) -> PathBuf {
+    let path = temp_path(name);
+    let args = [REPORT_ARGS, &["-o", path.to_str().unwrap()], extra_args].concat();
+    assert_eq!(run_files(&args, &[HAMLET_WORDS.path()]), "");
+    path
+}
+
+#[test]
+fn test_uncompressed() {
+    let path = report_to("report.txt", &[]);
+    let stdout = run_files(REPORT_ARGS, &[HAMLET_WORDS.path()]);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), stdout);
+    std::fs::remove_file(path).unwrap();
+}
+
+/// The compressed report decompresses to the same bytes as the uncompressed one
+#[cfg(feature = "gzip")]
+#[test]
+fn test_gzip() {
+    use std::io::Read;
+
+    let uncompressed = report_to("gzip-report.txt", &[]);
+    for (name, args) in [("report.gz", &[][..]), ("gzip-report.out", &["--output-compression", "gzip"]), ("report-level-1.gz", &["--compression-level", "1"])] {
+        let compressed = report_to(name, args);
+        let mut decompressed = Vec::new();
+        flate2::read::GzDecoder::new(std::fs::File::open(&compressed).unwrap()).read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, std::fs::read(&uncompressed).unwrap(), "{name}");
+        std::fs::remove_file(compressed).unwrap();
+    }
+    std::fs::remove_file(uncompressed).unwrap();
+}
+
+/// The compressed report decompresses to the same bytes as the uncompressed one
+#[cfg(feature = "zstd")]
+#[test]
+fn test_zstd() {
+    let uncompressed = report_to("zstd-report.txt", &[]);
+    for (name, args) in [("report.zst", &[][..]), ("zstd-report.out", &["--output-compression", "zstd"]), ("report-level-19.zst", &["--compression-level", "19"])] {
+        let compressed = report_to(name, args);
+        let decompressed = zstd::decode_all(std::fs::File::open(&compressed).unwrap()).unwrap();
+        assert_eq!(decompressed, std::fs::read(&uncompressed).unwrap(), "{name}");
+        std::fs::remove_file(compressed).unwrap();
+    }
+    std::fs::remove_file(uncompressed).unwrap();
+}
+
+/// A compression level can't be applied to uncompressed output, so it is rejected rather than
+/// ignored
+#[test]
+fn test_level_without_compression() {
+    let path = temp_path("level-without-compression.txt");
+    let args = [REPORT_ARGS, &["-o", path.to_str().unwrap(), "--compression-level", "5"]].concat();
+    let output = run_on_files(&args, &[HAMLET_WORDS.path()]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--compression-level"), "{stderr}");
+    assert!(!path.exists());
+}
@@ -0,0 +1,56 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--output-delimiter` and `--count-last`
+
+use common::{assert_usage_error, run_bytes, run_with_input};
+
+mod common;
+
+const INPUT: &[u8] = b"a\nb\nb\n";
+
+#[test]
+fn test_output_delimiter() {
+    assert_eq!(run_bytes(&["-c", "-s", "--no-pad", "--output-delimiter", "\\t"], INPUT), b"1\ta\n2\tb\n");
+    assert_eq!(run_bytes(&["-c", "-s", "--count-width", "3", "--output-delimiter", " | "], INPUT), b"  1 | a\n  2 | b\n");
+    assert_eq!(run_bytes(&["-c", "-s", "--no-pad", "--output-delimiter", "\\0"], INPUT), b"1\0a\n2\0b\n");
+    assert_eq!(run_bytes(&["-c", "-s", "--no-pad", "--output-delimiter", ""], INPUT), b"1a\n2b\n");
+}
+
+#[test]
+fn test_count_last() {
+    assert_eq!(run_bytes(&["-c", "-s", "--no-pad", "--count-last"], INPUT), b"a 1\nb 2\n");
+    assert_eq!(run_bytes(&["-c", "-s", "--no-pad", "--count-last", "--output-delimiter", "\\t"], INPUT), b"a\t1\nb\t2\n");
+    // counts after their lines are only padded to an explicit width
+    assert_eq!(run_bytes(&["-c", "-s", "--count-last", "--output-delimiter", "\\t"], INPUT), b"a\t1\nb\t2\n");
+    assert_eq!(run_bytes(&["-c", "-s", "--count-width", "3", "--count-last"], INPUT), b"a   1\nb   2\n");
+}
+
+/// `--group-case` uses the same layout for each group and its variants
+#[test]
+fn test_group_case() {
+    let output = run_bytes(&["-c", "--group-case", "--no-pad", "--count-last", "--output-delimiter", "="], b"A\na\n");
+    assert_eq!(output, b"a=2\n\tA=1\n\ta=1\n");
+}
+
+/// Other formats have their own delimiters, so these options are ignored with a warning
+#[test]
+fn test_other_formats() {
+    let output = run_with_input(&["-c", "--format", "ndjson", "--count-last", "--output-delimiter", "\\t"], b"a\n");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"{\"line\":\"a\",\"count\":1}\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--output-delimiter has no effect with --format ndjson"), "{stderr}");
+    assert!(stderr.contains("--count-last has no effect with --format ndjson"), "{stderr}");
+}
+
+#[test]
+fn test_usage_errors() {
+    for args in [
+        &["--output-delimiter", "\\t"][..],
+        &["--count-last"],
+        &["-d", "--count-last"],
+    ] {
+        assert_usage_error(args, INPUT);
+    }
+}
@@ -0,0 +1,50 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--precision`
+
+use std::process::Output;
+
+use test_support::HAMLET_WORDS;
+
+use common::run_on_files;
+
+mod common;
+
+fn run(args: &[&str]) -> Output {
+    run_on_files(args, &[HAMLET_WORDS.path()])
+}
+
+/// The estimate of hamlet_words.txt's distinct lines, printed with `precision`
+fn estimate(precision: &str) -> String {
+    let output = run(&["--mode", "estimate", "--precision", precision]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap().trim_end().to_string()
+}
+
+#[test]
+fn test_precision() {
+    // each run hashes with a different seed, so each estimate can only be compared to the true count
+    let raw: usize = estimate("exact-integer").parse().unwrap();
+    assert!(raw.abs_diff(HAMLET_WORDS.distinct.plain) < HAMLET_WORDS.distinct.plain / 20, "estimate was {raw}");
+
+    let sig2: usize = estimate("sig2").parse().unwrap();
+    assert_eq!(sig2 % 100, 0, "{sig2} has more than 2 significant digits");
+    // rounding may move it up to 50 further away
+    assert!(sig2.abs_diff(HAMLET_WORDS.distinct.plain) < HAMLET_WORDS.distinct.plain / 20 + 50, "estimate was {sig2}");
+    let sig3: usize = estimate("sig3").parse().unwrap();
+    assert_eq!(sig3 % 10, 0, "{sig3} has more than 3 significant digits");
+    assert!(sig3.abs_diff(HAMLET_WORDS.distinct.plain) < HAMLET_WORDS.distinct.plain / 20 + 5, "estimate was {sig3}");
+
+    let interval = estimate("interval");
+    let (low, high) = interval.split_once("..").unwrap();
+    let (low, high): (usize, usize) = (low.parse().unwrap(), high.parse().unwrap());
+    assert!(low < high, "empty interval {interval}");
+    assert!((low..=high).contains(&HAMLET_WORDS.distinct.plain), "{} is not in {interval}", HAMLET_WORDS.distinct.plain);
+}
+
+#[test]
+fn test_only_estimate_mode() {
+    assert_eq!(run(&["--precision", "sig2"]).status.code(), Some(2));
+    assert!(run(&["--precision", "exact-integer"]).status.success());
+}
@@ -0,0 +1,97 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--preview`
+
+use std::collections::HashSet;
+
+use test_support::{test_file, HAMLET_WORDS};
+
+use common::{run_test_file, run_with_input};
+
+mod common;
+
+/// The projected distinct count printed to stderr, if there was one
+fn projection(stderr: &str) -> Option<u64> {
+    let line = stderr.lines().find(|line| line.contains("preview: projected about "))?;
+    let projected = line.split("projected about ").nth(1).unwrap().split(' ').next().unwrap();
+    Some(projected.parse().unwrap())
+}
+
+#[test]
+fn test_count() {
+    let contents = std::fs::read_to_string(test_file("cardinality_1000.txt")).unwrap();
+    let first_lines: Vec<&str> = contents.lines().take(100).collect();
+    let distinct = first_lines.iter().collect::<HashSet<_>>().len();
+    let read = first_lines.iter().map(|line| line.len() as u64 + 1).sum::<u64>();
+
+    for mode in ["exact", "near-exact"] {
+        let (exit_code, stdout, stderr) = run_test_file(&["--mode", mode, "--preview", "100"], "cardinality_1000.txt");
+        assert_eq!((exit_code, stdout.as_str()), (0, format!("{distinct}\n").as_str()), "{mode} mode: {stderr}");
+        assert!(stderr.contains("warning: preview: only the first 100 lines of each input were read"), "{mode} mode: {stderr}");
+        assert!(stderr.contains(&format!("preview: {distinct} distinct in 100 lines read")), "{mode} mode: {stderr}");
+        let expected = (distinct as f64 * contents.len() as f64 / read as f64).round() as u64;
+        assert_eq!(projection(&stderr), Some(expected), "{mode} mode: {stderr}");
+    }
+
+    // an estimate is projected the same way, but may not be exact
+    let (exit_code, _stdout, stderr) = run_test_file(&["--mode", "estimate", "--preview", "100"], "cardinality_1000.txt");
+    assert_eq!(exit_code, 0, "{stderr}");
+    assert!(stderr.contains("in 100 lines read"), "{stderr}");
+    assert!(projection(&stderr).is_some(), "{stderr}");
+}
+
+/// A report covers exactly the lines previewed
+#[test]
+fn test_report() {
+    let (exit_code, stdout, stderr) = run_test_file(&["-c", "--preview", "250"], "cardinality_1000.txt");
+    assert_eq!(exit_code, 0, "{stderr}");
+    let total: u64 = stdout.lines().map(|line| line.split_whitespace().next().unwrap().parse::<u64>().unwrap()).sum();
+    assert_eq!(total, 250);
+
+    let (exit_code, stdout, stderr) = run_test_file(&["-c", "--header", "--preview", "250"], "cardinality_1000.txt");
+    assert_eq!(exit_code, 0, "{stderr}");
+    assert!(stdout.contains("\n# preview: only the first 250 lines of each input were read\n"), "{stdout}");
+}
+
+/// Each input is previewed separately
+#[test]
+fn test_several_inputs() {
+    let file = test_file("cardinality_1000.txt");
+    let file = file.to_str().unwrap();
+    let (exit_code, stdout, stderr) = run_test_file(&["-c", "--preview", "10", file], "same_line.txt");
+    assert_eq!(exit_code, 0, "{stderr}");
+    let total: u64 = stdout.lines().map(|line| line.split_whitespace().next().unwrap().parse::<u64>().unwrap()).sum();
+    assert_eq!(total, 20);
+    assert!(stderr.contains("in 20 lines read"), "{stderr}");
+}
+
+/// Previewing more lines than there are reads the whole input, so the projection is the count
+#[test]
+fn test_whole_input() {
+    let (exit_code, stdout, stderr) = run_test_file(&["--preview", "100000000"], HAMLET_WORDS.filename);
+    assert_eq!(exit_code, 0, "{stderr}");
+    assert_eq!(projection(&stderr), Some(stdout.trim().parse().unwrap()), "{stderr}");
+}
+
+/// The size of stdin isn't known in advance, so there is nothing to project over
+#[test]
+fn test_stdin() {
+    // cuniq stops reading after the previewed lines
+    let output = run_with_input(&["--preview", "2"], b"a
+b
+c
+");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2\n");
+    assert_eq!(projection(&stderr), None);
+    assert!(stderr.contains("no projection is possible"), "{stderr}");
+}
+
+#[test]
+fn test_invalid() {
+    for args in [&["--preview", "0"][..], &["--preview", "10", "--cap", "5"], &["--preview", "10", "--assume-sorted"]] {
+        let (exit_code, stdout, _) = run_test_file(args, "same_line.txt");
+        assert_eq!((exit_code, stdout.as_str()), (2, ""), "{args:?}");
+    }
+}
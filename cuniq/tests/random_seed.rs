@@ -0,0 +1,41 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--random-seed`
+
+use test_support::{CARDINALITY_1000, HAMLET_WORDS};
+
+use common::run_test_file;
+
+mod common;
+
+/// Seeds only affect hashing, so exact results don't change
+#[test]
+fn test_exact() {
+    let (exit_code, stdout, stderr) = run_test_file(&["--random-seed"], "cardinality_1000.txt");
+    assert_eq!((exit_code, stdout), (0, format!("{}\n", CARDINALITY_1000.distinct.plain)), "{stderr}");
+    assert!(!stderr.contains("differ between runs"), "{stderr}");
+
+    let (exit_code, stdout, stderr) = run_test_file(&["-c"], HAMLET_WORDS.filename);
+    assert_eq!(exit_code, 0, "{stderr}");
+    let mut expected: Vec<&str> = stdout.lines().collect();
+    expected.sort_unstable();
+    let (exit_code, stdout, stderr) = run_test_file(&["-c", "--random-seed"], HAMLET_WORDS.filename);
+    assert_eq!(exit_code, 0, "{stderr}");
+    let mut report: Vec<&str> = stdout.lines().collect();
+    report.sort_unstable();
+    assert_eq!(report, expected);
+}
+
+/// Modes whose results depend on the seed warn that they may not be reproducible
+#[test]
+fn test_warning() {
+    for mode in ["near-exact", "estimate"] {
+        let (exit_code, stdout, stderr) = run_test_file(&["--mode", mode, "--random-seed"], "cardinality_1000.txt");
+        assert_eq!(exit_code, 0, "{mode} mode: {stderr}");
+        assert!(!stdout.is_empty(), "{mode} mode");
+        assert!(stderr.contains(&format!("--random-seed: {mode} mode results may differ between runs")), "{mode} mode: {stderr}");
+    }
+    let (_, _, stderr) = run_test_file(&["--mode", "near-exact", "--random-seed", "--quiet"], "cardinality_1000.txt");
+    assert_eq!(stderr, "");
+}
@@ -0,0 +1,31 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--report-unseen`
+
+use common::{run, temp_file};
+
+mod common;
+
+const EXPECTED: &[u8] = b" Apple \nbanana\nCherry\n  cherry\n";
+
+#[test]
+fn test_unseen() {
+    let expected = temp_file("report-unseen.txt", EXPECTED);
+    let expected = expected.to_str().unwrap();
+    assert_eq!(run(&["--report-unseen", "--sort", "--expected", expected], b"banana\nCherry\n"), "  cherry\n Apple \n");
+    assert_eq!(run(&["--report-unseen", "--expected", expected], b" Apple \nbanana\nCherry\n  cherry\n"), "");
+    std::fs::remove_file(expected).unwrap();
+}
+
+/// Unseen lines are printed as they are in the expected file, not as `--trim` and `--lower` map them
+#[test]
+fn test_line_mapping() {
+    let expected = temp_file("report-unseen-mapped.txt", EXPECTED);
+    let expected = expected.to_str().unwrap();
+    assert_eq!(run(&["--report-unseen", "--sort", "--expected", expected, "--trim"], b"Apple\nbanana\n"), "  cherry\nCherry\n");
+    assert_eq!(run(&["--report-unseen", "--sort", "--expected", expected, "--lower"], b"banana\nCHERRY\n"), "  cherry\n Apple \n");
+    // of the two lines that map to "cherry", the first is printed
+    assert_eq!(run(&["--report-unseen", "--expected", expected, "--trim", "--lower"], b"apple\nBANANA\n"), "Cherry\n");
+    std::fs::remove_file(expected).unwrap();
+}
@@ -0,0 +1,60 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--since-line`
+
+use common::{assert_usage_error, run, temp_file};
+
+mod common;
+
+const INPUT: &[u8] = b"a\nb\na\nc\nb\nd\nc\nd\n";
+
+#[test]
+fn test_report() {
+    // counts include the occurrences before line 4
+    assert_eq!(run(&["-c", "-s", "--count-width", "0", "--since-line", "4"], INPUT), "2 c\n2 d\n");
+    assert_eq!(run(&["-c", "-s", "--count-width", "0", "--since-line", "1"], INPUT), "2 a\n2 b\n2 c\n2 d\n");
+    assert_eq!(run(&["-c", "-s", "--since-line", "9"], INPUT), "");
+    assert_eq!(run(&["-c", "--format", "ndjson", "--since-line", "6"], INPUT), "{\"line\":\"d\",\"count\":2}\n");
+}
+
+/// Lines are numbered after line mapping, so a line that only differs from an earlier one in case
+/// isn't new with `--lower`
+#[test]
+fn test_line_mapping() {
+    let input = b"Foo\nbar\nFOO\nbaz\n";
+    assert_eq!(run(&["-c", "-s", "--count-width", "0", "--since-line", "3"], input), "1 FOO\n1 baz\n");
+    assert_eq!(run(&["-c", "-s", "--count-width", "0", "--since-line", "3", "--lower"], input), "1 baz\n");
+}
+
+#[test]
+fn test_duplicates_and_unique_only() {
+    let input = b"a\nb\nc\nc\nd\n";
+    assert_eq!(run(&["-d", "--since-line", "2"], input), "c\n");
+    assert_eq!(run(&["-U", "-s", "--since-line", "2"], input), "b\nd\n");
+}
+
+/// Inputs are numbered as if they were concatenated, with stdin first
+#[test]
+fn test_multiple_inputs() {
+    let first = temp_file("first", b"x\ny\n");
+    let second = temp_file("second", b"y\nz\n");
+    let args = ["-c", "-s", "--count-width", "0", "--since-line", "4"];
+    let output = run(&[&args[..], &[first.to_str().unwrap(), second.to_str().unwrap()]].concat(), b"w\n");
+    assert_eq!(output, "1 z\n");
+    std::fs::remove_file(first).unwrap();
+    std::fs::remove_file(second).unwrap();
+}
+
+#[test]
+fn test_usage_errors() {
+    for args in [
+        &["--since-line", "2"][..],
+        &["-c", "--since-line", "0"],
+        &["-c", "--since-line", "2", "--mode", "near-exact"],
+        &["-c", "--since-line", "2", "--words"],
+        &["--unique", "--since-line", "2"],
+    ] {
+        assert_usage_error(args, INPUT);
+    }
+}
@@ -0,0 +1,118 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--sort=numeric`, `--sort-by-count`, and `--reverse`
+
+use std::collections::BTreeMap;
+
+use test_support::{test_file, CARDINALITY_10};
+
+use common::{run_files, run_on_files};
+
+mod common;
+
+/// Run cuniq on a test file and return its stdout
+fn run(args: &[&str], file: &str) -> String {
+    run_files(args, &[test_file(file)])
+}
+
+/// Occurrences of each number in a file containing one number per line, in numeric order
+fn numeric_occurrences(file: &str) -> BTreeMap<u64, u64> {
+    let contents = std::fs::read_to_string(test_file(file)).unwrap();
+    let mut occurrences = BTreeMap::new();
+    for line in contents.lines() {
+        *occurrences.entry(line.parse().unwrap()).or_default() += 1;
+    }
+    occurrences
+}
+
+/// Equivalent to `sort -n | uniq -c`, except with cuniq's count formatting
+#[test]
+fn test_numeric_report() {
+    let expected: String = numeric_occurrences("cardinality_1000.txt").into_iter()
+        .map(|(number, count)| format!("{count:7} {number}\n"))
+        .collect();
+    assert_eq!(run(&["-c", "--sort=numeric"], "cardinality_1000.txt"), expected);
+}
+
+#[test]
+fn test_numeric_unique() {
+    let expected: String = numeric_occurrences("cardinality_100.txt").into_keys()
+        .map(|number| format!("{number}\n"))
+        .collect();
+    assert_eq!(run(&["--unique", "--sort=numeric"], "cardinality_100.txt"), expected);
+}
+
+/// `-s` without a value still sorts bytewise, and doesn't consume the following file argument
+#[test]
+fn test_bytes_is_default() {
+    let mut expected: Vec<String> = numeric_occurrences("cardinality_100.txt").into_keys()
+        .map(|number| format!("{number}\n"))
+        .collect();
+    expected.sort_unstable();
+    assert_eq!(run(&["--unique", "-s"], "cardinality_100.txt"), expected.concat());
+    assert_eq!(run(&["--unique", "--sort=bytes"], "cardinality_100.txt"), expected.concat());
+}
+
+/// Equivalent to `sort | uniq -c | sort -k1,1nr -k2`, except with cuniq's count formatting. Each
+/// report path is covered: memmapped, line mapped, and reporting original lines.
+#[test]
+fn test_sort_by_count() {
+    let contents = std::fs::read_to_string(test_file("hamlet_words.txt")).unwrap();
+    let mut occurrences: BTreeMap<&str, u64> = BTreeMap::new();
+    for line in contents.lines() {
+        *occurrences.entry(line).or_default() += 1;
+    }
+    let mut occurrences: Vec<(&str, u64)> = occurrences.into_iter().collect();
+    occurrences.sort_by(|(_, a_count), (_, b_count)| b_count.cmp(a_count));
+    let expected: String = occurrences.into_iter()
+        .map(|(line, count)| format!("{count:7} {line}\n"))
+        .collect();
+    for args in [&["-c", "--sort-by-count"][..], &["-c", "-S", "--trim"], &["-c", "-S", "--strip-ansi"]] {
+        assert_eq!(run(args, "hamlet_words.txt"), expected, "{args:?}");
+    }
+}
+
+/// `--reverse` gives exactly the reverse of the sorted output, in every sorted path: memmapped,
+/// line mapped, and reporting original lines, sorted by line or by count, with and without counts
+#[test]
+fn test_reverse() {
+    for args in [
+        &["-c", "--sort"][..],
+        &["-c", "--sort", "--no-memmap"],
+        &["-c", "--sort", "--trim"],
+        &["-c", "--sort", "--strip-ansi"],
+        &["-c", "--sort=numeric"],
+        &["-c", "--sort-by-count"],
+        &["-c", "--sort-by-count", "--trim"],
+        &["-c", "--sort-by-count", "--strip-ansi"],
+        &["--unique", "--sort"],
+        &["--unique", "--sort=numeric"],
+        &["--unique", "--sort", "--strip-ansi"],
+    ] {
+        let sorted = run(args, "hamlet_words.txt");
+        let reversed_args: Vec<&str> = args.iter().copied().chain(["--reverse"]).collect();
+        let mut expected: Vec<&str> = sorted.lines().collect();
+        expected.reverse();
+        let expected: String = expected.into_iter().map(|line| format!("{line}\n")).collect();
+        assert_eq!(run(&reversed_args, "hamlet_words.txt"), expected, "{args:?}");
+    }
+}
+
+/// Without a sort, `--reverse` only warns
+#[test]
+fn test_reverse_without_sort() {
+    let output = run_on_files(&["-r"], &[test_file("cardinality_10.txt")]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), format!("{}\n", CARDINALITY_10.distinct.plain));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--reverse has no effect"), "{stderr}");
+}
+
+#[test]
+fn test_sort_by_count_conflicts() {
+    for args in [&["-c", "-S", "--sort"][..], &["-c", "-S", "--group-case"], &["-S"]] {
+        let status = run_on_files(args, &[test_file("cardinality_10.txt")]).status;
+        assert_eq!(status.code(), Some(2), "{args:?}");
+    }
+}
@@ -0,0 +1,34 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Startup time regression test
+
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+use common::cuniq;
+
+mod common;
+
+/// `--version` should take well under a millisecond in a release build. This budget is far larger
+/// than that so that it holds for debug builds on slow machines, but it still catches anything
+/// expensive being done before argument parsing.
+const VERSION_BUDGET: Duration = Duration::from_millis(250);
+
+#[test]
+fn test_version_startup_time() {
+    let fastest = (0..5)
+        .map(|_| {
+            let start = Instant::now();
+            let status = cuniq()
+                .arg("--version")
+                .stdout(Stdio::null())
+                .status()
+                .unwrap();
+            assert!(status.success());
+            start.elapsed()
+        })
+        .min()
+        .unwrap();
+    assert!(fastest < VERSION_BUDGET, "cuniq --version took {fastest:?}, which is over the budget of {VERSION_BUDGET:?}");
+}
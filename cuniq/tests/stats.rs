@@ -0,0 +1,60 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--stats`
+
+use std::collections::HashMap;
+
+use test_support::test_file;
+
+use common::{run_files, temp_file};
+
+mod common;
+
+/// Run cuniq with `--stats` on a test file and return its stdout
+fn run(args: &[&str], file: &str) -> String {
+    run_files(&[&["--stats"][..], args].concat(), &[test_file(file)])
+}
+
+#[test]
+fn test_singletons() {
+    let contents = std::fs::read_to_string(test_file("hamlet_words.txt")).unwrap();
+    let mut occurrences: HashMap<&str, u64> = HashMap::new();
+    for line in contents.lines() {
+        *occurrences.entry(line).or_default() += 1;
+    }
+    let singletons = occurrences.values().filter(|count| **count == 1).count();
+
+    let expected = format!("{}\nsingletons: {singletons}\nbytes_read: {}\n", occurrences.len(), contents.len());
+    assert_eq!(run(&[], "hamlet_words.txt"), expected);
+}
+
+#[test]
+fn test_no_singletons() {
+    let bytes = |file| std::fs::metadata(test_file(file)).unwrap().len();
+    assert_eq!(run(&[], "same_line.txt"), format!("1\nsingletons: 0\nbytes_read: {}\n", bytes("same_line.txt")));
+    // many empty lines
+    assert_eq!(run(&[], "empty.txt"), format!("1\nsingletons: 0\nbytes_read: {}\n", bytes("empty.txt")));
+}
+
+/// Every way of reading input counts the same bytes, whether or not it ends in a newline
+#[test]
+fn test_bytes_read() {
+    let contents = b"one
+two
+
+three";
+    let path = temp_file("bytes-read.txt", contents);
+    let path = path.to_str().unwrap();
+    let mut read_methods = vec!["--no-memmap"];
+    if cfg!(feature = "memmap") {
+        read_methods.push("--memmap");
+    }
+    for read_method in read_methods {
+        for args in [&[read_method][..], &[read_method, "--checksum"], &[read_method, "--words"], &[read_method, "--delimiter-str", "\\r\\n"]] {
+            let stdout = run_files(&[&["--stats"][..], args].concat(), &[path, path]);
+            assert_eq!(stdout.lines().last(), Some(format!("bytes_read: {}", contents.len() * 2).as_str()), "{args:?}");
+        }
+    }
+    std::fs::remove_file(path).unwrap();
+}
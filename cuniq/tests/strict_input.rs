@@ -0,0 +1,125 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--strict-input` and `--forbidden-bytes`, over copies of a fixture seeded with forbidden
+//! bytes at known positions
+
+use std::process::Output;
+
+use test_support::HAMLET_WORDS;
+
+use common::{assert_usage_error, run_on_files, run_with_input, temp_file, EXIT_CODE_INPUT_REJECTED};
+
+mod common;
+
+/// The line numbers the seeded bytes are put on, counted from 1
+const CR_LINE: usize = 100;
+const NUL_LINE: usize = 2000;
+
+/// A copy of the Hamlet fixture with a `\r` before the newline of line [`CR_LINE`], and a NUL in the
+/// middle of line [`NUL_LINE`]. Returns the contents, and the byte offsets of the `\r` and the NUL.
+fn seeded() -> (Vec<u8>, usize, usize) {
+    let mut contents = Vec::new();
+    let (mut cr_offset, mut nul_offset) = (0, 0);
+    for (i, line) in HAMLET_WORDS.read().split_inclusive(|&byte| byte == b'\n').enumerate() {
+        let line = line.strip_suffix(b"\n").unwrap();
+        if i + 1 == CR_LINE {
+            contents.extend_from_slice(line);
+            cr_offset = contents.len();
+            contents.push(b'\r');
+        } else if i + 1 == NUL_LINE {
+            let (start, end) = line.split_at(line.len() / 2);
+            contents.extend_from_slice(start);
+            nul_offset = contents.len();
+            contents.push(0);
+            contents.extend_from_slice(end);
+        } else {
+            contents.extend_from_slice(line);
+        }
+        contents.push(b'\n');
+    }
+    (contents, cr_offset, nul_offset)
+}
+
+fn assert_rejected(output: &Output, expected: &str) {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(EXIT_CODE_INPUT_REJECTED), "{stderr}");
+    assert!(output.stdout.is_empty());
+    assert!(stderr.contains(expected), "expected {expected:?} in {stderr}");
+}
+
+/// The first forbidden byte is reported, whichever way the file is read
+#[test]
+fn test_error() {
+    let (contents, cr_offset, _nul_offset) = seeded();
+    let path = temp_file("strict-input.txt", &contents);
+    let expected = format!("\"{}\" contains forbidden byte 0x0D on line {CR_LINE}, at byte offset {cr_offset}", path.display());
+    for args in [&["--strict-input"][..], &["--strict-input", "--no-memmap"], &["--strict-input", "-c"], &["--strict-input=error", "--unique"]] {
+        assert_rejected(&run_on_files(args, &[&path]), &expected);
+    }
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_stdin() {
+    let (contents, cr_offset, _nul_offset) = seeded();
+    let output = run_with_input(&["--strict-input"], &contents);
+    assert_rejected(&output, &format!("\"stdin\" contains forbidden byte 0x0D on line {CR_LINE}, at byte offset {cr_offset}"));
+}
+
+/// Line numbers and offsets are within the input that contains the forbidden byte
+#[test]
+fn test_second_input() {
+    let (contents, _cr_offset, nul_offset) = seeded();
+    let path = temp_file("strict-input-second.txt", &contents);
+    let output = run_on_files(&["--strict-input", "--forbidden-bytes", "\\0"], &[&HAMLET_WORDS.path(), &path]);
+    assert_rejected(&output, &format!("\"{}\" contains forbidden byte 0x00 on line {NUL_LINE}, at byte offset {nul_offset}", path.display()));
+    std::fs::remove_file(path).unwrap();
+}
+
+/// With `warn`, everything is counted as without `--strict-input`, and the offending lines are
+/// totalled
+#[test]
+fn test_warn() {
+    let (contents, cr_offset, _nul_offset) = seeded();
+    let path = temp_file("strict-input-warn.txt", &contents);
+    let plain = run_on_files(&["-c", "-s"], &[&path, &path]);
+    let output = run_on_files(&["-c", "-s", "--strict-input=warn"], &[&path, &path]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, plain.stdout);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let expected = format!("--strict-input: 4 lines contain forbidden bytes, the first being: \"{}\" contains forbidden byte 0x0D on line {CR_LINE}, at byte offset {cr_offset}", path.display());
+    assert!(stderr.contains(&expected), "{stderr}");
+    std::fs::remove_file(path).unwrap();
+}
+
+/// Clean input is counted as usual, without warnings
+#[test]
+fn test_clean() {
+    for args in [&["--strict-input"][..], &["--strict-input=warn"], &["--strict-input", "--forbidden-bytes", "\\t\\x7F"]] {
+        let output = run_on_files(args, &[&HAMLET_WORDS.path()]);
+        assert!(output.status.success(), "{args:?}");
+        assert_eq!(output.stdout, format!("{}\n", HAMLET_WORDS.distinct.plain).into_bytes(), "{args:?}");
+        assert!(output.stderr.is_empty(), "{args:?}: {}", String::from_utf8_lossy(&output.stderr));
+    }
+}
+
+#[test]
+fn test_forbidden_bytes() {
+    let output = run_with_input(&["--strict-input", "--forbidden-bytes", "\\t"], b"a\r\nb\tc\n");
+    assert_rejected(&output, "\"stdin\" contains forbidden byte 0x09 on line 2, at byte offset 4");
+}
+
+#[test]
+fn test_usage_errors() {
+    for args in [
+        &["--forbidden-bytes", "\\t"][..],
+        &["--strict-input", "--forbidden-bytes", ""],
+        &["--strict-input", "--forbidden-bytes", "a\\n"],
+        &["--strict-input=fail"],
+        &["--strict-input", "-z"],
+        &["--strict-input", "--cap", "10"],
+    ] {
+        assert_usage_error(args, b"a\n");
+    }
+}
@@ -0,0 +1,60 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--format tsv` and `--format csv`
+
+use test_support::test_file;
+
+use common::{assert_usage_error, run_bytes};
+
+mod common;
+
+#[test]
+fn test_tsv() {
+    let input = b"b\na\tx\nb\nback\\slash\n";
+    let expected = b"1\ta\\tx\n2\tb\n1\tback\\\\slash\n";
+    assert_eq!(run_bytes(&["-c", "-s", "--format", "tsv"], input), expected);
+    assert_eq!(run_bytes(&["-c", "-s", "--format", "tsv", "--strip-ansi"], input), expected);
+    assert_eq!(run_bytes(&["-c", "-s", "--format", "tsv", "--header-row"], input), [b"count\tline\n".as_slice(), expected].concat());
+}
+
+#[test]
+fn test_csv() {
+    let input = b"plain\nsay \"hi\"\na,b\nplain\n";
+    let expected = b"1,\"a,b\"\n2,plain\n1,\"say \"\"hi\"\"\"\n";
+    assert_eq!(run_bytes(&["-c", "-s", "--format", "csv"], input), expected);
+    assert_eq!(run_bytes(&["-c", "-s", "--format", "csv", "--header-row"], input), [b"count,line\n".as_slice(), expected].concat());
+    // with -z, rows end in NUL and a line may hold a newline, which is quoted
+    assert_eq!(run_bytes(&["-c", "-s", "-z", "--format", "csv"], b"a\nb\0c\0a\nb\0"), b"2,\"a\nb\"\x001,c\x00");
+}
+
+/// The borrowed memmap report writes the same rows, including the header row
+#[test]
+fn test_file_input() {
+    let path = test_file("hamlet_words.txt");
+    let path = path.to_str().unwrap();
+    let memmap = run_bytes(&["-c", "-s", "--format", "csv", "--header-row", "--no-stdin", "--memmap", path], b"");
+    let no_memmap = run_bytes(&["-c", "-s", "--format", "csv", "--header-row", "--no-stdin", "--no-memmap", path], b"");
+    assert_eq!(memmap, no_memmap);
+    assert!(memmap.starts_with(b"count,line\n"));
+    // counts aren't padded
+    assert!(memmap.split(|&byte| byte == b'\n').skip(1).filter(|row| !row.is_empty()).all(|row| row[0].is_ascii_digit()));
+}
+
+#[test]
+fn test_with_duplicates() {
+    assert_eq!(run_bytes(&["-c", "-d", "-s", "--format", "tsv"], b"a\nb\na\n"), b"2\ta\n");
+}
+
+#[test]
+fn test_usage_errors() {
+    for args in [
+        &["-c", "--header-row"][..],
+        &["-c", "--format", "ndjson", "--header-row"],
+        &["--format", "tsv"],
+        &["--header-row"],
+        &["-c", "--format", "csv", "--group-case"],
+    ] {
+        assert_usage_error(args, b"a\n");
+    }
+}
@@ -0,0 +1,53 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--tabulate`
+
+use common::{assert_usage_error, run, run_with_input};
+
+mod common;
+
+/// Hosts and the services running on them
+const CSV: &[u8] = b"web-1,nginx,80\nweb-1,sshd,22\nweb-2,nginx,80\ndb-1,postgres,5432\ndb-1,sshd,22\nWEB-2,nginx,443\n";
+
+#[test]
+fn test_tabulate() {
+    assert_eq!(run(&["--tabulate", "--field", "1", "--field", "2", "-F", ","], CSV), "field 1: 4\nfield 2: 3\npairs: 6\n");
+    assert_eq!(run(&["--tabulate", "-f", "1", "-f", "2", "-F", ",", "--lower"], CSV), "field 1: 3\nfield 2: 3\npairs: 5\n");
+    assert_eq!(run(&["--tabulate", "-f", "3", "-f", "2", "-F", ","], CSV), "field 3: 4\nfield 2: 3\npairs: 4\n");
+    for mode in ["near-exact", "estimate"] {
+        assert_eq!(run(&["--tabulate", "-f", "1", "-f", "2", "-F", ",", "--mode", mode], CSV), "field 1: 4\nfield 2: 3\npairs: 6\n", "{mode}");
+    }
+}
+
+/// Fields are tab separated by default
+#[test]
+fn test_default_delimiter() {
+    let tsv: Vec<u8> = CSV.iter().map(|&byte| if byte == b',' { b'\t' } else { byte }).collect();
+    assert_eq!(run(&["--tabulate", "-f", "1", "-f", "2"], &tsv), "field 1: 4\nfield 2: 3\npairs: 6\n");
+    assert_eq!(run(&["--tabulate", "-f", "1", "-f", "2", "-F", ","], &tsv), "field 1: 0\nfield 2: 0\npairs: 0\n");
+}
+
+#[test]
+fn test_short_lines() {
+    let output = run_with_input(&["--tabulate", "-f", "1", "-f", "3", "-F", ","], b"a,b,c\na,b\n\nd,e,f,g\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "field 1: 2\nfield 3: 2\npairs: 2\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("2 lines had fewer than 3 fields"), "{stderr}");
+}
+
+#[test]
+fn test_usage_errors() {
+    for args in [
+        &["--tabulate", "-f", "1"][..],
+        &["--tabulate", "-f", "1", "-f", "2", "-f", "3"],
+        &["--tabulate"],
+        &["--tabulate", "-f", "0", "-f", "1"],
+        &["--tabulate", "-f", "1", "-f", "2", "-F", ", "],
+        &["-f", "1", "-f", "2"],
+        &["--tabulate", "-f", "1", "-f", "2", "-c"],
+    ] {
+        assert_usage_error(args, CSV);
+    }
+}
@@ -0,0 +1,128 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--text-only` and `--binary-ok`
+
+use std::io::{self, Write};
+use std::process::Stdio;
+
+use test_support::HAMLET_WORDS;
+
+use common::{assert_usage_error, cuniq, run_on_files, run_with_input, temp_file, EXIT_CODE_INPUT_REJECTED};
+
+mod common;
+
+/// The start of a PNG file, which has a NUL byte at offset 8
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\x01\0";
+
+/// The sample peeked at from stdin must still be counted, including when input is larger than it
+#[test]
+fn test_text_stdin() {
+    let input: String = (0..10_000).map(|i| format!("line {i}\n")).collect();
+    let output = run_with_input(&["--text-only"], input.as_bytes());
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(output.stdout, b"10000\n");
+
+    let output = run_with_input(&["--text-only", "-c", "-s", "--no-pad"], "naïve\ncafé\ncafé\n".as_bytes());
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(output.stdout, "2 café\n1 naïve\n".as_bytes());
+}
+
+#[test]
+fn test_binary_stdin() {
+    let output = run_with_input(&["--text-only"], PNG);
+    assert_eq!(output.status.code(), Some(EXIT_CODE_INPUT_REJECTED));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("stdin looks like binary data rather than text: it contains a NUL byte at offset 8"), "{stderr}");
+    assert!(stderr.contains("--binary-ok"), "{stderr}");
+
+    let output = run_with_input(&["--text-only"], b"\x01\x02\x03\x04\xff\xfe\n");
+    assert_eq!(output.status.code(), Some(EXIT_CODE_INPUT_REJECTED));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("6 of its first 7 bytes are not text"), "{stderr}");
+}
+
+/// Binary input is counted as before without `--text-only`, or with `--binary-ok`
+#[test]
+fn test_permissive() {
+    for args in [&[][..], &["--text-only", "--binary-ok"]] {
+        let output = run_with_input(args, PNG);
+        assert!(output.status.success(), "{args:?}: {}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(output.stdout, b"3\n", "{args:?}");
+    }
+}
+
+/// Only the first input is checked, after which binary input is counted as usual
+#[test]
+fn test_files() {
+    let binary = temp_file("text-only.png", PNG);
+    let hamlet = HAMLET_WORDS.path();
+
+    let output = run_on_files(&["--text-only"], &[&binary, &hamlet]);
+    assert_eq!(output.status.code(), Some(EXIT_CODE_INPUT_REJECTED));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!("\"{}\" looks like binary data", binary.display())), "{stderr}");
+
+    let output = run_on_files(&["--text-only"], &[&hamlet, &binary]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    // the binary file is split into three lines
+    assert_eq!(output.stdout, format!("{}\n", HAMLET_WORDS.distinct.plain + 3).into_bytes());
+
+    std::fs::remove_file(binary).unwrap();
+}
+
+/// An empty stdin, such as `</dev/null`, is skipped over and the first file is checked instead
+#[test]
+fn test_empty_stdin() {
+    let binary = temp_file("text-only-empty-stdin.png", PNG);
+    let output = cuniq().arg("--text-only").arg(&binary).output().unwrap();
+    assert_eq!(output.status.code(), Some(EXIT_CODE_INPUT_REJECTED));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!("\"{}\" looks like binary data", binary.display())), "{stderr}");
+    std::fs::remove_file(binary).unwrap();
+}
+
+/// Stdin is sampled beyond its first read, which a pipe can cut short
+#[test]
+fn test_short_reads() {
+    let mut child = cuniq()
+        .arg("--text-only")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"text\n").unwrap();
+    stdin.flush().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    if let Err(e) = stdin.write_all(PNG) {
+        assert_eq!(e.kind(), io::ErrorKind::BrokenPipe);
+    }
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(EXIT_CODE_INPUT_REJECTED));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("stdin looks like binary data rather than text: it contains a NUL byte at offset 13"), "{stderr}");
+}
+
+#[test]
+fn test_binary_ok_alone() {
+    let output = run_with_input(&["--binary-ok"], b"a\n");
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--binary-ok has no effect without --text-only"), "{stderr}");
+}
+
+/// NUL-delimited input would always look binary
+#[test]
+fn test_usage_errors() {
+    for args in [
+        &["--text-only", "-z"][..],
+        &["--text-only", "--delimiter-str", "\\0"],
+        &["--text-only", "--delimiter-str", "a\\0"],
+    ] {
+        assert_usage_error(args, b"a\n");
+    }
+}
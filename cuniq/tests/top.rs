@@ -0,0 +1,63 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--top`
+
+use test_support::test_file;
+
+use common::{assert_usage_error, run, run_on_files, success};
+
+mod common;
+
+/// "a" once, "b" twice, "c" three times, and "d" four times
+const INPUT: &[u8] = b"a\nb\nb\nc\nc\nc\nd\nd\nd\nd\n";
+
+#[test]
+fn test_top() {
+    let report = |extra: &[&str]| run(&[&["-c", "--no-pad"][..], extra].concat(), INPUT);
+    assert_eq!(report(&["--top", "1"]), "4 d\n");
+    assert_eq!(report(&["--top", "2"]), "4 d\n3 c\n");
+    assert_eq!(report(&["--top", "4"]), "4 d\n3 c\n2 b\n1 a\n");
+    assert_eq!(report(&["--top", "10"]), "4 d\n3 c\n2 b\n1 a\n");
+}
+
+/// Lines with equal counts are chosen bytewise, whatever order they appeared in
+#[test]
+fn test_ties() {
+    assert_eq!(run(&["-c", "--no-pad", "--top", "2"], b"z\ny\nx\nx\n"), "2 x\n1 y\n");
+    assert_eq!(run(&["-c", "--no-pad", "--top", "2"], b"x\nx\nx\ny\nz\n"), "3 x\n1 y\n");
+}
+
+#[test]
+fn test_ordering() {
+    assert_eq!(run(&["-c", "--no-pad", "--top", "2", "-s"], INPUT), "3 c\n4 d\n");
+    assert_eq!(run(&["-c", "--no-pad", "--top", "2", "-r"], INPUT), "3 c\n4 d\n");
+    assert_eq!(run(&["-c", "--no-pad", "--top", "2", "-S"], INPUT), "4 d\n3 c\n");
+}
+
+/// The top lines are chosen from the lines that pass the other filters
+#[test]
+fn test_filters() {
+    assert_eq!(run(&["-c", "--no-pad", "--top", "2", "--max-count", "2"], INPUT), "2 b\n1 a\n");
+    assert_eq!(run(&["-c", "-d", "--no-pad", "--top", "5"], INPUT), "4 d\n3 c\n2 b\n");
+}
+
+/// A single file is reported without copying its lines, which must honor `--top` too
+#[test]
+fn test_file_input() {
+    let args = ["-c", "--no-pad", "--top", "2"];
+    assert_eq!(success(&args, run_on_files(&args, &[test_file("small.txt")])), b"3 three\n2 two\n");
+}
+
+#[test]
+fn test_usage_errors() {
+    for args in [
+        &["--top", "2"][..],
+        &["-c", "--top", "0"],
+        &["-c", "--top", "-1"],
+        &["-c", "--top", "2", "--group-case"],
+        &["-c", "--top", "2", "--mode", "near-exact"],
+    ] {
+        assert_usage_error(args, INPUT);
+    }
+}
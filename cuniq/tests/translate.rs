@@ -0,0 +1,65 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--translate` and `--translate-only`
+
+use common::{run, run_with_input, temp_file, temp_path};
+
+mod common;
+
+/// Two aliases for each of two hosts
+const MAPPING: &[u8] = b"web-1.example.com\tweb-1\nWEB-1\tweb-1\ndb-1.example.com\tdb-1\n\ndb-1.internal\tdb-1\n";
+
+const INPUT: &[u8] = b"web-1\nweb-1.example.com\nWEB-1\ndb-1.internal\ndb-1.example.com\ncache-1\n";
+
+#[test]
+fn test_hits_and_misses() {
+    let path = temp_file("hits.tsv", MAPPING);
+    let mapping = path.to_str().unwrap();
+    assert_eq!(run(&["--translate", mapping], INPUT), "3\n");
+    assert_eq!(run(&["--translate", mapping, "-c", "--sort"], INPUT), "      1 cache-1\n      2 db-1\n      3 web-1\n");
+    // lines without a mapping are skipped, but a canonical form doesn't need a mapping to itself
+    assert_eq!(run(&["--translate", mapping, "--translate-only", "-c", "--sort"], INPUT), "      2 db-1\n      2 web-1\n");
+    assert_eq!(run(&["--translate", mapping, "--unique", "--sort"], INPUT), "cache-1\ndb-1\nweb-1\n");
+    assert_eq!(run(&["--translate", mapping, "--mode", "near-exact"], INPUT), "3\n");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_duplicates() {
+    let path = temp_file("duplicates.tsv", b"a\tfirst\nb\tb\na\tsecond\na\tthird\n");
+    let output = run_with_input(&["--translate", path.to_str().unwrap(), "-c"], b"a\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "      1 third\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("2 lines of the --translate file repeat a raw value"), "{stderr}");
+    assert!(stderr.contains("\"a\" on line 3"), "{stderr}");
+    std::fs::remove_file(path).unwrap();
+}
+
+/// Input options apply before lines are looked up, and to the canonical forms that are counted.
+/// `--match` and `--words` apply before translation.
+#[test]
+fn test_composition() {
+    let path = temp_file("composition.tsv", b"Web-1.Example.com\tWeb-1\n");
+    let mapping = path.to_str().unwrap();
+    let input = b"  WEB-1.example.com\nweb-1\n";
+    assert_eq!(run(&["--translate", mapping, "-c", "--sort"], input), "      1   WEB-1.example.com\n      1 web-1\n");
+    assert_eq!(run(&["--translate", mapping, "--trim", "--lower", "-c"], input), "      2 web-1\n");
+    assert_eq!(run(&["--translate", mapping, "--match", r"host=(\S+)", "--key-group", "1", "--lower", "-c"], b"host=Web-1.example.com x\nhost=web-1 y\n"), "      2 web-1\n");
+    assert_eq!(run(&["--translate", mapping, "--words", "--lower"], b"web-1.example.com web-1 other\n"), "2\n");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_invalid_mapping() {
+    let path = temp_file("invalid.tsv", b"a\tb\nno tab here\n");
+    let output = run_with_input(&["--translate", path.to_str().unwrap()], b"a\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("line 2 of the --translate file"));
+    std::fs::remove_file(path).unwrap();
+
+    let missing = temp_path("missing.tsv");
+    assert_eq!(run_with_input(&["--translate", missing.to_str().unwrap()], b"a\n").status.code(), Some(1));
+    assert_eq!(run_with_input(&["--translate-only"], b"a\n").status.code(), Some(2));
+}
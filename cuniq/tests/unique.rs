@@ -0,0 +1,85 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--unique` and `--unique --stream`
+
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader};
+use std::process::Stdio;
+
+use test_support::test_file;
+
+use common::{cuniq, run_on_files, success};
+
+mod common;
+
+/// Run cuniq on a test file and return its stdout
+fn run(args: &[&str], file: &str) -> Vec<u8> {
+    success(args, run_on_files(args, &[test_file(file)]))
+}
+
+/// Each distinct line of a test file in the order it first occurs, compared after applying `key`
+fn first_occurrences(file: &str, key: impl Fn(&str) -> String) -> Vec<u8> {
+    let contents = std::fs::read_to_string(test_file(file)).unwrap();
+    let mut seen = HashSet::new();
+    let mut output = Vec::new();
+    for line in contents.lines() {
+        if seen.insert(key(line)) {
+            output.extend_from_slice(line.as_bytes());
+            output.push(b'\n');
+        }
+    }
+    output
+}
+
+#[test]
+fn test_stream_order() {
+    let expected = first_occurrences("hamlet_words.txt", str::to_string);
+    assert_eq!(run(&["--unique", "--stream"], "hamlet_words.txt"), expected);
+    assert_eq!(run(&["--unique", "--stream", "--mode", "near-exact"], "hamlet_words.txt"), expected);
+}
+
+#[test]
+fn test_stream_prints_original_lines() {
+    let expected = first_occurrences("hamlet_words.txt", str::to_lowercase);
+    assert_eq!(run(&["--unique", "--stream", "--lower"], "hamlet_words.txt"), expected);
+}
+
+#[test]
+fn test_unique_sorted() {
+    let mut expected: Vec<&[u8]> = include_bytes!("../../test_files/hamlet_words.txt")
+        .split(|b| *b == b'\n')
+        .filter(|line| !line.is_empty())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    expected.sort_unstable();
+    let expected: Vec<u8> = expected.into_iter().flat_map(|line| [line, b"\n"].concat()).collect();
+    assert_eq!(run(&["--unique", "--sort"], "hamlet_words.txt"), expected);
+}
+
+/// shuffled_numbers.txt produces far more output than a pipe can buffer, so cuniq can only exit if
+/// it stops when the pipe is closed
+#[test]
+fn test_stream_stops_when_output_closed() {
+    let mut child = cuniq()
+        .args(["--unique", "--stream", "--no-stdin"])
+        .arg(test_file("shuffled_numbers.txt"))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut first_line = String::new();
+    stdout.read_line(&mut first_line).unwrap();
+    // the first line of the input is always new
+    let mut expected_line = String::new();
+    BufReader::new(std::fs::File::open(test_file("shuffled_numbers.txt")).unwrap()).read_line(&mut expected_line).unwrap();
+    assert_eq!(first_line, expected_line);
+    drop(stdout);
+
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    // a closed pipe is not reported as an error
+    assert_eq!(output.stderr, b"");
+}
@@ -0,0 +1,55 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for which stderr messages are printed at each verbosity level
+
+use std::path::Path;
+
+use common::{run_on_files, temp_path};
+
+mod common;
+
+/// Run cuniq and return its exit code and the prefix of each stderr line
+fn run(args: &[&str], file: &Path) -> (i32, Vec<&'static str>) {
+    let output = run_on_files(args, &[file]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let prefixes = stderr.lines()
+        .map(|line| match line.split_once(": ") {
+            Some(("warning", _)) => "warning",
+            Some(("info", _)) => "info",
+            Some(("debug", _)) => "debug",
+            _ => "error",
+        })
+        .collect();
+    (output.status.code().unwrap(), prefixes)
+}
+
+#[test]
+fn test_verbosity_levels() {
+    let path = temp_path("verbosity.txt");
+    std::fs::write(&path, b"a\nb\na\n").unwrap();
+    // --size 1000 triggers a warning, as it gets rounded down to 512
+    let scenario = ["--mode", "estimate", "--size", "1000"];
+
+    let quiet = run(&[&["-q"], scenario.as_slice()].concat(), &path);
+    let default = run(&scenario, &path);
+    let verbose = run(&[&["-v"], scenario.as_slice()].concat(), &path);
+    let debug = run(&[&["-vv"], scenario.as_slice()].concat(), &path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(quiet, (0, vec![]));
+    // --size is also deprecated
+    assert_eq!(default, (0, vec!["warning", "warning"]));
+    // deprecation warning, mode, size warning, register count, input table, read method
+    assert_eq!(verbose, (0, vec!["warning", "info", "warning", "info", "info", "info"]));
+    // as above, plus the file timing
+    assert_eq!(debug, (0, vec!["warning", "info", "warning", "info", "info", "info", "debug"]));
+}
+
+#[test]
+fn test_errors_print_when_quiet() {
+    let path = temp_path("does-not-exist.txt");
+    let (exit_code, lines) = run(&["-q"], &path);
+    assert_eq!(exit_code, 1);
+    assert_eq!(lines, ["error"]);
+}
@@ -0,0 +1,67 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--version`
+
+use common::cuniq;
+
+mod common;
+
+#[test]
+fn test_version_includes_library_build_info() {
+    let output = cuniq().arg("--version").output().unwrap();
+    assert!(output.status.success());
+    let version = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = version.lines().collect();
+    assert!(lines[0].starts_with(&format!("cuniq {} commit ", env!("CARGO_PKG_VERSION"))), "{version}");
+    assert!(lines[1].starts_with("Built with feature flags: ["), "{version}");
+    let library = lines[2];
+    assert!(library.starts_with("line_cardinality "), "{version}");
+    // cuniq always enables these
+    for feature in ["ahash", "file", "hash-only"] {
+        assert!(library.contains(feature), "{feature} missing from {library}");
+    }
+    // and these are passed on from cuniq's features of the same name
+    for (feature, enabled) in [("memmap", cfg!(feature = "memmap")), ("compile-time-rng", cfg!(feature = "compile-time-rng")), ("unicode", cfg!(feature = "unicode"))] {
+        assert_eq!(library.contains(feature), enabled, "{feature} in {library}");
+        assert!(!lines[1].contains(feature), "{feature} should only be listed by line_cardinality: {}", lines[1]);
+    }
+}
+
+#[test]
+fn test_version_json() {
+    let output = cuniq().arg("--version-json").output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    // a single line, terminated by a newline
+    assert_eq!(stdout.lines().count(), 1, "{stdout}");
+    assert!(stdout.ends_with('\n'), "{stdout}");
+
+    let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+    assert!(json["commit"].is_string());
+    let features: Vec<&str> = json["features"].as_array().unwrap().iter().map(|feature| feature.as_str().unwrap()).collect();
+    for (feature, enabled) in [("memmap", cfg!(feature = "memmap")), ("regex", cfg!(feature = "regex")), ("gzip", cfg!(feature = "gzip"))] {
+        assert_eq!(features.contains(&feature), enabled, "{feature} in {features:?}");
+    }
+    assert!(!features.contains(&"default"), "{features:?}");
+    if cfg!(feature = "memmap") {
+        assert_eq!(json["max_memmap_bytes"].as_u64(), Some(isize::MAX as u64));
+    } else {
+        assert!(json["max_memmap_bytes"].is_null());
+    }
+
+    let library = &json["line_cardinality"];
+    assert!(library["version"].is_string());
+    assert!(library["hasher"].is_string());
+    let library_features: Vec<&str> = library["features"].as_array().unwrap().iter().map(|feature| feature.as_str().unwrap()).collect();
+    for feature in ["ahash", "file", "hash-only"] {
+        assert!(library_features.contains(&feature), "{feature} missing from {library_features:?}");
+    }
+}
+
+#[test]
+fn test_version_json_is_exclusive() {
+    let output = cuniq().args(["--version-json", "--report"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
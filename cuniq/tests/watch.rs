@@ -0,0 +1,111 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--watch` and `--rolling`
+
+use std::io::Write;
+use std::process::Stdio;
+use std::thread;
+use std::time::Duration;
+
+use test_support::test_file;
+
+use common::cuniq;
+
+mod common;
+
+/// Run cuniq, writing each of `chunks` to its stdin with `pause` between them, and return its exit
+/// code, stdout, and stderr
+fn run_paced(args: &[&str], chunks: &[&[u8]], pause: Duration) -> (i32, String, String) {
+    let mut child = cuniq()
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    for (index, chunk) in chunks.iter().enumerate() {
+        if index != 0 {
+            thread::sleep(pause);
+        }
+        stdin.write_all(chunk).unwrap();
+        stdin.flush().unwrap();
+    }
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    (output.status.code().unwrap(), String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap())
+}
+
+/// Split a line into its timestamp and the numbers after each label
+fn parse_line(line: &str) -> (&str, Vec<(&str, u64)>) {
+    let mut words = line.split(' ');
+    let timestamp = words.next().unwrap();
+    let mut counts = Vec::new();
+    while let (Some(label), Some(count)) = (words.next(), words.next()) {
+        counts.push((label, count.parse().unwrap()));
+    }
+    (timestamp, counts)
+}
+
+fn assert_timestamp(timestamp: &str) {
+    let bytes = timestamp.as_bytes();
+    assert_eq!(bytes.len(), 20, "{timestamp}");
+    assert_eq!((bytes[4], bytes[10], bytes[19]), (b'-', b'T', b'Z'), "{timestamp}");
+}
+
+/// Each interval counts only its own lines, while the total counts everything so far
+#[test]
+fn test_rolling() {
+    // the pause spans more than one tick, so the chunks are counted in different intervals
+    let (exit_code, stdout, stderr) = run_paced(&["--watch", "1", "--rolling"], &[b"a\nb\nc\n", b"c\nd\n"], Duration::from_millis(2500));
+    assert_eq!(exit_code, 0, "{stderr}");
+    let lines: Vec<(&str, Vec<(&str, u64)>)> = stdout.lines().map(parse_line).collect();
+    assert!(lines.len() >= 3, "{stdout}");
+    for (timestamp, _counts) in &lines {
+        assert_timestamp(timestamp);
+    }
+    assert_eq!(lines[0].1, [("interval", 3), ("total", 3)], "{stdout}");
+    assert_eq!(lines[lines.len() - 1].1, [("interval", 2), ("total", 4)], "{stdout}");
+    // ticks during the pause saw no new lines
+    for (_timestamp, counts) in &lines[1..lines.len() - 1] {
+        assert_eq!(counts, &[("interval", 0), ("total", 3)], "{stdout}");
+    }
+}
+
+/// Without `--rolling` only the total is printed, and input that ends before the first tick still
+/// gets a final line
+#[test]
+fn test_total() {
+    for mode in ["exact", "near-exact", "estimate"] {
+        let (exit_code, stdout, stderr) = run_paced(&["--mode", mode, "--watch", "60"], &[b"a\na\nb\n"], Duration::ZERO);
+        assert_eq!(exit_code, 0, "{mode} mode: {stderr}");
+        let lines: Vec<(&str, Vec<(&str, u64)>)> = stdout.lines().map(parse_line).collect();
+        assert_eq!(lines.len(), 1, "{mode} mode: {stdout}");
+        assert_timestamp(lines[0].0);
+        assert_eq!(lines[0].1, [("total", 2)], "{mode} mode: {stdout}");
+    }
+}
+
+/// Files are read after stdin, and line mapping applies to both counters
+#[test]
+fn test_files() {
+    let file = test_file("casing_variants.txt");
+    let expected = {
+        let (exit_code, stdout, _) = run_paced(&["--lower", "--no-stdin", file.to_str().unwrap()], &[], Duration::ZERO);
+        assert_eq!(exit_code, 0);
+        stdout.trim().parse::<u64>().unwrap()
+    };
+    let (exit_code, stdout, stderr) = run_paced(&["--watch", "60", "--rolling", "--lower", "--no-stdin", file.to_str().unwrap()], &[], Duration::ZERO);
+    assert_eq!(exit_code, 0, "{stderr}");
+    let (_timestamp, counts) = parse_line(stdout.trim());
+    assert_eq!(counts, [("interval", expected), ("total", expected)], "{stdout}");
+}
+
+#[test]
+fn test_invalid() {
+    for args in [&["--rolling"][..], &["--watch", "0"], &["--watch", "1", "--report"], &["--watch", "1", "--cap", "5"]] {
+        let (exit_code, stdout, _) = run_paced(args, &[], Duration::ZERO);
+        assert_eq!((exit_code, stdout.as_str()), (2, ""), "{args:?}");
+    }
+}
@@ -0,0 +1,65 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--words`
+
+use test_support::{test_file, HAMLET_WORDS};
+
+use common::{run, run_with_input, temp_file};
+
+mod common;
+
+/// The words of hamlet_words.txt, rejoined into lines of up to 12 words separated by varying runs
+/// of whitespace
+fn hamlet_text() -> (Vec<String>, Vec<u8>) {
+    let words: Vec<String> = std::fs::read_to_string(test_file("hamlet_words.txt")).unwrap().lines().map(str::to_string).collect();
+    let separators = [" ", "  ", "\t", " \t "];
+    let mut text = Vec::new();
+    for (index, line) in words.chunks(12).enumerate() {
+        text.extend_from_slice(separators[index % separators.len()].as_bytes());
+        for (word_index, word) in line.iter().enumerate() {
+            text.extend_from_slice(word.as_bytes());
+            text.extend_from_slice(separators[(index + word_index) % separators.len()].as_bytes());
+        }
+        text.push(b'\n');
+    }
+    (words, text)
+}
+
+#[test]
+fn test_hamlet() {
+    let (_, text) = hamlet_text();
+    let distinct = HAMLET_WORDS.distinct.plain;
+    assert_eq!(run(&["--words"], &text), format!("{distinct}\n"));
+    assert_eq!(run(&["--words", "--mode", "near-exact"], &text), format!("{distinct}\n"));
+    assert_eq!(run(&["--words", "--lower"], &text), format!("{}\n", HAMLET_WORDS.distinct.lower));
+
+    let estimate: f64 = run(&["--words", "--mode", "estimate"], &text).trim().parse().unwrap();
+    assert!((estimate - distinct as f64).abs() < distinct as f64 * 0.05, "estimate was {estimate}");
+}
+
+#[test]
+fn test_file_input() {
+    // files are read via memmap by default on unix, which keeps `\r`, so it must split words too
+    let (_, text) = hamlet_text();
+    let path = temp_file("words.txt", &text.iter().flat_map(|&byte| if byte == b'\n' { b"\r\n".to_vec() } else { vec![byte] }).collect::<Vec<u8>>());
+    assert_eq!(run(&["--words", "--no-stdin", path.to_str().unwrap()], b""), format!("{}\n", HAMLET_WORDS.distinct.plain));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_delimiter_runs() {
+    let input = b"  a  b\n\n \t \nb\ta  c \t\n";
+    assert_eq!(run(&["--words"], input), "3\n");
+    assert_eq!(run(&["--words", "--report", "--sort"], input), "      2 a\n      2 b\n      1 c\n");
+    assert_eq!(run(&["--words", "--unique", "--sort"], input), "a\nb\nc\n");
+    assert_eq!(run(&["--words", "--cap", "2"], input), ">=2\n");
+}
+
+#[test]
+fn test_word_delimiters() {
+    let input = b"a b,,c;\n,c;a b";
+    assert_eq!(run(&["--words", "--word-delimiters", ",;", "--report", "--sort"], input), "      2 a b\n      2 c\n");
+    assert_eq!(run_with_input(&["--words", "--word-delimiters", "→"], input).status.code(), Some(2));
+    assert_eq!(run_with_input(&["--word-delimiters", ","], input).status.code(), Some(2));
+}
@@ -0,0 +1,53 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Tests for `--zero-terminated`
+
+use common::{assert_usage_error, run_bytes, temp_file};
+
+mod common;
+
+/// File names as printed by `find -print0`, some of which contain newlines. The last has no
+/// terminator.
+const INPUT: &[u8] = b"./a\0./two\nlines\0./b\0./a\0./two\nlines\0./two\0./b";
+
+#[test]
+fn test_count() {
+    assert_eq!(run_bytes(&["-z"], INPUT), b"4\n");
+    assert_eq!(run_bytes(&["--zero-terminated", "--mode", "near-exact"], INPUT), b"4\n");
+    // without -z, lines are split on the newlines instead
+    assert_eq!(run_bytes(&[], INPUT), b"3\n");
+}
+
+/// Output lines end in NUL, from stdin and from files whether or not they are memmapped
+#[test]
+fn test_report() {
+    let expected = b"      2 ./a\0      2 ./b\0      1 ./two\0      2 ./two\nlines\0";
+    assert_eq!(run_bytes(&["-zc", "--sort"], INPUT), expected);
+    let path = temp_file("report.bin", INPUT);
+    let file = path.to_str().unwrap();
+    assert_eq!(run_bytes(&["-zc", "--sort", "--no-stdin", file], b""), expected);
+    assert_eq!(run_bytes(&["-zc", "--sort", "--no-stdin", "--no-memmap", file], b""), expected);
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(run_bytes(&["-zc", "--sort-by-count", "--trim"], INPUT), b"      2 ./a\0      2 ./b\0      2 ./two\nlines\0      1 ./two\0");
+}
+
+#[test]
+fn test_unique() {
+    let expected = b"./a\0./b\0./two\0./two\nlines\0";
+    assert_eq!(run_bytes(&["-z", "--unique", "--sort"], INPUT), expected);
+    assert_eq!(run_bytes(&["-z", "--unique", "--sort=numeric"], INPUT), expected);
+    assert_eq!(run_bytes(&["-z", "--unique", "--sort", "--strip-ansi"], INPUT), expected);
+}
+
+#[test]
+fn test_usage_errors() {
+    for args in [
+        &["-z", "--delimiter-str", "\\0"][..],
+        &["-z", "--unique", "--stream"],
+        &["-zc", "--format", "ndjson"],
+        &["-zc", "--no-trailing-newline"],
+    ] {
+        assert_usage_error(args, INPUT);
+    }
+}
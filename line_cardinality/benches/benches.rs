@@ -4,19 +4,20 @@
 //! Benchmarks for various functions
 
 use std::fs::File;
-use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 use ahash::RandomState;
 use bstr::ByteSlice;
-use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use test_support::{Fixture, HAMLET_WORDS, LARGE, SHUFFLED_NUMBERS, SMALL};
 
-use line_cardinality::{CountUnique, CountUniqueFromMemmapFile, CountUniqueFromReadFile, LineCounter};
+use line_cardinality::{fold_case, fold_case_full, BorrowedLineCounter, CountUnique, CountUniqueFromMemmapFile, CountUniqueFromReadFile, HashingLineCounter, HyperLogLog, InexactHashingLineCounter, LineCounter, MappedFile};
 
 // require certain features for this benchmark
-#[cfg(not(all(feature = "ahash", feature = "memmap", feature = "memchr", feature = "file")))]
+#[cfg(not(all(feature = "ahash", feature = "memmap", feature = "memchr", feature = "file", feature = "hash-only", feature = "unicode")))]
 compile_error!("missing required features");
 
-criterion_group!(benches, bench_small, bench_large, bench_tweaks);
+criterion_group!(benches, bench_small, bench_large, bench_tweaks, bench_borrowed, bench_count, bench_fold, bench_hot_cache);
 criterion_main!(benches);
 
 mod no_fn;
@@ -26,41 +27,19 @@ mod string;
 mod unstable_set;
 
 /// primary test condition for comparing high cardinality
-const TEST_FILE_ENGLISH_WORDS: TestFile = TestFile::new("hamlet_words.txt", 5414);
+static TEST_FILE_ENGLISH_WORDS: &Fixture = &HAMLET_WORDS;
 
-/// one-off count of the lowercase distinct words for a certain benchmark
-const ENGLISH_WORDS_LOWERCASE_COUNT: usize = 4900;
+static TEST_FILE_SMALL: &Fixture = &SMALL;
 
-const TEST_FILE_SMALL: TestFile = TestFile::new("small.txt", 3);
+static TEST_FILE_LARGE: &Fixture = &LARGE;
 
-const TEST_FILE_LARGE: TestFile = TestFile::new("large.txt", 100000);
+static TEST_FILE_NUMBERS: &Fixture = &SHUFFLED_NUMBERS;
 
 const FILE_HANDLE_BATCH_SIZE: BatchSize = BatchSize::SmallInput;
 
-struct TestFile {
-    filename: &'static str,
-    expected: usize,
-}
-
-impl TestFile {
-    const fn new(filename: &'static str, expected: usize) -> Self {
-        Self {
-            filename,
-            expected,
-        }
-    }
-
-    fn relative_path(&self) -> PathBuf {
-        let mut path: PathBuf = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        path.pop();
-        path.push("test_files");
-        path.push(self.filename);
-        path
-    }
-
-    fn open(&self) -> Vec<File> {
-        vec![File::open(self.relative_path()).unwrap()]
-    }
+/// Open a fixture, as the only input to count
+fn open(fixture: &Fixture) -> Vec<File> {
+    vec![File::open(fixture.path()).unwrap()]
 }
 
 /// hasher with pre-generated random seed
@@ -79,18 +58,18 @@ fn bench_small(c: &mut Criterion) {
     let mut group = c.benchmark_group("tweaks.small");
 
     group.bench_function("read", |bencher| {
-        bencher.iter_batched(|| TEST_FILE_SMALL.open(), |files| {
+        bencher.iter_batched(|| open(TEST_FILE_SMALL), |files| {
             let mut processor = LineCounter::default();
             processor.count_unique_in_files(&files).unwrap();
-            assert_eq!(processor.count(), TEST_FILE_SMALL.expected);
+            assert_eq!(processor.count(), TEST_FILE_SMALL.distinct.plain);
         }, FILE_HANDLE_BATCH_SIZE);
     });
 
     group.bench_function("memmap", |bencher| {
-        bencher.iter_batched(|| TEST_FILE_SMALL.open(), |files| {
+        bencher.iter_batched(|| open(TEST_FILE_SMALL), |files| {
             let mut processor = LineCounter::default();
             processor.count_unique_in_memmap_files(&files).unwrap();
-            assert_eq!(processor.count(), TEST_FILE_SMALL.expected);
+            assert_eq!(processor.count(), TEST_FILE_SMALL.distinct.plain);
         }, FILE_HANDLE_BATCH_SIZE);
     });
 }
@@ -101,18 +80,28 @@ fn bench_large(c: &mut Criterion) {
     let mut group = c.benchmark_group("tweaks.large");
 
     group.bench_function("read", |bencher| {
-        bencher.iter_batched(|| TEST_FILE_LARGE.open(), |files| {
+        bencher.iter_batched(|| open(TEST_FILE_LARGE), |files| {
             let mut processor = LineCounter::default();
             processor.count_unique_in_files(&files).unwrap();
-            assert_eq!(processor.count(), TEST_FILE_LARGE.expected);
+            assert_eq!(processor.count(), TEST_FILE_LARGE.distinct.plain);
         }, FILE_HANDLE_BATCH_SIZE);
     });
 
     group.bench_function("memmap", |bencher| {
-        bencher.iter_batched(|| TEST_FILE_LARGE.open(), |files| {
+        bencher.iter_batched(|| open(TEST_FILE_LARGE), |files| {
             let mut processor = LineCounter::default();
             processor.count_unique_in_memmap_files(&files).unwrap();
-            assert_eq!(processor.count(), TEST_FILE_LARGE.expected);
+            assert_eq!(processor.count(), TEST_FILE_LARGE.distinct.plain);
+        }, FILE_HANDLE_BATCH_SIZE);
+    });
+
+    group.bench_function("unbuffered-read", |bencher| {
+        bencher.iter_batched(|| open(TEST_FILE_LARGE), |files| {
+            let mut processor = LineCounter::default();
+            for file in files {
+                processor.count_unique_in_unbuffered_read(file, 64 * 1024).unwrap();
+            }
+            assert_eq!(processor.count(), TEST_FILE_LARGE.distinct.plain);
         }, FILE_HANDLE_BATCH_SIZE);
     });
 }
@@ -123,79 +112,258 @@ fn bench_tweaks(c: &mut Criterion) {
 
     // uses a map with () values
     group.bench_function("baseline", |bencher| {
-        bencher.iter_batched(|| TEST_FILE_ENGLISH_WORDS.open(), |files| {
+        bencher.iter_batched(|| open(TEST_FILE_ENGLISH_WORDS), |files| {
             let mut processor = LineCounter::default();
             processor.count_unique_in_memmap_files(&files).unwrap();
-            assert_eq!(processor.count(), TEST_FILE_ENGLISH_WORDS.expected);
+            assert_eq!(processor.count(), TEST_FILE_ENGLISH_WORDS.distinct.plain);
         }, FILE_HANDLE_BATCH_SIZE);
     });
 
     // same as baseline, but there's no FnMut floating around
     group.bench_function("no-fn", |bencher| {
-        bencher.iter_batched(|| TEST_FILE_ENGLISH_WORDS.open(), |files| {
+        bencher.iter_batched(|| open(TEST_FILE_ENGLISH_WORDS), |files| {
             let mut processor = no_fn::Processor::default();
             processor.count_unique_in_memmap_files(&files).unwrap();
-            assert_eq!(processor.count(), TEST_FILE_ENGLISH_WORDS.expected);
+            assert_eq!(processor.count(), TEST_FILE_ENGLISH_WORDS.distinct.plain);
         }, FILE_HANDLE_BATCH_SIZE);
     });
 
     // use BufRead instead of Mmap
     group.bench_function("read", |bencher| {
-        bencher.iter_batched(|| TEST_FILE_ENGLISH_WORDS.open(), |files| {
+        bencher.iter_batched(|| open(TEST_FILE_ENGLISH_WORDS), |files| {
             let mut processor = LineCounter::default();
             processor.count_unique_in_files(&files).unwrap();
-            assert_eq!(processor.count(), TEST_FILE_ENGLISH_WORDS.expected);
+            assert_eq!(processor.count(), TEST_FILE_ENGLISH_WORDS.distinct.plain);
         }, FILE_HANDLE_BATCH_SIZE);
     });
 
     // set impl, but doesn't use unstable set APIs
     group.bench_function("stable_set", |bencher| {
-        bencher.iter_batched(|| TEST_FILE_ENGLISH_WORDS.open(), |files| {
+        bencher.iter_batched(|| open(TEST_FILE_ENGLISH_WORDS), |files| {
             let mut processor = stable_set::Processor::default();
             processor.count_unique_in_memmap_files(&files).unwrap();
-            assert_eq!(processor.count(), TEST_FILE_ENGLISH_WORDS.expected);
+            assert_eq!(processor.count(), TEST_FILE_ENGLISH_WORDS.distinct.plain);
         }, FILE_HANDLE_BATCH_SIZE);
     });
 
     // set impl, but does use unstable set APIs
     group.bench_function("unstable_set", |bencher| {
-        bencher.iter_batched(|| TEST_FILE_ENGLISH_WORDS.open(), |files| {
+        bencher.iter_batched(|| open(TEST_FILE_ENGLISH_WORDS), |files| {
             let mut processor = unstable_set::Processor::default();
             processor.count_unique_in_memmap_files(&files).unwrap();
-            assert_eq!(processor.count(), TEST_FILE_ENGLISH_WORDS.expected);
+            assert_eq!(processor.count(), TEST_FILE_ENGLISH_WORDS.distinct.plain);
         }, FILE_HANDLE_BATCH_SIZE);
     });
 
     // map<()> impl, but doesn't use unstable set APIs
     group.bench_function("stable_map", |bencher| {
-        bencher.iter_batched(|| TEST_FILE_ENGLISH_WORDS.open(), |files| {
+        bencher.iter_batched(|| open(TEST_FILE_ENGLISH_WORDS), |files| {
             let mut processor = stable_map::Processor::default();
             processor.count_unique_in_memmap_files(&files).unwrap();
-            assert_eq!(processor.count(), TEST_FILE_ENGLISH_WORDS.expected);
+            assert_eq!(processor.count(), TEST_FILE_ENGLISH_WORDS.distinct.plain);
         }, FILE_HANDLE_BATCH_SIZE);
     });
 
     // uses str instead of bstr
     group.bench_function("str", |bencher| {
-        bencher.iter_batched(|| TEST_FILE_ENGLISH_WORDS.open(), |files| {
+        bencher.iter_batched(|| open(TEST_FILE_ENGLISH_WORDS), |files| {
             let mut processor = string::Processor::default();
             processor.count_unique_in_memmap_files(&files).unwrap();
-            assert_eq!(processor.count(), TEST_FILE_ENGLISH_WORDS.expected);
+            assert_eq!(processor.count(), TEST_FILE_ENGLISH_WORDS.distinct.plain);
         }, FILE_HANDLE_BATCH_SIZE);
     });
 
     // test lowercase performance
     group.bench_function("baseline.lower", |bencher| {
-        bencher.iter_batched(|| TEST_FILE_ENGLISH_WORDS.open(), |files| {
+        bencher.iter_batched(|| open(TEST_FILE_ENGLISH_WORDS), |files| {
+            let mut processor = LineCounter::with_line_mapper(|line, buffer| {
+                buffer.clear();
+                line.to_lowercase_into(buffer);
+                buffer
+            });
+            processor.count_unique_in_memmap_files(&files).unwrap();
+            assert_eq!(processor.count(), TEST_FILE_ENGLISH_WORDS.distinct.lower);
+        }, FILE_HANDLE_BATCH_SIZE);
+    });
+
+    group.finish();
+}
+
+/// Compare owned report keys against keys borrowed from the memmap
+fn bench_borrowed(c: &mut Criterion) {
+    let mut group = c.benchmark_group("borrowed");
+    group.sample_size(10);
+
+    group.bench_function("owned", |bencher| {
+        bencher.iter_batched(|| open(TEST_FILE_NUMBERS), |files| {
+            let mut processor = HashingLineCounter::<u64, ()>::new();
+            processor.count_unique_in_memmap_files(&files).unwrap();
+            assert_eq!(processor.count(), TEST_FILE_NUMBERS.distinct.plain);
+        }, FILE_HANDLE_BATCH_SIZE);
+    });
+
+    group.bench_function("borrowed", |bencher| {
+        bencher.iter_batched(|| open(TEST_FILE_NUMBERS), |files| {
+            let mapped_file = MappedFile::new(&files[0]).unwrap();
+            let mut processor = BorrowedLineCounter::<u64>::new();
+            processor.count_unique_in_slice(&mapped_file);
+            assert_eq!(processor.count(), TEST_FILE_NUMBERS.distinct.plain);
+        }, FILE_HANDLE_BATCH_SIZE);
+    });
+
+    group.finish();
+}
+
+/// `count()` may be polled after every line or output flush, so it must not scan a counter's map or
+/// registers. Besides benchmarking `count()` on small and large counters, this asserts that it
+/// doesn't get slower as the counter grows.
+fn bench_count(c: &mut Criterion) {
+    let small_files = open(TEST_FILE_ENGLISH_WORDS);
+    let large_files = open(TEST_FILE_NUMBERS);
+
+    let mut small_exact = LineCounter::default();
+    small_exact.count_unique_in_memmap_files(&small_files).unwrap();
+    let mut large_exact = LineCounter::default();
+    large_exact.count_unique_in_memmap_files(&large_files).unwrap();
+    assert_eq!(large_exact.count(), TEST_FILE_NUMBERS.distinct.plain);
+    assert_constant_time("exact", &small_exact, &large_exact);
+
+    let mut small_near_exact = InexactHashingLineCounter::default();
+    small_near_exact.count_unique_in_memmap_files(&small_files).unwrap();
+    let mut large_near_exact = InexactHashingLineCounter::default();
+    large_near_exact.count_unique_in_memmap_files(&large_files).unwrap();
+    assert_constant_time("near-exact", &small_near_exact, &large_near_exact);
+
+    // a large estimate has 2^20 registers, as used for high precision
+    let mut small_estimate = HyperLogLog::with_capacity(1 << 12).unwrap();
+    small_estimate.count_unique_in_memmap_files(&small_files).unwrap();
+    let mut large_estimate = HyperLogLog::with_capacity(1 << 20).unwrap();
+    large_estimate.count_unique_in_memmap_files(&large_files).unwrap();
+    assert_constant_time("estimate", &small_estimate, &large_estimate);
+
+    let mut group = c.benchmark_group("count");
+    group.bench_function("exact.small", |bencher| bencher.iter(|| black_box(&small_exact).count()));
+    group.bench_function("exact.large", |bencher| bencher.iter(|| black_box(&large_exact).count()));
+    group.bench_function("near-exact.small", |bencher| bencher.iter(|| black_box(&small_near_exact).count()));
+    group.bench_function("near-exact.large", |bencher| bencher.iter(|| black_box(&large_near_exact).count()));
+    group.bench_function("estimate.small", |bencher| bencher.iter(|| black_box(&small_estimate).count()));
+    group.bench_function("estimate.large", |bencher| bencher.iter(|| black_box(&large_estimate).count()));
+    group.finish();
+}
+
+/// Assert that `count()` takes about as long on `large` as it does on `small`
+fn assert_constant_time<T: CountUnique>(name: &str, small: &T, large: &T) {
+    const CALLS: u32 = 100_000;
+    let time = |counter: &T| {
+        (0..5)
+            .map(|_| {
+                let start = Instant::now();
+                for _ in 0..CALLS {
+                    black_box(black_box(counter).count());
+                }
+                start.elapsed()
+            })
+            .min()
+            .unwrap()
+    };
+    let small_time = time(small);
+    let large_time = time(large);
+    // the large counters have about 185 times as many lines, or 256 times as many registers, so
+    // scanning them would be far outside this margin
+    assert!(large_time < small_time * 10 + Duration::from_millis(1), "{name} count() took {large_time:?} on a large counter, but {small_time:?} on a small counter");
+}
+
+/// Compare case folding against `--lower`. The input is entirely ASCII, so `fold_case` should be
+/// about as fast as lowercasing.
+fn bench_fold(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fold");
+
+    group.bench_function("lower", |bencher| {
+        bencher.iter_batched(|| open(TEST_FILE_ENGLISH_WORDS), |files| {
             let mut processor = LineCounter::with_line_mapper(|line, buffer| {
                 buffer.clear();
                 line.to_lowercase_into(buffer);
                 buffer
             });
             processor.count_unique_in_memmap_files(&files).unwrap();
-            assert_eq!(processor.count(), ENGLISH_WORDS_LOWERCASE_COUNT);
+            assert_eq!(processor.count(), TEST_FILE_ENGLISH_WORDS.distinct.lower);
+        }, FILE_HANDLE_BATCH_SIZE);
+    });
+
+    group.bench_function("fold_case", |bencher| {
+        bencher.iter_batched(|| open(TEST_FILE_ENGLISH_WORDS), |files| {
+            let mut processor = LineCounter::with_line_mapper(fold_case);
+            processor.count_unique_in_memmap_files(&files).unwrap();
+            assert_eq!(processor.count(), TEST_FILE_ENGLISH_WORDS.distinct.lower);
         }, FILE_HANDLE_BATCH_SIZE);
     });
 
+    group.bench_function("fold_case_full", |bencher| {
+        bencher.iter_batched(|| open(TEST_FILE_ENGLISH_WORDS), |files| {
+            let mut processor = LineCounter::with_line_mapper(fold_case_full);
+            processor.count_unique_in_memmap_files(&files).unwrap();
+            assert_eq!(processor.count(), TEST_FILE_ENGLISH_WORDS.distinct.lower);
+        }, FILE_HANDLE_BATCH_SIZE);
+    });
+
+    group.finish();
+}
+
+/// distinct lines in the synthetic sorted input for [`bench_hot_cache`]
+const SORTED_DISTINCT: usize = 100_000;
+
+/// how many times each line is repeated in the synthetic sorted input for [`bench_hot_cache`]
+const SORTED_REPEATS: usize = 20;
+
+/// distinct lines in the synthetic clustered input for [`bench_hot_cache`]. This is enough that the
+/// map doesn't fit in CPU cache.
+const CLUSTERED_DISTINCT: usize = 1_000_000;
+
+/// the clustered input repeats each block of this many distinct lines before moving on to the next
+const CLUSTER_SIZE: usize = 128;
+
+/// how many times each block is repeated in the clustered input
+const CLUSTER_REPEATS: usize = 8;
+
+/// Compare counting with and without the hot cache. Clustered input, where lines are repeated a
+/// short distance apart, should be faster with the cache. Sorted input is about the same, as the
+/// map entry for a line repeated back to back is already in CPU cache. Shuffled input, where
+/// repeats are far apart, should also be about the same.
+fn bench_hot_cache(c: &mut Criterion) {
+    let sorted: Vec<u8> = (0..SORTED_DISTINCT * SORTED_REPEATS)
+        .flat_map(|i| format!("{:08}\n", i / SORTED_REPEATS).into_bytes())
+        .collect();
+    let clustered: Vec<u8> = (0..CLUSTERED_DISTINCT / CLUSTER_SIZE)
+        .flat_map(|block| (0..CLUSTER_REPEATS).flat_map(move |_| block * CLUSTER_SIZE..(block + 1) * CLUSTER_SIZE))
+        .flat_map(|line| format!("{line:08}\n").into_bytes())
+        .collect();
+    let shuffled = std::fs::read(TEST_FILE_NUMBERS.path()).unwrap();
+
+    let mut group = c.benchmark_group("hot_cache");
+    group.sample_size(10);
+
+    let inputs = [
+        ("sorted", &sorted, SORTED_DISTINCT),
+        ("clustered", &clustered, CLUSTERED_DISTINCT / CLUSTER_SIZE * CLUSTER_SIZE),
+        ("shuffled", &shuffled, TEST_FILE_NUMBERS.distinct.plain),
+    ];
+    for (name, input, expected) in inputs {
+        group.bench_function(format!("{name}.baseline"), |bencher| {
+            bencher.iter(|| {
+                let mut processor = LineCounter::new();
+                processor.count_unique_in_bytes(input);
+                assert_eq!(processor.count(), expected);
+            });
+        });
+
+        group.bench_function(format!("{name}.hot_cache"), |bencher| {
+            bencher.iter(|| {
+                let mut processor = LineCounter::new().with_hot_cache(1024);
+                processor.count_unique_in_bytes(input);
+                assert_eq!(processor.count(), expected);
+            });
+        });
+    }
+
     group.finish();
 }
@@ -0,0 +1,22 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Checks that every way of splitting and counting lines agrees with a naive reference
+//! implementation. Run with `cargo fuzz run split_paths` from the `line_cardinality` directory.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../tests/invariants/mod.rs"]
+mod invariants;
+
+fuzz_target!(|data: &[u8]| {
+    // the first byte picks how many of the following bytes are read chunk sizes
+    let Some((&chunk_count, rest)) = data.split_first() else {
+        return;
+    };
+    let (chunk_sizes, input) = rest.split_at(usize::from(chunk_count % 4).min(rest.len()));
+    let chunk_sizes: Vec<usize> = chunk_sizes.iter().map(|&size| usize::from(size) + 1).collect();
+    invariants::check(input, &chunk_sizes);
+});
@@ -0,0 +1,128 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! How this build of line_cardinality was compiled
+
+use std::fmt::{Display, Formatter};
+
+/// Every feature flag of this crate, and whether it is enabled in this build, in alphabetical order.
+/// `default` and `bench` only enable other features, so they are not listed.
+pub const FEATURES: &[(&str, bool)] = &[
+    ("ahash", cfg!(feature = "ahash")),
+    ("compile-time-rng", cfg!(feature = "compile-time-rng")),
+    ("diagnostics", cfg!(feature = "diagnostics")),
+    ("export", cfg!(feature = "export")),
+    ("file", cfg!(feature = "file")),
+    ("hash-only", cfg!(feature = "hash-only")),
+    ("memchr", cfg!(feature = "memchr")),
+    ("memmap", cfg!(feature = "memmap")),
+    ("unicode", cfg!(feature = "unicode")),
+];
+
+/// The version, feature flags, and hasher this build of line_cardinality was compiled with, as
+/// returned by [`build_info`]. This is meant for logs and bug reports: its [`Display`] impl prints
+/// all of it on one line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BuildInfo {
+    /// The crate version, such as `2.0.0`
+    pub version: &'static str,
+    /// The hash function the exact, near-exact, and estimating counters use, and how it is seeded
+    pub hasher: &'static str,
+}
+
+impl BuildInfo {
+    /// The names of the enabled feature flags, in alphabetical order
+    pub fn features(&self) -> impl Iterator<Item = &'static str> {
+        FEATURES.iter().filter(|(_feature, enabled)| *enabled).map(|(feature, _enabled)| *feature)
+    }
+}
+
+impl Display for BuildInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line_cardinality {} with feature flags: [", self.version)?;
+        for (index, feature) in self.features().enumerate() {
+            if index != 0 {
+                f.write_str(",")?;
+            }
+            f.write_str(feature)?;
+        }
+        write!(f, "], hasher: {}", self.hasher)
+    }
+}
+
+/// Returns the version, feature flags, and hasher this build of line_cardinality was compiled with.
+///
+/// ```rust
+/// let info = line_cardinality::build_info();
+/// assert_eq!(info.version, "2.0.0");
+/// assert_eq!(info.features().any(|feature| feature == "memchr"), cfg!(feature = "memchr"));
+/// println!("{info}");
+/// ```
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        hasher: hasher(),
+    }
+}
+
+/// Describes the hasher [`init_hasher_state`](crate::count_unique_impl::init_hasher_state) creates
+fn hasher() -> &'static str {
+    cfg_if::cfg_if! {
+        if #[cfg(all(feature = "ahash", feature = "compile-time-rng"))] {
+            "ahash, randomly seeded"
+        } else if #[cfg(feature = "ahash")] {
+            "ahash, with fixed seeds"
+        } else {
+            "std RandomState, randomly seeded"
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The `key = value` lines of a Cargo.toml section
+    fn section<'a>(manifest: &'a str, name: &str) -> impl Iterator<Item = (&'a str, &'a str)> {
+        let section = manifest.split(&format!("\n[{name}]")).nth(1).unwrap().split("\n[").next().unwrap();
+        section.lines().filter_map(|line| line.split_once(" = ")).map(|(key, value)| (key.trim(), value))
+    }
+
+    /// Every feature in Cargo.toml is listed, so a new feature can't be forgotten. This includes
+    /// the features implied by optional dependencies that aren't referred to with `dep:`.
+    #[test]
+    fn test_features_match_manifest() {
+        let manifest = include_str!("../Cargo.toml");
+        let mut manifest_features: Vec<&str> = section(manifest, "features")
+            .map(|(feature, _implies)| feature)
+            .filter(|feature| !matches!(*feature, "default" | "bench"))
+            .collect();
+        manifest_features.extend(section(manifest, "dependencies")
+            .filter(|(dependency, value)| value.contains("optional = true") && !manifest.contains(&format!("\"dep:{dependency}\"")))
+            .map(|(dependency, _value)| dependency));
+        manifest_features.sort_unstable();
+        let features: Vec<&str> = FEATURES.iter().map(|(feature, _enabled)| *feature).collect();
+        assert_eq!(features, manifest_features);
+    }
+
+    #[test]
+    fn test_enabled_features() {
+        let features: Vec<&str> = build_info().features().collect();
+        assert_eq!(features.contains(&"ahash"), cfg!(feature = "ahash"));
+        assert_eq!(features.contains(&"file"), cfg!(feature = "file"));
+        assert_eq!(features.contains(&"memchr"), cfg!(feature = "memchr"));
+        assert_eq!(features.contains(&"memmap"), cfg!(feature = "memmap"));
+        assert_eq!(features.contains(&"unicode"), cfg!(feature = "unicode"));
+        let mut sorted = features.clone();
+        sorted.sort_unstable();
+        assert_eq!(features, sorted);
+    }
+
+    #[test]
+    fn test_display() {
+        let info = BuildInfo { version: "1.2.3", hasher: "ahash, randomly seeded" };
+        let expected_features = info.features().collect::<Vec<_>>().join(",");
+        assert_eq!(info.to_string(), format!("line_cardinality 1.2.3 with feature flags: [{expected_features}], hasher: ahash, randomly seeded"));
+    }
+}
@@ -0,0 +1,221 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! The binary report format written by
+//! [`HashingLineCounter::write_binary`](crate::HashingLineCounter::write_binary).
+//!
+//! A report starts with the 4 byte magic `LCRB` and a 1 byte format version, which is currently
+//! [`VERSION`]. Then comes the number of entries, followed by each entry as the length of its line,
+//! the line's bytes, and its occurrence count. Every number is an unsigned LEB128 varint of at most
+//! 64 bits.
+
+use std::io::{self, Read, Write};
+
+/// The bytes every binary report starts with
+pub(crate) const MAGIC: &[u8; 4] = b"LCRB";
+
+/// The current version of the binary report format. Readers reject any other version.
+pub(crate) const VERSION: u8 = 1;
+
+/// Entries to reserve space for before reading them, so that a corrupt entry count can't cause a
+/// huge allocation
+pub(crate) const MAX_RESERVED_ENTRIES: u64 = 1 << 16;
+
+pub(crate) fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(crate) fn write_header<W: Write>(writer: &mut W, entries: u64) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION])?;
+    write_varint(writer, entries)
+}
+
+/// Read the header, and return the number of entries that follow it
+pub(crate) fn read_header<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut header = [0; 5];
+    reader.read_exact(&mut header)?;
+    if header[..4] != MAGIC[..] {
+        return Err(invalid_data("not a binary report"));
+    }
+    if header[4] != VERSION {
+        return Err(invalid_data("unsupported binary report version"));
+    }
+    read_varint(reader)
+}
+
+pub(crate) fn write_entry<W: Write>(writer: &mut W, line: &[u8], count: u64) -> io::Result<()> {
+    write_varint(writer, line.len() as u64)?;
+    writer.write_all(line)?;
+    write_varint(writer, count)
+}
+
+/// Read an entry's line into `line`, replacing its contents, and return the entry's count
+pub(crate) fn read_entry<R: Read>(reader: &mut R, line: &mut Vec<u8>) -> io::Result<u64> {
+    let len = read_varint(reader)?;
+    line.clear();
+    // read through `take` rather than allocating `len` up front, so a corrupt length fails at the
+    // end of the input instead of allocating
+    reader.take(len).read_to_end(line)?;
+    if (line.len() as u64) < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    read_varint(reader)
+}
+
+pub(crate) fn write_varint<W: Write>(writer: &mut W, mut value: u64) -> io::Result<()> {
+    let mut buffer = [0; 10];
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            buffer[len] = byte;
+            len += 1;
+            break;
+        }
+        buffer[len] = byte | 0x80;
+        len += 1;
+    }
+    writer.write_all(&buffer[..len])
+}
+
+pub(crate) fn read_varint<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut value: u64 = 0;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        let [byte] = byte;
+        let bits = u64::from(byte & 0x7F);
+        // the 10th byte only has room for the top bit
+        if shift == 63 && bits > 1 {
+            return Err(invalid_data("varint is too large"));
+        }
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid_data("varint is too large"))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CountUnique, HashingLineCounter, ReportUnique};
+
+    use super::*;
+
+    fn report_of<C: crate::Increment + Ord>(counter: &HashingLineCounter<C, ()>) -> Vec<(Vec<u8>, C)> {
+        let mut report: Vec<(Vec<u8>, C)> = counter.iter().map(|(line, count)| (line.to_vec(), *count)).collect();
+        report.sort_unstable();
+        report
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let long_line = vec![b'x'; 1000];
+        let mut counter = HashingLineCounter::<u64, ()>::new();
+        for _ in 0..300 {
+            counter.count_line(b"common");
+        }
+        for line in [b"".as_slice(), b"\xFF\xFE not utf-8", b"common", &long_line, b"a line that is too long to be stored inline"] {
+            counter.count_line(line);
+        }
+        let mut bytes = Vec::new();
+        counter.write_binary(&mut bytes).unwrap();
+        assert!(bytes.starts_with(b"LCRB\x01\x05"));
+
+        let mut loaded = HashingLineCounter::<u64, ()>::new();
+        loaded.read_binary(bytes.as_slice()).unwrap();
+        assert_eq!(loaded.count(), 5);
+        assert_eq!(report_of(&loaded), report_of(&counter));
+        assert_eq!(loaded.get(b"common"), Some(301));
+        // the heap memory of long lines is still accounted for
+        assert!(loaded.estimated_memory() > long_line.len());
+
+        // an empty counter
+        let mut bytes = Vec::new();
+        HashingLineCounter::<u64, ()>::new().write_binary(&mut bytes).unwrap();
+        assert_eq!(bytes, b"LCRB\x01\x00");
+        loaded.reset();
+        loaded.read_binary(bytes.as_slice()).unwrap();
+        assert_eq!(loaded.count(), 0);
+    }
+
+    /// Reading several reports into one counter adds their counts
+    #[test]
+    fn test_combine() {
+        let mut first = HashingLineCounter::<u32, ()>::new();
+        first.count_unique_in_bytes(b"a\nb\na\n");
+        let mut second = HashingLineCounter::<u32, ()>::new();
+        second.count_unique_in_bytes(b"b\nc\n");
+        let mut bytes = Vec::new();
+        first.write_binary(&mut bytes).unwrap();
+        second.write_binary(&mut bytes).unwrap();
+
+        let mut combined = HashingLineCounter::<u32, ()>::new();
+        let mut reader = bytes.as_slice();
+        combined.read_binary(&mut reader).unwrap();
+        combined.read_binary(&mut reader).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!(report_of(&combined), [(b"a".to_vec(), 2), (b"b".to_vec(), 2), (b"c".to_vec(), 1)]);
+    }
+
+    #[test]
+    fn test_invalid_reports() {
+        let read = |bytes: &[u8]| HashingLineCounter::<u8, ()>::new().read_binary(bytes);
+        assert!(read(b"").is_err());
+        assert!(read(b"LCRA\x01\x00").is_err());
+        assert!(read(b"LCRB\x02\x00").is_err());
+        // fewer entries than promised
+        assert!(read(b"LCRB\x01\x02\x01a\x01").is_err());
+        // a count of 0
+        assert!(read(b"LCRB\x01\x01\x01a\x00").is_err());
+        // a count that doesn't fit in a u8, alone or when added to an existing count
+        assert!(read(b"LCRB\x01\x01\x01a\x80\x02").is_err());
+        assert!(read(b"LCRB\x01\x02\x01a\xFF\x01\x01a\x01").is_err());
+        assert!(read(b"LCRB\x01\x02\x01a\xFE\x01\x01a\x01").is_ok());
+    }
+
+    fn varint(value: u64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, value).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_varint() {
+        assert_eq!(varint(0), [0]);
+        assert_eq!(varint(127), [0x7F]);
+        assert_eq!(varint(128), [0x80, 0x01]);
+        assert_eq!(varint(300), [0xAC, 0x02]);
+        assert_eq!(varint(u64::MAX).len(), 10);
+        for value in [0, 1, 127, 128, 300, u64::from(u32::MAX), u64::MAX - 1, u64::MAX] {
+            assert_eq!(read_varint(&mut varint(value).as_slice()).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_invalid_varint() {
+        // 11 bytes
+        let too_long = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x81, 0x00];
+        assert_eq!(read_varint(&mut too_long.as_slice()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        // 10 bytes, but more than 64 bits
+        let too_large = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x02];
+        assert_eq!(read_varint(&mut too_large.as_slice()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        // unterminated
+        assert_eq!(read_varint(&mut [0x80].as_slice()).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_truncated_entry() {
+        let mut bytes = Vec::new();
+        write_entry(&mut bytes, b"hello", 3).unwrap();
+        let mut line = Vec::new();
+        assert_eq!(read_entry(&mut bytes.as_slice(), &mut line).unwrap(), 3);
+        assert_eq!(line, b"hello");
+        for len in 0..bytes.len() {
+            assert_eq!(read_entry(&mut &bytes[..len], &mut line).unwrap_err().kind(), io::ErrorKind::UnexpectedEof, "{len} bytes");
+        }
+    }
+}
@@ -0,0 +1,171 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+use hashbrown::HashMap;
+
+use crate::{EmitLines, Increment};
+
+use super::{for_each_line, map_with_capacity_hint, RandomState};
+
+/// Counts occurrences of each line in a byte slice without copying the lines.
+///
+/// Unlike [`HashingLineCounter`](crate::HashingLineCounter), the map keys are borrowed slices of the
+/// input instead of owned copies, which removes an allocation per distinct line. The trade-off is
+/// that the input must outlive the counter and no line mapper can be used, as a mapped line would
+/// not be a slice of the input. This is a good fit for memory-mapped files, which stay valid for as
+/// long as the mapping is held.
+///
+/// ```rust
+/// use line_cardinality::BorrowedLineCounter;
+///
+/// let data = b"three\ntwo\nthree\ntwo\nthree\none";
+///
+/// let mut line_counter = BorrowedLineCounter::<u64>::new();
+/// line_counter.count_unique_in_slice(data);
+///
+/// assert_eq!(line_counter.count(), 3);
+/// assert_eq!(line_counter.get(b"three"), Some(3));
+/// ```
+pub struct BorrowedLineCounter<'data, C> {
+    map: HashMap<&'data [u8], C, RandomState>,
+}
+
+impl<'data, C> Default for BorrowedLineCounter<'data, C>
+where
+    C: Increment,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'data, C> BorrowedLineCounter<'data, C>
+where
+    C: Increment,
+{
+    /// Creates a new [`BorrowedLineCounter`].
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Creates a new [`BorrowedLineCounter`] with a cardinality hint of `capacity`.
+    ///
+    /// Note that it is best to leave `capacity` unset unless you have a near-perfect idea of your
+    /// data's cardinality lower bound, as it is extremely difficult to gain performance by setting
+    /// it, but extremely easy to lose performance.
+    pub fn with_capacity(capacity: usize) -> Self {
+        BorrowedLineCounter {
+            map: map_with_capacity_hint(capacity),
+        }
+    }
+
+    /// Count unique lines in newline-delimited bytes.
+    pub fn count_unique_in_slice(&mut self, bytes: &'data [u8]) {
+        for_each_line(bytes, |line| self.count_line(line));
+    }
+
+    /// Count a single line, incrementing counters if it is the first occurrence of that line.
+    #[inline(always)]
+    pub fn count_line(&mut self, line: &'data [u8]) {
+        self.map.entry(line)
+            .and_modify(|count| count.increment())
+            .or_insert_with(C::new);
+    }
+
+    /// Get the occurrence count for a specific line
+    pub fn get(&self, line: &[u8]) -> Option<C> {
+        self.map.get(line).copied()
+    }
+
+    /// `f` is called for each map entry.
+    pub fn for_each_report_entry<F: FnMut(&'data [u8], C)>(&self, mut f: F) {
+        self.map.iter()
+            .for_each(|(line, count)| f(line, *count));
+    }
+
+    /// Consume this [`BorrowedLineCounter`] and convert it into a [`Vec`]. Unlike
+    /// [`ReportUnique::to_report_vec`](crate::ReportUnique::to_report_vec) the lines are not copied.
+    pub fn to_report_vec(self) -> Vec<(&'data [u8], C)> {
+        self.map.into_iter().collect()
+    }
+
+    /// Iterate over each entry
+    pub fn iter(&self) -> impl Iterator<Item = (&'data [u8], C)> + '_ {
+        self.map.iter().map(|(line, count)| (*line, *count))
+    }
+}
+
+impl<'data, C> BorrowedLineCounter<'data, C> {
+    /// Returns current cardinality count of the [`BorrowedLineCounter`].
+    pub fn count(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Resets internal state of this [`BorrowedLineCounter`] for reuse
+    pub fn reset(&mut self) {
+        self.map.clear();
+    }
+}
+
+impl<'data, C> EmitLines for BorrowedLineCounter<'data, C> {
+    fn for_each_line<F>(&self, f: F)
+    where
+        F: FnMut(&[u8]),
+    {
+        self.map.keys()
+            .copied()
+            .for_each(f);
+    }
+
+    fn into_vec(self) -> Vec<Vec<u8>> {
+        self.map.into_keys().map(|line| line.to_vec()).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CountUnique, HashingLineCounter, ReportUnique};
+
+    use super::*;
+
+    static EDGE_CASES: &[&[u8]] = &[
+        b"",
+        b"\n",
+        b"\n\n",
+        b"a",
+        b"a\n",
+        b"a\n\nb\n\n",
+        b"a\r\na\n",
+        b"a\0b\na\0b",
+        b"three\ntwo\nthree\ntwo\nthree\none",
+    ];
+
+    #[test]
+    fn test_matches_owned() {
+        for bytes in EDGE_CASES {
+            let mut borrowed = BorrowedLineCounter::<u64>::new();
+            borrowed.count_unique_in_slice(bytes);
+            let mut owned = HashingLineCounter::<u64, ()>::new();
+            owned.count_unique_in_bytes(bytes);
+
+            assert_eq!(borrowed.count(), owned.count(), "count of {bytes:?}");
+            let mut borrowed_report: Vec<(Vec<u8>, u64)> = borrowed.iter()
+                .map(|(line, count)| (line.to_vec(), count))
+                .collect();
+            borrowed_report.sort_unstable();
+            let mut owned_report = owned.to_report_vec();
+            owned_report.sort_unstable();
+            assert_eq!(borrowed_report, owned_report, "report of {bytes:?}");
+        }
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut counter = BorrowedLineCounter::<u32>::new();
+        counter.count_unique_in_slice(b"a\nb\na");
+        assert_eq!(counter.get(b"a"), Some(2));
+        counter.reset();
+        assert_eq!(counter.count(), 0);
+        assert_eq!(counter.get(b"a"), None);
+    }
+}
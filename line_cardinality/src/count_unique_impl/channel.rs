@@ -0,0 +1,109 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+use std::sync::mpsc::Receiver;
+
+use crate::CountUnique;
+
+/// Count unique lines received over a channel into `counter`, until every sender has been dropped.
+/// Returns the final [`CountUnique::count`].
+///
+/// This lets input be read on other threads while lines are counted on this one, so that waiting on
+/// disks or pipes overlaps with hashing. Each message is counted with
+/// [`CountUnique::count_unique_in_bytes`], so it may hold a single line or a batch of whole lines.
+/// Batching is much faster than sending lines one at a time, as each message is a separate
+/// allocation. A line must not be split across messages, and an empty message counts nothing, so
+/// an empty line must be sent as `b"\n"`.
+///
+/// # Memory
+///
+/// An unbounded [`channel`](std::sync::mpsc::channel) queues everything the senders produce, so if
+/// reading is faster than counting, the whole input can end up in memory. Use a
+/// [`sync_channel`](std::sync::mpsc::sync_channel) instead: once it holds `bound` messages, senders
+/// block until this catches up. At most about `bound` batches, plus one in flight per sender, are
+/// held at once, which keeps memory bounded by the batch size.
+///
+/// ```rust
+/// use std::io::{BufRead, BufReader};
+/// use std::sync::mpsc::sync_channel;
+/// use std::thread;
+/// use line_cardinality::{count_unique_from_channel, LineCounter};
+///
+/// let inputs = [b"a\nb\nc\n".as_slice(), b"c\nd\n", b"a\ne"];
+/// let (sender, receiver) = sync_channel(4);
+/// let count = thread::scope(|scope| {
+///     for input in inputs {
+///         let sender = sender.clone();
+///         scope.spawn(move || {
+///             // send whole lines in batches of up to 64 KiB
+///             let mut reader = BufReader::new(input);
+///             let mut batch = Vec::new();
+///             while reader.read_until(b'\n', &mut batch).unwrap() != 0 {
+///                 if batch.len() >= 64 * 1024 {
+///                     sender.send(std::mem::take(&mut batch)).unwrap();
+///                 }
+///             }
+///             if !batch.is_empty() {
+///                 sender.send(batch).unwrap();
+///             }
+///         });
+///     }
+///     // only the readers' senders should keep the channel open
+///     drop(sender);
+///     count_unique_from_channel(&mut LineCounter::new(), receiver)
+/// });
+///
+/// assert_eq!(count, 5);
+/// ```
+pub fn count_unique_from_channel<C: CountUnique>(counter: &mut C, receiver: Receiver<Vec<u8>>) -> usize {
+    for lines in receiver {
+        counter.count_unique_in_bytes(&lines);
+    }
+    counter.count()
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc::{channel, sync_channel};
+    use std::thread;
+
+    use crate::{HashingLineCounter, LineCounter, ReportUnique};
+
+    use super::*;
+
+    #[test]
+    fn test_batches_and_single_lines() {
+        let (sender, receiver) = channel();
+        sender.send(b"a\nb\n".to_vec()).unwrap();
+        sender.send(b"b".to_vec()).unwrap();
+        sender.send(b"c\r\n".to_vec()).unwrap();
+        sender.send(Vec::new()).unwrap();
+        sender.send(b"\n".to_vec()).unwrap();
+        drop(sender);
+        let mut counter = HashingLineCounter::<u64, ()>::new();
+        assert_eq!(count_unique_from_channel(&mut counter, receiver), 4);
+        assert_eq!(counter.get(b"b"), Some(2));
+        // as with bytes, a `\r` before the `\n` is kept
+        assert_eq!(counter.get(b"c\r"), Some(1));
+        assert_eq!(counter.get(b""), Some(1));
+    }
+
+    /// Several senders blocked on a small bound all get through
+    #[test]
+    fn test_bounded_senders() {
+        let (sender, receiver) = sync_channel(1);
+        let count = thread::scope(|scope| {
+            for thread in 0..4 {
+                let sender = sender.clone();
+                scope.spawn(move || {
+                    for line in 0..1000 {
+                        sender.send(format!("{}\n", (thread * 500 + line) % 2500).into_bytes()).unwrap();
+                    }
+                });
+            }
+            drop(sender);
+            count_unique_from_channel(&mut LineCounter::new(), receiver)
+        });
+        assert_eq!(count, 2500);
+    }
+}
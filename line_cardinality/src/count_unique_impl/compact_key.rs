@@ -0,0 +1,136 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+use std::borrow::Borrow;
+use std::fmt::{Debug, Formatter};
+use std::hash::{Hash, Hasher};
+
+/// Longest key that is stored inline. This is chosen so that a [`CompactKey`] is no larger than the
+/// `Vec<u8>` it replaces: 22 bytes of key, a length byte, and the enum tag.
+const INLINE_CAPACITY: usize = 22;
+
+/// An owned map key. Short keys are stored inline, which avoids an allocation per distinct line and
+/// keeps the key in the same cache line as the rest of the map entry. Longer keys spill to the heap.
+///
+/// This hashes and compares exactly like the `[u8]` it holds, so maps keyed by it can be looked up
+/// with a `&[u8]` without allocating.
+#[derive(Clone)]
+pub struct CompactKey(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    Inline {
+        len: u8,
+        bytes: [u8; INLINE_CAPACITY],
+    },
+    Heap(Box<[u8]>),
+}
+
+impl CompactKey {
+    #[inline(always)]
+    pub fn new(line: &[u8]) -> Self {
+        if line.len() <= INLINE_CAPACITY {
+            let mut bytes = [0; INLINE_CAPACITY];
+            bytes[..line.len()].copy_from_slice(line);
+            CompactKey(Repr::Inline { len: line.len() as u8, bytes })
+        } else {
+            CompactKey(Repr::Heap(line.into()))
+        }
+    }
+
+    #[inline(always)]
+    pub fn as_slice(&self) -> &[u8] {
+        match &self.0 {
+            Repr::Inline { len, bytes } => &bytes[..*len as usize],
+            Repr::Heap(bytes) => bytes,
+        }
+    }
+
+    /// Heap memory allocated by this key, in bytes. Inline keys allocate nothing.
+    #[inline(always)]
+    pub fn heap_memory(&self) -> usize {
+        match &self.0 {
+            Repr::Inline { .. } => 0,
+            Repr::Heap(bytes) => bytes.len(),
+        }
+    }
+
+    /// Convert to a `Vec`. This only allocates for inline keys.
+    pub fn into_vec(self) -> Vec<u8> {
+        match self.0 {
+            Repr::Inline { .. } => self.as_slice().to_vec(),
+            Repr::Heap(bytes) => bytes.into_vec(),
+        }
+    }
+}
+
+impl Borrow<[u8]> for CompactKey {
+    fn borrow(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+/// Must match the `[u8]` hash for [`Borrow`] lookups to work
+impl Hash for CompactKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
+}
+
+impl PartialEq for CompactKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl Eq for CompactKey {}
+
+impl Debug for CompactKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_slice(), f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::hash_map::DefaultHasher;
+    use std::mem::size_of;
+
+    use super::*;
+
+    fn hash<T: Hash + ?Sized>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_size() {
+        assert_eq!(size_of::<CompactKey>(), size_of::<Vec<u8>>());
+    }
+
+    /// Every length around the inline/heap boundary round-trips, and hashes like a slice
+    #[test]
+    fn test_boundary_lengths() {
+        let source: Vec<u8> = (1..=u8::MAX).collect();
+        for len in 0..=INLINE_CAPACITY * 2 {
+            let line = &source[..len];
+            let key = CompactKey::new(line);
+            assert_eq!(matches!(key.0, Repr::Inline { .. }), len <= INLINE_CAPACITY, "len {len}");
+            assert_eq!(key.as_slice(), line);
+            assert_eq!(key.heap_memory(), if len <= INLINE_CAPACITY { 0 } else { len }, "len {len}");
+            assert_eq!(hash(&key), hash(line), "len {len}");
+            assert_eq!(key.clone(), key);
+            assert_eq!(key.into_vec(), line);
+        }
+    }
+
+    #[test]
+    fn test_eq() {
+        assert_eq!(CompactKey::new(b"abc"), CompactKey::new(b"abc"));
+        assert_ne!(CompactKey::new(b"abc"), CompactKey::new(b"abd"));
+        // zero padding in inline keys doesn't make a shorter key equal to a longer one
+        assert_ne!(CompactKey::new(b"a"), CompactKey::new(b"a\0"));
+        assert_ne!(CompactKey::new(&[b'x'; INLINE_CAPACITY]), CompactKey::new(&[b'x'; INLINE_CAPACITY + 1]));
+    }
+}
@@ -0,0 +1,278 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+use std::io::{self, BufRead, Read};
+use std::mem::size_of;
+
+use bstr::io::BufReadExt;
+
+use crate::count_unique_impl::for_each_record;
+use crate::{CountUnique, Error, Result};
+
+/// Wraps a [`CountUnique`] to split input into records on an arbitrary delimiter instead of on
+/// newlines, such as `\r\n` or a multi-byte marker.
+///
+/// Records are split the same way whether they are read from a [`BufRead`] or from bytes: each
+/// occurrence of the delimiter ends a record, and the delimiter is not part of it. Consecutive
+/// delimiters produce empty records, and a final record without a trailing delimiter is included,
+/// but an empty final record is not. Unlike [`CountUnique::count_unique_in_read`] with newlines, a
+/// `\r` before the delimiter is never removed.
+///
+/// Single-byte delimiters are found with the same fast search as newlines. Longer delimiters are
+/// found with a substring search, which is faster with the `memchr` feature.
+///
+/// ```rust
+/// use line_cardinality::{CountUnique, DelimitedCounter, LineCounter};
+///
+/// let mut records = DelimitedCounter::new(LineCounter::new(), b"\r\n");
+/// records.count_unique_in_read(b"a\r\nb\nc\r\n\r\na\r\n".as_slice()).unwrap();
+///
+/// // "a", "b\nc", and an empty record
+/// assert_eq!(records.count(), 3);
+/// ```
+pub struct DelimitedCounter<C> {
+    inner: C,
+    delimiter: Vec<u8>,
+    /// holds a record that spans more than one read of a multi-byte delimited [`BufRead`]
+    record_buffer: Vec<u8>,
+}
+
+impl<C: CountUnique> DelimitedCounter<C> {
+    /// Creates a new [`DelimitedCounter`] that splits records on `delimiter`.
+    ///
+    /// # Panics
+    ///
+    /// If `delimiter` is empty.
+    pub fn new(inner: C, delimiter: &[u8]) -> Self {
+        assert!(!delimiter.is_empty(), "record delimiter must not be empty");
+        DelimitedCounter {
+            inner,
+            delimiter: delimiter.to_vec(),
+            record_buffer: Vec::new(),
+        }
+    }
+
+    /// The wrapped counter
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// The wrapped counter, mutably
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    /// Consume this [`DelimitedCounter`] and return the wrapped counter
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    /// Call `f` with each record in `reader` until it returns `false`
+    fn for_each_record_in_read<T, F>(&mut self, mut reader: T, mut f: F) -> io::Result<()>
+    where
+        T: BufRead,
+        F: FnMut(&mut C, &[u8]) -> bool,
+    {
+        let inner = &mut self.inner;
+        if let [delimiter] = *self.delimiter {
+            return reader.for_byte_record(delimiter, |record| Ok(f(inner, record)));
+        }
+
+        // read up to each occurrence of the delimiter's last byte, until the record read so far
+        // ends with the whole delimiter
+        let last = self.delimiter[self.delimiter.len() - 1];
+        let buffer = &mut self.record_buffer;
+        buffer.clear();
+        loop {
+            if reader.read_until(last, buffer)? == 0 {
+                if !buffer.is_empty() {
+                    f(inner, buffer);
+                }
+                break;
+            }
+            if buffer.ends_with(&self.delimiter) {
+                let keep_going = f(inner, &buffer[..buffer.len() - self.delimiter.len()]);
+                buffer.clear();
+                if !keep_going {
+                    break;
+                }
+            }
+        }
+        buffer.clear();
+        Ok(())
+    }
+}
+
+/// Call `f` with each record in `bytes`, split on a `delimiter` of any length
+#[inline(always)]
+fn for_each_record_by<'a, F: FnMut(&'a [u8])>(bytes: &'a [u8], delimiter: &[u8], mut f: F) {
+    if let [delimiter] = *delimiter {
+        return for_each_record(bytes, delimiter, f);
+    }
+    let mut start: usize = 0;
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "memchr")] {
+            for delimiter_index in memchr::memmem::find_iter(bytes, delimiter) {
+                f(&bytes[start..delimiter_index]);
+                start = delimiter_index + delimiter.len();
+            }
+        } else {
+            while let Some(offset) = bytes[start..].windows(delimiter.len()).position(|window| window == delimiter) {
+                f(&bytes[start..start + offset]);
+                start += offset + delimiter.len();
+            }
+        }
+    }
+    // handle trailing
+    if start < bytes.len() {
+        f(&bytes[start..]);
+    }
+}
+
+impl<C: CountUnique> CountUnique for DelimitedCounter<C> {
+    fn count_unique_in_read<T: BufRead>(&mut self, reader: T) -> Result {
+        self.for_each_record_in_read(reader, |inner, record| {
+            inner.count_line(record);
+            true
+        }).map_err(|e| Error::io_static("failed to read from buffer", e))
+    }
+
+    /// Records can't be split straight from the reader's buffer like lines, so this is the same as
+    /// [`CountUnique::count_unique_in_read`] with a [`BufReader`](io::BufReader) of `buffer_size`.
+    fn count_unique_in_unbuffered_read<T: Read>(&mut self, reader: T, buffer_size: usize) -> Result {
+        self.count_unique_in_read(io::BufReader::with_capacity(buffer_size.max(1), reader))
+    }
+
+    fn count_until<T: BufRead>(&mut self, reader: T, cap: usize) -> std::result::Result<bool, Error> {
+        if self.inner.count() >= cap {
+            return Ok(true);
+        }
+        self.for_each_record_in_read(reader, |inner, record| {
+            inner.count_line(record);
+            inner.count() < cap
+        }).map_err(|e| Error::io_static("failed to read from buffer", e))?;
+        Ok(self.inner.count() >= cap)
+    }
+
+    fn count_unique_in_read_with<T: BufRead, F: FnMut(&[u8], usize, bool)>(&mut self, reader: T, mut on_line: F) -> Result {
+        self.for_each_record_in_read(reader, |inner, record| {
+            let mut new = false;
+            inner.count_line_with(record, |_| new = true);
+            on_line(record, inner.count(), new);
+            true
+        }).map_err(|e| Error::io_static("failed to read from buffer", e))
+    }
+
+    fn count_unique_in_bytes(&mut self, bytes: &[u8]) {
+        let inner = &mut self.inner;
+        for_each_record_by(bytes, &self.delimiter, |record| inner.count_line(record));
+    }
+
+    #[inline(always)]
+    fn count_line(&mut self, line: &[u8]) {
+        self.inner.count_line(line);
+    }
+
+    fn count_line_with<F: FnOnce(&[u8])>(&mut self, line: &[u8], on_new: F) {
+        self.inner.count_line_with(line, on_new);
+    }
+
+    fn count(&self) -> usize {
+        self.inner.count()
+    }
+
+    /// The memory of the wrapped counter, plus this wrapper's own fields and buffers. If the wrapped
+    /// counter's memory is unknown, only the rest is included.
+    fn estimated_memory(&self) -> usize {
+        size_of::<Self>() - size_of::<C>() + self.delimiter.capacity() + self.record_buffer.capacity() + self.inner.estimated_memory()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{EmitLines, LineCounter};
+
+    use super::*;
+
+    /// A delimiter, an input, and the records it splits into
+    type EdgeCase = (&'static [u8], &'static [u8], &'static [&'static [u8]]);
+
+    /// These are the same with and without the `memchr` feature
+    static EDGE_CASES: &[EdgeCase] = &[
+        (b"\r\n", b"", &[]),
+        (b"\r\n", b"\r\n", &[b""]),
+        (b"\r\n", b"\r\n\r\n", &[b"", b""]),
+        (b"\r\n", b"a", &[b"a"]),
+        (b"\r\n", b"a\r\n", &[b"a"]),
+        (b"\r\n", b"a\r\n\r\nb\r\n\r\n", &[b"a", b"", b"b", b""]),
+        (b"\r\n", b"a\nb\r\r\n\n", &[b"a\nb\r", b"\n"]),
+        (b"\r\n", b"\r", &[b"\r"]),
+        (b"<>", b"a<>b<c>d<><", &[b"a", b"b<c>d", b"<"]),
+        // overlapping occurrences split at the leftmost one
+        (b"aa", b"xaaay", &[b"x", b"ay"]),
+        (b"bab", b"xbababy", &[b"x", b"aby"]),
+        (b"--\n", b"one\n--\ntwo\n--\n", &[b"one\n", b"two\n"]),
+        // a single-byte delimiter takes the same path as newlines
+        (b"\0", b"a\0b\n\0\0", &[b"a", b"b\n", b""]),
+    ];
+
+    #[test]
+    fn test_for_each_record_by() {
+        for (delimiter, bytes, expected) in EDGE_CASES {
+            let mut records = Vec::new();
+            for_each_record_by(bytes, delimiter, |record| records.push(record));
+            assert_eq!(&records, expected, "records of {bytes:?} split on {delimiter:?}");
+        }
+    }
+
+    /// Reading splits the same way as bytes do, including when reads end partway through a
+    /// delimiter
+    #[test]
+    fn test_read_matches_bytes() {
+        for (delimiter, bytes, expected) in EDGE_CASES {
+            for capacity in [1, 2, 3, 64] {
+                let mut counter = DelimitedCounter::new(LineCounter::new(), delimiter);
+                counter.count_unique_in_read(io::BufReader::with_capacity(capacity, *bytes)).unwrap();
+                let mut distinct = expected.to_vec();
+                distinct.sort_unstable();
+                distinct.dedup();
+                let mut records = counter.into_inner().into_vec();
+                records.sort_unstable();
+                assert_eq!(records, distinct, "records of {bytes:?} split on {delimiter:?} with capacity {capacity}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_count_until() {
+        let mut counter = DelimitedCounter::new(LineCounter::new(), b"||");
+        assert!(counter.count_until(b"a||b||a||c||d".as_slice(), 3).unwrap());
+        assert_eq!(counter.count(), 3);
+        assert!(!counter.count_until(b"d||e".as_slice(), 10).unwrap());
+        assert_eq!(counter.count(), 5);
+        counter.reset();
+        assert_eq!(counter.count(), 0);
+    }
+
+    #[test]
+    fn test_count_unique_in_read_with() {
+        for delimiter in [b"|".as_slice(), b"||"] {
+            let input = [b"a".as_slice(), b"b", b"a", b"", b"c"].join(delimiter);
+            let mut counter = DelimitedCounter::new(LineCounter::new(), delimiter);
+            let mut observed = Vec::new();
+            counter.count_unique_in_read_with(input.as_slice(), |record, count, new| observed.push((record.to_vec(), count, new))).unwrap();
+            let expected = [(b"a".as_slice(), 1, true), (b"b", 2, true), (b"a", 2, false), (b"", 3, true), (b"c", 4, true)];
+            assert_eq!(observed, expected.map(|(record, count, new)| (record.to_vec(), count, new)), "{delimiter:?}");
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_empty_delimiter() {
+        DelimitedCounter::new(LineCounter::new(), b"");
+    }
+}
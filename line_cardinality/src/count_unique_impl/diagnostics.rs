@@ -0,0 +1,211 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Statistics about how well a [`HashingLineCounter`](crate::HashingLineCounter)'s map is behaving
+
+use std::fmt::{Display, Formatter};
+
+/// Statistics about the map of a [`HashingLineCounter`](crate::HashingLineCounter), as returned by
+/// [`HashingLineCounter::diagnostics`](crate::HashingLineCounter::diagnostics). These help tell
+/// whether slow counting is caused by the hasher clustering keys together, rather than by the data.
+///
+/// Its [`Display`] impl prints one `name: value` pair per line.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct HashDiagnostics {
+    /// Number of distinct lines in the map
+    pub len: usize,
+    /// Number of lines the map can hold before it grows again
+    pub capacity: usize,
+    /// Number of slots in the map, which is always a power of two
+    pub buckets: usize,
+    /// Number of times the map grew while lines were counted. Growing is expensive, as every line is
+    /// moved, so many growths suggest a larger capacity hint.
+    pub growths: usize,
+    /// Number of lines that [`HashDiagnostics::probe_distances`] was sampled from
+    pub sampled: usize,
+    /// How far each sampled line is from the slot its hash points at, in slots. The map searches a
+    /// group of slots at once, so this approximates probe length: distances well beyond the group
+    /// size of 16 mean lines had to be searched for. An even hasher keeps nearly all of them small.
+    pub probe_distances: Log2Histogram,
+    /// Length of every line in the map, in bytes. Lines of up to 22 bytes are stored inline, without
+    /// an allocation of their own.
+    pub key_lengths: Log2Histogram,
+}
+
+impl HashDiagnostics {
+    /// The fraction of slots that are in use
+    pub fn load_factor(&self) -> f64 {
+        self.len as f64 / self.buckets as f64
+    }
+}
+
+impl Display for HashDiagnostics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "distinct: {}", self.len)?;
+        writeln!(f, "capacity: {}", self.capacity)?;
+        writeln!(f, "buckets: {}", self.buckets)?;
+        writeln!(f, "load_factor: {:.4}", self.load_factor())?;
+        writeln!(f, "growths: {}", self.growths)?;
+        writeln!(f, "probe_distance_sample: {}", self.sampled)?;
+        for (range, count) in self.probe_distances.buckets() {
+            writeln!(f, "probe_distance {range}: {count}")?;
+        }
+        for (range, count) in self.key_lengths.buckets() {
+            writeln!(f, "key_length {range}: {count}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A histogram with one bucket for 0, then one for each power of two: 1, 2-3, 4-7, and so on
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Log2Histogram {
+    counts: Vec<u64>,
+}
+
+impl Log2Histogram {
+    /// Count one occurrence of `value`
+    pub fn record(&mut self, value: usize) {
+        let bucket = (usize::BITS - value.leading_zeros()) as usize;
+        if self.counts.len() <= bucket {
+            self.counts.resize(bucket + 1, 0);
+        }
+        self.counts[bucket] += 1;
+    }
+
+    /// Iterate over the non-empty buckets, in increasing order, with how many values fell in each
+    ///
+    /// ```rust
+    /// use line_cardinality::Log2Histogram;
+    ///
+    /// let mut histogram = Log2Histogram::default();
+    /// for value in [0, 1, 5, 6, 7] {
+    ///     histogram.record(value);
+    /// }
+    /// let buckets: Vec<String> = histogram.buckets().map(|(range, count)| format!("{range}={count}")).collect();
+    /// assert_eq!(buckets, ["0=1", "1=1", "4-7=3"]);
+    /// ```
+    pub fn buckets(&self) -> impl Iterator<Item = (Log2Range, u64)> + '_ {
+        self.counts.iter()
+            .enumerate()
+            .filter(|(_bucket, count)| **count != 0)
+            .map(|(bucket, count)| (Log2Range { bucket }, *count))
+    }
+
+    /// The total number of values recorded
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+/// The range of values in one bucket of a [`Log2Histogram`], which displays like `4-7`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Log2Range {
+    bucket: usize,
+}
+
+impl Log2Range {
+    /// The smallest value in this range
+    pub fn start(&self) -> usize {
+        match self.bucket {
+            0 => 0,
+            bucket => 1 << (bucket - 1),
+        }
+    }
+
+    /// The largest value in this range
+    pub fn end(&self) -> usize {
+        match self.bucket {
+            0 => 0,
+            bucket => usize::MAX >> (usize::BITS as usize - bucket),
+        }
+    }
+}
+
+impl Display for Log2Range {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.start() == self.end() {
+            write!(f, "{}", self.start())
+        } else {
+            write!(f, "{}-{}", self.start(), self.end())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CountUnique, HashingLineCounter};
+
+    use super::*;
+
+    /// `count` distinct lines
+    fn distinct_lines(count: usize) -> Vec<u8> {
+        (0..count).flat_map(|line| format!("line {line}\n").into_bytes()).collect()
+    }
+
+    #[test]
+    fn test_growths() {
+        // without a capacity hint the map grows from 0 to hold 3, 7, 14, 28, 56, then 112 lines
+        let mut line_counter = HashingLineCounter::<u64, ()>::new();
+        line_counter.count_unique_in_bytes(&distinct_lines(100));
+        line_counter.count_unique_in_bytes(&distinct_lines(100));
+        let diagnostics = line_counter.diagnostics(usize::MAX);
+        assert_eq!(diagnostics.growths, 6);
+        assert_eq!((diagnostics.len, diagnostics.capacity, diagnostics.buckets), (100, 112, 128));
+        assert_eq!(diagnostics.sampled, 100);
+        assert_eq!(diagnostics.probe_distances.total(), 100);
+
+        // a large enough hint means the map never grows
+        let mut line_counter = HashingLineCounter::<(), ()>::with_capacity(100);
+        line_counter.count_unique_in_bytes(&distinct_lines(100));
+        assert_eq!(line_counter.diagnostics(10).growths, 0);
+        assert_eq!(line_counter.diagnostics(10).sampled, 10);
+
+        line_counter.reset();
+        assert_eq!(line_counter.diagnostics(10), HashDiagnostics {
+            len: 0,
+            capacity: 112,
+            buckets: 128,
+            growths: 0,
+            sampled: 0,
+            probe_distances: Log2Histogram::default(),
+            key_lengths: Log2Histogram::default(),
+        });
+    }
+
+    #[test]
+    fn test_hot_cache_growths() {
+        let mut line_counter = crate::LineCounter::new().with_hot_cache(16);
+        line_counter.count_unique_in_bytes(&distinct_lines(8));
+        assert_eq!(line_counter.diagnostics(0).growths, 3);
+    }
+
+    #[test]
+    fn test_key_lengths() {
+        let mut line_counter = HashingLineCounter::<(), ()>::new();
+        line_counter.count_unique_in_bytes(b"\na\nbb\nccc\ndddd\na\n");
+        let lengths: Vec<(usize, u64)> = line_counter.diagnostics(0).key_lengths.buckets().map(|(range, count)| (range.start(), count)).collect();
+        assert_eq!(lengths, [(0, 1), (1, 1), (2, 2), (4, 1)]);
+    }
+
+    #[test]
+    fn test_log2_range() {
+        let ranges: Vec<(usize, usize)> = (0..5).map(|bucket| Log2Range { bucket }).map(|range| (range.start(), range.end())).collect();
+        assert_eq!(ranges, [(0, 0), (1, 1), (2, 3), (4, 7), (8, 15)]);
+        let last = Log2Range { bucket: usize::BITS as usize };
+        assert_eq!((last.start(), last.end()), (1 << (usize::BITS - 1), usize::MAX));
+    }
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Log2Histogram::default();
+        assert_eq!(histogram.buckets().count(), 0);
+        for value in [3, 2, 9, usize::MAX] {
+            histogram.record(value);
+        }
+        let buckets: Vec<(String, u64)> = histogram.buckets().map(|(range, count)| (range.to_string(), count)).collect();
+        assert_eq!(buckets, [("2-3".to_string(), 2), ("8-15".to_string(), 1), (format!("{}-{}", 1usize << (usize::BITS - 1), usize::MAX), 1)]);
+        assert_eq!(histogram.total(), 4);
+    }
+}
@@ -2,6 +2,8 @@
 // line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
 
 use std::fs::File;
+use std::io;
+use std::ops::Deref;
 
 use memmap2::Mmap;
 
@@ -9,6 +11,12 @@ use crate::count_unique_impl::result::Error;
 use crate::CountUnique;
 use crate::Result;
 
+/// The largest file, in bytes, that can be memory-mapped on this platform. A mapped file is used as
+/// a single `&[u8]`, and Rust limits slices to `isize::MAX` bytes, so this is 2 GiB on 32-bit
+/// platforms. In practice the address space may run out before then. Files of any size can still
+/// be read without memory-mapping.
+pub const MAX_MAPPED_LEN: u64 = isize::MAX as u64;
+
 /// Provides capability to read data from newline-delimited memory-mapped files
 pub trait CountUniqueFromMemmapFile: CountUnique {
     /// Count unique lines in some newline-delimited files.
@@ -29,20 +37,158 @@ where
         Ok(())
     }
 
+    /// An empty file is not mapped at all, as some platforms can't map zero bytes. It is counted
+    /// the same way as empty bytes, so it counts no lines.
     fn count_unique_in_memmap_file(&mut self, file: &File) -> Result {
+        let mapped_file = MappedFile::new(file)?;
+        self.count_unique_in_bytes(&mapped_file);
+        Ok(())
+    }
+}
+
+/// A read-only memory-mapped file, which can be used as a `&[u8]` via [`Deref`].
+///
+/// This is useful with [`BorrowedLineCounter`](crate::BorrowedLineCounter), which requires the
+/// counted bytes to stay alive for as long as the counter.
+pub struct MappedFile {
+    /// `None` for an empty file, which is never mapped
+    mem_map: Option<Mmap>,
+    advice_error: Option<io::Error>,
+}
+
+impl MappedFile {
+    /// Memory-map a file, hinting to the OS that it will be read sequentially.
+    ///
+    /// An empty file is not mapped, as mapping zero bytes fails on some platforms, such as
+    /// Windows. It derefs to an empty slice instead.
+    ///
+    /// A file longer than [`MAX_MAPPED_LEN`] is rejected with an error before trying to map it.
+    ///
+    /// The hints are only an optimization, so if the OS rejects them the file is still mapped, and
+    /// the error is available from [`MappedFile::advice_error`].
+    pub fn new(file: &File) -> std::result::Result<Self, Error> {
+        let len = file.metadata().map_err(|e| Error::io_static("failed to read file metadata", e))?.len();
+        if len == 0 {
+            return Ok(Self { mem_map: None, advice_error: None });
+        }
+        check_mapped_len(len)?;
+
         let mem_map = unsafe { Mmap::map(file) }
             .map_err(|e| Error::io_static("failed to memmap file", e))?;
 
         //TODO: we need to *not* Advice::Sequential if we ever get a parallel counting implementation
-        #[cfg(unix)] {
-            use memmap2::Advice;
-            mem_map.advise(Advice::WillNeed)
-                .map_err(|e| Error::io_static("failed to set memmap file to WillNeed mode", e))?;
-            mem_map.advise(Advice::Sequential)
-                .map_err(|e| Error::io_static("failed to set memmap file to Sequential mode", e))?;
-        }
+        #[cfg(unix)]
+        let advice_error = advise_all(|advice| mem_map.advise(advice));
+        #[cfg(not(unix))]
+        let advice_error = None;
 
-        self.count_unique_in_bytes(&mem_map);
+        Ok(Self { mem_map: Some(mem_map), advice_error })
+    }
+
+    /// The error from hinting to the OS how the file will be read, if it failed. Reading the file
+    /// still works, but may be slower. Always `None` on platforms that don't take hints.
+    pub fn advice_error(&self) -> Option<&io::Error> {
+        self.advice_error.as_ref()
+    }
+}
+
+/// Check that a file of `len` bytes is not too long to memory-map
+fn check_mapped_len(len: u64) -> std::result::Result<(), Error> {
+    if len > MAX_MAPPED_LEN {
+        Err(Error::message(format!("file is {len} bytes, but at most {MAX_MAPPED_LEN} bytes can be memory-mapped on this platform")))
+    } else {
         Ok(())
     }
 }
+
+/// Give each hint a memory-mapped file needs with `advise`, continuing past failures. Returns the
+/// first error, if any.
+#[cfg(unix)]
+fn advise_all<F: FnMut(memmap2::Advice) -> io::Result<()>>(mut advise: F) -> Option<io::Error> {
+    use memmap2::Advice;
+
+    let mut first_error = None;
+    for advice in [Advice::WillNeed, Advice::Sequential] {
+        if let Err(e) = advise(advice) {
+            first_error.get_or_insert(e);
+        }
+    }
+    first_error
+}
+
+impl Deref for MappedFile {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match &self.mem_map {
+            Some(mem_map) => mem_map,
+            None => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use crate::{CountUnique, LineCounter};
+
+    use super::*;
+
+    fn temp_file(name: &str, contents: &[u8]) -> (std::path::PathBuf, File) {
+        let path = std::env::temp_dir().join(format!("line_cardinality-test-{}-{name}", std::process::id()));
+        File::create(&path).unwrap().write_all(contents).unwrap();
+        let file = File::open(&path).unwrap();
+        (path, file)
+    }
+
+    #[test]
+    fn test_empty_file() {
+        let (path, file) = temp_file("empty", b"");
+        let mapped_file = MappedFile::new(&file).unwrap();
+        assert!(mapped_file.is_empty());
+        assert!(mapped_file.advice_error().is_none());
+        let mut counter = LineCounter::new();
+        counter.count_unique_in_memmap_file(&file).unwrap();
+        assert_eq!(counter.count(), 0);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_non_empty_file() {
+        let (path, file) = temp_file("non_empty", b"a\nb\n");
+        assert_eq!(&*MappedFile::new(&file).unwrap(), b"a\nb\n");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_advise_all() {
+        use memmap2::Advice;
+
+        assert!(advise_all(|_advice| Ok(())).is_none());
+
+        // a failed hint doesn't stop the others from being given
+        let mut given = Vec::new();
+        let error = advise_all(|advice| {
+            given.push(advice);
+            match advice {
+                Advice::WillNeed => Err(io::Error::new(io::ErrorKind::Other, "first")),
+                _ => Err(io::Error::new(io::ErrorKind::Other, "second")),
+            }
+        });
+        assert_eq!(error.unwrap().to_string(), "first");
+        assert_eq!(given.len(), 2);
+    }
+
+    #[test]
+    fn test_check_mapped_len() {
+        assert!(check_mapped_len(1).is_ok());
+        assert!(check_mapped_len(MAX_MAPPED_LEN).is_ok());
+        assert!(check_mapped_len(MAX_MAPPED_LEN + 1).is_err());
+        assert!(check_mapped_len(u64::MAX).is_err());
+        // lengths past 4 GiB only fit in the address space of 64-bit platforms
+        let past_u32 = u64::from(u32::MAX) + 1;
+        assert_eq!(check_mapped_len(past_u32).is_ok(), cfg!(target_pointer_width = "64"));
+    }
+}
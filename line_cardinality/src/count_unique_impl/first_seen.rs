@@ -0,0 +1,168 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+use std::io::BufRead;
+use std::mem;
+
+use crate::{CountUnique, Error};
+
+/// An iterator over the distinct lines of a [`BufRead`], each yielded the first time it is read.
+/// Created by [`CountUnique::first_seen_in_read`].
+///
+/// Lines are read one at a time as the iterator is advanced, and split the same way as
+/// [`CountUnique::count_unique_in_read`]. Each line is counted as it is read, and yielded as it
+/// appeared in the input if it was new, so a line mapper on the counter decides which lines are
+/// duplicates without changing what is yielded. Only the counter grows as input is read.
+///
+/// Once reading fails, the error is yielded and the iterator ends.
+///
+/// ```rust
+/// use line_cardinality::{CountUnique, LineCounter};
+///
+/// let data = b"b\na\r\nb\nc\na";
+///
+/// let lines: Vec<Vec<u8>> = LineCounter::new()
+///     .first_seen_in_read(data.as_slice())
+///     .collect::<Result<_, _>>()
+///     .unwrap();
+///
+/// assert_eq!(lines, [b"b".as_slice(), b"a", b"c"]);
+/// ```
+pub struct FirstSeenLines<C, R> {
+    counter: C,
+    reader: R,
+    /// the line being read, which is handed out if it is new and reused if it isn't
+    line: Vec<u8>,
+    done: bool,
+}
+
+impl<C: CountUnique, R: BufRead> FirstSeenLines<C, R> {
+    pub(crate) fn new(counter: C, reader: R) -> Self {
+        FirstSeenLines {
+            counter,
+            reader,
+            line: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// The counter, which has counted every line read so far
+    pub fn counter(&self) -> &C {
+        &self.counter
+    }
+
+    /// Consume this iterator and return the counter and the reader. The reader is positioned after
+    /// the last line read.
+    pub fn into_inner(self) -> (C, R) {
+        (self.counter, self.reader)
+    }
+}
+
+impl<C: CountUnique, R: BufRead> Iterator for FirstSeenLines<C, R> {
+    type Item = Result<Vec<u8>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            self.line.clear();
+            match self.reader.read_until(b'\n', &mut self.line) {
+                Ok(0) => self.done = true,
+                Ok(_) => {
+                    // remove the terminator the same way `count_unique_in_read` does
+                    if self.line.last() == Some(&b'\n') {
+                        self.line.pop();
+                        if self.line.last() == Some(&b'\r') {
+                            self.line.pop();
+                        }
+                    }
+                    let mut new = false;
+                    self.counter.count_line_with(&self.line, |_| new = true);
+                    if new {
+                        return Some(Ok(mem::take(&mut self.line)));
+                    }
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(Error::io_static("failed to read from buffer", e)));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{self, BufReader, Read};
+
+    use bstr::ByteSlice;
+
+    use super::*;
+    use crate::LineCounter;
+
+    fn first_seen<C: CountUnique>(counter: C, input: &[u8]) -> Vec<Vec<u8>> {
+        counter.first_seen_in_read(input).collect::<Result<_, _>>().unwrap()
+    }
+
+    /// Lines are split the same way as `count_unique_in_read`, including a final line without a
+    /// newline and an empty line
+    #[test]
+    fn test_line_splitting() {
+        let input = b"a\n\nb\r\na\r\n\nc\rd\nb\n";
+        let lines = first_seen(LineCounter::new(), input);
+        assert_eq!(lines, [b"a".as_slice(), b"", b"b", b"c\rd"]);
+
+        let mut counter = LineCounter::new();
+        counter.count_unique_in_read(input.as_slice()).unwrap();
+        assert_eq!(lines.len(), counter.count());
+
+        assert_eq!(first_seen(LineCounter::new(), b"x\ny"), [b"x".as_slice(), b"y"]);
+        assert!(first_seen(LineCounter::new(), b"").is_empty());
+    }
+
+    /// The line mapper decides what is a duplicate, but lines are yielded as they were read
+    #[test]
+    fn test_line_mapper() {
+        let counter = LineCounter::with_line_mapper(|line, buffer| {
+            buffer.clear();
+            line.to_lowercase_into(buffer);
+            buffer
+        });
+        assert_eq!(first_seen(counter, b"B\na\nb\nA\nc"), [b"B".as_slice(), b"a", b"c"]);
+    }
+
+    /// Lines are only read as the iterator is advanced, and a borrowed counter keeps what it counted
+    #[test]
+    fn test_lazy() {
+        let mut counter = LineCounter::new();
+        let mut reader = BufReader::with_capacity(2, b"a\na\nb\nc\n".as_slice());
+        let mut lines = (&mut counter).first_seen_in_read(&mut reader);
+        assert_eq!(lines.next().unwrap().unwrap(), b"a");
+        assert_eq!(lines.next().unwrap().unwrap(), b"b");
+        assert_eq!(lines.counter().count(), 2);
+        drop(lines);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"c\n");
+        assert_eq!(counter.count(), 2);
+    }
+
+    /// A reader that fails after its data
+    struct FailingReader<'a>(&'a [u8]);
+
+    impl Read for FailingReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::Other, "disconnected"));
+            }
+            self.0.read(buf)
+        }
+    }
+
+    #[test]
+    fn test_error_ends_iteration() {
+        let mut lines = LineCounter::new().first_seen_in_read(BufReader::new(FailingReader(b"a\nb")));
+        assert_eq!(lines.next().unwrap().unwrap(), b"a");
+        assert!(lines.next().unwrap().is_err());
+        assert!(lines.next().is_none());
+    }
+}
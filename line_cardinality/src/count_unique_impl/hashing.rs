@@ -1,11 +1,21 @@
 // This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
 // line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
 
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::io::{self, Read, Write};
+use std::mem::size_of;
+
+use hashbrown::hash_map::RawEntryMut;
 use hashbrown::HashMap;
 
-use crate::{CountUnique, EmitLines, Increment, ReportUnique};
+use crate::{ContainsLine, CountUnique, EmitLines, Error, Increment, LineMapper, ReportUnique};
 
-use super::{init_hasher_state, RandomState};
+use super::binary;
+use super::compact_key::CompactKey;
+#[cfg(feature = "diagnostics")]
+use super::diagnostics::{HashDiagnostics, Log2Histogram};
+use super::hot_cache::{self, HotCache};
+use super::{hash_table_memory, map_with_capacity_hint, RandomState};
 
 /// Calculates the unique count and holds necessary state.
 ///
@@ -18,11 +28,24 @@ use super::{init_hasher_state, RandomState};
 /// [`HashingLineCounter::with_line_mapper`]. If provided, this function will be applied to each
 /// line before checking if it is unique or not. Note that this also affects the output that will be
 /// seen from functions that enumerate internal state, such as [`EmitLines::for_each_line`].
-pub struct HashingLineCounter<T, M> {
-    map: HashMap<Vec<u8>, T, RandomState>,
-    string_buffer: Vec<u8>,
+///
+/// Lines of up to 22 bytes are stored inside the map entry, so counting short lines such as words
+/// or IDs doesn't allocate per distinct line.
+pub struct HashingLineCounter<T, M>
+where
+    M: LineMapper,
+{
+    map: HashMap<CompactKey, T, RandomState>,
+    /// scratch space for `line_mapper`, which is `()` when there is no mapper
+    string_buffer: M::Buffer,
     count: usize,
+    /// heap memory allocated by keys that are too long to store inline
+    key_memory: usize,
     line_mapper: M,
+    hot_cache: Option<HotCache>,
+    /// how many times the map grew, for [`HashingLineCounter::diagnostics`]
+    #[cfg(feature = "diagnostics")]
+    growths: usize,
 }
 
 impl<T> Default for HashingLineCounter<T, ()> {
@@ -45,10 +68,14 @@ impl<T> HashingLineCounter<T, ()> {
     /// it, but extremely easy to lose performance.
     pub fn with_capacity(capacity: usize) -> Self {
         HashingLineCounter {
-            map: HashMap::with_capacity_and_hasher(capacity, init_hasher_state()),
-            string_buffer: Vec::new(),
+            map: map_with_capacity_hint(capacity),
+            string_buffer: (),
             count: 0,
+            key_memory: 0,
+            hot_cache: None,
             line_mapper: (),
+            #[cfg(feature = "diagnostics")]
+            growths: 0,
         }
     }
 }
@@ -72,40 +99,230 @@ where
     /// it, but extremely easy to lose performance.
     pub fn with_line_mapper_and_capacity(line_mapper: M, capacity: usize) -> Self {
         HashingLineCounter {
-            map: HashMap::with_capacity_and_hasher(capacity, init_hasher_state()),
+            map: map_with_capacity_hint(capacity),
             string_buffer: Vec::new(),
             count: 0,
+            key_memory: 0,
+            hot_cache: None,
             line_mapper,
+            #[cfg(feature = "diagnostics")]
+            growths: 0,
         }
     }
 }
 
-impl<T, M> HashingLineCounter<T, M> {
+/// Options only available when not tracking occurrence counts
+impl<M> HashingLineCounter<(), M>
+where
+    M: LineMapper,
+{
+    /// Most lines a hot cache holds. Larger sizes given to
+    /// [`with_hot_cache`](Self::with_hot_cache) are reduced to this.
+    pub const MAX_HOT_CACHE_SLOTS: usize = hot_cache::MAX_SLOTS;
+
+    /// Check a small cache of recently seen lines before the map. This speeds up input where
+    /// repeated lines are close together, such as sorted or clustered data, as a repeat found in
+    /// the cache doesn't touch the much larger map. On input without such locality it is a small
+    /// slowdown. The cache holds at least `slots` lines, organized as 4-way sets, up to
+    /// [`MAX_HOT_CACHE_SLOTS`](Self::MAX_HOT_CACHE_SLOTS) lines.
+    ///
+    /// ```rust
+    /// use line_cardinality::{CountUnique, LineCounter};
+    ///
+    /// let mut line_counter = LineCounter::new().with_hot_cache(1024);
+    /// line_counter.count_unique_in_bytes(b"a\na\na\nb\nb\na");
+    /// assert_eq!(line_counter.count(), 2);
+    /// ```
+    pub fn with_hot_cache(mut self, slots: usize) -> Self {
+        self.hot_cache = Some(HotCache::new(slots));
+        self
+    }
+
+    /// Count a line that has already been mapped, going through the hot cache
+    #[inline(always)]
+    fn count_line_cached(map: &mut HashMap<CompactKey, (), RandomState>, hot_cache: &mut HotCache, count: &mut usize, key_memory: &mut usize, line: &[u8]) {
+        let mut hasher = map.hasher().build_hasher();
+        line.hash(&mut hasher);
+        let hash = hasher.finish();
+        if hot_cache.contains(hash, line) {
+            return;
+        }
+        map.raw_entry_mut()
+            .from_key_hashed_nocheck(hash, line)
+            .or_insert_with(|| {
+                *count += 1;
+                let key = CompactKey::new(line);
+                *key_memory += key.heap_memory();
+                (key, ())
+            });
+        hot_cache.insert(hash, line);
+    }
+}
+
+impl<T, M> HashingLineCounter<T, M>
+where
+    M: LineMapper,
+{
+    /// O(1), as the count is maintained as lines are inserted. See [`CountUnique::count`].
     fn count(&self) -> usize {
         self.count
     }
 
+    /// See [`CountUnique::estimated_memory`]. The map keeps its capacity when cleared, so that is
+    /// still counted after a reset.
+    fn estimated_memory(&self) -> usize {
+        size_of::<Self>()
+            + hash_table_memory::<(CompactKey, T)>(self.map.capacity())
+            + self.key_memory
+            + M::buffer_memory(&self.string_buffer)
+            + self.hot_cache.as_ref().map_or(0, HotCache::heap_memory)
+    }
+
     fn reset(&mut self) {
         self.count = 0;
+        self.key_memory = 0;
+        #[cfg(feature = "diagnostics")]
+        {
+            self.growths = 0;
+        }
         self.map.clear();
+        if let Some(hot_cache) = &mut self.hot_cache {
+            hot_cache.clear();
+        }
+    }
+}
+
+#[cfg(feature = "hash-only")]
+impl<T, M> HashingLineCounter<T, M>
+where
+    M: LineMapper,
+{
+    /// Consume this counter and hash each of its distinct lines with `seed`, keeping only the
+    /// hashes. The result can then be [merged](crate::InexactHashingLineCounter::merge) with the
+    /// [`InexactHashingLineCounter`](crate::InexactHashingLineCounter) that `seed` came from, to
+    /// count the distinct lines seen by either, at near-exact accuracy.
+    ///
+    /// **`seed` must come from [`InexactHashingLineCounter::seed`](crate::InexactHashingLineCounter::seed)
+    /// of the counter this will be merged with.** Lines hashed with any other seed never match, so
+    /// a line seen by both counters would be counted twice. Lines are hashed as they were stored,
+    /// after this counter's line mapper, so the other counter must use the same mapper.
+    ///
+    /// ```rust
+    /// use line_cardinality::{CountUnique, HashingLineCounter, InexactHashingLineCounter};
+    ///
+    /// let mut near_exact = InexactHashingLineCounter::new();
+    /// near_exact.count_unique_in_bytes(b"a\nb\n");
+    /// let mut exact = HashingLineCounter::<(), ()>::new();
+    /// exact.count_unique_in_bytes(b"b\nc\n");
+    ///
+    /// near_exact.merge(&exact.into_hash_set(&near_exact.seed()));
+    /// assert_eq!(near_exact.count(), 3);
+    /// ```
+    pub fn into_hash_set(self, seed: &crate::HashSeed) -> crate::InexactHashingLineCounter<()> {
+        let mut hash_set = crate::InexactHashingLineCounter::with_seed(seed);
+        for line in self.map.keys() {
+            hash_set.count_line(line.as_slice());
+        }
+        hash_set
+    }
+}
+
+/// Statistics about the map, for finding out why counting is slow on particular data
+#[cfg(feature = "diagnostics")]
+impl<T, M> HashingLineCounter<T, M>
+where
+    M: LineMapper,
+{
+    /// Count a growth of the map if counting a line changed its capacity from `capacity`. Capacity
+    /// is kept when the map is cleared, so it only changes when the map grows.
+    #[inline(always)]
+    fn record_growth(&mut self, capacity: usize) {
+        if self.map.capacity() != capacity {
+            self.growths += 1;
+        }
+    }
+
+    /// Returns statistics about the map: how full it is, how many times it grew, how far up to
+    /// `sample` of its lines are from the slot their hash points at, and the length of every line.
+    /// The sample is spread evenly through the map. Each sampled line is hashed and looked up
+    /// again, so this takes time proportional to `sample`, plus a pass over every line for their
+    /// lengths.
+    ///
+    /// ```rust
+    /// use line_cardinality::{CountUnique, HashingLineCounter};
+    ///
+    /// let mut line_counter = HashingLineCounter::<(), ()>::new();
+    /// line_counter.count_unique_in_bytes(b"a\nb\na\nc");
+    /// let diagnostics = line_counter.diagnostics(1000);
+    /// assert_eq!(diagnostics.len, 3);
+    /// assert_eq!(diagnostics.sampled, 3);
+    /// assert_eq!(diagnostics.key_lengths.total(), 3);
+    /// assert!(diagnostics.load_factor() <= 1.0);
+    /// ```
+    pub fn diagnostics(&self, sample: usize) -> HashDiagnostics {
+        let table = self.map.raw_table();
+        let bucket_mask = table.buckets() - 1;
+        let step = (self.map.len() / sample.max(1)).max(1);
+        let mut probe_distances = Log2Histogram::default();
+        let mut sampled = 0;
+        for (key, _value) in self.map.iter().step_by(step).take(sample) {
+            let line = key.as_slice();
+            let mut hasher = self.map.hasher().build_hasher();
+            line.hash(&mut hasher);
+            let hash = hasher.finish();
+            if let Some(bucket) = table.find(hash, |(candidate, _value)| candidate.as_slice() == line) {
+                // SAFETY: the bucket was just found in this table, which can't have changed since
+                let index = unsafe { table.bucket_index(&bucket) };
+                // probing starts at the slot given by the low bits of the hash, and wraps around
+                probe_distances.record(index.wrapping_sub(hash as usize) & bucket_mask);
+                sampled += 1;
+            }
+        }
+        let mut key_lengths = Log2Histogram::default();
+        for key in self.map.keys() {
+            key_lengths.record(key.as_slice().len());
+        }
+        HashDiagnostics {
+            len: self.map.len(),
+            capacity: self.map.capacity(),
+            buckets: table.buckets(),
+            growths: self.growths,
+            sampled,
+            probe_distances,
+            key_lengths,
+        }
     }
 }
 
 impl CountUnique for HashingLineCounter<(), ()> {
     #[inline(always)]
     fn count_line(&mut self, line: &[u8]) {
-        self.map.raw_entry_mut()
-            .from_key(line)
-            .or_insert_with(|| {
-                self.count += 1;
-                (line.to_vec(), ())
-            });
+        #[cfg(feature = "diagnostics")]
+        let capacity = self.map.capacity();
+        if let Some(hot_cache) = &mut self.hot_cache {
+            Self::count_line_cached(&mut self.map, hot_cache, &mut self.count, &mut self.key_memory, line);
+        } else {
+            self.map.raw_entry_mut()
+                .from_key(line)
+                .or_insert_with(|| {
+                    self.count += 1;
+                    let key = CompactKey::new(line);
+                    self.key_memory += key.heap_memory();
+                    (key, ())
+                });
+        }
+        #[cfg(feature = "diagnostics")]
+        self.record_growth(capacity);
     }
 
     fn count(&self) -> usize {
         HashingLineCounter::count(self)
     }
 
+    fn estimated_memory(&self) -> usize {
+        HashingLineCounter::estimated_memory(self)
+    }
+
     fn reset(&mut self) {
         HashingLineCounter::reset(self)
     }
@@ -119,18 +336,32 @@ where
     #[inline(always)]
     fn count_line(&mut self, line: &[u8]) {
         let line = (self.line_mapper)(line, &mut self.string_buffer);
-        self.map.raw_entry_mut()
-            .from_key(line)
-            .or_insert_with(|| {
-                self.count += 1;
-                (line.to_vec(), ())
-            });
+        #[cfg(feature = "diagnostics")]
+        let capacity = self.map.capacity();
+        if let Some(hot_cache) = &mut self.hot_cache {
+            Self::count_line_cached(&mut self.map, hot_cache, &mut self.count, &mut self.key_memory, line);
+        } else {
+            self.map.raw_entry_mut()
+                .from_key(line)
+                .or_insert_with(|| {
+                    self.count += 1;
+                    let key = CompactKey::new(line);
+                    self.key_memory += key.heap_memory();
+                    (key, ())
+                });
+        }
+        #[cfg(feature = "diagnostics")]
+        self.record_growth(capacity);
     }
 
     fn count(&self) -> usize {
         HashingLineCounter::count(self)
     }
 
+    fn estimated_memory(&self) -> usize {
+        HashingLineCounter::estimated_memory(self)
+    }
+
     fn reset(&mut self) {
         HashingLineCounter::reset(self)
     }
@@ -142,19 +373,29 @@ where
 {
     #[inline(always)]
     fn count_line(&mut self, line: &[u8]) {
+        #[cfg(feature = "diagnostics")]
+        let capacity = self.map.capacity();
         self.map.raw_entry_mut()
             .from_key(line)
             .and_modify(|_line, count| count.increment())
             .or_insert_with(|| {
                 self.count += 1;
-                (line.to_vec(), C::new())
+                let key = CompactKey::new(line);
+                self.key_memory += key.heap_memory();
+                (key, C::new())
             });
+        #[cfg(feature = "diagnostics")]
+        self.record_growth(capacity);
     }
 
     fn count(&self) -> usize {
         HashingLineCounter::count(self)
     }
 
+    fn estimated_memory(&self) -> usize {
+        HashingLineCounter::estimated_memory(self)
+    }
+
     fn reset(&mut self) {
         HashingLineCounter::reset(self)
     }
@@ -169,26 +410,53 @@ where
     #[inline(always)]
     fn count_line(&mut self, line: &[u8]) {
         let line = (self.line_mapper)(line, &mut self.string_buffer);
+        #[cfg(feature = "diagnostics")]
+        let capacity = self.map.capacity();
         self.map.raw_entry_mut()
             .from_key(line)
             .and_modify(|_line, count| count.increment())
             .or_insert_with(|| {
                 self.count += 1;
-                (line.to_vec(), C::new())
+                let key = CompactKey::new(line);
+                self.key_memory += key.heap_memory();
+                (key, C::new())
             });
+        #[cfg(feature = "diagnostics")]
+        self.record_growth(capacity);
     }
 
     fn count(&self) -> usize {
         HashingLineCounter::count(self)
     }
 
+    fn estimated_memory(&self) -> usize {
+        HashingLineCounter::estimated_memory(self)
+    }
+
     fn reset(&mut self) {
         HashingLineCounter::reset(self)
     }
 }
 
+impl<T> ContainsLine for HashingLineCounter<T, ()> {
+    fn contains_line(&mut self, line: &[u8]) -> bool {
+        self.map.contains_key(line)
+    }
+}
+
+impl<T, M> ContainsLine for HashingLineCounter<T, M>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    fn contains_line(&mut self, line: &[u8]) -> bool {
+        let line = (self.line_mapper)(line, &mut self.string_buffer);
+        self.map.contains_key(line)
+    }
+}
+
 impl<T, M> EmitLines for HashingLineCounter<T, M>
 where
+    M: LineMapper,
     HashingLineCounter<T, M>: CountUnique,
 {
     fn for_each_line<F>(&self, f: F)
@@ -201,28 +469,29 @@ where
     }
 
     fn into_vec(self) -> Vec<Vec<u8>> {
-        self.map.into_keys().collect()
+        self.map.into_keys().map(CompactKey::into_vec).collect()
     }
 }
 
 impl<C, M> ReportUnique<C> for HashingLineCounter<C, M>
 where
+    M: LineMapper,
     C: Increment,
 {
     fn for_each_report_entry<F: FnMut(&[u8], C)>(&self, mut f: F) {
         self.map.iter()
-            .for_each(|(line, count)| f(line, *count));
+            .for_each(|(line, count)| f(line.as_slice(), *count));
     }
 
     fn to_report_vec(self) -> Vec<(Vec<u8>, C)> {
-        self.map.into_iter().collect()
+        self.map.into_iter().map(|(line, count)| (line.into_vec(), count)).collect()
     }
 
     fn get(&self, line: &[u8]) -> Option<C> {
         self.map.get(line).copied()
     }
 
-    fn iter(&self) -> HashingLineCounterIter<C> {
+    fn iter(&self) -> HashingLineCounterIter<'_, C> {
         HashingLineCounterIter { inner: self.map.iter() }
     }
 
@@ -231,8 +500,123 @@ where
     }
 }
 
+/// Statistics that need each line's occurrence count
+impl<C, M> HashingLineCounter<C, M>
+where
+    M: LineMapper,
+    C: Increment + PartialEq,
+{
+    /// Returns the number of distinct lines that were seen exactly once. This scans every entry, so
+    /// it takes time proportional to [`CountUnique::count`].
+    ///
+    /// ```rust
+    /// use line_cardinality::{CountUnique, HashingLineCounter};
+    ///
+    /// let mut line_counter = HashingLineCounter::<u64, _>::new();
+    /// line_counter.count_unique_in_bytes(b"a\nb\na\nc\na");
+    /// assert_eq!(line_counter.count(), 3);
+    /// assert_eq!(line_counter.singleton_count(), 2);
+    /// ```
+    pub fn singleton_count(&self) -> usize {
+        let single = C::new();
+        self.map.values()
+            .filter(|count| **count == single)
+            .count()
+    }
+}
+
+/// Saving and loading reports in a compact binary format
+impl<C, M> HashingLineCounter<C, M>
+where
+    M: LineMapper,
+    C: Increment + TryInto<u64> + TryFrom<u64>,
+{
+    /// Write every distinct line and its occurrence count to `writer` in a compact binary format,
+    /// which [`HashingLineCounter::read_binary`] can load much faster than parsing a text report.
+    ///
+    /// The format starts with a version number. A released version of the format never changes, and
+    /// a report in a version this crate doesn't support is rejected rather than misread. Lines are
+    /// written as they were stored, after the line mapper, in an unspecified order. Each line is
+    /// prefixed by its length, and each count is a varint, so short lines with small counts take
+    /// only a few bytes more than the lines themselves. Writes are small, so `writer` should be
+    /// buffered.
+    ///
+    /// ```rust
+    /// use line_cardinality::{CountUnique, HashingLineCounter, ReportUnique};
+    ///
+    /// let mut counter = HashingLineCounter::<u64, ()>::new();
+    /// counter.count_unique_in_bytes(b"a\nb\na\n");
+    /// let mut report = Vec::new();
+    /// counter.write_binary(&mut report).unwrap();
+    ///
+    /// let mut loaded = HashingLineCounter::<u64, ()>::new();
+    /// loaded.read_binary(report.as_slice()).unwrap();
+    /// assert_eq!(loaded.count(), 2);
+    /// assert_eq!(loaded.get(b"a"), Some(2));
+    /// ```
+    pub fn write_binary<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let error = |e| Error::io_static("failed to write binary report", e);
+        binary::write_header(&mut writer, self.map.len() as u64).map_err(error)?;
+        for (line, count) in &self.map {
+            let count: u64 = (*count).try_into().map_err(|_| error(binary::invalid_data("count does not fit in 64 bits")))?;
+            binary::write_entry(&mut writer, line.as_slice(), count).map_err(error)?;
+        }
+        writer.flush().map_err(error)
+    }
+
+    /// Read a report written by [`HashingLineCounter::write_binary`] and add it to this counter.
+    /// Counts of lines that were already counted are added together, so several reports can be
+    /// combined into one by reading them all into the same counter.
+    ///
+    /// Lines are added exactly as they were written, without applying this counter's line mapper,
+    /// as they were already mapped when they were counted. Reads are small, so `reader` should be
+    /// buffered. Exactly one report is read, so further data may follow it in `reader`.
+    ///
+    /// Returns an error if the report is invalid or from an unsupported version, or if a count does
+    /// not fit in `C`. Entries read before an error are kept.
+    pub fn read_binary<R: Read>(&mut self, mut reader: R) -> Result<(), Error> {
+        let error = |e| Error::io_static("failed to read binary report", e);
+        let entries = binary::read_header(&mut reader).map_err(error)?;
+        self.map.reserve(entries.min(binary::MAX_RESERVED_ENTRIES) as usize);
+        let mut line = Vec::new();
+        for _ in 0..entries {
+            let count = binary::read_entry(&mut reader, &mut line).map_err(error)?;
+            self.add_count(&line, count).map_err(error)?;
+        }
+        Ok(())
+    }
+
+    /// Add `count` occurrences of an already mapped `line`
+    fn add_count(&mut self, line: &[u8], count: u64) -> io::Result<()> {
+        if count == 0 {
+            return Err(binary::invalid_data("count of 0"));
+        }
+        let too_large = || binary::invalid_data("count is too large for this counter");
+        #[cfg(feature = "diagnostics")]
+        let capacity = self.map.capacity();
+        match self.map.raw_entry_mut().from_key(line) {
+            RawEntryMut::Occupied(mut entry) => {
+                let existing: u64 = (*entry.get()).try_into().map_err(|_| too_large())?;
+                let total = existing.checked_add(count).ok_or_else(too_large)?;
+                *entry.get_mut() = C::try_from(total).map_err(|_| too_large())?;
+            }
+            RawEntryMut::Vacant(entry) => {
+                let count = C::try_from(count).map_err(|_| too_large())?;
+                self.count += 1;
+                let key = CompactKey::new(line);
+                self.key_memory += key.heap_memory();
+                entry.insert(key, count);
+            }
+        }
+        #[cfg(feature = "diagnostics")]
+        self.record_growth(capacity);
+        Ok(())
+    }
+}
+
 impl<'a, C, M> IntoIterator for &'a HashingLineCounter<C, M>
 where
+    M: LineMapper,
     C: Increment,
 {
     type Item = (&'a [u8], &'a C);
@@ -248,7 +632,7 @@ where
 /// Currently implemented as a wrapper around [`hashbrown::hash_map::Iter`]. This is done to
 /// avoid breaking changes if the internal map implementation changes.
 pub struct HashingLineCounterIter<'a, C> {
-    inner: hashbrown::hash_map::Iter<'a, Vec<u8>, C>,
+    inner: hashbrown::hash_map::Iter<'a, CompactKey, C>,
 }
 
 /// wrapper around [`hashbrown::hash_map::Iter`]'s Iterator impl
@@ -268,7 +652,7 @@ where
 /// Currently implemented as a wrapper around [`hashbrown::hash_map::IntoIter`]. This is done to
 /// avoid breaking changes if the internal map implementation changes.
 pub struct HashingLineCounterIntoIter<C> {
-    inner: hashbrown::hash_map::IntoIter<Vec<u8>, C>,
+    inner: hashbrown::hash_map::IntoIter<CompactKey, C>,
 }
 
 /// wrapper around [`hashbrown::hash_map::IntoIter`]'s Iterator impl
@@ -279,6 +663,6 @@ where
     type Item = (Vec<u8>, C);
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.inner.next()
+        self.inner.next().map(|(key, value)| (key.into_vec(), value))
     }
 }
@@ -3,11 +3,12 @@
 
 #[cfg(not(feature = "ahash"))]
 use std::hash::BuildHasher;
+use std::mem::size_of;
 
 use hashbrown::HashTable;
 
-use crate::count_unique_impl::init_hasher_state;
-use crate::CountUnique;
+use crate::count_unique_impl::{hash_table_memory, hash_table_with_capacity_hint, init_hasher_state};
+use crate::{ContainsLine, CountUnique, LineMapper};
 
 use super::RandomState;
 
@@ -25,14 +26,26 @@ use super::RandomState;
 /// [`EmitLines::for_each_line`](crate::EmitLines::for_each_line).
 pub struct InexactHashingLineCounter<M>
 where
+    M: LineMapper,
 {
     map: HashTable<u64>,
     random_state: RandomState,
-    string_buffer: Vec<u8>,
+    /// scratch space for `line_mapper`, which is `()` when there is no mapper
+    string_buffer: M::Buffer,
     count: usize,
     line_mapper: M,
 }
 
+/// The seed an [`InexactHashingLineCounter`] hashes lines with, from
+/// [`InexactHashingLineCounter::seed`].
+///
+/// Two counters only agree on which hash a line has if they share a seed, so counters must have the
+/// same seed to be [merged](InexactHashingLineCounter::merge). Counters created separately get
+/// different seeds unless the `ahash` feature is enabled without `compile-time-rng`. A seed cannot
+/// be serialized, so all the counters being combined must live in one process.
+#[derive(Clone)]
+pub struct HashSeed(RandomState);
+
 impl Default for InexactHashingLineCounter<()> {
     fn default() -> Self {
         Self::new()
@@ -53,13 +66,22 @@ impl InexactHashingLineCounter<()> {
     /// it, but extremely easy to lose performance.
     pub fn with_capacity(capacity: usize) -> Self {
         InexactHashingLineCounter {
-            map: HashTable::with_capacity(capacity),
+            map: hash_table_with_capacity_hint(capacity),
             random_state: init_hasher_state(),
-            string_buffer: Vec::new(),
+            string_buffer: (),
             count: 0,
             line_mapper: (),
         }
     }
+
+    /// Creates a new [`InexactHashingLineCounter`] that hashes lines with `seed`, so that it can be
+    /// [merged](InexactHashingLineCounter::merge) with the counter the seed came from.
+    pub fn with_seed(seed: &HashSeed) -> Self {
+        InexactHashingLineCounter {
+            random_state: seed.0.clone(),
+            ..Self::new()
+        }
+    }
 }
 
 /// Constructors that take a custom line mapper
@@ -81,7 +103,7 @@ where
     /// it, but extremely easy to lose performance.
     pub fn with_line_mapper_and_capacity(line_mapper: M, capacity: usize) -> Self {
         InexactHashingLineCounter {
-            map: HashTable::with_capacity(capacity),
+            map: hash_table_with_capacity_hint(capacity),
             random_state: init_hasher_state(),
             string_buffer: Vec::new(),
             count: 0,
@@ -90,12 +112,81 @@ where
     }
 }
 
-impl<M> InexactHashingLineCounter<M> {
+impl<M> InexactHashingLineCounter<M>
+where
+    M: LineMapper,
+{
+    /// Count a precomputed hash as if it were the hash of a line, bypassing the line mapper and
+    /// internal hashing. Hashes are compared directly, so any two lines that produce the same hash
+    /// are counted once. `hash` should be evenly distributed over all 64 bits, as the map uses both
+    /// its high and low bits.
+    ///
+    /// Hashes added this way only match hashes of counted lines if they were computed the same way,
+    /// which is not possible from outside this crate. This is intended for populating a counter
+    /// entirely from externally computed hashes, or from another [`InexactHashingLineCounter`].
+    ///
+    /// ```rust
+    /// use line_cardinality::{CountUnique, InexactHashingLineCounter};
+    ///
+    /// let mut counter = InexactHashingLineCounter::new();
+    /// for hash in [0x9E37_79B9_7F4A_7C15, 0xBF58_476D_1CE4_E5B9, 0x9E37_79B9_7F4A_7C15] {
+    ///     counter.add_hash(hash);
+    /// }
+    /// assert_eq!(counter.count(), 2);
+    /// ```
+    #[inline(always)]
+    pub fn add_hash(&mut self, hash: u64) {
+        let entry = self.map.entry(hash, |found_hash| *found_hash == hash, |rehash| *rehash);
+        entry.or_insert_with(|| {
+            self.count += 1;
+            hash
+        });
+    }
+
+    /// The seed this counter hashes lines with. Pass it to [`InexactHashingLineCounter::with_seed`]
+    /// or [`HashingLineCounter::into_hash_set`](crate::HashingLineCounter::into_hash_set) to create
+    /// a counter that can be merged into this one.
+    pub fn seed(&self) -> HashSeed {
+        HashSeed(self.random_state.clone())
+    }
+
+    /// Add every hash counted by `other` to this counter, so that this counts the distinct lines
+    /// seen by either. `other` is left unchanged.
+    ///
+    /// **Both counters must share a seed**, which means `other` was created from this counter's
+    /// [`seed`](InexactHashingLineCounter::seed), or both were created from the same one. Hashes
+    /// from different seeds never match, so merging them counts every line seen by both counters
+    /// twice, and nothing detects this. Both counters must also map lines the same way, as only the
+    /// hash of each mapped line is kept.
+    ///
+    /// ```rust
+    /// use line_cardinality::{CountUnique, InexactHashingLineCounter};
+    ///
+    /// let mut first = InexactHashingLineCounter::new();
+    /// first.count_unique_in_bytes(b"a\nb\nc\n");
+    /// let mut second = InexactHashingLineCounter::with_seed(&first.seed());
+    /// second.count_unique_in_bytes(b"c\nd\n");
+    ///
+    /// first.merge(&second);
+    /// assert_eq!(first.count(), 4);
+    /// ```
+    pub fn merge<N: LineMapper>(&mut self, other: &InexactHashingLineCounter<N>) {
+        for hash in other.map.iter() {
+            self.add_hash(*hash);
+        }
+    }
+
+    /// O(1), as the count is maintained as hashes are inserted. See [`CountUnique::count`].
     #[inline(always)]
     fn count(&self) -> usize {
         self.count
     }
 
+    /// See [`CountUnique::estimated_memory`]. Each distinct line takes just its 8 byte hash.
+    fn estimated_memory(&self) -> usize {
+        size_of::<Self>() + hash_table_memory::<u64>(self.map.capacity()) + M::buffer_memory(&self.string_buffer)
+    }
+
     #[inline(always)]
     fn reset(&mut self) {
         self.count = 0;
@@ -106,22 +197,39 @@ impl<M> InexactHashingLineCounter<M> {
 impl CountUnique for InexactHashingLineCounter<()> {
     fn count_line(&mut self, line: &[u8]) {
         let hash = self.random_state.hash_one(line);
-        let entry = self.map.entry(hash, |found_hash| *found_hash == hash, |rehash| *rehash);
-        entry.or_insert_with(|| {
-            self.count += 1;
-            hash
-        });
+        self.add_hash(hash);
     }
 
     fn count(&self) -> usize {
         InexactHashingLineCounter::count(self)
     }
 
+    fn estimated_memory(&self) -> usize {
+        InexactHashingLineCounter::estimated_memory(self)
+    }
+
     fn reset(&mut self) {
         InexactHashingLineCounter::reset(self)
     }
 }
 
+impl ContainsLine for InexactHashingLineCounter<()> {
+    fn contains_line(&mut self, line: &[u8]) -> bool {
+        let hash = self.random_state.hash_one(line);
+        self.map.find(hash, |found_hash| *found_hash == hash).is_some()
+    }
+}
+
+impl<M> ContainsLine for InexactHashingLineCounter<M>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    fn contains_line(&mut self, line: &[u8]) -> bool {
+        let line = (self.line_mapper)(line, &mut self.string_buffer);
+        let hash = self.random_state.hash_one(line);
+        self.map.find(hash, |found_hash| *found_hash == hash).is_some()
+    }
+}
 
 impl<M> CountUnique for InexactHashingLineCounter<M>
 where
@@ -130,18 +238,75 @@ where
     fn count_line(&mut self, line: &[u8]) {
         let line = (self.line_mapper)(line, &mut self.string_buffer);
         let hash = self.random_state.hash_one(line);
-        let entry = self.map.entry(hash, |found_hash| *found_hash == hash, |rehash| *rehash);
-        entry.or_insert_with(|| {
-            self.count += 1;
-            hash
-        });
+        self.add_hash(hash);
     }
 
     fn count(&self) -> usize {
         InexactHashingLineCounter::count(self)
     }
 
+    fn estimated_memory(&self) -> usize {
+        InexactHashingLineCounter::estimated_memory(self)
+    }
+
     fn reset(&mut self) {
         InexactHashingLineCounter::reset(self)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_add_hash_matches_count_line() {
+        let lines: Vec<String> = (0..10_000).map(|i| format!("line {}", i % 7_000)).collect();
+        let mut counted = InexactHashingLineCounter::new();
+        for line in &lines {
+            counted.count_line(line.as_bytes());
+        }
+
+        // hashed with the same seed as `counted`
+        let mut added = InexactHashingLineCounter::new();
+        for line in &lines {
+            added.add_hash(counted.random_state.hash_one(line.as_bytes()));
+        }
+        assert_eq!(added.count(), 7_000);
+        assert_eq!(added.count(), counted.count());
+
+        // adding the hashes of lines that were already counted changes nothing
+        for line in &lines {
+            counted.add_hash(counted.random_state.hash_one(line.as_bytes()));
+        }
+        assert_eq!(counted.count(), 7_000);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut first = InexactHashingLineCounter::new();
+        first.count_unique_in_bytes(b"a\nb\nc\n");
+        let mut second = InexactHashingLineCounter::with_seed(&first.seed());
+        second.count_unique_in_bytes(b"b\nc\nd\ne\n");
+        first.merge(&second);
+        assert_eq!(first.count(), 5);
+        assert_eq!(second.count(), 4);
+        // merging the same hashes again changes nothing
+        first.merge(&second);
+        assert_eq!(first.count(), 5);
+        assert!(first.contains_line(b"e"));
+    }
+
+    #[test]
+    fn test_into_hash_set() {
+        let mut near_exact = InexactHashingLineCounter::new();
+        near_exact.count_unique_in_bytes(b"a\nb\nc\n");
+        let mut exact = crate::HashingLineCounter::<(), ()>::new();
+        exact.count_unique_in_bytes(b"c\nd\nd\na long line that does not fit inline in a compact key\n");
+
+        let converted = exact.into_hash_set(&near_exact.seed());
+        assert_eq!(converted.count(), 3);
+        near_exact.merge(&converted);
+        assert_eq!(near_exact.count(), 5);
+        assert!(near_exact.contains_line(b"a long line that does not fit inline in a compact key"));
+    }
+}
@@ -0,0 +1,153 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+use std::mem::size_of;
+
+/// Number of entries in each set of the cache
+const WAYS: usize = 4;
+
+/// Most entries a cache can have. Past this the cache is far larger than any CPU cache, so checking
+/// it is no faster than checking the map.
+pub(crate) const MAX_SLOTS: usize = 1 << 20;
+
+/// A small set-associative cache of recently counted lines, checked before the map in
+/// [`HashingLineCounter::with_hot_cache`](crate::HashingLineCounter::with_hot_cache). Each line is
+/// cached with its hash, and a hit compares the full line, so a hash collision can't cause a miss
+/// to be reported as a hit.
+pub(crate) struct HotCache {
+    /// hash of each entry. These are kept apart from the lines so a set's hashes share a cache line.
+    hashes: Vec<u64>,
+    /// line of each entry, or `None` if the entry is empty. Buffers are reused between lines, so
+    /// replacing an entry rarely allocates.
+    lines: Vec<Option<Vec<u8>>>,
+    /// index within each set of the next entry to replace
+    next: Vec<u8>,
+    /// total capacity of the line buffers
+    line_memory: usize,
+    set_mask: usize,
+}
+
+impl HotCache {
+    /// Create a cache of at least `slots` entries, rounded up to a power of two, and at most
+    /// [`MAX_SLOTS`] entries
+    pub(crate) fn new(slots: usize) -> Self {
+        // capped first, so neither rounding up can overflow
+        let slots = slots.min(MAX_SLOTS);
+        let sets = ((slots + WAYS - 1) / WAYS).max(1).next_power_of_two();
+        HotCache {
+            hashes: vec![0; sets * WAYS],
+            lines: (0..sets * WAYS).map(|_| None).collect(),
+            next: vec![0; sets],
+            line_memory: 0,
+            set_mask: sets - 1,
+        }
+    }
+
+    #[inline(always)]
+    fn set_index(&self, hash: u64) -> usize {
+        // the low bits of the hash pick the bucket in the map, so use the high bits here
+        (hash >> 32) as usize & self.set_mask
+    }
+
+    /// Whether `line`, which hashes to `hash`, is in the cache
+    #[inline(always)]
+    pub(crate) fn contains(&self, hash: u64, line: &[u8]) -> bool {
+        let start = self.set_index(hash) * WAYS;
+        (start..start + WAYS).any(|index| self.hashes[index] == hash && self.lines[index].as_deref() == Some(line))
+    }
+
+    /// Add `line`, which hashes to `hash`, evicting the least recently added line in its set
+    #[inline(always)]
+    pub(crate) fn insert(&mut self, hash: u64, line: &[u8]) {
+        let set_index = self.set_index(hash);
+        let way = self.next[set_index];
+        self.next[set_index] = (way + 1) % WAYS as u8;
+        let index = set_index * WAYS + way as usize;
+        self.hashes[index] = hash;
+        let buffer = self.lines[index].get_or_insert_with(Vec::new);
+        let capacity = buffer.capacity();
+        buffer.clear();
+        buffer.extend_from_slice(line);
+        self.line_memory += buffer.capacity() - capacity;
+    }
+
+    /// Heap memory allocated by the cache, in bytes
+    pub(crate) fn heap_memory(&self) -> usize {
+        self.hashes.capacity() * size_of::<u64>()
+            + self.lines.capacity() * size_of::<Option<Vec<u8>>>()
+            + self.next.capacity()
+            + self.line_memory
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.line_memory = 0;
+        self.lines.iter_mut().for_each(|line| *line = None);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CountUnique, EmitLines, LineCounter};
+
+    use super::*;
+
+    #[test]
+    fn test_size() {
+        assert_eq!(HotCache::new(0).hashes.len(), 4);
+        assert_eq!(HotCache::new(1024).hashes.len(), 1024);
+        assert_eq!(HotCache::new(1025).hashes.len(), 2048);
+        assert_eq!(HotCache::new(MAX_SLOTS).hashes.len(), MAX_SLOTS);
+        assert_eq!(HotCache::new(MAX_SLOTS + 1).hashes.len(), MAX_SLOTS);
+        assert_eq!(HotCache::new(usize::MAX).hashes.len(), MAX_SLOTS);
+    }
+
+    #[test]
+    fn test_contains() {
+        let mut cache = HotCache::new(16);
+        assert!(!cache.contains(0, b""));
+        cache.insert(0, b"");
+        assert!(cache.contains(0, b""));
+        cache.insert(1 << 32, b"a");
+        assert!(cache.contains(1 << 32, b"a"));
+        // same hash, different line
+        assert!(!cache.contains(1 << 32, b"b"));
+        cache.clear();
+        assert!(!cache.contains(0, b""));
+        assert!(!cache.contains(1 << 32, b"a"));
+    }
+
+    #[test]
+    fn test_eviction() {
+        let mut cache = HotCache::new(4);
+        for line in 0..5u8 {
+            cache.insert(u64::from(line), &[line]);
+        }
+        // only one set, so the first line was evicted
+        assert!(!cache.contains(0, &[0]));
+        for line in 1..5u8 {
+            assert!(cache.contains(u64::from(line), &[line]));
+        }
+    }
+
+    /// A cached counter finds the same lines as an uncached one, with clustered and spread-out
+    /// repeats, and after a reset
+    #[test]
+    fn test_counter_matches_uncached() {
+        let input: Vec<u8> = (0..50_000u32)
+            .flat_map(|i| format!("{}\n{}\n", i / 7, (i * 7919) % 3001).into_bytes())
+            .collect();
+        let mut uncached = LineCounter::new();
+        uncached.count_unique_in_bytes(&input);
+        let mut expected = uncached.into_vec();
+        expected.sort_unstable();
+
+        let mut cached = LineCounter::with_line_mapper(|line, _buffer| line).with_hot_cache(8);
+        cached.count_unique_in_bytes(b"stale\n");
+        cached.reset();
+        cached.count_unique_in_bytes(&input);
+        assert_eq!(cached.count(), expected.len());
+        let mut lines = cached.into_vec();
+        lines.sort_unstable();
+        assert_eq!(lines, expected);
+    }
+}
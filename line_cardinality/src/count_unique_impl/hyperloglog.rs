@@ -4,27 +4,68 @@
 use std::f64::consts::E;
 #[cfg(not(feature = "ahash"))]
 use std::hash::BuildHasher;
+use std::mem::size_of;
 
-use crate::{CountUnique, Error};
+use crate::{CountUnique, Error, LineMapper};
 
 use super::{init_hasher_state, RandomState};
 
 type Hash = u64;
 
-const DEFAULT_SIZE: usize = 65536;
-static DEFAULT_SIZE_ERROR_MESSAGE: &str = "expected DEFAULT_SIZE to be a valid size";
+/// Largest value a register may hold. Registers are clamped to the range of a 6-bit value, which is
+/// enough to record the leftmost 1 bit of any 64-bit hash.
+const MAX_REGISTER_VALUE: u8 = 63;
+
+/// Number of bytes used to store each register
+const REGISTER_BYTES: usize = std::mem::size_of::<u8>();
+
+/// How many registers hold each value from 0 to [`MAX_REGISTER_VALUE`]
+type Histogram = [usize; MAX_REGISTER_VALUE as usize + 1];
+
+/// The histogram of `size` registers that are all 0
+fn empty_histogram(size: usize) -> Histogram {
+    let mut histogram = [0; MAX_REGISTER_VALUE as usize + 1];
+    histogram[0] = size;
+    histogram
+}
+
+/// Returns the largest [`HyperLogLog`] size (register count) whose registers fit within `bytes`
+/// of memory.
+///
+/// The result is always a power of 2, or 0 if not even a single register fits. Note that sizes
+/// below 16 are rejected by the [`HyperLogLog`] constructors, so budgets under 16 bytes do not
+/// yield a usable size.
+///
+/// ```rust
+/// use line_cardinality::hll_size_for_memory;
+///
+/// assert_eq!(hll_size_for_memory(1024 * 1024), 1048576);
+/// assert_eq!(hll_size_for_memory(100_000), 65536);
+/// ```
+pub fn hll_size_for_memory(bytes: usize) -> usize {
+    let registers = bytes / REGISTER_BYTES;
+    if registers == 0 {
+        0
+    } else {
+        1usize << registers.ilog2()
+    }
+}
 
 /// Estimates the unique count and holds necessary state.
 ///
 /// The estimate is performed using [HyperLogLog](https://en.wikipedia.org/wiki/HyperLogLog), a
-/// state-of-the art cardinality approximation algorithm. This uses constant memory.
+/// state-of-the art cardinality approximation algorithm. This uses constant memory. Each register is
+/// stored in one byte, but never holds a value greater than 63.
 ///
 /// This implementation also has accepts a customizable `line_mapper` function with
 /// [`HyperLogLog::with_line_mapper`]. If provided, this function will be applied to each
 /// line before checking if it is unique or not. Note that this also affects the output that will be
 /// seen from functions that enumerate internal state, such as
 /// [`EmitLines::for_each_line`](crate::EmitLines::for_each_line).
-pub struct HyperLogLog<M> {
+pub struct HyperLogLog<M>
+where
+    M: LineMapper,
+{
     random_state: RandomState,
     size: usize,
     /// number of bits in the left part == log2(size)
@@ -34,7 +75,11 @@ pub struct HyperLogLog<M> {
     /// mask used to isolate the right side
     mask: Hash,
     counters: Vec<u8>,
-    string_buffer: Vec<u8>,
+    /// kept up to date as registers change, so that the estimate can be computed from it in
+    /// constant time instead of from every register
+    histogram: Histogram,
+    /// scratch space for `line_mapper`, which is `()` when there is no mapper
+    string_buffer: M::Buffer,
     line_mapper: M,
 }
 
@@ -44,17 +89,26 @@ fn check_size(size: usize) -> Result<SizeInfo, Error> {
     } else if size < 16 {
         Err(Error::hyper_log_log(format!("HyperLogLog size must be at least 16, but was {}", size), size))
     } else {
-        let bits = size.ilog2();
-        let shift_bits: u32 = Hash::BITS - bits;
-        let mask: Hash = 0xFFFFFFFFFFFFFFFF >> bits;
-        Ok(SizeInfo {
-            bits,
-            shift_bits,
-            mask,
-        })
+        Ok(size_info(size))
+    }
+}
+
+/// The [`SizeInfo`] of a size that is already known to be valid
+const fn size_info(size: usize) -> SizeInfo {
+    let bits = size.ilog2();
+    let shift_bits: u32 = Hash::BITS - bits;
+    let mask: Hash = 0xFFFFFFFFFFFFFFFF >> bits;
+    SizeInfo {
+        bits,
+        shift_bits,
+        mask,
     }
 }
 
+// checked at compile time, so that the default constructors can't fail
+const _: () = assert!(HyperLogLog::<()>::DEFAULT_SIZE.is_power_of_two() && HyperLogLog::<()>::DEFAULT_SIZE >= 16);
+const DEFAULT_SIZE_INFO: SizeInfo = size_info(HyperLogLog::<()>::DEFAULT_SIZE);
+
 struct SizeInfo {
     bits: u32,
     shift_bits: u32,
@@ -69,24 +123,19 @@ impl Default for HyperLogLog<()> {
 
 /// Constructors that do not take a custom line mapper
 impl HyperLogLog<()> {
-    /// Creates a new [`HyperLogLog`] with 65536 bytes of memory used to store state.
+    /// The size (register count) used by [`HyperLogLog::new`] and [`HyperLogLog::with_line_mapper`],
+    /// which takes 64 KiB of memory.
+    pub const DEFAULT_SIZE: usize = 65536;
+
+    /// Creates a new [`HyperLogLog`] with [`HyperLogLog::DEFAULT_SIZE`] bytes of memory used to
+    /// store state.
     pub fn new() -> Self {
-        Self::with_capacity(DEFAULT_SIZE).expect(DEFAULT_SIZE_ERROR_MESSAGE)
+        Self::from_size_info(Self::DEFAULT_SIZE, DEFAULT_SIZE_INFO, ())
     }
 
     /// Creates a new [`HyperLogLog`] with `size` bytes of memory used to store state.
     pub fn with_capacity(size: usize) -> Result<Self, Error> {
-        let SizeInfo { bits, shift_bits, mask } = check_size(size)?;
-        Ok(HyperLogLog {
-            random_state: init_hasher_state(),
-            size,
-            bits,
-            shift_bits,
-            mask,
-            counters: vec![0; size],
-            string_buffer: Vec::new(),
-            line_mapper: (),
-        })
+        Ok(Self::from_size_info(size, check_size(size)?, ()))
     }
 }
 
@@ -95,30 +144,80 @@ impl<M> HyperLogLog<M>
 where
     M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
 {
-    /// Creates a new [`HyperLogLog`] with 65536 bytes of memory used to store state and a custom
-    /// `line_mapper` function which will be applied to each read line before counting.
+    /// Creates a new [`HyperLogLog`] with [`HyperLogLog::DEFAULT_SIZE`] bytes of memory used to
+    /// store state and a custom `line_mapper` function which will be applied to each read line
+    /// before counting.
     pub fn with_line_mapper(line_mapper: M) -> Self {
-        Self::with_line_mapper_and_capacity(line_mapper, DEFAULT_SIZE).expect(DEFAULT_SIZE_ERROR_MESSAGE)
+        Self::from_size_info(HyperLogLog::DEFAULT_SIZE, DEFAULT_SIZE_INFO, line_mapper)
     }
 
     /// Creates a new [`HyperLogLog`] with `size` bytes of memory used to store state and a custom
     /// `line_mapper` function which will be applied to each read line before counting.
     pub fn with_line_mapper_and_capacity(line_mapper: M, size: usize) -> Result<Self, Error> {
-        let SizeInfo { bits, shift_bits, mask } = check_size(size)?;
-        Ok(HyperLogLog {
+        Ok(Self::from_size_info(size, check_size(size)?, line_mapper))
+    }
+}
+
+impl<M> HyperLogLog<M>
+where
+    M: LineMapper,
+{
+    /// `size_info` must be the [`SizeInfo`] of `size`
+    fn from_size_info(size: usize, size_info: SizeInfo, line_mapper: M) -> Self {
+        let SizeInfo { bits, shift_bits, mask } = size_info;
+        HyperLogLog {
             random_state: init_hasher_state(),
             size,
             bits,
             shift_bits,
             mask,
             counters: vec![0; size],
-            string_buffer: Vec::new(),
+            histogram: empty_histogram(size),
+            string_buffer: M::Buffer::default(),
             line_mapper,
-        })
+        }
     }
 }
 
-impl<M> HyperLogLog<M> {
+impl<M> HyperLogLog<M>
+where
+    M: LineMapper,
+{
+    /// Relative standard error of the estimate, which depends only on the size. For example, with
+    /// the default size of 65536 the estimate is typically within about 0.4% of the true count.
+    ///
+    /// ```rust
+    /// use line_cardinality::HyperLogLog;
+    ///
+    /// assert_eq!(HyperLogLog::new().standard_error(), 1.04 / 256.0);
+    /// ```
+    pub fn standard_error(&self) -> f64 {
+        1.04 / (self.size as f64).sqrt()
+    }
+
+    /// The number of registers, as given to the constructor. Each register takes one byte.
+    ///
+    /// ```rust
+    /// use line_cardinality::HyperLogLog;
+    ///
+    /// assert_eq!(HyperLogLog::new().size(), HyperLogLog::DEFAULT_SIZE);
+    /// assert_eq!(HyperLogLog::with_capacity(1024).unwrap().size(), 1024);
+    /// ```
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The number of hash bits used to pick a register, which is log2 of [`HyperLogLog::size`]
+    ///
+    /// ```rust
+    /// use line_cardinality::HyperLogLog;
+    ///
+    /// assert_eq!(HyperLogLog::new().bits(), 16);
+    /// ```
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
     /// get the first b bits where b == log2(SIZE) == bits()
     #[inline(always)]
     fn left_bits(&self, hash: Hash) -> usize {
@@ -131,6 +230,20 @@ impl<M> HyperLogLog<M> {
         hash & self.mask
     }
 
+    /// Update the register selected by the [`Self::left_bits`] of `hash`
+    #[inline(always)]
+    fn update_register(&mut self, hash: Hash) {
+        let index = self.left_bits(hash);
+        let value = register_value(self.right_bits(hash), self.bits);
+        let counter = &mut self.counters[index];
+        // registers stop changing once most of them are large, so this is rarely taken on long inputs
+        if value > *counter {
+            self.histogram[*counter as usize] -= 1;
+            self.histogram[value as usize] += 1;
+            *counter = value;
+        }
+    }
+
     /// Lifted straight from wikipedia
     /// https://en.wikipedia.org/wiki/HyperLogLog#Practical_considerations
     fn magic_bias_constant(&self) -> f64 {
@@ -142,20 +255,24 @@ impl<M> HyperLogLog<M> {
         }
     }
 
+    /// Computes the estimate from the histogram of register values, so this takes constant time
+    /// regardless of the size
     #[inline(always)]
     fn count(&self) -> usize {
-        let sum: f64 = self.counters.iter()
-            .map(|value| 2f64.powf(-(*value as f64)))
-            .sum();
+        // each register contributes 2^-value. Halving is exact, so this is the same as powi but faster.
+        let mut sum = 0.0;
+        let mut power = 1.0;
+        for registers in self.histogram {
+            sum += registers as f64 * power;
+            power *= 0.5;
+        }
         let sum = 1.0 / sum;
         let size_float = self.size as f64;
         let count: f64 = self.magic_bias_constant() * size_float * size_float * sum;
 
         if count < size_float * 5.0 / 2.0 {
             // fall back to linear counting if cardinality estimate is below some threshold
-            let zeroed_counters = self.counters.iter()
-                .filter(|value| **value == 0)
-                .count();
+            let zeroed_counters = self.histogram[0];
             if zeroed_counters == 0 {
                 (count + 0.5) as usize // `as usize` truncates, so by adding 0.5 we achieve round-nearest behavior
             } else {
@@ -169,29 +286,43 @@ impl<M> HyperLogLog<M> {
         //let count = (-2f64).powf(32f64) * f64::log2(1.0 - (count / 2f64.powf(32f64)));
     }
 
+    /// See [`CountUnique::estimated_memory`]. This is fixed by the size, apart from the line
+    /// mapper's buffer.
+    fn estimated_memory(&self) -> usize {
+        size_of::<Self>() + self.counters.capacity() + M::buffer_memory(&self.string_buffer)
+    }
+
     #[inline(always)]
     fn reset(&mut self) {
         self.counters.fill(0);
+        self.histogram = empty_histogram(self.size);
     }
 }
 
+/// Compute the register value for the [`HyperLogLog::right_bits`] of a hash, where `bits` is the
+/// number of bits used for the register index. This is the position of the leftmost 1 bit after the
+/// index bits (hence the +1), clamped to [`MAX_REGISTER_VALUE`].
+#[inline(always)]
+fn register_value(right_bits: Hash, bits: u32) -> u8 {
+    let position = right_bits.leading_zeros() + 1 - bits;
+    // a narrower hash or a larger register count could otherwise exceed the documented maximum
+    u32::min(position, MAX_REGISTER_VALUE as u32) as u8
+}
+
 impl CountUnique for HyperLogLog<()> {
     fn count_line(&mut self, line: &[u8]) {
         let hash: Hash = self.random_state.hash_one(line);
-        let index = self.left_bits(hash);
-
-        // This is actually the position of the leftmost 1, which is why there's a +1 in there.
-        // Since we're counting bits in a u64 this is guaranteed to fit in a u8.
-        let zero_count = (self.right_bits(hash).leading_zeros() + 1 - self.bits) as u8;
-
-        let counter = &mut self.counters[index];
-        *counter = u8::max(*counter, zero_count);
+        self.update_register(hash);
     }
 
     fn count(&self) -> usize {
         HyperLogLog::count(self)
     }
 
+    fn estimated_memory(&self) -> usize {
+        HyperLogLog::estimated_memory(self)
+    }
+
     fn reset(&mut self) {
         HyperLogLog::reset(self);
     }
@@ -205,20 +336,17 @@ where
         let line = (self.line_mapper)(line, &mut self.string_buffer);
 
         let hash: Hash = self.random_state.hash_one(line);
-        let index = self.left_bits(hash);
-
-        // This is actually the position of the leftmost 1, which is why there's a +1 in there.
-        // Since we're counting bits in a u64 this is guaranteed to fit in a u8.
-        let zero_count = (self.right_bits(hash).leading_zeros() + 1 - self.bits) as u8;
-
-        let counter = &mut self.counters[index];
-        *counter = u8::max(*counter, zero_count);
+        self.update_register(hash);
     }
 
     fn count(&self) -> usize {
         HyperLogLog::count(self)
     }
 
+    fn estimated_memory(&self) -> usize {
+        HyperLogLog::estimated_memory(self)
+    }
+
     fn reset(&mut self) {
         HyperLogLog::reset(self);
     }
@@ -246,6 +374,38 @@ mod test {
         assert_eq!(check_size(256).unwrap().mask, 0x00FFFFFFFFFFFFFF);
     }
 
+    #[test]
+    fn test_hll_size_for_memory() {
+        assert_eq!(hll_size_for_memory(0), 0);
+        assert_eq!(hll_size_for_memory(1), 1);
+        assert_eq!(hll_size_for_memory(15), 8);
+        assert_eq!(hll_size_for_memory(16), 16);
+        assert_eq!(hll_size_for_memory(HyperLogLog::DEFAULT_SIZE), HyperLogLog::DEFAULT_SIZE);
+        assert_eq!(hll_size_for_memory(HyperLogLog::DEFAULT_SIZE + 1), HyperLogLog::DEFAULT_SIZE);
+        assert_eq!(hll_size_for_memory(1024 * 1024), 1048576);
+        assert_eq!(hll_size_for_memory(usize::MAX), 1usize.rotate_right(1));
+        assert!(check_size(hll_size_for_memory(1024 * 1024 - 1)).is_ok());
+    }
+
+    /// The default constructors skip [`check_size`], so they must agree with it
+    #[test]
+    fn test_default_size_info() {
+        let checked = check_size(HyperLogLog::DEFAULT_SIZE).unwrap();
+        assert_eq!((DEFAULT_SIZE_INFO.bits, DEFAULT_SIZE_INFO.shift_bits, DEFAULT_SIZE_INFO.mask), (checked.bits, checked.shift_bits, checked.mask));
+        let hll = HyperLogLog::new();
+        assert_eq!((hll.bits, hll.shift_bits, hll.mask), (checked.bits, checked.shift_bits, checked.mask));
+    }
+
+    #[test]
+    fn test_size_and_bits() {
+        for bits in 4..=20 {
+            let hll = HyperLogLog::with_line_mapper_and_capacity(|line: &[u8], _buffer: &mut Vec<u8>| line, 1 << bits).unwrap();
+            assert_eq!(hll.size(), 1 << bits);
+            assert_eq!(hll.bits(), bits);
+        }
+        assert_eq!(HyperLogLog::with_line_mapper(|line: &[u8], _buffer: &mut Vec<u8>| line).size(), HyperLogLog::DEFAULT_SIZE);
+    }
+
     #[test]
     fn test_left_bits() {
         assert_eq!(HyperLogLog::with_capacity(16).unwrap().left_bits(0x5FFFFFFFFFFFFFFF), 0x05);
@@ -257,4 +417,68 @@ mod test {
         assert_eq!(HyperLogLog::with_capacity(16).unwrap().right_bits(0xF876543210EDCBA9), 0x0876543210EDCBA9);
         assert_eq!(HyperLogLog::with_capacity(256).unwrap().right_bits(0xFF76543210EDCBA9), 0x0076543210EDCBA9);
     }
+
+    #[test]
+    fn test_register_value() {
+        // leftmost 1 directly after the 4 index bits
+        assert_eq!(register_value(0x0800000000000000, 4), 1);
+        assert_eq!(register_value(0x0000000000000001, 4), 60);
+        // all right bits zero: the largest value a 64-bit hash can produce
+        assert_eq!(register_value(0, 4), 61);
+        // a narrower index leaves more right bits than the clamp allows
+        assert_eq!(register_value(0, 1), MAX_REGISTER_VALUE);
+        assert_eq!(register_value(0, 0), MAX_REGISTER_VALUE);
+    }
+
+    #[test]
+    fn test_update_register_with_zero_right_bits() {
+        let mut hll = HyperLogLog::with_capacity(16).unwrap();
+        hll.update_register(0x5000000000000000);
+        assert_eq!(hll.counters[5], 61);
+        // a smaller value never lowers the register
+        hll.update_register(0x5800000000000000);
+        assert_eq!(hll.counters[5], 61);
+        assert!(hll.counters.iter().enumerate().all(|(index, value)| index == 5 || *value == 0));
+
+        let mut hll = HyperLogLog::with_capacity(1 << 20).unwrap();
+        hll.update_register(0xFFFFF00000000000);
+        assert_eq!(hll.counters[0xFFFFF], 45);
+    }
+
+    /// The estimate from the histogram, computed from every register instead
+    fn count_from_registers(hll: &HyperLogLog<()>) -> usize {
+        let mut histogram = [0; MAX_REGISTER_VALUE as usize + 1];
+        for value in &hll.counters {
+            histogram[*value as usize] += 1;
+        }
+        assert_eq!(histogram, hll.histogram);
+        let sum: f64 = hll.counters.iter().map(|value| 2f64.powf(-(*value as f64))).sum();
+        let size = hll.size as f64;
+        let count = hll.magic_bias_constant() * size * size / sum;
+        let zeroed = hll.counters.iter().filter(|value| **value == 0).count();
+        if count < size * 5.0 / 2.0 && zeroed != 0 {
+            (size * f64::ln(size / zeroed as f64) + 0.5) as usize
+        } else {
+            (count + 0.5) as usize
+        }
+    }
+
+    #[test]
+    fn test_histogram_matches_registers() {
+        let mut hll = HyperLogLog::with_capacity(256).unwrap();
+        assert_eq!(hll.count(), 0);
+        // both below and above the linear counting threshold
+        for lines in [10, 100, 1000, 100_000] {
+            hll.reset();
+            for line in 0..lines {
+                hll.count_line(format!("{line}").as_bytes());
+            }
+            let count = hll.count();
+            assert!(count.abs_diff(count_from_registers(&hll)) <= 1, "{lines} lines");
+            assert!(count.abs_diff(lines) < lines / 5 + 2, "{lines} lines estimated as {count}");
+        }
+        hll.reset();
+        assert_eq!(hll.histogram, empty_histogram(256));
+        assert_eq!(hll.count(), 0);
+    }
 }
@@ -0,0 +1,231 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+use std::borrow::Cow;
+use std::mem::size_of;
+
+use hashbrown::HashMap;
+
+use crate::{ContainsLine, CountUnique, EmitLines, Increment};
+
+use super::{hash_table_memory, map_with_capacity_hint, RandomState};
+
+/// The first line seen with a key, and how many lines had that key
+struct Entry<C> {
+    /// `None` if the line is identical to its key, which avoids storing it twice
+    line: Option<Box<[u8]>>,
+    count: C,
+}
+
+/// Counts occurrences of each distinct key, where a line's key is computed by a `key_fn`, and
+/// remembers the first line seen with each key.
+///
+/// A line mapper such as the one given to
+/// [`HashingLineCounter::with_line_mapper`](crate::HashingLineCounter::with_line_mapper) replaces
+/// the line, so the mapped form is both what is compared and what is reported. Here the key is only
+/// used for hashing and equality, so reports show lines as they appeared in the input. For example,
+/// a key can be the lowercase form of a line, or just one of its fields.
+///
+/// `key_fn` returns a [`Cow`], which can borrow from the line it is given. A borrowed key costs
+/// nothing to look up, while an owned key is an allocation for every line counted. Either way the key
+/// is only copied into the counter the first time it is seen, along with the line if it differs
+/// from its key. `key_fn` can't keep a buffer between calls, so a key that can't be borrowed from
+/// the line is best computed into a new [`Vec`] sized for it.
+///
+/// ```rust
+/// use std::borrow::Cow;
+/// use line_cardinality::{CountUnique, KeyedLineCounter};
+///
+/// // the key is the first field, so lines are distinct by user
+/// let mut counter = KeyedLineCounter::<u64, _>::with_key_fn(|line| {
+///     Cow::Borrowed(line.split(|byte| *byte == b' ').next().unwrap_or(line))
+/// });
+/// counter.count_unique_in_bytes(b"alice login\nbob login\nalice logout\n");
+///
+/// assert_eq!(counter.count(), 2);
+/// assert_eq!(counter.get(b"alice"), Some(2));
+/// let mut report: Vec<(&[u8], u64)> = counter.iter().collect();
+/// report.sort_unstable();
+/// assert_eq!(report, [(b"alice login".as_slice(), 2), (b"bob login", 1)]);
+/// ```
+pub struct KeyedLineCounter<C, K> {
+    map: HashMap<Box<[u8]>, Entry<C>, RandomState>,
+    /// heap memory allocated by keys and stored lines
+    heap_memory: usize,
+    key_fn: K,
+}
+
+impl<C, K> KeyedLineCounter<C, K>
+where
+    C: Increment,
+    K: for<'a> Fn(&'a [u8]) -> Cow<'a, [u8]>,
+{
+    /// Creates a new [`KeyedLineCounter`] that compares lines by the key `key_fn` returns for them.
+    pub fn with_key_fn(key_fn: K) -> Self {
+        Self::with_key_fn_and_capacity(key_fn, 0)
+    }
+
+    /// Creates a new [`KeyedLineCounter`] that compares lines by the key `key_fn` returns for them,
+    /// with a cardinality hint of `capacity`.
+    ///
+    /// Note that it is best to leave `capacity` unset unless you have a near-perfect idea of your
+    /// data's cardinality lower bound, as it is extremely difficult to gain performance by setting
+    /// it, but extremely easy to lose performance.
+    pub fn with_key_fn_and_capacity(key_fn: K, capacity: usize) -> Self {
+        KeyedLineCounter {
+            map: map_with_capacity_hint(capacity),
+            heap_memory: 0,
+            key_fn,
+        }
+    }
+
+    /// Get the occurrence count of lines with the same key as `line`
+    pub fn get(&self, line: &[u8]) -> Option<C> {
+        let key = (self.key_fn)(line);
+        self.map.get(key.as_ref()).map(|entry| entry.count)
+    }
+
+    /// Iterate over the first line seen with each distinct key, and how many lines had that key
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], C)> {
+        self.map.iter()
+            .map(|(key, entry)| (entry.line.as_deref().unwrap_or(key), entry.count))
+    }
+
+    /// Iterate over each distinct key, the first line seen with it, and how many lines had it
+    pub fn iter_keys(&self) -> impl Iterator<Item = (&[u8], &[u8], C)> {
+        self.map.iter()
+            .map(|(key, entry)| (key.as_ref(), entry.line.as_deref().unwrap_or(key), entry.count))
+    }
+}
+
+impl<C, K> CountUnique for KeyedLineCounter<C, K>
+where
+    C: Increment,
+    K: for<'a> Fn(&'a [u8]) -> Cow<'a, [u8]>,
+{
+    #[inline(always)]
+    fn count_line(&mut self, line: &[u8]) {
+        let key = (self.key_fn)(line);
+        match self.map.get_mut(key.as_ref()) {
+            Some(entry) => entry.count.increment(),
+            None => {
+                let stored_line = (key.as_ref() != line).then(|| Box::<[u8]>::from(line));
+                self.heap_memory += key.len() + stored_line.as_ref().map_or(0, |line| line.len());
+                self.map.insert(key.into_owned().into_boxed_slice(), Entry { line: stored_line, count: C::new() });
+            }
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.map.len()
+    }
+
+    /// See [`CountUnique::estimated_memory`]. The map keeps its capacity when cleared, so that is
+    /// still counted after a reset.
+    fn estimated_memory(&self) -> usize {
+        size_of::<Self>()
+            + hash_table_memory::<(Box<[u8]>, Entry<C>)>(self.map.capacity())
+            + self.heap_memory
+    }
+
+    fn reset(&mut self) {
+        self.map.clear();
+        self.heap_memory = 0;
+    }
+}
+
+impl<C, K> ContainsLine for KeyedLineCounter<C, K>
+where
+    C: Increment,
+    K: for<'a> Fn(&'a [u8]) -> Cow<'a, [u8]>,
+{
+    /// Returns `true` if a line with the same key as `line` has been counted
+    fn contains_line(&mut self, line: &[u8]) -> bool {
+        let key = (self.key_fn)(line);
+        self.map.contains_key(key.as_ref())
+    }
+}
+
+/// Emits the first line seen with each distinct key
+impl<C, K> EmitLines for KeyedLineCounter<C, K> {
+    fn for_each_line<F>(&self, mut f: F)
+    where
+        F: FnMut(&[u8]),
+    {
+        self.map.iter()
+            .for_each(|(key, entry)| f(entry.line.as_deref().unwrap_or(key)));
+    }
+
+    fn into_vec(self) -> Vec<Vec<u8>> {
+        self.map.into_iter()
+            .map(|(key, entry)| entry.line.unwrap_or(key).into_vec())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bstr::ByteSlice;
+
+    use super::*;
+
+    fn lowercase(line: &[u8]) -> Cow<'_, [u8]> {
+        if line.iter().any(u8::is_ascii_uppercase) {
+            Cow::Owned(line.to_ascii_lowercase())
+        } else {
+            Cow::Borrowed(line)
+        }
+    }
+
+    fn sorted_report<C: Increment + Ord, K: for<'a> Fn(&'a [u8]) -> Cow<'a, [u8]>>(counter: &KeyedLineCounter<C, K>) -> Vec<(Vec<u8>, C)> {
+        let mut report: Vec<(Vec<u8>, C)> = counter.iter().map(|(line, count)| (line.to_vec(), count)).collect();
+        report.sort_unstable();
+        report
+    }
+
+    /// The first line seen with each key is reported, in its original form
+    #[test]
+    fn test_first_line_is_reported() {
+        let mut counter = KeyedLineCounter::<u32, _>::with_key_fn(lowercase);
+        counter.count_unique_in_bytes(b"Apple\napple\nAPPLE\nbanana\nBanana\n");
+        assert_eq!(counter.count(), 2);
+        assert_eq!(sorted_report(&counter), [(b"Apple".to_vec(), 3), (b"banana".to_vec(), 2)]);
+        assert_eq!(counter.get(b"aPPLE"), Some(3));
+        assert_eq!(counter.get(b"cherry"), None);
+        assert!(counter.contains_line(b"BANANA"));
+
+        let mut keys: Vec<(&[u8], &[u8])> = counter.iter_keys().map(|(key, line, _count)| (key, line)).collect();
+        keys.sort_unstable();
+        assert_eq!(keys, [(b"apple".as_slice(), b"Apple".as_slice()), (b"banana", b"banana")]);
+
+        let mut lines = counter.into_vec();
+        lines.sort_unstable();
+        assert_eq!(lines, [b"Apple".to_vec(), b"banana".to_vec()]);
+    }
+
+    /// The key doesn't have to resemble the line at all
+    #[test]
+    fn test_computed_key() {
+        let mut counter = KeyedLineCounter::<u64, _>::with_key_fn(|line| Cow::Owned(line.len().to_le_bytes().to_vec()));
+        counter.count_unique_in_bytes(b"a\nb\ncd\nef\nghi\n");
+        assert_eq!(sorted_report(&counter), [(b"a".to_vec(), 2), (b"cd".to_vec(), 2), (b"ghi".to_vec(), 1)]);
+    }
+
+    #[test]
+    fn test_memory_and_reset() {
+        let mut counter = KeyedLineCounter::<u64, _>::with_key_fn(|line| Cow::Borrowed(line.trim()));
+        let empty_memory = counter.estimated_memory();
+        let long_line = [b'x'; 1000];
+        counter.count_line(&long_line);
+        // the line is its own key, so it is only stored once
+        assert!(counter.estimated_memory() >= empty_memory + long_line.len());
+        assert!(counter.estimated_memory() < empty_memory + 2 * long_line.len());
+        counter.count_line(b" padded ");
+        assert_eq!(counter.count(), 2);
+        assert_eq!(sorted_report(&counter)[0], (b" padded ".to_vec(), 1));
+
+        counter.reset();
+        assert_eq!(counter.count(), 0);
+        assert!(counter.estimated_memory() < long_line.len());
+    }
+}
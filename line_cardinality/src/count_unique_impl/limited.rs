@@ -0,0 +1,160 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+use std::mem::size_of;
+
+use crate::{ContainsLine, CountUnique};
+
+/// Wraps a [`CountUnique`] to bound how many distinct lines it stores, which bounds its memory use
+/// even if the input is hostile.
+///
+/// Until `max_distinct` distinct lines have been counted, every line is passed to the wrapped
+/// counter. After that only lines the wrapped counter already contains are passed to it, so
+/// occurrence counts of the lines it tracks stay exact. Lines that would have been new are rejected:
+/// they are tallied, and passed to an `overflow` counter instead (typically a small
+/// [`HyperLogLog`](crate::HyperLogLog) using the same line mapper) to estimate how many distinct
+/// lines were not tracked.
+///
+/// ```rust
+/// use line_cardinality::{CountUnique, DistinctLimit, HashingLineCounter, HyperLogLog, ReportUnique};
+///
+/// let counter = HashingLineCounter::<u64, _>::new();
+/// let overflow = HyperLogLog::with_capacity(1024).unwrap();
+/// let mut limited = DistinctLimit::new(counter, 2, overflow);
+/// limited.count_unique_in_bytes(b"a\nb\nc\na\nd\nc");
+///
+/// assert_eq!(limited.count(), 2);
+/// assert_eq!(limited.inner().get(b"a"), Some(2));
+/// assert_eq!(limited.rejected_lines(), 3);
+/// assert_eq!(limited.rejected_distinct_estimate(), 2);
+/// ```
+pub struct DistinctLimit<C, O> {
+    inner: C,
+    max_distinct: usize,
+    rejected_lines: u64,
+    overflow: O,
+}
+
+impl<C, O> DistinctLimit<C, O>
+where
+    C: CountUnique + ContainsLine,
+    O: CountUnique,
+{
+    /// Creates a new [`DistinctLimit`] that stops admitting new lines to `inner` once it contains
+    /// `max_distinct` distinct lines. Rejected lines are counted by `overflow`.
+    pub fn new(inner: C, max_distinct: usize, overflow: O) -> Self {
+        DistinctLimit {
+            inner,
+            max_distinct,
+            rejected_lines: 0,
+            overflow,
+        }
+    }
+
+    /// The wrapped counter
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Consume this [`DistinctLimit`] and return the wrapped counter
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    /// Whether any line has been rejected, meaning the true number of distinct lines is larger than
+    /// [`CountUnique::count`]
+    pub fn limit_exceeded(&self) -> bool {
+        self.rejected_lines != 0
+    }
+
+    /// How many lines were rejected because they were not already tracked when the limit was
+    /// reached. A line that is rejected more than once is counted each time.
+    pub fn rejected_lines(&self) -> u64 {
+        self.rejected_lines
+    }
+
+    /// How many distinct lines were rejected, according to the `overflow` counter. A line that is
+    /// tracked is never rejected, so these are all in addition to [`CountUnique::count`].
+    pub fn rejected_distinct_estimate(&self) -> usize {
+        self.overflow.count()
+    }
+}
+
+impl<C, O> CountUnique for DistinctLimit<C, O>
+where
+    C: CountUnique + ContainsLine,
+    O: CountUnique,
+{
+    #[inline(always)]
+    fn count_line(&mut self, line: &[u8]) {
+        // the containment check is only needed once the limit is reached, so until then there's no
+        // extra cost
+        if self.inner.count() < self.max_distinct || self.inner.contains_line(line) {
+            self.inner.count_line(line);
+        } else {
+            self.rejected_lines += 1;
+            self.overflow.count_line(line);
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.inner.count()
+    }
+
+    /// The memory of both counters, plus this wrapper's own fields. If either counter's memory is
+    /// unknown, only the rest is included.
+    fn estimated_memory(&self) -> usize {
+        size_of::<Self>() - size_of::<C>() - size_of::<O>() + self.inner.estimated_memory() + self.overflow.estimated_memory()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.rejected_lines = 0;
+        self.overflow.reset();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{HashingLineCounter, HyperLogLog, ReportUnique};
+
+    use super::*;
+
+    #[test]
+    fn test_tracked_counts_stay_exact() {
+        let counter = HashingLineCounter::<u64, _>::new();
+        let overflow = HyperLogLog::with_capacity(4096).unwrap();
+        let mut limited = DistinctLimit::new(counter, 3, overflow);
+        // 3 tracked lines that keep recurring, and 1000 distinct lines that arrive after the limit
+        for i in 0..1000 {
+            limited.count_line(b"a");
+            limited.count_line(b"b");
+            limited.count_line(b"c");
+            limited.count_line(format!("rejected {i}").as_bytes());
+        }
+
+        assert_eq!(limited.count(), 3);
+        assert!(limited.limit_exceeded());
+        for line in [b"a", b"b", b"c"] {
+            assert_eq!(limited.inner().get(line), Some(1000));
+        }
+        assert_eq!(limited.inner().get(b"rejected 0"), None);
+        assert_eq!(limited.rejected_lines(), 1000);
+        let estimate = limited.rejected_distinct_estimate();
+        assert!((900..=1100).contains(&estimate), "estimate of 1000 rejected lines was {estimate}");
+
+        limited.reset();
+        assert_eq!(limited.count(), 0);
+        assert!(!limited.limit_exceeded());
+        assert_eq!(limited.rejected_distinct_estimate(), 0);
+    }
+
+    #[test]
+    fn test_under_limit() {
+        let mut limited = DistinctLimit::new(HashingLineCounter::<(), _>::new(), 3, HyperLogLog::with_capacity(16).unwrap());
+        limited.count_unique_in_bytes(b"a\nb\na\nc\nb");
+        assert_eq!(limited.count(), 3);
+        assert!(!limited.limit_exceeded());
+        assert_eq!(limited.rejected_distinct_estimate(), 0);
+    }
+}
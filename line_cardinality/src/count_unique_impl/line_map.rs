@@ -0,0 +1,110 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+use hashbrown::HashMap;
+
+use super::compact_key::CompactKey;
+use super::{map_with_capacity_hint, RandomState};
+
+/// A map from lines to `V`, with the same keys and hasher as
+/// [`HashingLineCounter`](crate::HashingLineCounter). This is for callers that need to store
+/// something other than a count for each line.
+///
+/// On 64-bit platforms each key takes 24 bytes in its entry. Lines longer than 22 bytes also take
+/// their length on the heap.
+pub struct LineMap<V> {
+    map: HashMap<CompactKey, V, RandomState>,
+}
+
+impl<V> LineMap<V> {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Creates an empty map with room for `capacity` lines
+    pub fn with_capacity(capacity: usize) -> Self {
+        LineMap {
+            map: map_with_capacity_hint(capacity),
+        }
+    }
+
+    /// The number of lines in the map
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn contains_line(&self, line: &[u8]) -> bool {
+        self.map.contains_key(line)
+    }
+
+    pub fn get(&self, line: &[u8]) -> Option<&V> {
+        self.map.get(line)
+    }
+
+    pub fn get_mut(&mut self, line: &[u8]) -> Option<&mut V> {
+        self.map.get_mut(line)
+    }
+
+    /// Inserts a value for `line`, returning the previous value if the line was already present.
+    /// The line is only copied if it was not already present.
+    pub fn insert(&mut self, line: &[u8], value: V) -> Option<V> {
+        match self.map.get_mut(line) {
+            Some(existing) => Some(std::mem::replace(existing, value)),
+            None => {
+                self.map.insert(CompactKey::new(line), value);
+                None
+            }
+        }
+    }
+
+    /// Iterates over each line and its value, in arbitrary order
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &V)> {
+        self.map.iter().map(|(line, value)| (line.as_slice(), value))
+    }
+
+    /// Iterates over each value, in arbitrary order
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.map.values_mut()
+    }
+
+    /// Removes every line, keeping the allocated memory
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+}
+
+impl<V> Default for LineMap<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_insert() {
+        let mut map = LineMap::new();
+        assert!(map.is_empty());
+        assert_eq!(map.insert(b"foo", 1), None);
+        assert_eq!(map.insert(b"a line longer than the inline key capacity", 2), None);
+        assert_eq!(map.insert(b"foo", 3), Some(1));
+        assert_eq!(map.len(), 2);
+        assert!(map.contains_line(b"foo"));
+        assert!(!map.contains_line(b"bar"));
+        assert_eq!(map.get(b"a line longer than the inline key capacity"), Some(&2));
+        *map.get_mut(b"foo").unwrap() += 1;
+        let mut entries: Vec<_> = map.iter().collect();
+        entries.sort();
+        assert_eq!(entries, [(&b"a line longer than the inline key capacity"[..], &2), (&b"foo"[..], &4)]);
+        map.values_mut().for_each(|value| *value = 0);
+        assert_eq!(map.get(b"foo"), Some(&0));
+        map.clear();
+        assert!(map.is_empty());
+    }
+}
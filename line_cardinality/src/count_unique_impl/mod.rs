@@ -1,7 +1,13 @@
 // This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
 // line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
 
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use cfg_if::cfg_if;
+use hashbrown::HashMap;
+#[cfg(feature = "hash-only")]
+use hashbrown::HashTable;
 
 cfg_if! {
     if #[cfg(feature = "ahash")] {
@@ -11,19 +17,55 @@ cfg_if! {
     }
 }
 
+pub(crate) mod binary;
+pub(crate) mod borrowed;
+pub(crate) mod channel;
+pub(crate) mod compact_key;
+pub(crate) mod delimited;
+#[cfg(feature = "diagnostics")]
+pub(crate) mod diagnostics;
+pub(crate) mod first_seen;
 pub(crate) mod increment;
 #[cfg(feature = "file")]
 pub(crate) mod file_io;
 pub(crate) mod hashing;
 #[cfg(feature = "hash-only")]
 pub(crate) mod hashing_inexact;
+pub(crate) mod hot_cache;
 pub(crate) mod hyperloglog;
+pub(crate) mod keyed;
+pub(crate) mod limited;
+pub(crate) mod line_map;
 pub(crate) mod result;
+pub(crate) mod sorted;
+pub(crate) mod tokenizing;
+pub(crate) mod two_pass;
+
+/// Set by [`use_random_seeds`]
+static RANDOM_SEEDS: AtomicBool = AtomicBool::new(false);
+
+/// Make every counter created after this call seed its hasher with fresh random seeds from the
+/// operating system, instead of seeds that are the same on every run.
+///
+/// With the `ahash` feature, seeds are normally fixed: either chosen when the crate was compiled,
+/// with `compile-time-rng`, or the same for every build without it. Fixed seeds make near-exact and
+/// estimated counts reproducible from run to run, but anyone who knows the seeds can craft lines
+/// whose hashes collide. On such input an
+/// [`InexactHashingLineCounter`](crate::InexactHashingLineCounter) undercounts, and a
+/// [`HashingLineCounter`](crate::HashingLineCounter) slows down. Random seeds resist this, at the
+/// cost of reproducibility. Without the `ahash` feature, seeds are always random and this has no
+/// effect.
+pub fn use_random_seeds() {
+    RANDOM_SEEDS.store(true, Ordering::Relaxed);
+}
 
 /// Handle getting a hasher for various hasher and RNG feature flag settings.
 pub(crate) fn init_hasher_state() -> RandomState {
     cfg_if! {
         if #[cfg(feature = "ahash")] {
+            if RANDOM_SEEDS.load(Ordering::Relaxed) {
+                return random_hasher_state();
+            }
             cfg_if! {
                 if #[cfg(feature = "compile-time-rng")] {
                     Default::default()
@@ -43,3 +85,292 @@ pub(crate) fn init_hasher_state() -> RandomState {
         }
     }
 }
+
+/// An ahash state with seeds from the operating system's random source. std's `RandomState` is
+/// seeded that way, so its hashes of a few fixed values serve as random seeds without depending on
+/// an RNG crate.
+#[cfg(feature = "ahash")]
+fn random_hasher_state() -> RandomState {
+    use std::hash::{BuildHasher, Hasher};
+
+    let source = std::collections::hash_map::RandomState::new();
+    let seed = |index: u64| {
+        let mut hasher = source.build_hasher();
+        hasher.write_u64(index);
+        hasher.finish()
+    };
+    RandomState::with_seeds(seed(0), seed(1), seed(2), seed(3))
+}
+
+/// Call `f` with each line in newline-delimited bytes, without its `\n`. A final line without a
+/// trailing `\n` is included, but an empty final line is not. Unlike
+/// [`CountUnique::count_unique_in_read`](crate::CountUnique::count_unique_in_read), a `\r` before the
+/// `\n` is kept.
+///
+/// This splits lines the same way with or without the `memchr` feature: memchr only makes finding
+/// each newline faster.
+#[inline(always)]
+pub(crate) fn for_each_line<'a, F: FnMut(&'a [u8])>(bytes: &'a [u8], f: F) {
+    for_each_record(bytes, b'\n', f)
+}
+
+/// Call `f` with each line read from `reader`, splitting lines the same way as
+/// [`CountUnique::count_unique_in_read`](crate::CountUnique::count_unique_in_read): each line ends
+/// at a `\n`, and a `\r` before the `\n` is removed.
+///
+/// `reader` is read straight into a buffer of `buffer_size` bytes, and `f` is given lines within
+/// that buffer. Only a line that is still incomplete at the end of a read is moved, to the start of
+/// the buffer. The buffer doubles in size whenever a single line doesn't fit in it.
+pub(crate) fn for_each_line_in_read<R: Read, F: FnMut(&[u8])>(mut reader: R, buffer_size: usize, mut f: F) -> io::Result<()> {
+    let mut buffer = vec![0; buffer_size.max(1)];
+    // length of the incomplete line at the start of the buffer
+    let mut filled = 0;
+    loop {
+        if filled == buffer.len() {
+            buffer.resize(buffer.len() * 2, 0);
+        }
+        let read = match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        let end = filled + read;
+        // earlier bytes are known not to hold a newline, so only the new bytes are searched
+        match last_newline(&buffer[filled..end]) {
+            Some(index) => {
+                let complete = filled + index + 1;
+                for_each_line(&buffer[..complete], |line| f(line.strip_suffix(b"\r").unwrap_or(line)));
+                buffer.copy_within(complete..end, 0);
+                filled = end - complete;
+            }
+            None => filled = end,
+        }
+    }
+    // a final line without a `\n` keeps any `\r`, as it does with `BufRead`
+    if filled > 0 {
+        f(&buffer[..filled]);
+    }
+    Ok(())
+}
+
+#[inline(always)]
+fn last_newline(bytes: &[u8]) -> Option<usize> {
+    cfg_if! {
+        if #[cfg(feature = "memchr")] {
+            memchr::memrchr(b'\n', bytes)
+        } else {
+            bytes.iter().rposition(|&byte| byte == b'\n')
+        }
+    }
+}
+
+/// Like [`for_each_line`], but records end at each `delimiter` instead of at each `\n`
+#[inline(always)]
+pub(crate) fn for_each_record<'a, F: FnMut(&'a [u8])>(bytes: &'a [u8], delimiter: u8, mut f: F) {
+    let mut start: usize = 0;
+    cfg_if! {
+        if #[cfg(feature = "memchr")] {
+            for delimiter_index in memchr::memchr_iter(delimiter, bytes) {
+                f(&bytes[start..delimiter_index]);
+                start = delimiter_index + 1;
+            }
+        } else {
+            while let Some(offset) = bytes[start..].iter().position(|&byte| byte == delimiter) {
+                f(&bytes[start..start + offset]);
+                start += offset + 1;
+            }
+        }
+    }
+    // handle trailing
+    if start < bytes.len() {
+        f(&bytes[start..]);
+    }
+}
+
+/// An empty map with room for `capacity` entries, if that much can be allocated. Capacities are
+/// only hints, so one too large to allocate is ignored rather than panicking or aborting.
+pub(crate) fn map_with_capacity_hint<K: Eq + std::hash::Hash, V>(capacity: usize) -> HashMap<K, V, RandomState> {
+    let mut map = HashMap::with_hasher(init_hasher_state());
+    let _ = map.try_reserve(capacity);
+    map
+}
+
+/// Like [`map_with_capacity_hint`], for a table of hashes
+#[cfg(feature = "hash-only")]
+pub(crate) fn hash_table_with_capacity_hint(capacity: usize) -> HashTable<u64> {
+    let mut table = HashTable::new();
+    let _ = table.try_reserve(capacity, |hash| *hash);
+    table
+}
+
+/// Number of control bytes hashbrown appends to every table, one SIMD group. This is 16 with SSE2 and
+/// 8 on other platforms: using the larger width means memory is overestimated rather than
+/// underestimated.
+const HASH_TABLE_GROUP_WIDTH: usize = 16;
+
+/// Estimate the heap memory used by a hashbrown table of `T` that reports a `capacity()` of
+/// `capacity`. This works backwards from capacity to the number of buckets, then adds up a `T` and a
+/// control byte for each bucket. It assumes the table has had no entries removed, as removals
+/// reduce the reported capacity until the table is next resized or cleared.
+pub(crate) fn hash_table_memory<T>(capacity: usize) -> usize {
+    // tables of up to 8 buckets always keep one bucket empty, and larger tables keep 1/8 of them
+    // empty
+    let buckets = match capacity {
+        0 => return 0,
+        1..=7 => capacity + 1,
+        _ => capacity / 7 * 8,
+    };
+    let align = std::mem::align_of::<T>().max(HASH_TABLE_GROUP_WIDTH);
+    let entries = (buckets * std::mem::size_of::<T>() + align - 1) / align * align;
+    entries + buckets + HASH_TABLE_GROUP_WIDTH
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{BorrowedLineCounter, CountUnique, EmitLines, LineCounter};
+
+    use super::*;
+
+    /// Each input and the lines it splits into. These are the same with and without the `memchr`
+    /// feature, so running these tests under both (for example with
+    /// `--no-default-features --features file`) checks that both produce the same lines.
+    static EDGE_CASES: &[(&[u8], &[&[u8]])] = &[
+        (b"", &[]),
+        (b"\n", &[b""]),
+        (b"\n\n", &[b"", b""]),
+        (b"a", &[b"a"]),
+        (b"a\n", &[b"a"]),
+        (b"a\n\nb\n\n", &[b"a", b"", b"b", b""]),
+        (b"a\r\na\n", &[b"a\r", b"a"]),
+        (b"a\r\n", &[b"a\r"]),
+        (b"\r", &[b"\r"]),
+        (b"a\0b\na\0b", &[b"a\0b", b"a\0b"]),
+        (b"\0\n\0\n", &[b"\0", b"\0"]),
+    ];
+
+    #[test]
+    fn test_for_each_line() {
+        for (bytes, expected) in EDGE_CASES {
+            let mut lines = Vec::new();
+            for_each_line(bytes, |line| lines.push(line));
+            assert_eq!(&lines, expected, "lines of {bytes:?}");
+        }
+    }
+
+    #[test]
+    fn test_count_unique_in_bytes() {
+        for (bytes, expected) in EDGE_CASES {
+            let mut distinct = expected.to_vec();
+            distinct.sort_unstable();
+            distinct.dedup();
+
+            let mut owned = LineCounter::new();
+            owned.count_unique_in_bytes(bytes);
+            assert_eq!(owned.count(), distinct.len(), "count of {bytes:?}");
+            let mut borrowed = BorrowedLineCounter::<u64>::new();
+            borrowed.count_unique_in_slice(bytes);
+            assert_eq!(borrowed.count(), distinct.len(), "borrowed count of {bytes:?}");
+        }
+    }
+
+    /// Lines are sorted and terminated with the given byte, even lines that contain a newline
+    #[test]
+    fn test_write_sorted_lines() {
+        let mut counter = LineCounter::new();
+        counter.count_unique_in_read_delimited(b"b\0two\nlines\0a\0b".as_slice(), b'\0').unwrap();
+        let mut output = Vec::new();
+        counter.write_sorted_lines(&mut output, b'\0').unwrap();
+        assert_eq!(output, b"a\0b\0two\nlines\0");
+    }
+
+    /// Capacities too large to allocate are ignored, rather than panicking
+    #[test]
+    fn test_capacity_hint_too_large() {
+        for capacity in [usize::MAX, usize::MAX / 2, 1 << 60] {
+            let mut counter = LineCounter::with_capacity(capacity);
+            counter.count_unique_in_bytes(b"a\nb\na\n");
+            assert_eq!(counter.count(), 2, "capacity {capacity}");
+            #[cfg(feature = "hash-only")]
+            {
+                let mut inexact = crate::InexactHashingLineCounter::with_capacity(capacity);
+                inexact.count_unique_in_bytes(b"a\nb\na\n");
+                assert_eq!(inexact.count(), 2, "capacity {capacity}");
+            }
+            let mut borrowed = BorrowedLineCounter::<u64>::with_capacity(capacity);
+            borrowed.count_unique_in_slice(b"a\nb\na\n");
+            assert_eq!(borrowed.count(), 2, "capacity {capacity}");
+            assert!(crate::LineMap::<()>::with_capacity(capacity).is_empty());
+        }
+    }
+
+    /// Reads at most `chunk` bytes at a time, and is interrupted before every other read
+    struct ChunkedReader<'a> {
+        bytes: &'a [u8],
+        chunk: usize,
+        interrupt: bool,
+    }
+
+    impl Read for ChunkedReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+            let len = buf.len().min(self.chunk).min(self.bytes.len());
+            buf[..len].copy_from_slice(&self.bytes[..len]);
+            self.bytes = &self.bytes[len..];
+            Ok(len)
+        }
+    }
+
+    /// Reading unbuffered splits lines exactly like reading a [`BufRead`](std::io::BufRead), whatever
+    /// the buffer size and however the reads are split up
+    #[test]
+    fn test_unbuffered_read_matches_buffered() {
+        use bstr::io::BufReadExt;
+
+        let long_line = [b'x'; 100];
+        let long_lines = [&long_line[..], b"\r\n", &long_line, b"\nshort\r\n", &long_line, b"\r"].concat();
+        let inputs = EDGE_CASES.iter().map(|(bytes, _expected)| *bytes)
+            .chain([b"a\r\nb\r\n\r\n".as_slice(), b"a\rb\n\r\r\n\r", b"\r\n\r", &long_lines]);
+        for bytes in inputs {
+            let mut expected = Vec::new();
+            { bytes }.for_byte_line(|line| {
+                expected.push(line.to_vec());
+                Ok(true)
+            }).unwrap();
+
+            for buffer_size in [0, 1, 2, 3, 64] {
+                for chunk in [1, 2, 7, usize::MAX] {
+                    let reader = ChunkedReader { bytes, chunk, interrupt: false };
+                    let mut lines = Vec::new();
+                    for_each_line_in_read(reader, buffer_size, |line| lines.push(line.to_vec())).unwrap();
+                    assert_eq!(lines, expected, "lines of {bytes:?} with buffer size {buffer_size} and chunk {chunk}");
+                }
+
+                let mut buffered = LineCounter::new();
+                buffered.count_unique_in_read(bytes).unwrap();
+                let mut unbuffered = LineCounter::new();
+                unbuffered.count_unique_in_unbuffered_read(bytes, buffer_size).unwrap();
+                assert_eq!(unbuffered.count(), buffered.count(), "count of {bytes:?} with buffer size {buffer_size}");
+            }
+        }
+    }
+
+    /// Random states hash the same line differently, unlike the fixed states they replace
+    #[cfg(feature = "ahash")]
+    #[test]
+    fn test_random_hasher_state() {
+        use std::hash::{BuildHasher, Hasher};
+
+        let hash = |state: &RandomState| {
+            let mut hasher = state.build_hasher();
+            hasher.write(b"line");
+            hasher.finish()
+        };
+        assert_ne!(hash(&random_hasher_state()), hash(&random_hasher_state()));
+        let state = random_hasher_state();
+        assert_eq!(hash(&state), hash(&state.clone()));
+    }
+}
@@ -22,6 +22,9 @@ pub enum Cause {
     Size(usize),
     /// User-proved error message
     User,
+    /// The input was read, but rejected, such as unsorted input to
+    /// [`SortedLineCounter`](crate::SortedLineCounter)
+    Input,
 }
 
 /// Errors returned by line_cardinality
@@ -64,6 +67,14 @@ impl Error {
         }
     }
 
+    /// Input that was read successfully, but is not acceptable
+    pub fn input(message: String) -> Self {
+        Self {
+            message: Message::Dynamic(message),
+            cause: Cause::Input,
+        }
+    }
+
     pub(crate) fn hyper_log_log(message: String, size: usize) -> Self {
         Self {
             message: Message::Dynamic(message),
@@ -82,6 +93,7 @@ impl StdError for Error {
             Cause::Io(e) => Some(e),
             Cause::Size(_) => None,
             Cause::User => None,
+            Cause::Input => None,
         }
     }
 }
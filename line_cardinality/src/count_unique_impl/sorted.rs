@@ -0,0 +1,217 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io::BufRead;
+
+use bstr::ByteSlice;
+
+use crate::{Error, Result};
+
+/// Counts unique lines in inputs that are each already sorted, using memory proportional to the
+/// number of inputs rather than the cardinality of the data.
+///
+/// Inputs are combined with a k-way merge, so only the current line of each input is held in
+/// memory. Every input must be sorted bytewise (the order produced by `LC_ALL=C sort`) after the
+/// `line_mapper` has been applied. If a line is found that sorts before the line preceding it an
+/// error is returned, as the count would otherwise silently be wrong.
+///
+/// ```rust
+/// use line_cardinality::SortedLineCounter;
+///
+/// let first = b"apple\nbanana\nbanana\ncherry\n";
+/// let second = b"banana\ndate\n";
+///
+/// let mut line_counter = SortedLineCounter::new();
+/// line_counter.count_unique_in_sorted_reads(vec![first.as_slice(), second.as_slice()]).unwrap();
+///
+/// assert_eq!(line_counter.count(), 4);
+/// ```
+pub struct SortedLineCounter<M> {
+    /// the most recently counted line, which every subsequent line must sort after
+    last_line: Option<Vec<u8>>,
+    string_buffer: Vec<u8>,
+    count: usize,
+    line_mapper: M,
+}
+
+/// Line mapper used when no custom line mapper is provided
+type IdentityMapper = for<'a> fn(&'a [u8], &'a mut Vec<u8>) -> &'a [u8];
+
+/// A single input being merged
+struct SortedSource<R> {
+    reader: R,
+    raw_line: Vec<u8>,
+}
+
+impl Default for SortedLineCounter<IdentityMapper> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Constructors that do not take a custom line mapper
+impl SortedLineCounter<IdentityMapper> {
+    /// Creates a new [`SortedLineCounter`].
+    pub fn new() -> Self {
+        Self::with_line_mapper(identity)
+    }
+}
+
+fn identity<'a>(line: &'a [u8], _buffer: &'a mut Vec<u8>) -> &'a [u8] {
+    line
+}
+
+/// Constructors that take a custom line mapper
+impl<M> SortedLineCounter<M>
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    /// Creates a new [`SortedLineCounter`] with a custom `line_mapper` function which will be
+    /// applied to each read line before counting. Inputs must be sorted *after* mapping.
+    pub fn with_line_mapper(line_mapper: M) -> Self {
+        SortedLineCounter {
+            last_line: None,
+            string_buffer: Vec::new(),
+            count: 0,
+            line_mapper,
+        }
+    }
+
+    /// Count unique lines across several newline-delimited [`BufRead`]s, each of which must already
+    /// be sorted.
+    ///
+    /// Lines are counted as if all calls to this function were part of one sorted stream, so a
+    /// subsequent call must only contain lines that sort at or after the last line of the previous
+    /// call.
+    pub fn count_unique_in_sorted_reads<R: BufRead>(&mut self, readers: Vec<R>) -> Result {
+        let mut sources: Vec<SortedSource<R>> = readers.into_iter()
+            .map(|reader| SortedSource { reader, raw_line: Vec::new() })
+            .collect();
+
+        // min-heap of the current line of each source
+        let mut heap: BinaryHeap<Reverse<(Vec<u8>, usize)>> = BinaryHeap::with_capacity(sources.len());
+        for (index, source) in sources.iter_mut().enumerate() {
+            let mut line = Vec::new();
+            if self.read_line(source, &mut line)? {
+                heap.push(Reverse((line, index)));
+            }
+        }
+
+        while let Some(Reverse((line, index))) = heap.pop() {
+            if let Some(last_line) = &self.last_line {
+                match line.as_slice().cmp(last_line.as_slice()) {
+                    std::cmp::Ordering::Less => return Err(not_sorted_error(index, &line, last_line)),
+                    std::cmp::Ordering::Equal => (),
+                    std::cmp::Ordering::Greater => self.count += 1,
+                }
+            } else {
+                self.count += 1;
+            }
+
+            // reuse the allocation of the previous line to read the next line from this source
+            let mut next_line = self.last_line.take().unwrap_or_default();
+            let read = self.read_line(&mut sources[index], &mut next_line);
+            let line = self.last_line.insert(line);
+            if read? {
+                if next_line < *line {
+                    return Err(not_sorted_error(index, &next_line, line));
+                }
+                heap.push(Reverse((next_line, index)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the next mapped line from `source` into `line`. Returns `false` once the source is empty.
+    fn read_line<R: BufRead>(&mut self, source: &mut SortedSource<R>, line: &mut Vec<u8>) -> std::result::Result<bool, Error> {
+        source.raw_line.clear();
+        let bytes_read = source.reader.read_until(b'\n', &mut source.raw_line)
+            .map_err(|e| Error::io_static("failed to read from buffer", e))?;
+        if bytes_read == 0 {
+            return Ok(false);
+        }
+        let mapped = (self.line_mapper)(trim_line_terminator(&source.raw_line), &mut self.string_buffer);
+        line.clear();
+        line.extend_from_slice(mapped);
+        Ok(true)
+    }
+}
+
+impl<M> SortedLineCounter<M> {
+    /// Returns current cardinality count of the [`SortedLineCounter`].
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Resets internal state of this [`SortedLineCounter`] for reuse
+    pub fn reset(&mut self) {
+        self.count = 0;
+        self.last_line = None;
+    }
+}
+
+/// Strip a trailing `\n` or `\r\n`, matching [`CountUnique::count_unique_in_read`](crate::CountUnique::count_unique_in_read)
+fn trim_line_terminator(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+fn not_sorted_error(index: usize, line: &[u8], previous: &[u8]) -> Error {
+    Error::input(format!("input #{} is not sorted: \"{}\" appears after \"{}\"", index + 1, line.as_bstr(), previous.as_bstr()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let readers: Vec<&[u8]> = vec![b"a\nc\nc\ne\n", b"", b"b\nc\nd\ne", b"\n\nf\n"];
+        let mut counter = SortedLineCounter::new();
+        counter.count_unique_in_sorted_reads(readers).unwrap();
+        // "", a, b, c, d, e, f
+        assert_eq!(counter.count(), 7);
+    }
+
+    #[test]
+    fn test_crlf() {
+        let readers: Vec<&[u8]> = vec![b"a\r\nb\r\n", b"a\nb"];
+        let mut counter = SortedLineCounter::new();
+        counter.count_unique_in_sorted_reads(readers).unwrap();
+        assert_eq!(counter.count(), 2);
+    }
+
+    #[test]
+    fn test_multiple_calls() {
+        let mut counter = SortedLineCounter::new();
+        counter.count_unique_in_sorted_reads(vec![b"a\nb\n".as_slice()]).unwrap();
+        counter.count_unique_in_sorted_reads(vec![b"b\nc\n".as_slice()]).unwrap();
+        assert_eq!(counter.count(), 3);
+        assert!(counter.count_unique_in_sorted_reads(vec![b"a\n".as_slice()]).is_err());
+        counter.reset();
+        counter.count_unique_in_sorted_reads(vec![b"a\n".as_slice()]).unwrap();
+        assert_eq!(counter.count(), 1);
+    }
+
+    #[test]
+    fn test_not_sorted() {
+        let readers: Vec<&[u8]> = vec![b"a\nb\n", b"b\na\n"];
+        let error = SortedLineCounter::new().count_unique_in_sorted_reads(readers).err().unwrap();
+        assert_eq!(error.to_string(), "input #2 is not sorted: \"a\" appears after \"b\"");
+        assert!(matches!(error.get_cause(), crate::ErrorCause::Input));
+    }
+
+    #[test]
+    fn test_line_mapper() {
+        let readers: Vec<&[u8]> = vec![b"A\nb\n", b"a\nB\n"];
+        let mut counter = SortedLineCounter::with_line_mapper(|line, buffer| {
+            buffer.clear();
+            line.to_lowercase_into(buffer);
+            buffer
+        });
+        counter.count_unique_in_sorted_reads(readers).unwrap();
+        assert_eq!(counter.count(), 2);
+    }
+}
@@ -0,0 +1,211 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+use std::mem::{size_of, size_of_val};
+
+use crate::CountUnique;
+
+/// The bytes [`TokenizingCounter::new`] splits on: ASCII space, tab, newline, vertical tab, form
+/// feed, and carriage return
+pub const ASCII_WHITESPACE: &[u8] = b" \t\n\x0B\x0C\r";
+
+/// Wraps a [`CountUnique`] to count distinct tokens instead of distinct lines.
+///
+/// Each line is split on runs of delimiter bytes, and each token is passed to the wrapped counter
+/// as if it were a line of its own, so any line mapper the wrapped counter has is applied to each
+/// token. Delimiters at the start or end of a line are ignored, and empty tokens are never counted,
+/// so a line made up only of delimiters contributes nothing.
+///
+/// ```rust
+/// use line_cardinality::{CountUnique, LineCounter, TokenizingCounter};
+///
+/// let mut words = TokenizingCounter::new(LineCounter::new());
+/// words.count_unique_in_bytes(b"to be or\n  not to be\n\n");
+///
+/// assert_eq!(words.count(), 4);
+/// ```
+pub struct TokenizingCounter<C> {
+    inner: C,
+    delimiters: Delimiters,
+}
+
+/// How to find the next delimiter. With the `memchr` feature, up to three delimiters are found with
+/// memchr. Larger sets, including [`ASCII_WHITESPACE`], use a lookup table: tokens are typically
+/// short enough that this is about as fast as several memchr passes would be.
+enum Delimiters {
+    #[cfg(feature = "memchr")]
+    One(u8),
+    #[cfg(feature = "memchr")]
+    Two(u8, u8),
+    #[cfg(feature = "memchr")]
+    Three(u8, u8, u8),
+    Table(Box<[bool; 256]>),
+}
+
+impl Delimiters {
+    fn new(delimiters: &[u8]) -> Self {
+        #[cfg(feature = "memchr")]
+        match *delimiters {
+            [a] => return Delimiters::One(a),
+            [a, b] => return Delimiters::Two(a, b),
+            [a, b, c] => return Delimiters::Three(a, b, c),
+            _ => {}
+        }
+        let mut table = Box::new([false; 256]);
+        for &delimiter in delimiters {
+            table[delimiter as usize] = true;
+        }
+        Delimiters::Table(table)
+    }
+
+    /// The index of the first delimiter in `bytes`
+    #[inline(always)]
+    fn find(&self, bytes: &[u8]) -> Option<usize> {
+        match self {
+            #[cfg(feature = "memchr")]
+            Delimiters::One(a) => memchr::memchr(*a, bytes),
+            #[cfg(feature = "memchr")]
+            Delimiters::Two(a, b) => memchr::memchr2(*a, *b, bytes),
+            #[cfg(feature = "memchr")]
+            Delimiters::Three(a, b, c) => memchr::memchr3(*a, *b, *c, bytes),
+            Delimiters::Table(table) => bytes.iter().position(|&byte| table[byte as usize]),
+        }
+    }
+
+    fn memory(&self) -> usize {
+        match self {
+            Delimiters::Table(table) => size_of_val(table.as_ref()),
+            #[allow(unreachable_patterns)]
+            _ => 0,
+        }
+    }
+}
+
+impl<C: CountUnique> TokenizingCounter<C> {
+    /// Creates a new [`TokenizingCounter`] that splits lines on [`ASCII_WHITESPACE`]
+    pub fn new(inner: C) -> Self {
+        Self::with_delimiters(inner, ASCII_WHITESPACE)
+    }
+
+    /// Creates a new [`TokenizingCounter`] that splits lines on any of the bytes in `delimiters`.
+    /// If `delimiters` is empty each whole line is a single token.
+    pub fn with_delimiters(inner: C, delimiters: &[u8]) -> Self {
+        TokenizingCounter {
+            inner,
+            delimiters: Delimiters::new(delimiters),
+        }
+    }
+
+    /// The wrapped counter
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// The wrapped counter, mutably
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    /// Consume this [`TokenizingCounter`] and return the wrapped counter
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    /// Call `f` with each non-empty token in `line`
+    #[inline(always)]
+    fn for_each_token<'a, F: FnMut(&'a [u8])>(delimiters: &Delimiters, line: &'a [u8], mut f: F) {
+        let mut start = 0;
+        while start < line.len() {
+            let end = delimiters.find(&line[start..]).map_or(line.len(), |offset| start + offset);
+            if end > start {
+                f(&line[start..end]);
+            }
+            start = end + 1;
+        }
+    }
+}
+
+impl<C: CountUnique> CountUnique for TokenizingCounter<C> {
+    #[inline(always)]
+    fn count_line(&mut self, line: &[u8]) {
+        let inner = &mut self.inner;
+        Self::for_each_token(&self.delimiters, line, |token| inner.count_line(token));
+    }
+
+    fn count(&self) -> usize {
+        self.inner.count()
+    }
+
+    /// The memory of the wrapped counter, plus this wrapper's own fields. If the wrapped counter's
+    /// memory is unknown, only the rest is included.
+    fn estimated_memory(&self) -> usize {
+        size_of::<Self>() - size_of::<C>() + self.delimiters.memory() + self.inner.estimated_memory()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{EmitLines, HashingLineCounter, LineCounter, ReportUnique};
+
+    use super::*;
+
+    /// The distinct tokens counted in `input`, sorted
+    fn tokens(delimiters: &[u8], input: &[u8]) -> Vec<Vec<u8>> {
+        let mut counter = TokenizingCounter::with_delimiters(LineCounter::new(), delimiters);
+        counter.count_unique_in_bytes(input);
+        let mut tokens = counter.into_inner().into_vec();
+        tokens.sort();
+        tokens
+    }
+
+    #[test]
+    fn test_delimiter_runs() {
+        let expected = [b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        // one, two, three, and more delimiters take different search paths
+        for delimiters in [b" ".as_slice(), b" \t", b" \t,", ASCII_WHITESPACE] {
+            // each `_` is replaced by the delimiters in turn
+            let mut next = delimiters.iter().cycle();
+            let input: Vec<u8> = b"a__b\n_b_a_\n\n___\nc_a_c".iter().map(|&byte| if byte == b'_' { *next.next().unwrap() } else { byte }).collect();
+            assert_eq!(tokens(delimiters, &input), expected, "delimiters {delimiters:?}");
+        }
+    }
+
+    #[test]
+    fn test_leading_and_trailing_delimiters() {
+        assert_eq!(tokens(b" ", b"  a b  \n"), [b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(tokens(b",;", b",a;;b,\n;\n"), [b"a".to_vec(), b"b".to_vec()]);
+        assert!(tokens(ASCII_WHITESPACE, b" \t \r\n\n  ").is_empty());
+    }
+
+    #[test]
+    fn test_custom_delimiters() {
+        // whitespace is part of a token unless it is a delimiter
+        assert_eq!(tokens(b",", b"a b,c\n"), [b"a b".to_vec(), b"c".to_vec()]);
+        assert_eq!(tokens(b"", b"a b\n"), [b"a b".to_vec()]);
+    }
+
+    #[test]
+    fn test_counts_each_token() {
+        let mut counter = TokenizingCounter::new(HashingLineCounter::<u64, _>::new());
+        counter.count_unique_in_read(b"the cat\r\nthe hat the end".as_slice()).unwrap();
+        assert_eq!(counter.count(), 4);
+        assert_eq!(counter.inner().get(b"the"), Some(3));
+        counter.reset();
+        assert_eq!(counter.count(), 0);
+    }
+
+    #[test]
+    fn test_line_mapper_applies_to_tokens() {
+        let mut counter = TokenizingCounter::new(LineCounter::with_line_mapper(|token: &[u8], buffer: &mut Vec<u8>| {
+            buffer.clear();
+            buffer.extend(token.iter().map(u8::to_ascii_lowercase));
+            buffer.as_slice()
+        }));
+        counter.count_unique_in_bytes(b"The the THE tHe\nend");
+        assert_eq!(counter.count(), 2);
+    }
+}
@@ -0,0 +1,101 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+use crate::{CountUnique, Error};
+
+/// Count unique lines in two passes over a seekable input, such as a [`File`](std::fs::File),
+/// without memmap.
+///
+/// The first pass counts lines into `first` with [`CountUnique::count_until`], stopping as soon as
+/// `cap` distinct lines have been counted. Then `second` is called with `first` and whether the cap
+/// was reached, and may return a counter for the second pass, typically chosen or sized using what
+/// the first pass found. If it does, `input` is rewound to where it started and all of it is counted
+/// into that counter, which is returned. If it returns `None` the second pass is skipped. If the cap
+/// was not reached, `first` has already counted all of the input, so a second pass is rarely needed.
+///
+/// Rewinding requires [`Seek`], so this can't be used with pipes such as stdin. As with
+/// [`CountUnique::count_until`], `first` should be a counter with a cheap [`CountUnique::count`],
+/// such as a [`HashingLineCounter`](crate::HashingLineCounter).
+///
+/// ```rust
+/// use std::io::Cursor;
+/// use line_cardinality::{count_unique_in_two_passes, CountUnique, HashingLineCounter, LineCounter};
+///
+/// let input = Cursor::new(b"a\nb\na\nc\nd".to_vec());
+/// let mut first = LineCounter::new();
+/// let second = count_unique_in_two_passes(input, &mut first, 2, |first, cap_reached| {
+///     // too many distinct lines for the first pass, so count them again with occurrence counts
+///     cap_reached.then(|| HashingLineCounter::<u64, ()>::with_capacity(first.count() * 2))
+/// }).unwrap();
+///
+/// assert_eq!(first.count(), 2);
+/// assert_eq!(second.unwrap().count(), 4);
+/// ```
+pub fn count_unique_in_two_passes<R, E, C, F>(mut input: R, first: &mut E, cap: usize, second: F) -> Result<Option<C>, Error>
+where
+    R: Read + Seek,
+    E: CountUnique,
+    C: CountUnique,
+    F: FnOnce(&E, bool) -> Option<C>,
+{
+    let start = input.stream_position().map_err(|e| Error::io_static("failed to get input position", e))?;
+    let cap_reached = first.count_until(BufReader::new(&mut input), cap)?;
+    let Some(mut counter) = second(first, cap_reached) else {
+        return Ok(None);
+    };
+    input.seek(SeekFrom::Start(start)).map_err(|e| Error::io_static("failed to rewind input", e))?;
+    counter.count_unique_in_read(BufReader::new(input))?;
+    Ok(Some(counter))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use crate::{HashingLineCounter, LineCounter, ReportUnique};
+
+    use super::*;
+
+    fn input() -> Cursor<Vec<u8>> {
+        let lines: Vec<u8> = (0..100_000).flat_map(|i| format!("{}\n", i % 1000).into_bytes()).collect();
+        Cursor::new(lines)
+    }
+
+    #[test]
+    fn test_second_pass_counts_everything() {
+        let mut first = LineCounter::new();
+        let second = count_unique_in_two_passes(input(), &mut first, 10, |_first, cap_reached| {
+            assert!(cap_reached);
+            Some(HashingLineCounter::<u64, ()>::new())
+        }).unwrap().unwrap();
+        assert_eq!(first.count(), 10);
+        assert_eq!(second.count(), 1000);
+        assert_eq!(second.get(b"999"), Some(100));
+    }
+
+    #[test]
+    fn test_skip_second_pass() {
+        let mut first = LineCounter::new();
+        let second = count_unique_in_two_passes(input(), &mut first, 5000, |_first, cap_reached| {
+            assert!(!cap_reached);
+            None::<LineCounter<()>>
+        }).unwrap();
+        assert!(second.is_none());
+        assert_eq!(first.count(), 1000);
+    }
+
+    /// The input is rewound to where it was, not to its beginning
+    #[test]
+    fn test_rewinds_to_start_position() {
+        let mut input = Cursor::new(b"skipped\na\nb\nc\n".to_vec());
+        input.set_position(8);
+        let mut first = LineCounter::new();
+        let second = count_unique_in_two_passes(input, &mut first, 1, |_first, _cap_reached| Some(HashingLineCounter::<u64, ()>::new()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(second.count(), 3);
+        assert_eq!(second.get(b"skipped"), None);
+    }
+}
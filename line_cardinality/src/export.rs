@@ -0,0 +1,316 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! A simple binary format for exporting every distinct line and its occurrence count, for tools
+//! that want the whole table rather than a text report.
+//!
+//! Unlike [`HashingLineCounter::write_binary`](crate::HashingLineCounter::write_binary), every
+//! number has a fixed width, so the format is easy to read from any language, and the whole export
+//! is covered by a checksum. All numbers are little-endian.
+//!
+//! | Field       | Size      | Contents                                                            |
+//! |-------------|-----------|---------------------------------------------------------------------|
+//! | magic       | 4 bytes   | `LCEX`                                                              |
+//! | version     | 1 byte    | [`VERSION`]                                                         |
+//! | flags       | 1 byte    | bit 0 is set if entries are sorted. Other bits are always 0.        |
+//! | entry count | `u64`     | the number of entries that follow                                   |
+//! | entries     |           | for each entry: the line's length as a `u64`, the line's bytes, and its count as a `u64` |
+//! | checksum    | `u32`     | CRC-32 (as used by gzip) of every byte before it                    |
+//!
+//! Entries of a sorted export are in ascending bytewise order of their lines, as by
+//! `LC_ALL=C sort`, so sorted exports can be merged without loading them into memory. Counts are
+//! never 0.
+//!
+//! ```rust
+//! use line_cardinality::export::{ExportReader, ExportWriter};
+//!
+//! let mut writer = ExportWriter::new(Vec::new(), 2, true).unwrap();
+//! writer.write_entry(b"apple", 3).unwrap();
+//! writer.write_entry(b"banana", 1).unwrap();
+//! let export = writer.finish().unwrap();
+//!
+//! let mut reader = ExportReader::new(export.as_slice()).unwrap();
+//! assert_eq!(reader.entries(), 2);
+//! assert!(reader.is_sorted());
+//! let mut line = Vec::new();
+//! assert_eq!(reader.read_entry(&mut line).unwrap(), Some(3));
+//! assert_eq!(line, b"apple");
+//! assert_eq!(reader.read_entry(&mut line).unwrap(), Some(1));
+//! assert_eq!(reader.read_entry(&mut line).unwrap(), None);
+//! ```
+
+use std::io::{self, Read, Write};
+
+use crc32fast::Hasher;
+
+use crate::count_unique_impl::binary::invalid_data;
+use crate::Error;
+
+/// The bytes every export starts with
+pub const MAGIC: &[u8; 4] = b"LCEX";
+
+/// The current version of the export format. Readers reject any other version.
+pub const VERSION: u8 = 1;
+
+/// Set in the flags byte if entries are sorted by line
+const FLAG_SORTED: u8 = 1;
+
+const WRITE_ERROR_MESSAGE: &str = "failed to write export";
+const READ_ERROR_MESSAGE: &str = "failed to read export";
+
+/// Writes an export one entry at a time, so that a counter's entries can be written straight from
+/// it without collecting them first.
+///
+/// The number of entries must be known up front, as it is part of the header. Writes are small, so
+/// the underlying writer should be buffered.
+pub struct ExportWriter<W> {
+    writer: W,
+    hasher: Hasher,
+    /// entries promised by the header that haven't been written yet
+    remaining: u64,
+}
+
+impl<W: Write> ExportWriter<W> {
+    /// Write the header of an export with `entries` entries. If `sorted` is true, the entries must
+    /// be written in ascending bytewise order of their lines.
+    pub fn new(writer: W, entries: u64, sorted: bool) -> Result<Self, Error> {
+        let mut export = ExportWriter { writer, hasher: Hasher::new(), remaining: entries };
+        let flags = if sorted { FLAG_SORTED } else { 0 };
+        export.write(MAGIC)?;
+        export.write(&[VERSION, flags])?;
+        export.write(&entries.to_le_bytes())?;
+        Ok(export)
+    }
+
+    /// Write one line and its occurrence count. Returns an error if `count` is 0, or if every entry
+    /// promised by the header has already been written.
+    pub fn write_entry(&mut self, line: &[u8], count: u64) -> Result<(), Error> {
+        if count == 0 {
+            return Err(Error::io_static(WRITE_ERROR_MESSAGE, invalid_data("count of 0")));
+        }
+        self.remaining = self.remaining.checked_sub(1)
+            .ok_or_else(|| Error::io_static(WRITE_ERROR_MESSAGE, invalid_data("more entries than the header promised")))?;
+        self.write(&(line.len() as u64).to_le_bytes())?;
+        self.write(line)?;
+        self.write(&count.to_le_bytes())
+    }
+
+    /// Write the checksum and flush, and return the underlying writer. Returns an error if fewer
+    /// entries were written than the header promised.
+    pub fn finish(mut self) -> Result<W, Error> {
+        if self.remaining != 0 {
+            return Err(Error::io_static(WRITE_ERROR_MESSAGE, invalid_data("fewer entries than the header promised")));
+        }
+        let checksum = self.hasher.finalize();
+        self.writer.write_all(&checksum.to_le_bytes()).map_err(|e| Error::io_static(WRITE_ERROR_MESSAGE, e))?;
+        self.writer.flush().map_err(|e| Error::io_static(WRITE_ERROR_MESSAGE, e))?;
+        Ok(self.writer)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.hasher.update(bytes);
+        self.writer.write_all(bytes).map_err(|e| Error::io_static(WRITE_ERROR_MESSAGE, e))
+    }
+}
+
+/// Reads an export one entry at a time, without loading it all into memory.
+///
+/// The checksum is checked once the last entry has been read, so entries returned before then may
+/// come from a corrupt export: only trust them once [`ExportReader::read_entry`] has returned
+/// `None`. The order of a sorted export is checked as it is read. Reads are small, so the
+/// underlying reader should be buffered.
+pub struct ExportReader<R> {
+    reader: R,
+    hasher: Hasher,
+    entries: u64,
+    /// entries that haven't been read yet
+    remaining: u64,
+    sorted: bool,
+    /// the line of the previous entry, to check the order of a sorted export
+    previous: Vec<u8>,
+    /// whether the checksum has been read and checked
+    finished: bool,
+}
+
+impl<R: Read> ExportReader<R> {
+    /// Read the header of an export. Returns an error if `reader` doesn't start with an export in a
+    /// supported version.
+    pub fn new(reader: R) -> Result<Self, Error> {
+        let mut export = ExportReader {
+            reader,
+            hasher: Hasher::new(),
+            entries: 0,
+            remaining: 0,
+            sorted: false,
+            previous: Vec::new(),
+            finished: false,
+        };
+        let mut header = [0; 6];
+        export.read(&mut header)?;
+        if header[..4] != MAGIC[..] {
+            return Err(Error::io_static(READ_ERROR_MESSAGE, invalid_data("not an export")));
+        }
+        if header[4] != VERSION {
+            return Err(Error::io_static(READ_ERROR_MESSAGE, invalid_data("unsupported export version")));
+        }
+        if header[5] & !FLAG_SORTED != 0 {
+            return Err(Error::io_static(READ_ERROR_MESSAGE, invalid_data("unknown export flags")));
+        }
+        export.sorted = header[5] & FLAG_SORTED != 0;
+        export.entries = export.read_u64()?;
+        export.remaining = export.entries;
+        Ok(export)
+    }
+
+    /// The number of entries in the export, as given by its header
+    pub fn entries(&self) -> u64 {
+        self.entries
+    }
+
+    /// Whether the entries are in ascending bytewise order of their lines
+    pub fn is_sorted(&self) -> bool {
+        self.sorted
+    }
+
+    /// Read the next entry's line into `line`, replacing its contents, and return its count. Returns
+    /// `None` once every entry has been read and the checksum has been checked.
+    pub fn read_entry(&mut self, line: &mut Vec<u8>) -> Result<Option<u64>, Error> {
+        if self.remaining == 0 {
+            if !self.finished {
+                self.check_checksum()?;
+                self.finished = true;
+            }
+            return Ok(None);
+        }
+        let len = self.read_u64()?;
+        line.clear();
+        // read through `take` rather than allocating `len` up front, so a corrupt length fails at the
+        // end of the input instead of allocating
+        (&mut self.reader).take(len).read_to_end(line).map_err(|e| Error::io_static(READ_ERROR_MESSAGE, e))?;
+        if (line.len() as u64) < len {
+            return Err(Error::io_static(READ_ERROR_MESSAGE, io::ErrorKind::UnexpectedEof.into()));
+        }
+        self.hasher.update(line);
+        let count = self.read_u64()?;
+        if count == 0 {
+            return Err(Error::io_static(READ_ERROR_MESSAGE, invalid_data("count of 0")));
+        }
+        if self.sorted {
+            if self.remaining != self.entries && line.as_slice() <= self.previous.as_slice() {
+                return Err(Error::io_static(READ_ERROR_MESSAGE, invalid_data("entries of a sorted export are out of order")));
+            }
+            self.previous.clear();
+            self.previous.extend_from_slice(line);
+        }
+        self.remaining -= 1;
+        Ok(Some(count))
+    }
+
+    /// The underlying reader, positioned after whatever has been read so far
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn check_checksum(&mut self) -> Result<(), Error> {
+        let expected = self.hasher.clone().finalize();
+        let mut checksum = [0; 4];
+        self.reader.read_exact(&mut checksum).map_err(|e| Error::io_static(READ_ERROR_MESSAGE, e))?;
+        if u32::from_le_bytes(checksum) != expected {
+            return Err(Error::io_static(READ_ERROR_MESSAGE, invalid_data("checksum does not match")));
+        }
+        Ok(())
+    }
+
+    fn read_u64(&mut self) -> Result<u64, Error> {
+        let mut bytes = [0; 8];
+        self.read(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn read(&mut self, bytes: &mut [u8]) -> Result<(), Error> {
+        self.reader.read_exact(bytes).map_err(|e| Error::io_static(READ_ERROR_MESSAGE, e))?;
+        self.hasher.update(bytes);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn export(entries: &[(&[u8], u64)], sorted: bool) -> Vec<u8> {
+        let mut writer = ExportWriter::new(Vec::new(), entries.len() as u64, sorted).unwrap();
+        for (line, count) in entries {
+            writer.write_entry(line, *count).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    fn read_all(bytes: &[u8]) -> Result<Vec<(Vec<u8>, u64)>, Error> {
+        let mut reader = ExportReader::new(bytes)?;
+        let mut entries = Vec::new();
+        let mut line = Vec::new();
+        while let Some(count) = reader.read_entry(&mut line)? {
+            entries.push((line.clone(), count));
+        }
+        Ok(entries)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let long_line = vec![b'x'; 1000];
+        let entries: &[(&[u8], u64)] = &[(b"", 1), (b"\xFF\xFE not utf-8", 2), (&long_line, u64::MAX)];
+        let bytes = export(entries, false);
+        assert!(bytes.starts_with(b"LCEX\x01\x00\x03\x00\x00\x00\x00\x00\x00\x00"));
+        let read = read_all(&bytes).unwrap();
+        let read: Vec<(&[u8], u64)> = read.iter().map(|(line, count)| (line.as_slice(), *count)).collect();
+        assert_eq!(read, entries);
+
+        // an empty export is a header and a checksum
+        let bytes = export(&[], true);
+        assert_eq!(bytes.len(), 4 + 1 + 1 + 8 + 4);
+        assert!(read_all(&bytes).unwrap().is_empty());
+    }
+
+    /// Once the checksum has been checked, the reader keeps returning `None` and leaves any data
+    /// after the export unread
+    #[test]
+    fn test_end() {
+        let mut bytes = export(&[(b"a", 1)], true);
+        bytes.extend_from_slice(b"after");
+        let mut reader = ExportReader::new(bytes.as_slice()).unwrap();
+        let mut line = Vec::new();
+        assert_eq!(reader.read_entry(&mut line).unwrap(), Some(1));
+        assert_eq!(reader.read_entry(&mut line).unwrap(), None);
+        assert_eq!(reader.read_entry(&mut line).unwrap(), None);
+        assert_eq!(reader.into_inner(), b"after");
+    }
+
+    #[test]
+    fn test_invalid_exports() {
+        let valid = export(&[(b"a", 1), (b"b", 2)], true);
+        assert!(read_all(&valid).is_ok());
+        // every truncation fails, including a missing checksum
+        for len in 0..valid.len() {
+            assert!(read_all(&valid[..len]).is_err(), "{len} bytes");
+        }
+        // any flipped bit fails, whether it's caught by the header, the checksum, or a length
+        for index in 0..valid.len() {
+            let mut corrupt = valid.clone();
+            corrupt[index] ^= 0x10;
+            assert!(read_all(&corrupt).is_err(), "byte {index}");
+        }
+        assert!(read_all(&export(&[(b"b", 1), (b"a", 1)], true)).is_err());
+        assert!(read_all(&export(&[(b"a", 1), (b"a", 1)], true)).is_err());
+        assert!(read_all(&export(&[(b"b", 1), (b"a", 1)], false)).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_writes() {
+        let mut writer = ExportWriter::new(Vec::new(), 1, false).unwrap();
+        assert!(writer.write_entry(b"a", 0).is_err());
+        writer.write_entry(b"a", 1).unwrap();
+        assert!(writer.write_entry(b"b", 1).is_err());
+        assert!(ExportWriter::new(Vec::new(), 1, false).unwrap().finish().is_err());
+    }
+}
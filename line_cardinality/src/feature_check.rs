@@ -0,0 +1,8 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Compile-time checks for feature combinations that would otherwise silently do nothing
+
+// the memmap functions read file handles, so they are only compiled with the file feature
+#[cfg(all(feature = "memmap", not(feature = "file")))]
+compile_error!("the `memmap` feature requires the `file` feature");
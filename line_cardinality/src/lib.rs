@@ -10,25 +10,61 @@
 //! Examples of counting total distinct lines can be found in [`CountUnique`].
 //!
 //! Examples of reporting occurrences of each distinct line can be found in [`ReportUnique`].
+//!
+//! # Panics
+//!
+//! No input data or I/O failure makes line_cardinality panic: conditions that can fail return an
+//! [`Error`] instead. Arguments are checked differently. A function that panics on an invalid
+//! argument says so in its own `# Panics` section, such as [`DelimitedCounter::new`] with an empty
+//! delimiter. A buffer size too large to allocate fails as it would for a [`Vec`]. Capacities given
+//! to `with_capacity` constructors are only hints, and are ignored if too large to allocate, and
+//! [`HashingLineCounter::with_hot_cache`] caps its size, so neither panics.
+//!
+//! Panics in code you provide, such as a custom line mapper or the closure given to
+//! [`CountUnique::count_line_with`], propagate to the caller unchanged. Every counter is owned by a
+//! single thread, so such a panic can leave at most that counter partially updated.
 
-use std::io::BufRead;
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+
+use std::io::{self, BufRead, Read, Write};
 
 use bstr::io::BufReadExt;
-use cfg_if::cfg_if;
 
+pub use build_info::{build_info, BuildInfo, FEATURES};
 #[cfg(all(feature = "file", feature = "memmap"))]
-pub use count_unique_impl::file_io::memmap::CountUniqueFromMemmapFile;
+pub use count_unique_impl::file_io::memmap::{CountUniqueFromMemmapFile, MappedFile, MAX_MAPPED_LEN};
 #[cfg(feature = "file")]
 pub use count_unique_impl::file_io::read::CountUniqueFromReadFile;
+pub use count_unique_impl::borrowed::BorrowedLineCounter;
+pub use count_unique_impl::channel::count_unique_from_channel;
+pub use count_unique_impl::delimited::DelimitedCounter;
+#[cfg(feature = "diagnostics")]
+pub use count_unique_impl::diagnostics::{HashDiagnostics, Log2Histogram, Log2Range};
+pub use count_unique_impl::first_seen::FirstSeenLines;
 pub use count_unique_impl::hashing::{HashingLineCounter, HashingLineCounterIntoIter, HashingLineCounterIter};
 #[cfg(feature = "hash-only")]
-pub use count_unique_impl::hashing_inexact::InexactHashingLineCounter;
-pub use count_unique_impl::hyperloglog::HyperLogLog;
+pub use count_unique_impl::hashing_inexact::{HashSeed, InexactHashingLineCounter};
+pub use count_unique_impl::hyperloglog::{hll_size_for_memory, HyperLogLog};
+pub use count_unique_impl::keyed::KeyedLineCounter;
+pub use count_unique_impl::limited::DistinctLimit;
+pub use count_unique_impl::line_map::LineMap;
 pub use count_unique_impl::result::Cause as ErrorCause;
 pub use count_unique_impl::result::Error;
+pub use count_unique_impl::sorted::SortedLineCounter;
+pub use count_unique_impl::tokenizing::{TokenizingCounter, ASCII_WHITESPACE};
+pub use count_unique_impl::two_pass::count_unique_in_two_passes;
+pub use count_unique_impl::use_random_seeds;
 use count_unique_impl::result::Result;
+pub use line_mapper::{strip_ansi, strip_ansi_in_place, LineMapper};
+#[cfg(feature = "unicode")]
+pub use line_mapper::{fold_case, fold_case_full, fold_case_simple};
 
+mod build_info;
 pub(crate) mod count_unique_impl;
+#[cfg(feature = "export")]
+pub mod export;
+mod feature_check;
+pub(crate) mod line_mapper;
 
 /// A [`CountUnique`] that does not track each line's occurrence count, but is still
 /// useful for finding the total number of distinct lines in the input data.
@@ -132,35 +168,316 @@ pub trait CountUnique: Sized {
         }).map_err(|e| Error::io_static("failed to read from buffer", e))
     }
 
-    /// Count unique lines in newline-delimited bytes.
-    fn count_unique_in_bytes(&mut self, bytes: &[u8]) {
-        cfg_if! {
-            if #[cfg(feature = "memchr")] {
-                let mut start: usize = 0;
-                for newline_index in memchr::memchr_iter(b'\n', bytes) {
-                    self.count_line(&bytes[start..newline_index]);
-                    start = newline_index + 1;
-                }
-                // handle trailing
-                if start < bytes.len() {
-                    self.count_line(&bytes[start..]);
-                }
-            } else {
-                self.count_unique_in_read(bytes).expect("somehow failed to BufRead bytes from memory!?")
-            }
+    /// Count unique lines in a newline-delimited [`Read`] that isn't a [`BufRead`], such as a
+    /// [`File`](std::fs::File) or a socket. Lines are split the same way as
+    /// [`CountUnique::count_unique_in_read`].
+    ///
+    /// `reader` is read directly into a buffer of `buffer_size` bytes, which is allocated once per
+    /// call and grows if a single line doesn't fit in it. Lines are counted straight from that buffer.
+    /// Wrapping a raw reader in a [`BufReader`](std::io::BufReader) instead copies every byte into
+    /// the `BufReader`'s buffer. A reader that already buffers, such as a decompressor, would be
+    /// buffered twice.
+    ///
+    /// Prefer [`CountUnique::count_unique_in_read`] if you already have a [`BufRead`], such as
+    /// [`Stdin`](std::io::Stdin), as it reads from the existing buffer without another one. Prefer
+    /// [`CountUnique::count_unique_in_bytes`] if the whole input is already in memory.
+    ///
+    /// ```rust
+    /// use line_cardinality::{CountUnique, LineCounter};
+    ///
+    /// let data = b"three\r\ntwo\nthree\ntwo\nthree\none";
+    ///
+    /// let mut line_counter = LineCounter::new();
+    /// line_counter.count_unique_in_unbuffered_read(data.as_slice(), 64 * 1024).unwrap();
+    ///
+    /// assert_eq!(line_counter.count(), 3);
+    /// ```
+    fn count_unique_in_unbuffered_read<T: Read>(&mut self, reader: T, buffer_size: usize) -> Result {
+        count_unique_impl::for_each_line_in_read(reader, buffer_size, |line| self.count_line(line))
+            .map_err(|e| Error::io_static("failed to read from buffer", e))
+    }
+
+    /// Count unique lines in a newline-delimited [`BufRead`], but stop reading as soon as `cap`
+    /// distinct lines have been counted. Returns `true` if the cap was reached, in which case the
+    /// rest of `reader` has not been read.
+    ///
+    /// [`CountUnique::count`] is checked after every line, so this should only be used with counters
+    /// where that is cheap, such as [`HashingLineCounter`]. It is much slower with [`HyperLogLog`],
+    /// whose estimate takes longer to compute than a line takes to count.
+    ///
+    /// ```rust
+    /// use line_cardinality::{CountUnique, LineCounter};
+    ///
+    /// let data = b"one\ntwo\none\nthree\nfour";
+    ///
+    /// let mut line_counter = LineCounter::new();
+    /// assert!(line_counter.count_until(data.as_slice(), 3).unwrap());
+    /// assert_eq!(line_counter.count(), 3);
+    ///
+    /// let mut line_counter = LineCounter::new();
+    /// assert!(!line_counter.count_until(data.as_slice(), 5).unwrap());
+    /// assert_eq!(line_counter.count(), 4);
+    /// ```
+    fn count_until<T: BufRead>(&mut self, mut reader: T, cap: usize) -> std::result::Result<bool, Error> {
+        if self.count() >= cap {
+            return Ok(true);
         }
+        reader.for_byte_line(|line| {
+            self.count_line(line);
+            Ok(self.count() < cap)
+        }).map_err(|e| Error::io_static("failed to read from buffer", e))?;
+        Ok(self.count() >= cap)
+    }
+
+    /// Count unique lines in a newline-delimited [`BufRead`] like
+    /// [`CountUnique::count_unique_in_read`], calling `on_line` after each line is counted. It is
+    /// given the line as it was read, the distinct count so far, and whether this was the first
+    /// occurrence of the line. This is the streaming form of [`CountUnique::count_line_with`], and
+    /// can be used to report the running count live, or to alert as soon as a new value appears.
+    ///
+    /// As with [`CountUnique::count_line_with`], a line is new if it increased
+    /// [`CountUnique::count`], which is checked after every line. This should only be used with
+    /// counters where that is cheap and exact, such as [`HashingLineCounter`].
+    ///
+    /// ```rust
+    /// use line_cardinality::{CountUnique, LineCounter};
+    ///
+    /// let data = b"one\ntwo\none\nthree";
+    ///
+    /// let mut line_counter = LineCounter::new();
+    /// let mut observed = Vec::new();
+    /// line_counter.count_unique_in_read_with(data.as_slice(), |line, count, new| {
+    ///     observed.push((line.to_vec(), count, new));
+    /// }).unwrap();
+    ///
+    /// assert_eq!(observed, [
+    ///     (b"one".to_vec(), 1, true),
+    ///     (b"two".to_vec(), 2, true),
+    ///     (b"one".to_vec(), 2, false),
+    ///     (b"three".to_vec(), 3, true),
+    /// ]);
+    /// ```
+    fn count_unique_in_read_with<T: BufRead, F: FnMut(&[u8], usize, bool)>(&mut self, mut reader: T, mut on_line: F) -> Result {
+        reader.for_byte_line(|line| {
+            let mut new = false;
+            self.count_line_with(line, |_| new = true);
+            on_line(line, self.count(), new);
+            Ok(true)
+        }).map_err(|e| Error::io_static("failed to read from buffer", e))
+    }
+
+    /// Wrap `reader` in an iterator that yields each distinct line the first time it is read, in
+    /// input order, counting every line with this counter as it goes. Lines are read lazily as the
+    /// iterator is advanced, so this can filter an unbounded stream of lines down to its distinct
+    /// ones, like `sort -u` but without sorting. See [`FirstSeenLines`] for details.
+    ///
+    /// To keep using the counter afterwards, call this on `&mut counter`, or take it back from
+    /// [`FirstSeenLines::into_inner`]. As with [`CountUnique::count_line_with`], a line is new if
+    /// it increased [`CountUnique::count`], so this should only be used with counters where that is
+    /// cheap and exact, such as [`HashingLineCounter`].
+    ///
+    /// ```rust
+    /// use line_cardinality::{CountUnique, LineCounter};
+    ///
+    /// let data = b"one\ntwo\none\nthree\ntwo";
+    ///
+    /// let mut line_counter = LineCounter::new();
+    /// let mut lines = (&mut line_counter).first_seen_in_read(data.as_slice());
+    /// assert_eq!(lines.next().unwrap().unwrap(), b"one");
+    /// assert_eq!(lines.next().unwrap().unwrap(), b"two");
+    /// assert_eq!(lines.next().unwrap().unwrap(), b"three");
+    /// assert!(lines.next().is_none());
+    ///
+    /// assert_eq!(line_counter.count(), 3);
+    /// ```
+    fn first_seen_in_read<T: BufRead>(self, reader: T) -> FirstSeenLines<Self, T> {
+        FirstSeenLines::new(self, reader)
+    }
+
+    /// Count unique lines in newline-delimited bytes. Lines end at each `\n`, so unlike
+    /// [`CountUnique::count_unique_in_read`] a `\r` before the `\n` is kept as part of the line.
+    /// Lines are split the same way whether or not the `memchr` feature is enabled, but the feature
+    /// makes this faster.
+    fn count_unique_in_bytes(&mut self, bytes: &[u8]) {
+        self.count_unique_in_bytes_delimited(bytes, b'\n');
+    }
+
+    /// Count unique records in a [`BufRead`] where each record ends with `delimiter` instead of a
+    /// newline, such as `\0` or `\x1e` (the ASCII record separator). A final record without a
+    /// trailing `delimiter` is counted. Unlike [`CountUnique::count_unique_in_read`], nothing is
+    /// removed from the end of a record, so with a `\n` delimiter a `\r` before it is kept.
+    ///
+    /// Use [`DelimitedCounter`] to split on a delimiter longer than one byte.
+    ///
+    /// ```rust
+    /// use line_cardinality::{CountUnique, LineCounter};
+    ///
+    /// let data = b"one\x1etwo\nlines\x1eone\x1e";
+    ///
+    /// let mut line_counter = LineCounter::new();
+    /// line_counter.count_unique_in_read_delimited(data.as_slice(), 0x1e).unwrap();
+    ///
+    /// assert_eq!(line_counter.count(), 2);
+    /// ```
+    fn count_unique_in_read_delimited<T: BufRead>(&mut self, mut reader: T, delimiter: u8) -> Result {
+        reader.for_byte_record(delimiter, |record| {
+            self.count_line(record);
+            Ok(true)
+        }).map_err(|e| Error::io_static("failed to read from buffer", e))
+    }
+
+    /// Count unique records in bytes where each record ends with `delimiter` instead of a newline.
+    /// Records are split the same way as [`CountUnique::count_unique_in_read_delimited`], and as
+    /// with [`CountUnique::count_unique_in_bytes`] the `memchr` feature makes this faster.
+    ///
+    /// ```rust
+    /// use line_cardinality::{CountUnique, LineCounter};
+    ///
+    /// let mut line_counter = LineCounter::new();
+    /// line_counter.count_unique_in_bytes_delimited(b"one\x1etwo\nlines\x1eone", 0x1e);
+    ///
+    /// assert_eq!(line_counter.count(), 2);
+    /// ```
+    fn count_unique_in_bytes_delimited(&mut self, bytes: &[u8], delimiter: u8) {
+        count_unique_impl::for_each_record(bytes, delimiter, |record| self.count_line(record));
     }
 
     /// Count a single line, incrementing counters if it is the first occurrence of that line.
     fn count_line(&mut self, line: &[u8]);
 
+    /// Count a single line like [`CountUnique::count_line`], and call `on_new` if it is the first
+    /// occurrence of that line. `on_new` is given the line as it was passed in, before any line
+    /// mapper was applied. This makes it possible to stream each distinct line to an output as soon
+    /// as it is first seen, in input order.
+    ///
+    /// A line is considered new if it increased [`CountUnique::count`], so as with
+    /// [`CountUnique::count_until`] this should only be used with counters where that is cheap and
+    /// exact, such as [`HashingLineCounter`].
+    ///
+    /// ```rust
+    /// use bstr::ByteSlice;
+    /// use line_cardinality::{CountUnique, LineCounter};
+    ///
+    /// let mut line_counter = LineCounter::with_line_mapper(|line, buffer| {
+    ///     buffer.clear();
+    ///     line.to_lowercase_into(buffer);
+    ///     buffer
+    /// });
+    ///
+    /// let mut new_lines = Vec::new();
+    /// for line in [b"b".as_slice(), b"A", b"B", b"a", b"c"] {
+    ///     line_counter.count_line_with(line, |line| new_lines.push(line.to_vec()));
+    /// }
+    /// assert_eq!(new_lines, [b"b".as_slice(), b"A", b"c"]);
+    /// ```
+    fn count_line_with<F: FnOnce(&[u8])>(&mut self, line: &[u8], on_new: F) {
+        let count = self.count();
+        self.count_line(line);
+        if self.count() > count {
+            on_new(line);
+        }
+    }
+
     /// Returns current cardinality count of the [`CountUnique`].
+    ///
+    /// # Complexity
+    ///
+    /// Every counter in this crate answers this in constant time, regardless of how many lines it
+    /// has counted or how large it is, so it is cheap enough to poll each time output is flushed or
+    /// progress is shown. Implementations outside this crate should keep to this too.
+    ///
+    /// Exact and near-exact counters ([`HashingLineCounter`] and `InexactHashingLineCounter`)
+    /// maintain a running count as lines are inserted, and never scan their map. This is cheap
+    /// enough to call after every line.
+    ///
+    /// [`HyperLogLog`] keeps a histogram of its register values up to date as they change, and
+    /// computes its estimate from the 64 buckets of that histogram rather than from every register.
+    /// This is a fixed cost whatever the size, but it is over a hundred floating point operations, so
+    /// calling it after every line slows counting down considerably.
     fn count(&self) -> usize;
 
+    /// Returns an estimate of the memory used by this [`CountUnique`] in bytes. This covers the
+    /// value itself and everything it has allocated. It is kept up to date as lines are counted, so
+    /// it is cheap to call after every line, for example to stop at a memory budget.
+    ///
+    /// The default implementation returns 0, which means the memory use is unknown.
+    ///
+    /// ```rust
+    /// use line_cardinality::{CountUnique, LineCounter};
+    ///
+    /// let mut line_counter = LineCounter::new();
+    /// let empty = line_counter.estimated_memory();
+    /// line_counter.count_unique_in_bytes(b"a line that is too long to store inline\nanother");
+    /// assert!(line_counter.estimated_memory() > empty);
+    /// ```
+    fn estimated_memory(&self) -> usize {
+        0
+    }
+
     /// Resets internal state of this [`CountUnique`] for reuse
     fn reset(&mut self);
 }
 
+/// Counting through a mutable reference, so that a wrapper such as [`TokenizingCounter`] can borrow
+/// a counter instead of taking ownership of it
+impl<C: CountUnique> CountUnique for &mut C {
+    fn count_unique_in_read<T: BufRead>(&mut self, reader: T) -> Result {
+        (**self).count_unique_in_read(reader)
+    }
+
+    fn count_unique_in_unbuffered_read<T: Read>(&mut self, reader: T, buffer_size: usize) -> Result {
+        (**self).count_unique_in_unbuffered_read(reader, buffer_size)
+    }
+
+    fn count_until<T: BufRead>(&mut self, reader: T, cap: usize) -> std::result::Result<bool, Error> {
+        (**self).count_until(reader, cap)
+    }
+
+    fn count_unique_in_read_with<T: BufRead, F: FnMut(&[u8], usize, bool)>(&mut self, reader: T, on_line: F) -> Result {
+        (**self).count_unique_in_read_with(reader, on_line)
+    }
+
+    fn count_unique_in_bytes(&mut self, bytes: &[u8]) {
+        (**self).count_unique_in_bytes(bytes)
+    }
+
+    fn count_unique_in_read_delimited<T: BufRead>(&mut self, reader: T, delimiter: u8) -> Result {
+        (**self).count_unique_in_read_delimited(reader, delimiter)
+    }
+
+    fn count_unique_in_bytes_delimited(&mut self, bytes: &[u8], delimiter: u8) {
+        (**self).count_unique_in_bytes_delimited(bytes, delimiter)
+    }
+
+    #[inline(always)]
+    fn count_line(&mut self, line: &[u8]) {
+        (**self).count_line(line)
+    }
+
+    fn count_line_with<F: FnOnce(&[u8])>(&mut self, line: &[u8], on_new: F) {
+        (**self).count_line_with(line, on_new)
+    }
+
+    fn count(&self) -> usize {
+        (**self).count()
+    }
+
+    fn estimated_memory(&self) -> usize {
+        (**self).estimated_memory()
+    }
+
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+}
+
+/// Functionality to check whether a [`CountUnique`] has already counted a line, without counting it
+pub trait ContainsLine {
+    /// Returns `true` if `line` has been counted. The line mapper is applied to `line` first, which
+    /// is why this needs `&mut self`.
+    fn contains_line(&mut self, line: &[u8]) -> bool;
+}
+
 /// Functionality to emit lines from a [`CountUnique`]
 pub trait EmitLines {
     /// `f` is called for each map entry.
@@ -170,6 +487,38 @@ pub trait EmitLines {
 
     /// Consume this [`EmitLines`] and convert it into a [`Vec`]
     fn into_vec(self) -> Vec<Vec<u8>>;
+
+    /// Consume this [`EmitLines`] and write each line to `writer` in bytewise sorted order. Every
+    /// line, including the last, is followed by `terminator`, such as `b'\n'`, or `b'\0'` for
+    /// lines that may themselves contain newlines.
+    ///
+    /// This sorts the result of [`into_vec`](Self::into_vec), so it needs as much memory. For
+    /// [`HashingLineCounter`], lines long enough to be stored on the heap are moved out of the map
+    /// rather than copied, but short lines are stored inline in the map and are copied while the
+    /// map is still held.
+    ///
+    /// ```rust
+    /// use line_cardinality::{CountUnique, EmitLines, LineCounter};
+    ///
+    /// let mut line_counter = LineCounter::new();
+    /// line_counter.count_unique_in_bytes(b"b\nc\na\nb");
+    ///
+    /// let mut output = Vec::new();
+    /// line_counter.write_sorted_lines(&mut output, b'\n').unwrap();
+    /// assert_eq!(output, b"a\nb\nc\n");
+    /// ```
+    fn write_sorted_lines<W: Write>(self, writer: &mut W, terminator: u8) -> io::Result<()>
+    where
+        Self: Sized,
+    {
+        let mut lines = self.into_vec();
+        lines.sort_unstable();
+        for line in lines {
+            writer.write_all(&line)?;
+            writer.write_all(&[terminator])?;
+        }
+        Ok(())
+    }
 }
 
 /// Functionality to count occurrences of each line
@@ -204,7 +553,7 @@ pub trait ReportUnique<T> {
     fn get(&self, line: &[u8]) -> Option<T>;
 
     /// Convert this [`ReportUnique`] into a borrowed iter over each entry
-    fn iter(&self) -> HashingLineCounterIter<T>;
+    fn iter(&self) -> HashingLineCounterIter<'_, T>;
 
     /// Convert this [`ReportUnique`] into an owned iter over each entry
     fn into_iter(self) -> HashingLineCounterIntoIter<T>;
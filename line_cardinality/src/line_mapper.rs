@@ -0,0 +1,401 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Reusable line mappers, for use with constructors such as
+//! [`HashingLineCounter::with_line_mapper`](crate::HashingLineCounter::with_line_mapper).
+
+use std::ops::Range;
+
+const ESC: u8 = 0x1B;
+
+/// The line mapper type parameter of a counter: either `()` for no mapper, or a function
+/// `for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8]`. This can't be implemented outside this
+/// crate.
+pub trait LineMapper: private::Sealed {
+    /// Scratch space passed to the mapper, which a mapped line may be written into. Counters
+    /// without a mapper use `()`, so they don't carry a buffer they never use.
+    type Buffer: Default;
+
+    /// Map `line`, possibly writing the result to `buffer`
+    fn map_line<'a>(&'a mut self, line: &'a [u8], buffer: &'a mut Self::Buffer) -> &'a [u8];
+
+    /// Heap memory allocated by `buffer`, in bytes
+    fn buffer_memory(buffer: &Self::Buffer) -> usize;
+}
+
+impl LineMapper for () {
+    type Buffer = ();
+
+    #[inline(always)]
+    fn map_line<'a>(&'a mut self, line: &'a [u8], _buffer: &'a mut ()) -> &'a [u8] {
+        line
+    }
+
+    fn buffer_memory(_buffer: &()) -> usize {
+        0
+    }
+}
+
+impl<M> LineMapper for M
+where
+    M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+{
+    type Buffer = Vec<u8>;
+
+    #[inline(always)]
+    fn map_line<'a>(&'a mut self, line: &'a [u8], buffer: &'a mut Vec<u8>) -> &'a [u8] {
+        self(line, buffer)
+    }
+
+    fn buffer_memory(buffer: &Vec<u8>) -> usize {
+        buffer.capacity()
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+
+    impl Sealed for () {}
+
+    // `()` can never implement `FnMut`, so this doesn't overlap with the impl above
+    impl<M> Sealed for M where M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8] {}
+}
+
+/// Line mapper that removes ANSI CSI escape sequences, such as the SGR sequences used to color
+/// terminal output (`\x1B[31m`).
+///
+/// A CSI sequence is `ESC [`, followed by any parameter bytes (`0x30..=0x3F`), then any intermediate
+/// bytes (`0x20..=0x2F`), and ends with a final byte (`0x40..=0x7E`). An escape sequence that is cut
+/// off by the end of the line is removed entirely. If a byte that cannot be part of a CSI sequence
+/// is found before the final byte, the sequence is removed up to that byte, and the byte itself is
+/// kept. An `ESC` that does not start a CSI sequence is kept.
+///
+/// `buffer` is only written to if the line contains an `ESC` byte.
+///
+/// ```rust
+/// use line_cardinality::{strip_ansi, CountUnique, LineCounter};
+///
+/// let mut line_counter = LineCounter::with_line_mapper(strip_ansi);
+/// line_counter.count_unique_in_bytes(b"\x1B[1;31merror\x1B[0m: oops\nerror: oops");
+/// assert_eq!(line_counter.count(), 1);
+/// ```
+pub fn strip_ansi<'a>(line: &'a [u8], buffer: &'a mut Vec<u8>) -> &'a [u8] {
+    let Some(first_escape) = find_escape(line) else {
+        return line;
+    };
+    buffer.clear();
+    buffer.extend_from_slice(&line[..first_escape]);
+    let mut index = first_escape;
+    while index < line.len() {
+        let kept = next_kept_range(line, index);
+        index = kept.end;
+        buffer.extend_from_slice(&line[kept]);
+    }
+    buffer
+}
+
+/// Like [`strip_ansi`], but removes the escape sequences from `line` in place.
+pub fn strip_ansi_in_place(line: &mut Vec<u8>) {
+    let Some(first_escape) = find_escape(line) else {
+        return;
+    };
+    let mut write_index = first_escape;
+    let mut index = first_escape;
+    while index < line.len() {
+        let kept = next_kept_range(line, index);
+        index = kept.end;
+        // kept bytes are always at or after the write position, so they can be shifted down
+        let len = kept.len();
+        line.copy_within(kept, write_index);
+        write_index += len;
+    }
+    line.truncate(write_index);
+}
+
+/// Given the index of an `ESC` byte, return the range of bytes to keep between the end of its
+/// escape sequence and the next `ESC` (or the end of the line). The range may be empty.
+fn next_kept_range(line: &[u8], escape_index: usize) -> Range<usize> {
+    let start = match escape_end(line, escape_index) {
+        Some(end) => end,
+        // not an escape sequence, so keep the ESC
+        None => escape_index,
+    };
+    // an ESC ends a CSI sequence, so an escape sequence never contains another ESC
+    let search_start = usize::max(start, escape_index + 1);
+    let end = find_escape(&line[search_start..]).map_or(line.len(), |offset| search_start + offset);
+    start..end
+}
+
+/// Line mapper that applies Unicode simple case folding (the `C` and `S` mappings from
+/// `CaseFolding.txt`) to each character.
+///
+/// Simple case folding maps each character to exactly one character, so lines that differ only in
+/// case become identical without changing the number of characters. Unlike lowercasing, it
+/// normalizes characters that have several lowercase forms: for example, Greek final sigma (`ς`) and
+/// `σ` both fold to `σ`. Characters whose folding would require more than one character, such as
+/// `ß`, are left unchanged, so `"straße"` and `"STRASSE"` remain distinct. The Turkic-specific
+/// mappings are not used, so `I` folds to `i`, and `İ` and `ı` are left unchanged.
+///
+/// Bytes that are not valid UTF-8 are copied unchanged. The folded line is always written to
+/// `buffer`.
+///
+/// ```rust
+/// use line_cardinality::{fold_case_simple, CountUnique, LineCounter};
+///
+/// let mut line_counter = LineCounter::with_line_mapper(fold_case_simple);
+/// line_counter.count_unique_in_bytes("ΣΊΣΥΦΟΣ\nσίσυφος\nσίσυφοσ".as_bytes());
+/// assert_eq!(line_counter.count(), 1);
+/// ```
+#[cfg(feature = "unicode")]
+pub fn fold_case_simple<'a>(line: &'a [u8], buffer: &'a mut Vec<u8>) -> &'a [u8] {
+    buffer.clear();
+    for_each_char(line, buffer, |c, buffer| {
+        let folded = unicode_case_mapping::case_folded(c)
+            .and_then(|folded| char::from_u32(folded.get()))
+            .unwrap_or(c);
+        push_char(buffer, folded);
+    });
+    buffer
+}
+
+/// Line mapper that applies Unicode full case folding (the `C` and `F` mappings from
+/// `CaseFolding.txt`) to each character.
+///
+/// Full case folding is what Unicode defines for caseless matching: two lines are counted as the same
+/// line if and only if they match under Unicode default caseless matching. A character may fold to
+/// several characters, so `"straße"` and `"STRASSE"` both fold to `"strasse"`, and `İ` folds to
+/// `i` followed by a combining dot above. As with [`fold_case_simple`], the Turkic-specific mappings
+/// are not used. No Unicode normalization is performed, so precomposed and decomposed forms of the
+/// same character remain distinct.
+///
+/// Bytes that are not valid UTF-8 are copied unchanged. The folded line is always written to
+/// `buffer`.
+///
+/// ```rust
+/// use line_cardinality::{fold_case_full, CountUnique, LineCounter};
+///
+/// let mut line_counter = LineCounter::with_line_mapper(fold_case_full);
+/// line_counter.count_unique_in_bytes("straße\nSTRASSE\nStrasse".as_bytes());
+/// assert_eq!(line_counter.count(), 1);
+/// ```
+#[cfg(feature = "unicode")]
+pub fn fold_case_full<'a>(line: &'a [u8], buffer: &'a mut Vec<u8>) -> &'a [u8] {
+    use caseless::Caseless;
+
+    buffer.clear();
+    for_each_char(line, buffer, |c, buffer| {
+        std::iter::once(c)
+            .default_case_fold()
+            .for_each(|folded| push_char(buffer, folded));
+    });
+    buffer
+}
+
+/// Line mapper that gives the same result as [`fold_case_full`], but is much faster for lines that
+/// are entirely ASCII. Such lines are lowercased directly, which is all full case folding does to
+/// ASCII characters, and full case folding is only done for lines containing a byte `>= 0x80`. This
+/// is the best choice for mostly-ASCII input that may contain occasional Unicode text.
+///
+/// The folded line is always written to `buffer`.
+///
+/// ```rust
+/// use line_cardinality::{fold_case, CountUnique, LineCounter};
+///
+/// let mut line_counter = LineCounter::with_line_mapper(fold_case);
+/// line_counter.count_unique_in_bytes("Hello\nHELLO\nstraße\nSTRASSE".as_bytes());
+/// assert_eq!(line_counter.count(), 2);
+/// ```
+#[cfg(feature = "unicode")]
+pub fn fold_case<'a>(line: &'a [u8], buffer: &'a mut Vec<u8>) -> &'a [u8] {
+    if line.is_ascii() {
+        buffer.clear();
+        // branchless, so that the compiler can vectorize it
+        buffer.extend(line.iter().map(|byte| byte | (((byte.wrapping_sub(b'A') < 26) as u8) << 5)));
+        buffer
+    } else {
+        fold_case_full(line, buffer)
+    }
+}
+
+/// Call `f` for each valid UTF-8 character in `line`, copying invalid bytes directly into `buffer`
+#[cfg(feature = "unicode")]
+fn for_each_char<F: FnMut(char, &mut Vec<u8>)>(line: &[u8], buffer: &mut Vec<u8>, mut f: F) {
+    use bstr::ByteSlice;
+
+    for (start, end, c) in line.char_indices() {
+        if c == char::REPLACEMENT_CHARACTER {
+            // either invalid UTF-8, or a literal replacement character: either way, keep the original bytes
+            buffer.extend_from_slice(&line[start..end]);
+        } else {
+            f(c, buffer);
+        }
+    }
+}
+
+#[cfg(feature = "unicode")]
+#[inline(always)]
+fn push_char(buffer: &mut Vec<u8>, c: char) {
+    let mut encoded = [0; 4];
+    buffer.extend_from_slice(c.encode_utf8(&mut encoded).as_bytes());
+}
+
+#[inline(always)]
+fn find_escape(bytes: &[u8]) -> Option<usize> {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "memchr")] {
+            memchr::memchr(ESC, bytes)
+        } else {
+            bytes.iter().position(|byte| *byte == ESC)
+        }
+    }
+}
+
+/// Given the index of an `ESC` byte, return the index of the first byte after its escape sequence.
+/// Returns `None` if the `ESC` does not start a CSI sequence.
+fn escape_end(line: &[u8], escape_index: usize) -> Option<usize> {
+    let mut index = escape_index + 1;
+    match line.get(index) {
+        // truncated escape
+        None => return Some(index),
+        Some(b'[') => index += 1,
+        Some(_) => return None,
+    }
+    while let Some(byte) = line.get(index) {
+        match byte {
+            0x20..=0x3F => index += 1,
+            0x40..=0x7E => return Some(index + 1),
+            // not valid in a CSI sequence, so the sequence ends here
+            _ => return Some(index),
+        }
+    }
+    // truncated escape
+    Some(index)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Counters without a mapper don't carry a buffer
+    #[test]
+    fn test_no_mapper_buffer() {
+        use std::mem::size_of;
+
+        use crate::{HyperLogLog, LineCounter};
+
+        type Mapper = for<'a> fn(&'a [u8], &'a mut Vec<u8>) -> &'a [u8];
+        assert_eq!(size_of::<<() as LineMapper>::Buffer>(), 0);
+        let buffer_and_fn = size_of::<Vec<u8>>() + size_of::<Mapper>();
+        assert_eq!(size_of::<LineCounter<()>>() + buffer_and_fn, size_of::<LineCounter<Mapper>>());
+        assert_eq!(size_of::<HyperLogLog<()>>() + buffer_and_fn, size_of::<HyperLogLog<Mapper>>());
+    }
+
+    #[cfg(feature = "unicode")]
+    fn distinct_lines<M>(lines: &[&str], line_mapper: M) -> usize
+    where
+        M: for<'a> FnMut(&'a [u8], &'a mut Vec<u8>) -> &'a [u8],
+    {
+        use crate::{CountUnique, LineCounter};
+
+        let mut counter = LineCounter::with_line_mapper(line_mapper);
+        lines.iter().for_each(|line| counter.count_line(line.as_bytes()));
+        counter.count()
+    }
+
+    #[cfg(feature = "unicode")]
+    fn lowercase<'a>(line: &'a [u8], buffer: &'a mut Vec<u8>) -> &'a [u8] {
+        use bstr::ByteSlice;
+
+        buffer.clear();
+        line.to_lowercase_into(buffer);
+        buffer
+    }
+
+    /// Each case is a set of lines, and how many distinct lines it contains under lowercasing,
+    /// simple folding, and full folding
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn test_case_folding() {
+        let cases: &[(&[&str], usize, usize, usize)] = &[
+            // ASCII
+            (&["Hello", "HELLO", "hello"], 1, 1, 1),
+            // German sharp s: only full folding expands it
+            (&["straße", "STRASSE"], 2, 2, 1),
+            // capital sharp s folds to sharp s
+            (&["ß", "ẞ"], 1, 1, 1),
+            (&["ẞ", "ss"], 2, 2, 1),
+            // Turkish dotted capital I: simple folding leaves it as-is, full folding expands it
+            (&["İ", "i\u{307}"], 1, 2, 1),
+            // Turkish dotless i is never merged with i, as Turkic mappings are not used
+            (&["ı", "i"], 2, 2, 2),
+            (&["I", "ı"], 2, 2, 2),
+            // Greek final sigma only merges with sigma under folding
+            (&["ς", "σ", "Σ"], 2, 1, 1),
+        ];
+        for (lines, lower, simple, full) in cases {
+            assert_eq!(distinct_lines(lines, lowercase), *lower, "lowercase {lines:?}");
+            assert_eq!(distinct_lines(lines, fold_case_simple), *simple, "simple fold {lines:?}");
+            assert_eq!(distinct_lines(lines, fold_case_full), *full, "full fold {lines:?}");
+            assert_eq!(distinct_lines(lines, fold_case), *full, "fast full fold {lines:?}");
+        }
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn test_case_folding_invalid_utf8() {
+        let mut buffer = Vec::new();
+        assert_eq!(fold_case_simple(b"A\xFF\xFEB", &mut buffer), b"a\xFF\xFEb");
+        assert_eq!(fold_case_full(b"A\xFF\xFEB", &mut buffer), b"a\xFF\xFEb");
+        assert_eq!(fold_case_full("\u{FFFD}".as_bytes(), &mut buffer), "\u{FFFD}".as_bytes());
+        assert_eq!(fold_case(b"A\xFF\xFEB", &mut buffer), b"a\xFF\xFEb");
+    }
+
+    /// The ASCII fast path must agree with full case folding for every ASCII byte
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn test_fold_case_ascii() {
+        let ascii: Vec<u8> = (0..0x80).collect();
+        let mut buffer = Vec::new();
+        let expected = fold_case_full(&ascii, &mut buffer).to_vec();
+        assert_eq!(fold_case(&ascii, &mut buffer), expected);
+        assert_eq!(fold_case(b"MiXeD 123", &mut buffer), b"mixed 123");
+    }
+
+    fn strip(line: &[u8]) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        let stripped = strip_ansi(line, &mut buffer).to_vec();
+
+        let mut in_place = line.to_vec();
+        strip_ansi_in_place(&mut in_place);
+        assert_eq!(stripped, in_place, "in place stripping of {line:?}");
+
+        stripped
+    }
+
+    #[test]
+    fn test_strip_ansi() {
+        assert_eq!(strip(b""), b"");
+        assert_eq!(strip(b"plain"), b"plain");
+        assert_eq!(strip(b"\x1B[31mred\x1B[0m"), b"red");
+        assert_eq!(strip(b"\x1B[1;38;5;208mbold orange"), b"bold orange");
+        assert_eq!(strip(b"a\x1B[Kb\x1B[2Jc"), b"abc");
+        assert_eq!(strip(b"\x1B[m\x1B[m"), b"");
+        // intermediate bytes
+        assert_eq!(strip(b"a\x1B[1 qb"), b"ab");
+    }
+
+    #[test]
+    fn test_truncated() {
+        assert_eq!(strip(b"text\x1B"), b"text");
+        assert_eq!(strip(b"text\x1B["), b"text");
+        assert_eq!(strip(b"text\x1B[1;3"), b"text");
+    }
+
+    #[test]
+    fn test_not_csi() {
+        // ESC that does not start a CSI sequence is kept
+        assert_eq!(strip(b"a\x1B(Bb"), b"a\x1B(Bb");
+        // invalid byte ends the sequence, but is kept
+        assert_eq!(strip(b"a\x1B[1\xC3\xA9b"), b"a\xC3\xA9b");
+    }
+}
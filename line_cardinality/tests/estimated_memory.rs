@@ -0,0 +1,115 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Checks [`CountUnique::estimated_memory`] against what the allocator actually handed out
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::mem::size_of_val;
+
+use line_cardinality::{CountUnique, DistinctLimit, HashingLineCounter, HyperLogLog, LineCounter};
+
+/// Tracks the bytes currently allocated by each thread, so tests running in parallel don't see each
+/// other's allocations
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATED: Cell<isize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.with(|allocated| allocated.set(allocated.get() + layout.size() as isize));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.with(|allocated| allocated.set(allocated.get() - layout.size() as isize));
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.with(|allocated| allocated.set(allocated.get() + new_size as isize - layout.size() as isize));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocated() -> isize {
+    ALLOCATED.with(Cell::get)
+}
+
+/// Input with a mix of short lines, which are stored inline, and long lines, which are not
+fn input() -> Vec<u8> {
+    (0..50_000)
+        .flat_map(|i| {
+            let line = if i % 3 == 0 { format!("a much longer line that spills to the heap {i}\n") } else { format!("{i}\n") };
+            line.into_bytes()
+        })
+        .collect()
+}
+
+/// Build a counter, count `input` with it, and check its estimate against the heap memory it
+/// allocated, within 5%
+fn assert_estimate<C, F>(name: &str, input: &[u8], new: F)
+where
+    C: CountUnique,
+    F: FnOnce() -> C,
+{
+    let before = allocated();
+    let mut counter = new();
+    counter.count_unique_in_bytes(input);
+    let heap = (allocated() - before) as f64;
+    let estimated = (counter.estimated_memory() - size_of_val(&counter)) as f64;
+    assert!(heap > 0.0, "{name}: nothing was allocated");
+    assert!((estimated - heap).abs() <= heap * 0.05, "{name}: estimated {estimated} bytes, but {heap} were allocated");
+}
+
+#[test]
+fn test_hashing_line_counter() {
+    let input = input();
+    assert_estimate("LineCounter", &input, LineCounter::new);
+    assert_estimate("HashingLineCounter", &input, HashingLineCounter::<u64, _>::new);
+    assert_estimate("LineCounter with hot cache", &input, || LineCounter::new().with_hot_cache(1024));
+    assert_estimate("LineCounter with capacity", &input, || LineCounter::with_capacity(100_000));
+    assert_estimate("LineCounter with line mapper", &input, || {
+        LineCounter::with_line_mapper(|line: &[u8], buffer: &mut Vec<u8>| {
+            buffer.clear();
+            buffer.extend_from_slice(line);
+            buffer.reverse();
+            buffer.as_slice()
+        })
+    });
+}
+
+#[test]
+fn test_reset() {
+    let input = input();
+    let mut counter = LineCounter::new();
+    counter.count_unique_in_bytes(&input);
+    let before = allocated();
+    let estimated = counter.estimated_memory() as isize;
+    counter.reset();
+    let freed = before - allocated();
+    assert_eq!(counter.estimated_memory() as isize, estimated - freed);
+}
+
+#[test]
+fn test_hyperloglog() {
+    assert_estimate("HyperLogLog", &input(), HyperLogLog::new);
+}
+
+#[cfg(feature = "hash-only")]
+#[test]
+fn test_inexact_hashing_line_counter() {
+    assert_estimate("InexactHashingLineCounter", &input(), line_cardinality::InexactHashingLineCounter::new);
+}
+
+#[test]
+fn test_distinct_limit() {
+    assert_estimate("DistinctLimit", &input(), || {
+        DistinctLimit::new(HashingLineCounter::<u64, _>::new(), 1000, HyperLogLog::with_capacity(4096).unwrap())
+    });
+}
@@ -0,0 +1,68 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Reading a file and memory-mapping it count the same lines, including for files that are empty or
+//! only contain newlines
+
+#![cfg(all(feature = "file", feature = "memmap"))]
+
+use std::fs::File;
+
+use line_cardinality::{CountUnique, CountUniqueFromMemmapFile, CountUniqueFromReadFile, LineCounter};
+use test_support::{CARDINALITY_10, CARDINALITY_100, CARDINALITY_1000, EMPTY, HAMLET_WORDS, SAME_LINE};
+
+/// Count `contents` by reading it, by memory-mapping it, and from bytes, and check each gives
+/// `expected`
+fn check(name: &str, contents: &[u8], expected: usize) {
+    let path = std::env::temp_dir().join(format!("line_cardinality-test-{}-{name}", std::process::id()));
+    std::fs::write(&path, contents).unwrap();
+    let file = File::open(&path).unwrap();
+
+    let mut read = LineCounter::new();
+    read.count_unique_in_file(&file).unwrap();
+    let mut memmap = LineCounter::new();
+    memmap.count_unique_in_memmap_file(&file).unwrap();
+    let mut bytes = LineCounter::new();
+    bytes.count_unique_in_bytes(contents);
+
+    assert_eq!(read.count(), expected, "read {name}");
+    assert_eq!(memmap.count(), expected, "memmap {name}");
+    assert_eq!(bytes.count(), expected, "bytes {name}");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_empty() {
+    check("empty", b"", 0);
+}
+
+#[test]
+fn test_newlines_only() {
+    check("one_newline", b"\n", 1);
+    check("newlines", b"\n\n\n", 1);
+    check("newlines_then_line", b"\n\n\na", 2);
+    // a million newlines
+    let contents = EMPTY.read();
+    assert!(contents.iter().all(|&byte| byte == b'\n'));
+    check("empty_lines", &contents, 1);
+}
+
+#[test]
+fn test_files() {
+    for fixture in [&CARDINALITY_1000, &HAMLET_WORDS, &SAME_LINE] {
+        check(fixture.filename, &fixture.read(), fixture.distinct.plain);
+    }
+}
+
+/// Each file in a list is counted the same way as it would be alone
+#[test]
+fn test_memmap_files_with_empty_file() {
+    let path = std::env::temp_dir().join(format!("line_cardinality-test-{}-empty-list", std::process::id()));
+    std::fs::write(&path, b"").unwrap();
+    let files = [File::open(CARDINALITY_10.path()).unwrap(), File::open(&path).unwrap(), File::open(CARDINALITY_100.path()).unwrap()];
+    let mut counter = LineCounter::new();
+    counter.count_unique_in_memmap_files(&files).unwrap();
+    // both files count from 1, so the smaller is a subset of the larger
+    assert_eq!(counter.count(), CARDINALITY_100.distinct.plain);
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,162 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Invariants that every way of splitting and counting the same input must agree on, checked
+//! against a naive reference implementation. This is shared by the property tests and the fuzz
+//! target.
+
+use std::collections::HashSet;
+use std::io::{self, BufReader, Read};
+
+use line_cardinality::{BorrowedLineCounter, CountUnique, DelimitedCounter, EmitLines, HyperLogLog, LineCounter};
+
+/// The multi-byte delimiter [`check`] splits records on. It overlaps itself, and shares bytes with
+/// `\r\n`, to catch searches that resume in the wrong place.
+pub const MULTI_BYTE_DELIMITER: &[u8] = b"\r\n\r";
+
+/// Split `input` on each `delimiter`, without using any of the library's splitting code. A final
+/// record without a trailing delimiter is kept, but an empty final record is not.
+pub fn reference_split<'a>(input: &'a [u8], delimiter: &[u8]) -> Vec<&'a [u8]> {
+    let mut records = Vec::new();
+    let mut start = 0;
+    let mut index = 0;
+    while index + delimiter.len() <= input.len() {
+        if &input[index..index + delimiter.len()] == delimiter {
+            records.push(&input[start..index]);
+            index += delimiter.len();
+            start = index;
+        } else {
+            index += 1;
+        }
+    }
+    if start < input.len() {
+        records.push(&input[start..]);
+    }
+    records
+}
+
+/// The distinct lines [`CountUnique::count_unique_in_read`] should see: like splitting on `\n`,
+/// but with a `\r` before each `\n` removed. A final line without a `\n` is kept as it is.
+pub fn reference_read_lines(input: &[u8]) -> HashSet<Vec<u8>> {
+    let mut lines = HashSet::new();
+    let mut start = 0;
+    for (index, &byte) in input.iter().enumerate() {
+        if byte == b'\n' {
+            let line = &input[start..index];
+            lines.insert(line.strip_suffix(b"\r").unwrap_or(line).to_vec());
+            start = index + 1;
+        }
+    }
+    if start < input.len() {
+        lines.insert(input[start..].to_vec());
+    }
+    lines
+}
+
+/// Returns data from a slice in chunks of the given sizes, cycling through them, so that records
+/// and delimiters are split across reads
+pub struct ChunkedReader<'a> {
+    data: &'a [u8],
+    chunk_sizes: &'a [usize],
+    next_chunk: usize,
+}
+
+impl<'a> ChunkedReader<'a> {
+    pub fn new(data: &'a [u8], chunk_sizes: &'a [usize]) -> Self {
+        ChunkedReader { data, chunk_sizes, next_chunk: 0 }
+    }
+}
+
+impl Read for ChunkedReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let chunk_size = self.chunk_sizes.get(self.next_chunk % self.chunk_sizes.len().max(1)).copied().unwrap_or(usize::MAX).max(1);
+        self.next_chunk += 1;
+        let len = chunk_size.min(buf.len()).min(self.data.len());
+        buf[..len].copy_from_slice(&self.data[..len]);
+        self.data = &self.data[len..];
+        Ok(len)
+    }
+}
+
+/// A [`BufReader`] over `input` that never holds more than one chunk at a time
+fn chunked<'a>(input: &'a [u8], chunk_sizes: &'a [usize]) -> BufReader<ChunkedReader<'a>> {
+    BufReader::with_capacity(chunk_sizes.iter().copied().max().unwrap_or(1).max(1), ChunkedReader::new(input, chunk_sizes))
+}
+
+fn distinct(counter: impl EmitLines) -> HashSet<Vec<u8>> {
+    counter.into_vec().into_iter().collect()
+}
+
+/// Check that every counting path agrees with the reference implementation on `input`. Buffered
+/// reads are split into chunks of `chunk_sizes`. Panics if any invariant does not hold.
+pub fn check(input: &[u8], chunk_sizes: &[usize]) {
+    let lines: HashSet<Vec<u8>> = reference_split(input, b"\n").into_iter().map(<[u8]>::to_vec).collect();
+
+    // bytes keep a `\r` before each `\n`
+    let mut from_bytes = LineCounter::new();
+    from_bytes.count_unique_in_bytes(input);
+    assert_eq!(from_bytes.count(), lines.len(), "count_unique_in_bytes of {input:?}");
+    assert_eq!(distinct(from_bytes), lines, "count_unique_in_bytes of {input:?}");
+
+    let mut borrowed = BorrowedLineCounter::<u64>::new();
+    borrowed.count_unique_in_slice(input);
+    assert_eq!(borrowed.count(), lines.len(), "count_unique_in_slice of {input:?}");
+
+    // reads strip it
+    let mut from_read = LineCounter::new();
+    from_read.count_unique_in_read(chunked(input, chunk_sizes)).unwrap();
+    assert_eq!(distinct(from_read), reference_read_lines(input), "count_unique_in_read of {input:?} in chunks of {chunk_sizes:?}");
+
+    // and so do unbuffered reads, whatever the size of their buffer
+    for buffer_size in [1, chunk_sizes.iter().copied().max().unwrap_or(1)] {
+        let mut from_unbuffered = LineCounter::new();
+        from_unbuffered.count_unique_in_unbuffered_read(ChunkedReader::new(input, chunk_sizes), buffer_size).unwrap();
+        assert_eq!(distinct(from_unbuffered), reference_read_lines(input), "count_unique_in_unbuffered_read of {input:?} with a buffer of {buffer_size} in chunks of {chunk_sizes:?}");
+    }
+
+    // a delimiter splits reads and bytes the same way
+    for delimiter in [b"\n".as_slice(), MULTI_BYTE_DELIMITER] {
+        let records: HashSet<Vec<u8>> = reference_split(input, delimiter).into_iter().map(<[u8]>::to_vec).collect();
+        let mut from_bytes = DelimitedCounter::new(LineCounter::new(), delimiter);
+        from_bytes.count_unique_in_bytes(input);
+        assert_eq!(distinct(from_bytes.into_inner()), records, "{delimiter:?} delimited bytes of {input:?}");
+        let mut from_read = DelimitedCounter::new(LineCounter::new(), delimiter);
+        from_read.count_unique_in_read(chunked(input, chunk_sizes)).unwrap();
+        assert_eq!(distinct(from_read.into_inner()), records, "{delimiter:?} delimited read of {input:?} in chunks of {chunk_sizes:?}");
+    }
+
+    // single-byte delimiters split reads and bytes the same way, without a counter to wrap
+    for delimiter in [b'\n', b'\r'] {
+        let records: HashSet<Vec<u8>> = reference_split(input, &[delimiter]).into_iter().map(<[u8]>::to_vec).collect();
+        let mut from_bytes = LineCounter::new();
+        from_bytes.count_unique_in_bytes_delimited(input, delimiter);
+        assert_eq!(distinct(from_bytes), records, "{delimiter:?} delimited bytes of {input:?}");
+        let mut from_read = LineCounter::new();
+        from_read.count_unique_in_read_delimited(chunked(input, chunk_sizes), delimiter).unwrap();
+        assert_eq!(distinct(from_read), records, "{delimiter:?} delimited read of {input:?} in chunks of {chunk_sizes:?}");
+    }
+
+    // hash collisions can only merge lines, never split them
+    #[cfg(feature = "hash-only")]
+    {
+        let mut near_exact = line_cardinality::InexactHashingLineCounter::new();
+        near_exact.count_unique_in_bytes(input);
+        assert!(near_exact.count() <= lines.len(), "near-exact count of {input:?}");
+    }
+
+    // with the same seed, an estimate only depends on which lines were seen, not on how they were
+    // read or in what order
+    let mut estimate = HyperLogLog::with_capacity(64).unwrap();
+    estimate.count_unique_in_bytes(input);
+    let expected = estimate.count();
+    estimate.reset();
+    DelimitedCounter::new(&mut estimate, b"\n").count_unique_in_read(chunked(input, chunk_sizes)).unwrap();
+    assert_eq!(estimate.count(), expected, "estimate of {input:?} read in chunks of {chunk_sizes:?}");
+    estimate.reset();
+    let mut reordered: Vec<&Vec<u8>> = lines.iter().collect();
+    reordered.sort_unstable_by(|a, b| b.cmp(a));
+    for line in reordered {
+        estimate.count_line(line);
+    }
+    assert_eq!(estimate.count(), expected, "estimate of the distinct lines of {input:?}");
+}
@@ -0,0 +1,45 @@
+// This file is part of line_cardinality. Copyright © 2024 line_cardinality contributors.
+// line_cardinality is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Property tests checking that every way of splitting and counting input agrees with a naive
+//! reference implementation. The `fuzz` directory has a fuzz target that checks the same
+//! invariants for longer.
+
+mod invariants;
+
+use proptest::prelude::*;
+
+/// Inputs made mostly of delimiter bytes, so that empty lines, `\r\n`, and overlapping delimiters
+/// are common
+fn input() -> impl Strategy<Value = Vec<u8>> {
+    let byte = prop_oneof![
+        3 => Just(b'\n'),
+        2 => Just(b'\r'),
+        2 => Just(b'a'),
+        1 => Just(b'b'),
+        1 => any::<u8>(),
+    ];
+    prop::collection::vec(byte, 0..200)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn test_paths_agree(input in input(), chunk_sizes in prop::collection::vec(1usize..16, 1..4)) {
+        invariants::check(&input, &chunk_sizes);
+    }
+
+    #[test]
+    fn test_paths_agree_on_any_bytes(input in any::<Vec<u8>>(), chunk_sizes in prop::collection::vec(1usize..4096, 1..4)) {
+        invariants::check(&input, &chunk_sizes);
+    }
+}
+
+#[test]
+fn test_reference_split() {
+    assert_eq!(invariants::reference_split(b"", b"\n"), [&[] as &[u8]; 0]);
+    assert_eq!(invariants::reference_split(b"a\n\nb", b"\n"), [b"a".as_slice(), b"", b"b"]);
+    assert_eq!(invariants::reference_split(b"a\n", b"\n"), [b"a".as_slice()]);
+    assert_eq!(invariants::reference_split(b"x\r\n\r\n\r", b"\r\n\r"), [b"x".as_slice(), b"\n\r"]);
+}
@@ -0,0 +1,71 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Generating synthetic fixtures, so that the large ones need not be checked in
+
+use std::io::{self, Write};
+
+/// How to generate a synthetic [`Fixture`](crate::Fixture). Generated files have the same number
+/// of lines and distinct lines as the checked in copies, but not the same lines, as those were made
+/// with an unrecorded random seed. Output is deterministic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Generator {
+    /// `lines` lines, each a number from 1 to `distinct`. Every number occurs at least once, and
+    /// the lines are in a random order.
+    Numbers { lines: usize, distinct: usize },
+    /// `lines` copies of `line`, except that the line numbered `needle.0`, counting from 1, is
+    /// `needle.1` instead
+    Repeated {
+        line: &'static str,
+        lines: usize,
+        needle: Option<(usize, &'static str)>,
+    },
+}
+
+impl Generator {
+    /// Write the fixture to `writer`
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match *self {
+            Generator::Numbers { lines, distinct } => {
+                assert!(lines >= distinct, "{lines} lines can't hold {distinct} distinct numbers");
+                let mut numbers: Vec<usize> = (0..lines).map(|line| line % distinct + 1).collect();
+                shuffle(&mut numbers);
+                for number in numbers {
+                    writeln!(writer, "{number}")?;
+                }
+            }
+            Generator::Repeated { line, lines, needle } => {
+                for line_number in 1..=lines {
+                    match needle {
+                        Some((needle_line, needle)) if needle_line == line_number => writeln!(writer, "{needle}")?,
+                        _ => writeln!(writer, "{line}")?,
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The fixture's contents
+    pub fn generate(&self) -> Vec<u8> {
+        let mut contents = Vec::new();
+        self.write(&mut contents).expect("writing to a Vec can't fail");
+        contents
+    }
+}
+
+/// Fisher-Yates shuffle with a fixed seed, so that generated fixtures are the same on every run
+fn shuffle<T>(values: &mut [T]) {
+    // xorshift64*, which is plenty random enough to scatter lines
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut next = move || {
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    };
+    for i in (1..values.len()).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        values.swap(i, j);
+    }
+}
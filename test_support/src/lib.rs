@@ -0,0 +1,252 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! The files in `test_files`, and the results the cuniq and line_cardinality tests and benchmarks
+//! expect from them. Keeping every expected count in this one registry means a fixture can't be
+//! edited without the suites that use it noticing: `tests/registry.rs` recomputes everything here
+//! from the fixtures with a naive reference implementation.
+//!
+//! The larger fixtures are synthetic, so each has a [`Generator`]. If one isn't in `test_files`,
+//! [`Fixture::path`] generates a file with the same line and distinct counts instead.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+pub use generate::Generator;
+pub use reference::{distinct_lines, lines, report, report_checksum};
+
+mod generate;
+mod reference;
+
+/// A file in `test_files`
+#[derive(Debug)]
+pub struct Fixture {
+    /// Name of the file in `test_files`
+    pub filename: &'static str,
+    /// Number of lines in the file
+    pub lines: usize,
+    /// Number of distinct lines, with and without cuniq's `--trim` and `--lower`
+    pub distinct: Distinct,
+    /// [`report_checksum`] of the file's [`report`]. Generated fixtures don't have one, as a
+    /// generated file only has the same counts as the checked in one, not the same lines.
+    pub report_checksum: Option<u64>,
+    /// How to generate this fixture, if it is synthetic
+    pub generator: Option<Generator>,
+}
+
+/// Number of distinct lines in a [`Fixture`] after each combination of cuniq's `--trim` and
+/// `--lower`. Trimming is done before lowercasing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Distinct {
+    /// Lines as they are
+    pub plain: usize,
+    /// Lines with leading and trailing whitespace removed
+    pub trim: usize,
+    /// Lowercased lines
+    pub lower: usize,
+    /// Trimmed, then lowercased lines
+    pub trim_lower: usize,
+}
+
+impl Distinct {
+    /// The same count under every mapping, as for fixtures with no whitespace or letters
+    const fn uniform(distinct: usize) -> Self {
+        Distinct {
+            plain: distinct,
+            trim: distinct,
+            lower: distinct,
+            trim_lower: distinct,
+        }
+    }
+}
+
+pub static CARDINALITY_10: Fixture = Fixture {
+    filename: "cardinality_10.txt",
+    lines: 1_000_000,
+    distinct: Distinct::uniform(10),
+    report_checksum: None,
+    generator: Some(Generator::Numbers { lines: 1_000_000, distinct: 10 }),
+};
+
+pub static CARDINALITY_100: Fixture = Fixture {
+    filename: "cardinality_100.txt",
+    lines: 1_000_000,
+    distinct: Distinct::uniform(100),
+    report_checksum: None,
+    generator: Some(Generator::Numbers { lines: 1_000_000, distinct: 100 }),
+};
+
+pub static CARDINALITY_1000: Fixture = Fixture {
+    filename: "cardinality_1000.txt",
+    lines: 1_000_000,
+    distinct: Distinct::uniform(1000),
+    report_checksum: None,
+    generator: Some(Generator::Numbers { lines: 1_000_000, distinct: 1000 }),
+};
+
+/// Variants of a few words that differ in case and surrounding whitespace
+pub static CASING_VARIANTS: Fixture = Fixture {
+    filename: "casing_variants.txt",
+    lines: 18,
+    distinct: Distinct {
+        plain: 15,
+        trim: 14,
+        lower: 8,
+        trim_lower: 6,
+    },
+    report_checksum: Some(0xfcdc_df60_6e87_224c),
+    generator: None,
+};
+
+/// Nothing but empty lines
+pub static EMPTY: Fixture = Fixture {
+    filename: "empty.txt",
+    lines: 1_000_000,
+    distinct: Distinct::uniform(1),
+    report_checksum: None,
+    generator: Some(Generator::Repeated { line: "", lines: 1_000_000, needle: None }),
+};
+
+/// The words of Hamlet, one per line: the primary high cardinality fixture
+pub static HAMLET_WORDS: Fixture = Fixture {
+    filename: "hamlet_words.txt",
+    lines: 31_992,
+    distinct: Distinct {
+        plain: 5414,
+        trim: 5414,
+        lower: 4900,
+        trim_lower: 4900,
+    },
+    report_checksum: Some(0x4778_5fc6_408f_437c),
+    generator: None,
+};
+
+pub static LARGE: Fixture = Fixture {
+    filename: "large.txt",
+    lines: 4_000_000,
+    distinct: Distinct::uniform(100_000),
+    report_checksum: None,
+    generator: Some(Generator::Numbers { lines: 4_000_000, distinct: 100_000 }),
+};
+
+/// A single "needle" line near the start of the file
+pub static NEEDLE_AT_START: Fixture = Fixture {
+    filename: "needle1.txt",
+    lines: 1_000_000,
+    distinct: Distinct::uniform(2),
+    report_checksum: None,
+    generator: Some(Generator::Repeated { line: "foo", lines: 1_000_000, needle: Some((2, "needle")) }),
+};
+
+/// A single "needle" line well into the file
+pub static NEEDLE_AT_END: Fixture = Fixture {
+    filename: "needle2.txt",
+    lines: 1_000_000,
+    distinct: Distinct::uniform(2),
+    report_checksum: None,
+    generator: Some(Generator::Repeated { line: "foo", lines: 1_000_000, needle: Some((653_202, "needle")) }),
+};
+
+pub static SAME_LINE: Fixture = Fixture {
+    filename: "same_line.txt",
+    lines: 1_000_000,
+    distinct: Distinct::uniform(1),
+    report_checksum: None,
+    generator: Some(Generator::Repeated { line: "foo", lines: 1_000_000, needle: None }),
+};
+
+/// The numbers from 1 to 1,000,000, each once, in random order
+pub static SHUFFLED_NUMBERS: Fixture = Fixture {
+    filename: "shuffled_numbers.txt",
+    lines: 1_000_000,
+    distinct: Distinct::uniform(1_000_000),
+    report_checksum: None,
+    generator: Some(Generator::Numbers { lines: 1_000_000, distinct: 1_000_000 }),
+};
+
+pub static SMALL: Fixture = Fixture {
+    filename: "small.txt",
+    lines: 6,
+    distinct: Distinct::uniform(3),
+    report_checksum: Some(0x6b51_d069_a118_ab95),
+    generator: None,
+};
+
+/// Every fixture in `test_files`
+pub static ALL: &[&Fixture] = &[
+    &CARDINALITY_10,
+    &CARDINALITY_100,
+    &CARDINALITY_1000,
+    &CASING_VARIANTS,
+    &EMPTY,
+    &HAMLET_WORDS,
+    &LARGE,
+    &NEEDLE_AT_START,
+    &NEEDLE_AT_END,
+    &SAME_LINE,
+    &SHUFFLED_NUMBERS,
+    &SMALL,
+];
+
+impl Fixture {
+    /// The fixture in `test_files` named `filename`
+    ///
+    /// # Panics
+    /// If there is no such fixture
+    pub fn named(filename: &str) -> &'static Fixture {
+        ALL.iter()
+            .find(|fixture| fixture.filename == filename)
+            .unwrap_or_else(|| panic!("{filename} is not in the test_support fixture registry"))
+    }
+
+    /// The path of this fixture in `test_files`. If it isn't there and it is synthetic, it is
+    /// generated into the target directory the first time this is called.
+    ///
+    /// # Panics
+    /// If the fixture is missing and can't be generated
+    pub fn path(&self) -> PathBuf {
+        let checked_in = workspace_dir().join("test_files").join(self.filename);
+        if checked_in.exists() {
+            return checked_in;
+        }
+        let Some(generator) = self.generator else {
+            panic!("{} is missing, and is not a synthetic fixture that can be generated", checked_in.display());
+        };
+        let generated = workspace_dir().join("target").join("test_fixtures").join(self.filename);
+        if !generated.exists() {
+            generate_file(&generator, &generated);
+        }
+        generated
+    }
+
+    /// The contents of this fixture
+    pub fn read(&self) -> Vec<u8> {
+        std::fs::read(self.path()).unwrap_or_else(|e| panic!("failed to read {}: {e}", self.filename))
+    }
+}
+
+/// The path of the fixture in `test_files` named `name`, for tests that refer to fixtures by name.
+/// See [`Fixture::path`].
+pub fn test_file(name: &str) -> PathBuf {
+    Fixture::named(name).path()
+}
+
+/// The workspace root, which `test_files` is in
+fn workspace_dir() -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.pop();
+    path
+}
+
+/// Write `generator`'s output to `path`. Tests run in parallel, so it is written to a temporary file
+/// first and then renamed into place, so that no test reads a partially written fixture.
+fn generate_file(generator: &Generator, path: &PathBuf) {
+    let parent = path.parent().expect("generated fixtures are in a directory");
+    std::fs::create_dir_all(parent).unwrap_or_else(|e| panic!("failed to create {}: {e}", parent.display()));
+    let temp = path.with_extension(format!("{}.tmp", std::process::id()));
+    let mut writer = BufWriter::new(File::create(&temp).unwrap_or_else(|e| panic!("failed to create {}: {e}", temp.display())));
+    generator.write(&mut writer).and_then(|()| writer.flush()).unwrap_or_else(|e| panic!("failed to write {}: {e}", temp.display()));
+    drop(writer);
+    std::fs::rename(&temp, path).unwrap_or_else(|e| panic!("failed to move {} into place: {e}", path.display()));
+}
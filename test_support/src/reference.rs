@@ -0,0 +1,87 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! A naive reference implementation of line counting, to check the registry's expected results
+//! against. This is kept simple rather than fast, except that mappings are applied to each distinct
+//! line rather than to every line, as the large fixtures have millions of lines but few distinct.
+
+use std::collections::{BTreeMap, HashSet};
+
+use bstr::ByteSlice;
+
+use crate::Distinct;
+
+/// How lines are mapped before they are compared, matching cuniq's `--trim` and `--lower`
+#[derive(Clone, Copy)]
+enum Mapping {
+    Trim,
+    Lower,
+    TrimLower,
+}
+
+/// Split `contents` into lines like cuniq splits files: on `\n`, with a `\r` before it removed, and
+/// with a final line counted even if it has no trailing newline
+pub fn lines(contents: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let lines = if contents.is_empty() { 0 } else { usize::MAX };
+    contents.strip_suffix(b"\n")
+        .unwrap_or(contents)
+        .split(|byte| *byte == b'\n')
+        .take(lines)
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+/// Apply `mapping` to `line`, trimming before lowercasing as cuniq does
+fn map_line(line: &[u8], mapping: Mapping) -> Vec<u8> {
+    match mapping {
+        Mapping::Trim => line.trim().to_vec(),
+        Mapping::Lower => line.to_lowercase(),
+        Mapping::TrimLower => line.trim().to_lowercase(),
+    }
+}
+
+/// The number of distinct lines in `contents`, with and without `--trim` and `--lower`
+pub fn distinct_lines(contents: &[u8]) -> Distinct {
+    let plain: HashSet<&[u8]> = lines(contents).collect();
+    let mapped = |mapping| plain.iter().map(|line| map_line(line, mapping)).collect::<HashSet<_>>().len();
+    Distinct {
+        plain: plain.len(),
+        trim: mapped(Mapping::Trim),
+        lower: mapped(Mapping::Lower),
+        trim_lower: mapped(Mapping::TrimLower),
+    }
+}
+
+/// The report of `contents`: each distinct line sorted bytewise, with its count before it, as
+/// printed by `cuniq --report --sort --no-pad`
+pub fn report(contents: &[u8]) -> Vec<u8> {
+    let mut counts: BTreeMap<&[u8], u64> = BTreeMap::new();
+    for line in lines(contents) {
+        *counts.entry(line).or_default() += 1;
+    }
+    let mut report = Vec::new();
+    for (line, count) in counts {
+        report.extend_from_slice(format!("{count} ").as_bytes());
+        report.extend_from_slice(line);
+        report.push(b'\n');
+    }
+    report
+}
+
+/// A 64-bit FNV-1a hash of `report`. This only needs to notice changes, not resist them.
+pub fn report_checksum(report: &[u8]) -> u64 {
+    report.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lines() {
+        let split = |contents: &'static [u8]| lines(contents).collect::<Vec<_>>();
+        assert!(split(b"").is_empty());
+        assert_eq!(split(b"\n"), [b""]);
+        assert_eq!(split(b"a\r\nb"), [b"a", b"b"]);
+        assert_eq!(split(b"a\n\nb\n"), [&b"a"[..], b"", b"b"]);
+    }
+}
@@ -0,0 +1,53 @@
+// This file is part of cuniq. Copyright © 2024 cuniq contributors.
+// cuniq is licensed under the GNU GPL v3.0 or any later version. See LICENSE file for full text.
+
+//! Recompute every expected result in the registry from the fixtures, so that editing a fixture
+//! without updating its expectations fails here rather than silently weakening other tests
+
+use test_support::{distinct_lines, lines, report, report_checksum, Fixture, ALL};
+
+/// Check `contents` against every count `fixture` expects
+fn check_counts(fixture: &Fixture, contents: &[u8], source: &str) {
+    assert_eq!(lines(contents).count(), fixture.lines, "{} ({source}) lines", fixture.filename);
+    assert_eq!(distinct_lines(contents), fixture.distinct, "{} ({source}) distinct lines", fixture.filename);
+}
+
+#[test]
+fn test_fixtures() {
+    for fixture in ALL {
+        let contents = fixture.read();
+        check_counts(fixture, &contents, "test_files");
+        if let Some(checksum) = fixture.report_checksum {
+            assert_eq!(report_checksum(&report(&contents)), checksum, "{} report checksum", fixture.filename);
+        }
+    }
+}
+
+/// Generated fixtures must have the same counts as the checked in copies they stand in for
+#[test]
+fn test_generators() {
+    for fixture in ALL {
+        if let Some(generator) = fixture.generator {
+            let contents = generator.generate();
+            check_counts(fixture, &contents, "generated");
+            assert_eq!(generator.generate(), contents, "{} is not generated deterministically", fixture.filename);
+        }
+    }
+}
+
+#[test]
+fn test_registry() {
+    for (i, fixture) in ALL.iter().enumerate() {
+        assert!(ALL[..i].iter().all(|other| other.filename != fixture.filename), "{} is registered twice", fixture.filename);
+        assert!(std::ptr::eq(Fixture::named(fixture.filename), *fixture));
+        assert_eq!(fixture.report_checksum.is_some(), fixture.generator.is_none(), "{} report checksum", fixture.filename);
+    }
+}
+
+#[test]
+fn test_report() {
+    let report = report(b"b\na\r\nb\n\n");
+    assert_eq!(report, b"1 \n1 a\n2 b\n");
+    assert_eq!(report_checksum(b""), 0xcbf2_9ce4_8422_2325);
+    assert_ne!(report_checksum(&report), report_checksum(b"1 \n1 a\n2 c\n"));
+}